@@ -0,0 +1,36 @@
+//! Fuzzes heading extraction and `ToC` generation with arbitrary documents.
+//!
+//! Run with `cargo +nightly fuzz run toc` from the repository root. Cache
+//! paths are built in the binary, so `url_to_path` is covered by its property
+//! tests instead.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use llms_fetch_mcp::toc::{self, TocConfig, TocStyle, TocText};
+
+fuzz_target!(|input: (&str, u16, bool, bool)| {
+    let (markdown, budget, annotated, clean) = input;
+    let lines = markdown.split('\n').count();
+    for heading in toc::extract_headings(markdown) {
+        assert!((1..=6).contains(&heading.level));
+        assert!(heading.line_number <= lines);
+        assert!(!toc::clean_text(&heading.text).is_empty());
+    }
+
+    let config = TocConfig {
+        toc_budget: budget.into(),
+        full_content_threshold: 0,
+        offsets: true,
+        style: if annotated {
+            TocStyle::Annotated
+        } else {
+            TocStyle::Plain
+        },
+        text: if clean { TocText::Clean } else { TocText::Raw },
+        ..TocConfig::default()
+    };
+    if let Some(rendered) = toc::generate_toc(markdown, markdown.len(), &config) {
+        assert!(rendered.len() <= config.toc_budget);
+    }
+});
@@ -0,0 +1,197 @@
+//! Admonitions (note, tip and warning boxes), converted to GFM alerts.
+//!
+//! Docs generators mark callouts with classes: Docusaurus with
+//! `theme-admonition-note` and an `admonitionHeading`, `MkDocs` Material and
+//! Sphinx with `admonition note` and a `.admonition-title` paragraph, or a
+//! `<details class="note">` for collapsible ones. Converted as they are, the
+//! box is lost and its title reads as a stray "Note" line. They're rewritten
+//! as blockquotes opening with `[!NOTE]` before conversion, which the
+//! Markdown keeps as a `> [!NOTE]` alert; titles other than the kind's name
+//! stay as the alert's first, bold line.
+
+use std::borrow::Cow;
+use std::fmt::Write;
+
+/// Elements taken for admonitions.
+const SELECTOR: &str = ".admonition, .theme-admonition, details.note, details.tip, \
+    details.info, details.warning, details.danger, details.example, details.question";
+
+/// Title elements inside an admonition.
+const TITLE_SELECTOR: &str = ".admonition-title, summary, .admonition-heading, \
+    [class*='admonitionHeading']";
+
+/// Class names (without a `theme-admonition-` or `admonition-` prefix) and
+/// the GFM alert each becomes.
+const KINDS: [(&str, &str); 27] = [
+    ("note", "NOTE"),
+    ("info", "NOTE"),
+    ("abstract", "NOTE"),
+    ("summary", "NOTE"),
+    ("tldr", "NOTE"),
+    ("seealso", "NOTE"),
+    ("todo", "NOTE"),
+    ("question", "NOTE"),
+    ("faq", "NOTE"),
+    ("example", "NOTE"),
+    ("quote", "NOTE"),
+    ("tip", "TIP"),
+    ("hint", "TIP"),
+    ("success", "TIP"),
+    ("check", "TIP"),
+    ("done", "TIP"),
+    ("important", "IMPORTANT"),
+    ("attention", "IMPORTANT"),
+    ("warning", "WARNING"),
+    ("caution", "CAUTION"),
+    ("danger", "CAUTION"),
+    ("error", "CAUTION"),
+    ("failure", "CAUTION"),
+    ("fail", "CAUTION"),
+    ("bug", "CAUTION"),
+    ("missing", "CAUTION"),
+    ("secondary", "NOTE"),
+];
+
+/// The alert a class list names, and the class it was named by.
+fn kind(classes: &str) -> Option<(&str, &'static str)> {
+    classes.split_whitespace().find_map(|class| {
+        let name = class
+            .strip_prefix("theme-admonition-")
+            .or_else(|| class.strip_prefix("admonition-"))
+            .unwrap_or(class);
+        KINDS
+            .iter()
+            .find(|(known, _)| name.eq_ignore_ascii_case(known))
+            .map(|(_, alert)| (name, *alert))
+    })
+}
+
+/// `html` with its admonitions rewritten as blockquotes for GFM alerts.
+pub fn prepare(html: &str) -> Cow<'_, str> {
+    let document = dom_query::Document::from(html);
+    let admonitions: Vec<_> = document.select(SELECTOR).iter().collect();
+    if admonitions.is_empty() {
+        return Cow::Borrowed(html);
+    }
+    // Innermost first, so an outer admonition takes in the inner one's quote
+    for admonition in admonitions.into_iter().rev() {
+        let classes = admonition.attr("class").unwrap_or_default();
+        let (name, alert) = kind(&classes).unwrap_or(("note", "NOTE"));
+        let title = admonition.select(TITLE_SELECTOR).first();
+        let text = title
+            .text()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        title.remove();
+        let mut quote = format!("<blockquote><p>[!{alert}]</p>");
+        if !text.is_empty() && !text.eq_ignore_ascii_case(name) && !text.eq_ignore_ascii_case(alert)
+        {
+            let text = html_escape::encode_text(&text);
+            write!(quote, "<p><strong>{text}</strong></p>").unwrap();
+        }
+        quote.push_str(&admonition.inner_html());
+        quote.push_str("</blockquote>");
+        admonition.replace_with_html(quote);
+    }
+    Cow::Owned(document.html().to_string())
+}
+
+/// `markdown` with the empty quote lines the conversion puts around an
+/// alert's `[!KIND]` removed, so the alert opens its blockquote and its
+/// first line follows it.
+pub fn tidy(markdown: &str) -> Cow<'_, str> {
+    if !markdown.contains("> [!") {
+        return Cow::Borrowed(markdown);
+    }
+    let in_code = crate::toc::fenced_lines(markdown);
+    let mut lines: Vec<&str> = Vec::new();
+    // Prefix of the alert line just pushed, while only empty quote lines follow it
+    let mut after_alert: Option<&str> = None;
+    for (line, fenced) in markdown.split_inclusive('\n').zip(in_code) {
+        if let Some(prefix) = after_alert {
+            if !fenced && line.trim() == prefix {
+                continue;
+            }
+            after_alert = None;
+        }
+        let is_alert = !fenced
+            && line
+                .trim_start_matches(['>', ' '])
+                .trim_end()
+                .strip_prefix("[!")
+                .and_then(|rest| rest.strip_suffix(']'))
+                .is_some_and(|alert| KINDS.iter().any(|(_, known)| *known == alert));
+        if is_alert {
+            let prefix = line.rfind('>').map_or("", |end| &line[..=end]);
+            while lines
+                .last()
+                .is_some_and(|previous| !previous.trim().is_empty() && previous.trim() == prefix)
+            {
+                lines.pop();
+            }
+            after_alert = Some(prefix.trim());
+        }
+        lines.push(line);
+    }
+    Cow::Owned(lines.concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(html: &str) -> String {
+        tidy(&html2md::parse_html(&prepare(html))).into_owned()
+    }
+
+    #[test]
+    fn test_prepare() {
+        // MkDocs Material and Sphinx
+        let markdown = convert(
+            r#"<div class="admonition warning"><p class="admonition-title">Warning</p>
+               <p>Back up first.</p></div>"#,
+        );
+        assert!(markdown.starts_with("> [!WARNING]\n"), "{markdown:?}");
+        assert!(markdown.contains("> Back up first."), "{markdown:?}");
+        assert!(!markdown.contains("Warning"), "{markdown:?}");
+
+        // Docusaurus, with a custom title
+        let markdown = convert(
+            r#"<div class="theme-admonition theme-admonition-tip alert alert--success">
+               <div class="admonitionHeading_Gvgb"><span><svg></svg></span>Pro tip</div>
+               <div class="admonitionContent_BuS1"><p>Use <code>--watch</code>.</p></div></div>"#,
+        );
+        assert!(
+            markdown.starts_with("> [!TIP]\n> **Pro tip**"),
+            "{markdown:?}"
+        );
+        assert!(markdown.contains("> Use `--watch`."), "{markdown:?}");
+
+        // Collapsible, nested in another admonition
+        let markdown = convert(
+            r#"<p>Intro.</p><div class="admonition danger"><p class="admonition-title">Danger</p>
+               <details class="note"><summary>Details</summary><p>Inner.</p></details></div>"#,
+        );
+        assert!(
+            markdown.starts_with("Intro.\n\n> [!CAUTION]\n"),
+            "{markdown:?}"
+        );
+        assert!(markdown.contains("> > [!NOTE]"), "{markdown:?}");
+        assert!(markdown.contains("**Details**"), "{markdown:?}");
+
+        let plain = "<p>No callouts.</p>";
+        assert!(matches!(prepare(plain), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_tidy() {
+        assert_eq!(
+            tidy(">\n>\n> [!NOTE]\n>\n>\n> Text\n"),
+            "> [!NOTE]\n> Text\n"
+        );
+        let code = "```\n>\n> [!NOTE]\n```\n";
+        assert_eq!(tidy(code), code);
+        assert_eq!(tidy(">\n> [!FOO]\n"), ">\n> [!FOO]\n");
+    }
+}
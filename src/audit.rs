@@ -0,0 +1,72 @@
+//! Append-only log of every fetch, for reviewing what the model accessed.
+//!
+//! Each line of `fetch-audit.jsonl` in the cache directory is one JSON record.
+//! The file is only ever appended to; rotating or truncating it is left to the
+//! operator.
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+/// File name of the audit log inside the cache directory.
+pub const AUDIT_FILE: &str = "fetch-audit.jsonl";
+
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    /// Serializes appends so concurrent tool calls don't interleave lines
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(cache_dir: &Path) -> Self {
+        Self {
+            path: cache_dir.join(AUDIT_FILE),
+            lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Appends `record` as a single JSON line.
+    pub async fn append(&self, record: &impl Serialize) -> std::io::Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        let _guard = self.lock.lock().await;
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        file.flush().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_append_only_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(dir.path());
+        log.append(&serde_json::json!({ "url": "https://example.com/a" }))
+            .await
+            .unwrap();
+        log.append(&serde_json::json!({ "url": "https://example.com/b" }))
+            .await
+            .unwrap();
+
+        let text = std::fs::read_to_string(dir.path().join(AUDIT_FILE)).unwrap();
+        let urls: Vec<String> = text
+            .lines()
+            .map(|line| {
+                let value: serde_json::Value = serde_json::from_str(line).unwrap();
+                value["url"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(urls, ["https://example.com/a", "https://example.com/b"]);
+    }
+}
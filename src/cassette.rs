@@ -0,0 +1,299 @@
+//! Recording HTTP traffic to a directory and replaying it later, for
+//! `--record` and `--replay`.
+//!
+//! Each request is saved, with the response it got, to its own TOML file in
+//! the cassette directory, named after its host and a hash of its method, URL
+//! and body. The file is in the format of the handler test fixtures
+//! ([`crate::fixtures`]): the URL requested and a single `[[exchange]]`, so a
+//! recording can be turned into a test case as it is. Replaying serves the
+//! recorded responses instead of making requests, so a bad conversion can be
+//! reproduced from a cassette attached to a bug report, and demos and tests
+//! give the same results offline. Requests that weren't recorded fail as
+//! network errors would.
+//!
+//! Cassettes are independent of the cache: a page `--max-age` serves from the
+//! cache makes no request to record or replay. Only responses are saved, so
+//! request headers such as the GitHub token stay out of cassettes, and so do
+//! the `set-cookie` headers of responses. Bodies are saved as sent, though,
+//! including those of pages fetched with cookies or a token.
+
+use crate::sidecar;
+use base64::Engine;
+use reqwest::ResponseBuilderExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Make requests, saving each with its response
+    Record,
+    /// Serve recorded responses and make no requests
+    Replay,
+}
+
+/// A cassette directory and whether it's being recorded or replayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cassette {
+    pub dir: PathBuf,
+    pub mode: Mode,
+}
+
+/// A recorded file: the URL requested and its exchange.
+#[derive(Debug, Serialize, Deserialize)]
+struct Recording {
+    url: String,
+    exchange: Vec<Exchange>,
+}
+
+/// A request and the response recorded for it.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Exchange {
+    #[serde(default = "get")]
+    pub method: String,
+    /// Path and query, as requested
+    pub path: String,
+    #[serde(default = "ok")]
+    pub status: u16,
+    /// Where the response came from, when a redirect the client followed
+    /// itself led elsewhere
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_url: Option<String>,
+    /// Repeated headers are joined with commas, and `set-cookie` isn't kept
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// The body, when it's UTF-8
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub body: String,
+    /// The body otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_base64: Option<String>,
+}
+
+fn get() -> String {
+    "GET".to_string()
+}
+
+fn ok() -> u16 {
+    200
+}
+
+impl Cassette {
+    /// Creates the directory to record to, or checks the one to replay exists.
+    pub fn prepare(&self) -> Result<(), String> {
+        match self.mode {
+            Mode::Record => std::fs::create_dir_all(&self.dir)
+                .map_err(|e| format!("Failed to create cassette {}: {e}", self.dir.display())),
+            Mode::Replay if !self.dir.is_dir() => {
+                Err(format!("Cassette {} doesn't exist", self.dir.display()))
+            }
+            Mode::Replay => Ok(()),
+        }
+    }
+
+    /// Sends `request` with `client` and records the exchange, or serves its
+    /// recording.
+    pub async fn send(
+        &self,
+        client: &reqwest::Client,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response, Error> {
+        let path = self.path(&request);
+        match self.mode {
+            Mode::Record => {
+                let method = request.method().to_string();
+                let url = request.url().clone();
+                let response = client.execute(request).await?;
+                let mut recording = Recording {
+                    url: url.to_string(),
+                    exchange: vec![Exchange::record(method, &url, response).await?],
+                };
+                let toml = toml::to_string(&recording)?;
+                if let Err(e) = tokio::fs::write(&path, toml).await {
+                    eprintln!("warning: failed to record {url}: {e}");
+                }
+                recording.exchange.remove(0).into_response(&url)
+            }
+            Mode::Replay => {
+                let missing = || {
+                    format!(
+                        "no recording of {} {} in {}",
+                        request.method(),
+                        request.url(),
+                        self.dir.display()
+                    )
+                };
+                let toml = tokio::fs::read_to_string(&path)
+                    .await
+                    .map_err(|_| missing())?;
+                let recording: Recording = toml::from_str(&toml)
+                    .map_err(|e| format!("invalid recording {}: {e}", path.display()))?;
+                let path_and_query = &request.url()[url::Position::BeforePath..];
+                recording
+                    .exchange
+                    .into_iter()
+                    .find(|exchange| {
+                        exchange
+                            .method
+                            .eq_ignore_ascii_case(request.method().as_str())
+                            && exchange.path == path_and_query
+                    })
+                    .ok_or_else(missing)?
+                    .into_response(request.url())
+            }
+        }
+    }
+
+    /// File `request` is recorded in.
+    fn path(&self, request: &reqwest::Request) -> PathBuf {
+        let mut key = format!("{} {}\n", request.method(), request.url()).into_bytes();
+        if let Some(body) = request.body().and_then(reqwest::Body::as_bytes) {
+            key.extend_from_slice(body);
+        }
+        let host = request.url().host_str().unwrap_or("local");
+        self.dir
+            .join(format!("{host}-{}.toml", &sidecar::sha256(&key)[..16]))
+    }
+}
+
+impl Exchange {
+    async fn record(
+        method: String,
+        url: &url::Url,
+        response: reqwest::Response,
+    ) -> Result<Self, Error> {
+        let status = response.status().as_u16();
+        let final_url = Some(response.url())
+            .filter(|final_url| *final_url != url)
+            .map(ToString::to_string);
+        let mut headers = BTreeMap::<String, String>::new();
+        for (name, value) in response.headers() {
+            // Cookies the site set would let anyone with the cassette log in
+            if name == reqwest::header::SET_COOKIE {
+                continue;
+            }
+            let value = String::from_utf8_lossy(value.as_bytes());
+            headers
+                .entry(name.to_string())
+                .and_modify(|joined| {
+                    joined.push_str(", ");
+                    joined.push_str(&value);
+                })
+                .or_insert_with(|| value.into_owned());
+        }
+        let bytes = response.bytes().await?;
+        let (body, body_base64) = match String::from_utf8(bytes.to_vec()) {
+            Ok(text) => (text, None),
+            Err(_) => (
+                String::new(),
+                Some(base64::engine::general_purpose::STANDARD.encode(&bytes)),
+            ),
+        };
+        Ok(Self {
+            method,
+            path: url[url::Position::BeforePath..].to_string(),
+            status,
+            final_url,
+            headers,
+            body,
+            body_base64,
+        })
+    }
+
+    /// The response body.
+    pub fn body(&self) -> Result<Vec<u8>, Error> {
+        Ok(match &self.body_base64 {
+            Some(encoded) => base64::engine::general_purpose::STANDARD.decode(encoded)?,
+            None => self.body.clone().into_bytes(),
+        })
+    }
+
+    /// The response to a request for `url` as reqwest would have returned it.
+    fn into_response(self, url: &url::Url) -> Result<reqwest::Response, Error> {
+        let url = match &self.final_url {
+            Some(final_url) => url::Url::parse(final_url)?,
+            None => url.clone(),
+        };
+        let body = self.body()?;
+        let mut response = http::Response::builder().status(self.status).url(url);
+        for (name, value) in &self.headers {
+            response = response.header(name, value);
+        }
+        Ok(response.body(body)?.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let app = axum::Router::new()
+            .route(
+                "/page",
+                axum::routing::get(|| async {
+                    (
+                        [("content-type", "text/markdown"), ("set-cookie", "a=1")],
+                        "# Page\n",
+                    )
+                }),
+            )
+            .route(
+                "/latin1",
+                axum::routing::get(|| async { vec![b'C', b'a', b'f', 0xE9] }),
+            );
+        let server = tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let cassette = |dir: &std::path::Path, mode| Cassette {
+            dir: dir.to_path_buf(),
+            mode,
+        };
+        let recorder = cassette(&dir.path().join("recorded"), Mode::Record);
+        recorder.prepare().unwrap();
+        for path in ["/page", "/latin1"] {
+            let request = client.get(format!("{base}{path}")).build().unwrap();
+            assert_eq!(recorder.send(&client, request).await.unwrap().status(), 200);
+        }
+        server.abort();
+        let recordings: Vec<_> = std::fs::read_dir(&recorder.dir)
+            .unwrap()
+            .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect();
+        assert_eq!(recordings.len(), 2);
+        for recording in &recordings {
+            // Recordings are test fixtures as they are, without the cookie
+            toml::from_str::<crate::fixtures::Cassette>(recording).unwrap();
+            assert!(!recording.contains("set-cookie"), "{recording}");
+        }
+
+        let player = cassette(&recorder.dir, Mode::Replay);
+        player.prepare().unwrap();
+        let replay = |path: &str| {
+            let request = client.get(format!("{base}{path}")).build().unwrap();
+            player.send(&client, request)
+        };
+        let page = replay("/page").await.unwrap();
+        assert_eq!(page.url().as_str(), format!("{base}/page"));
+        assert_eq!(page.headers()["content-type"], "text/markdown");
+        assert!(!page.headers().contains_key("set-cookie"));
+        assert_eq!(page.text().await.unwrap(), "# Page\n");
+        let latin1 = replay("/latin1").await.unwrap();
+        assert_eq!(latin1.bytes().await.unwrap().as_ref(), b"Caf\xE9");
+        let missing = replay("/missing").await.unwrap_err();
+        assert!(missing.to_string().starts_with("no recording of GET"));
+
+        assert!(
+            cassette(&dir.path().join("nope"), Mode::Replay)
+                .prepare()
+                .is_err()
+        );
+    }
+}
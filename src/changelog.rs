@@ -0,0 +1,269 @@
+//! Changelog parsing and version filtering.
+//!
+//! Finds version headings (`## [1.2.0] - 2024-01-01`, `## v2.0.0-beta.1`, ...) and
+//! slices the document into per-version entries so callers can keep only the
+//! releases newer than a given version.
+
+use crate::toc::{Heading, extract_headings};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Version parsed from a heading or user input.
+///
+/// Numeric components are compared pairwise with missing components treated as zero,
+/// and a pre-release sorts before the release with the same components. Pre-releases
+/// compare as in semver: identifier by identifier, numerically where both are
+/// numbers, so `beta.2` comes before `beta.10`.
+#[derive(Debug, Clone)]
+pub struct Version {
+    components: Vec<u64>,
+    pre_release: Option<String>,
+}
+
+impl Version {
+    /// Parses the first version-looking token in `text`.
+    ///
+    /// Requires at least `major.minor` so dates (`2024-01-01`) and counts aren't
+    /// mistaken for versions. A leading `v`/`V` is allowed.
+    pub fn find_in(text: &str) -> Option<Self> {
+        let bytes = text.as_bytes();
+        let mut start = 0;
+        while start < bytes.len() {
+            let boundary = start == 0 || !bytes[start - 1].is_ascii_alphanumeric();
+            let digits_from = if boundary && matches!(bytes[start], b'v' | b'V') {
+                start + 1
+            } else {
+                start
+            };
+            if (boundary || digits_from > start)
+                && bytes.get(digits_from).is_some_and(u8::is_ascii_digit)
+                && let Some(version) = Self::parse_at(&text[digits_from..])
+            {
+                return Some(version);
+            }
+            start += 1;
+        }
+        None
+    }
+
+    fn parse_at(text: &str) -> Option<Self> {
+        let end = text
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(text.len());
+        let numeric = text[..end].trim_end_matches('.');
+        let components = numeric
+            .split('.')
+            .map(str::parse)
+            .collect::<Result<Vec<u64>, _>>()
+            .ok()?;
+        if components.len() < 2 {
+            return None;
+        }
+
+        let rest = &text[numeric.len()..];
+        let pre_release = rest.strip_prefix('-').and_then(|pre| {
+            let pre_end = pre
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '-'))
+                .unwrap_or(pre.len());
+            let pre = pre[..pre_end].trim_end_matches(['.', '-']);
+            // `1.2.0-2024-01-01` is a date suffix, not a pre-release
+            (!pre.is_empty() && !pre.starts_with(|c: char| c.is_ascii_digit()))
+                .then(|| pre.to_string())
+        });
+
+        Some(Self {
+            components,
+            pre_release,
+        })
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let len = self.components.len().max(other.components.len());
+        for i in 0..len {
+            let a = self.components.get(i).copied().unwrap_or(0);
+            let b = other.components.get(i).copied().unwrap_or(0);
+            match a.cmp(&b) {
+                Ordering::Equal => {}
+                ord => return ord,
+            }
+        }
+        match (&self.pre_release, &other.pre_release) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(a), Some(b)) => compare_pre_releases(a, b),
+        }
+    }
+}
+
+/// Orders pre-release tags by their dot-separated identifiers: numbers
+/// numerically and before words, words in ASCII order, and a tag that runs
+/// out of identifiers first before the longer one (semver §11).
+fn compare_pre_releases(a: &str, b: &str) -> Ordering {
+    let mut a = a.split('.');
+    let mut b = b.split('.');
+    loop {
+        let ord = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => match (x.parse::<u64>(), y.parse::<u64>()) {
+                (Ok(x), Ok(y)) => x.cmp(&y),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => x.cmp(y),
+            },
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let numbers: Vec<String> = self.components.iter().map(u64::to_string).collect();
+        write!(f, "{}", numbers.join("."))?;
+        if let Some(pre) = &self.pre_release {
+            write!(f, "-{pre}")?;
+        }
+        Ok(())
+    }
+}
+
+/// One version section of a changelog.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub version: Version,
+    /// Line number of the version heading (1-indexed)
+    pub line_number: usize,
+    /// Heading and body, verbatim
+    pub text: String,
+}
+
+/// Splits a changelog into version entries in document order.
+///
+/// Entries are the headings at the shallowest level that carries a version, so
+/// `### Upgrading from 1.0` inside a `## 2.0.0` section doesn't start a new entry.
+/// Each entry runs until the next heading at the same or a shallower level.
+pub fn parse_entries(markdown: &str) -> Vec<Entry> {
+    let headings = extract_headings(markdown);
+    let versioned: Vec<(&Heading, Version)> = headings
+        .iter()
+        .filter_map(|h| Version::find_in(&h.text).map(|v| (h, v)))
+        .collect();
+
+    let Some(entry_level) = versioned.iter().map(|(h, _)| h.level).min() else {
+        return Vec::new();
+    };
+
+    let lines: Vec<&str> = markdown.lines().collect();
+
+    versioned
+        .into_iter()
+        .filter(|(h, _)| h.level == entry_level)
+        .map(|(heading, version)| {
+            let end_line = headings
+                .iter()
+                .find(|h| h.line_number > heading.line_number && h.level <= entry_level)
+                .map_or(lines.len(), |h| h.line_number - 1);
+            let start = heading.line_number - 1;
+            let text = lines
+                .get(start..end_line.max(heading.line_number))
+                .unwrap_or_default()
+                .join("\n")
+                .trim_end()
+                .to_string();
+            Entry {
+                version,
+                line_number: heading.line_number,
+                text,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(text: &str) -> Version {
+        Version::find_in(text).unwrap()
+    }
+
+    #[test]
+    fn test_find_version_formats() {
+        assert_eq!(v("## [1.2.3] - 2024-01-01").to_string(), "1.2.3");
+        assert_eq!(v("## v2.0.0-beta.1").to_string(), "2.0.0-beta.1");
+        assert_eq!(v("# Release V10.4").to_string(), "10.4");
+        assert_eq!(v("## 3.12.0 (2023-10-02)").to_string(), "3.12.0");
+        assert_eq!(v("1.2.0-2024-01-01").to_string(), "1.2.0");
+    }
+
+    #[test]
+    fn test_find_version_rejects_non_versions() {
+        assert!(Version::find_in("## Unreleased").is_none());
+        assert!(Version::find_in("## 2024-01-01").is_none());
+        assert!(Version::find_in("## Release 7").is_none());
+        assert!(Version::find_in("## libv8 notes").is_none());
+    }
+
+    #[test]
+    fn test_version_ordering() {
+        assert!(v("1.10.0") > v("1.9.9"));
+        assert!(v("2.0") == v("2.0.0"));
+        assert!(v("2.0.0") > v("2.0.0-rc.1"));
+        assert!(v("2.0.0-rc.1") > v("2.0.0-beta.2"));
+        assert!(v("2.0.0-beta.10") > v("2.0.0-beta.2"));
+        assert!(v("2.0.0-beta.1") > v("2.0.0-beta"));
+        assert!(v("2.0.0-alpha.beta") > v("2.0.0-alpha.1"));
+        assert!(v("0.1.8") > v("0.1.7"));
+    }
+
+    #[test]
+    fn test_entries_newer_than() {
+        let md = "# Changelog\n\n## [Unreleased]\n\n## [1.2.0] - 2024-03-01\n### Added\n- Thing\n\n## [1.1.0] - 2024-02-01\n- Fix\n\n## [1.0.0] - 2024-01-01\n- Initial";
+        let since = v("1.0.0");
+        let entries: Vec<_> = parse_entries(md)
+            .into_iter()
+            .filter(|e| e.version > since)
+            .collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].version.to_string(), "1.2.0");
+        assert_eq!(entries[0].line_number, 5);
+        assert_eq!(
+            entries[0].text,
+            "## [1.2.0] - 2024-03-01\n### Added\n- Thing"
+        );
+        assert_eq!(entries[1].text, "## [1.1.0] - 2024-02-01\n- Fix");
+    }
+
+    #[test]
+    fn test_nested_version_mentions_stay_in_entry() {
+        let md = "## 2.0.0\n### Migrating from 1.5.0\nSteps\n## 1.5.0\nOld";
+        let entries = parse_entries(md);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].text, "## 2.0.0\n### Migrating from 1.5.0\nSteps");
+    }
+
+    #[test]
+    fn test_no_versions() {
+        assert!(parse_entries("# Docs\n## Intro\ntext").is_empty());
+    }
+}
@@ -0,0 +1,168 @@
+//! Citations for cached content.
+//!
+//! Only the cache knows which URL a file was fetched from, so the `cite` tool
+//! maps a range of a cached file's lines back to its source: the URL, with
+//! the anchor of the nearest heading above the range, ready to link to. The
+//! range can also be given as character offsets, like those the `ToC` shows
+//! with `--toc-offsets`, which don't depend on how a client counts lines.
+
+use crate::toc;
+use regex::Regex;
+use std::sync::LazyLock;
+use url::Url;
+
+/// Permalinks with no text, like `[](#install)` or `[¶](#install)`, which
+/// converted docs pages keep next to their headings, and `{#install}` ids.
+static EXPLICIT_ANCHOR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[[^\]\w]*\]\(#([^)\s]+)\)|\{#([^}\s]+)\}").unwrap());
+
+/// `[text](target)`, for keeping only the text.
+static LINK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[([^\]]*)\]\([^)]*\)").unwrap());
+
+/// A heading a citation points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Anchor {
+    /// Heading text without Markdown formatting
+    pub heading: String,
+    /// Fragment identifying the heading on the source page
+    pub id: String,
+    /// 1-based line of the heading
+    pub line: usize,
+}
+
+/// `## The `spawn` [function](url) [](#x)` → `The spawn function`.
+pub fn plain_text(heading: &str) -> String {
+    let text = EXPLICIT_ANCHOR.replace_all(heading, "");
+    let text = LINK.replace_all(&text, "$1");
+    let text: String = text
+        .trim()
+        .trim_matches('#')
+        .chars()
+        .filter(|c| !matches!(c, '`' | '*'))
+        .collect();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// GitHub-style anchor of a heading: lowercase, punctuation dropped, spaces
+/// turned into hyphens.
+pub fn slug(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The page's own id a heading written as `line` kept, as a permalink or a
+/// `{#id}` suffix.
+pub fn explicit_id(line: &str) -> Option<&str> {
+    EXPLICIT_ANCHOR
+        .captures(line)
+        .and_then(|captures| captures.get(1).or_else(|| captures.get(2)))
+        .map(|id| id.as_str())
+}
+
+/// The nearest heading at or above `line` in `content`, with its anchor: the
+/// page's own id when the heading kept one, otherwise the slug of its text.
+pub fn anchor_before(content: &str, line: usize) -> Option<Anchor> {
+    let headings = toc::extract_headings(content);
+    let heading = headings.iter().rev().find(|h| h.line_number <= line)?;
+    let raw = content
+        .lines()
+        .nth(heading.line_number - 1)
+        .unwrap_or_default();
+    let text = plain_text(&heading.text);
+    let id = explicit_id(raw).map_or_else(|| slug(&text), str::to_string);
+    Some(Anchor {
+        heading: text,
+        id,
+        line: heading.line_number,
+    })
+}
+
+/// 1-based line of the character at `offset` in `content`, or `None` past its
+/// end.
+pub fn line_at(content: &str, offset: usize) -> Option<usize> {
+    let mut line = 1;
+    let mut chars = content.chars();
+    for _ in 0..offset {
+        if chars.next()? == '\n' {
+            line += 1;
+        }
+    }
+    Some(line)
+}
+
+/// `url` pointing at `anchor`, replacing any fragment it had.
+pub fn link(url: &str, anchor: Option<&Anchor>) -> String {
+    let Some(anchor) = anchor.filter(|anchor| !anchor.id.is_empty()) else {
+        return url.to_string();
+    };
+    match Url::parse(url) {
+        Ok(mut parsed) => {
+            parsed.set_fragment(Some(&anchor.id));
+            parsed.to_string()
+        }
+        Err(_) => format!("{url}#{}", anchor.id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anchor_before() {
+        let content = "intro\n# Guide\ntext\n## Install `tokio` [](#setup)\ncargo add\n## The [Runtime](r.md): Basics!\nrun\n";
+        assert_eq!(anchor_before(content, 1), None);
+        assert_eq!(
+            anchor_before(content, 3),
+            Some(Anchor {
+                heading: "Guide".to_string(),
+                id: "guide".to_string(),
+                line: 2,
+            })
+        );
+        let install = anchor_before(content, 5).unwrap();
+        assert_eq!(
+            (install.heading.as_str(), install.id.as_str()),
+            ("Install tokio", "setup")
+        );
+        let runtime = anchor_before(content, 7).unwrap();
+        assert_eq!(
+            (runtime.heading.as_str(), runtime.id.as_str()),
+            ("The Runtime: Basics!", "the-runtime-basics")
+        );
+    }
+
+    #[test]
+    fn test_line_at() {
+        let content = "é\r\n# Guide\r\n\r\ntext";
+        assert_eq!(line_at(content, 0), Some(1));
+        assert_eq!(line_at(content, 2), Some(1));
+        assert_eq!(line_at(content, 3), Some(2));
+        assert_eq!(line_at(content, 14), Some(4));
+        assert_eq!(line_at(content, 18), Some(4));
+        assert_eq!(line_at(content, 19), None);
+    }
+
+    #[test]
+    fn test_link() {
+        let anchor = Anchor {
+            heading: "Install".to_string(),
+            id: "install".to_string(),
+            line: 1,
+        };
+        assert_eq!(
+            link("https://example.com/guide#old", Some(&anchor)),
+            "https://example.com/guide#install"
+        );
+        assert_eq!(
+            link("https://example.com/guide", None),
+            "https://example.com/guide"
+        );
+    }
+}
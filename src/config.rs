@@ -0,0 +1,1355 @@
+//! Server settings from the command line, environment, and config file.
+//!
+//! Each option is resolved with the precedence CLI > environment variable >
+//! config file > built-in default. Clap handles the first two layers; options
+//! left unset there fall through to the TOML file given by `--config`.
+
+use crate::cassette::{self, Cassette};
+use crate::index::DedupScope;
+use crate::lockfile::Drift;
+use crate::normalize::Normalize;
+use crate::policy::{DomainPolicy, Policies};
+use crate::site::SameSite;
+use crate::{dns, memory, pins, quota, toc};
+use clap::{Parser, Subcommand};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const DEFAULT_CACHE_DIR: &str = ".llms-fetch-mcp";
+pub const DEFAULT_PREVIEW_THRESHOLD: usize = 500_000;
+pub const DEFAULT_PREVIEW_LINES: usize = 100;
+
+// Each switch is independent, so they stay plain flags
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Parser, Debug, Clone)]
+#[command(author, version, about = "MCP server for fetching and caching web documentation", long_about = None)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Cache directory path (default: .llms-fetch-mcp)
+    #[arg(value_name = "CACHE_DIR", env = "LLMS_FETCH_CACHE_DIR")]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Keep this server's cached files under their own namespace in the cache
+    /// directory; tools can override it per call
+    #[arg(long, value_parser = parse_namespace, env = "LLMS_FETCH_NAMESPACE")]
+    pub namespace: Option<String>,
+
+    /// TOML config file for options not given on the command line or environment
+    #[arg(long, value_name = "PATH", env = "LLMS_FETCH_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Maximum `ToC` size in bytes (default: 4000)
+    #[arg(long, env = "LLMS_FETCH_TOC_BUDGET")]
+    pub toc_budget: Option<usize>,
+
+    /// Minimum document size in bytes to generate `ToC` (default: 8000)
+    #[arg(long, env = "LLMS_FETCH_TOC_THRESHOLD")]
+    pub toc_threshold: Option<usize>,
+
+    /// Follow each `ToC` entry with its character offset (`@1234`), which
+    /// `cite` accepts in place of line numbers
+    #[arg(long, env = "LLMS_FETCH_TOC_OFFSETS")]
+    pub toc_offsets: bool,
+
+    /// `annotated` follows each `ToC` entry with the first sentence of its
+    /// section, when the budget allows (default: plain)
+    #[arg(long, value_name = "STYLE", env = "LLMS_FETCH_TOC_STYLE")]
+    pub toc_style: Option<toc::TocStyle>,
+
+    /// Text between each `ToC` line number and heading; `\t` is a tab
+    /// (default: →)
+    #[arg(long, value_name = "TEXT", value_parser = parse_separator, env = "LLMS_FETCH_TOC_SEPARATOR")]
+    pub toc_separator: Option<String>,
+
+    /// `none` leaves `ToC` line numbers unpadded instead of right-aligning
+    /// them (default: aligned)
+    #[arg(long, value_name = "PADDING", env = "LLMS_FETCH_TOC_PADDING")]
+    pub toc_padding: Option<toc::TocPadding>,
+
+    /// `clean` strips hashes and inline markdown from `ToC` headings, keeping
+    /// code spans (default: raw)
+    #[arg(long, value_name = "TEXT", env = "LLMS_FETCH_TOC_TEXT")]
+    pub toc_text: Option<toc::TocText>,
+
+    /// Turn standalone bold lines (`**Section Title**`) of converted pages into
+    /// headings, for sites that don't mark up their sections
+    #[arg(long, env = "LLMS_FETCH_BOLD_HEADINGS")]
+    pub bold_headings: bool,
+
+    /// `ascii` also replaces typographic quotes and dashes in converted pages;
+    /// `off` leaves their entities and unusual spaces (default: text)
+    #[arg(long, value_enum, env = "LLMS_FETCH_NORMALIZE")]
+    pub normalize: Option<Normalize>,
+
+    /// Documents of at least this many bytes get only a preview inline (default: 500000)
+    #[arg(long, value_name = "BYTES", env = "LLMS_FETCH_PREVIEW_THRESHOLD")]
+    pub preview_threshold: Option<usize>,
+
+    /// Number of lines in the preview of oversized documents (default: 100)
+    #[arg(long, value_name = "N", env = "LLMS_FETCH_PREVIEW_LINES")]
+    pub preview_lines: Option<usize>,
+
+    /// GitHub token for API-backed pages (higher rate limit, enables discussions)
+    #[arg(long, env = "GITHUB_TOKEN", hide_env_values = true)]
+    pub github_token: Option<String>,
+
+    /// Resolve HOST:PORT to ADDRESS instead of using DNS, like curl's --resolve (repeatable)
+    #[arg(
+        long,
+        value_name = "HOST:PORT:ADDRESS",
+        value_parser = parse_resolve,
+        env = "LLMS_FETCH_RESOLVE",
+        value_delimiter = ','
+    )]
+    pub resolve: Vec<(String, SocketAddr)>,
+
+    /// Send `Host: HEADER` on requests to DOMAIN, e.g. for staging servers (repeatable)
+    #[arg(
+        long,
+        value_name = "DOMAIN=HEADER",
+        value_parser = parse_host_header,
+        env = "LLMS_FETCH_HOST_HEADER",
+        value_delimiter = ','
+    )]
+    pub host_header: Vec<(String, String)>,
+
+    /// Address family to connect over (default: auto)
+    #[arg(long, value_enum, env = "LLMS_FETCH_IP_VERSION")]
+    pub ip_version: Option<dns::IpVersion>,
+
+    /// Query this DNS server instead of the system resolver (repeatable)
+    #[arg(
+        long,
+        value_name = "IP[:PORT]",
+        value_parser = dns::parse_dns_server,
+        env = "LLMS_FETCH_DNS_SERVER",
+        value_delimiter = ','
+    )]
+    pub dns_server: Vec<SocketAddr>,
+
+    /// Give up connecting to a host after this many seconds (default: 10)
+    #[arg(long, value_name = "SECONDS", env = "LLMS_FETCH_CONNECT_TIMEOUT")]
+    pub connect_timeout: Option<u64>,
+
+    /// Give up on a response that sends nothing for this many seconds (default: 30)
+    #[arg(long, value_name = "SECONDS", env = "LLMS_FETCH_READ_TIMEOUT")]
+    pub read_timeout: Option<u64>,
+
+    /// Give up on a single URL variation after this many seconds (default: 30)
+    #[arg(long, value_name = "SECONDS", env = "LLMS_FETCH_VARIATION_TIMEOUT")]
+    pub variation_timeout: Option<u64>,
+
+    /// Stop waiting for a fetch's variations after this many seconds and
+    /// return those that finished (default: 60)
+    #[arg(long, value_name = "SECONDS", env = "LLMS_FETCH_FETCH_TIMEOUT")]
+    pub fetch_timeout: Option<u64>,
+
+    /// Follow at most this many redirects per request (default: 10)
+    #[arg(long, value_name = "N", env = "LLMS_FETCH_MAX_REDIRECTS")]
+    pub max_redirects: Option<usize>,
+
+    /// Don't follow redirects to another origin; report them so the caller
+    /// can fetch the target explicitly
+    #[arg(long, env = "LLMS_FETCH_CONFIRM_CROSS_ORIGIN_REDIRECTS")]
+    pub confirm_cross_origin_redirects: bool,
+
+    /// Only follow redirects and alternate versions (AMP canonicals, other
+    /// languages) on the requested URL's site or an `--allow-site` domain
+    #[arg(long, env = "LLMS_FETCH_SAME_SITE")]
+    pub same_site: bool,
+
+    /// Under `--same-site`, also follow to DOMAIN and its subdomains
+    /// (repeatable)
+    #[arg(
+        long,
+        value_name = "DOMAIN",
+        value_parser = parse_site,
+        env = "LLMS_FETCH_ALLOW_SITE",
+        value_delimiter = ','
+    )]
+    pub allow_site: Vec<String>,
+
+    /// Serve cached pages younger than this many seconds without a network
+    /// request (disabled by default)
+    #[arg(long, value_name = "SECONDS", env = "LLMS_FETCH_MAX_AGE")]
+    pub max_age: Option<u64>,
+
+    /// Serve cached pages up to this many seconds past `--max-age` while
+    /// refreshing them in the background (disabled by default)
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        env = "LLMS_FETCH_STALE_WHILE_REVALIDATE"
+    )]
+    pub stale_while_revalidate: Option<u64>,
+
+    /// Refetch URL every SECONDS while the server runs, keeping it warm in the
+    /// cache (repeatable)
+    #[arg(long, value_name = "URL=SECONDS", value_parser = parse_pin, env = "LLMS_FETCH_PIN")]
+    pub pin: Vec<(String, u64)>,
+
+    /// Remember variations that returned 404/410 for this many seconds and skip
+    /// them on later fetches (disabled by default)
+    #[arg(long, value_name = "SECONDS", env = "LLMS_FETCH_NEGATIVE_CACHE_TTL")]
+    pub negative_cache_ttl: Option<u64>,
+
+    /// Where identical content fetched from another URL is saved once, with
+    /// the URL recorded as an alias (default: call)
+    #[arg(long, value_enum, env = "LLMS_FETCH_DEDUP_SCOPE")]
+    pub dedup_scope: Option<DedupScope>,
+
+    /// Fail fetches after this many HTTP requests until `reset_quota` is called
+    #[arg(long, value_name = "N", env = "LLMS_FETCH_MAX_REQUESTS")]
+    pub max_requests: Option<u64>,
+
+    /// Fail fetches after downloading this many bytes until `reset_quota` is called
+    #[arg(long, value_name = "BYTES", env = "LLMS_FETCH_MAX_BYTES")]
+    pub max_bytes: Option<u64>,
+
+    /// Don't record fetches in fetch-audit.jsonl in the cache directory
+    #[arg(long, env = "LLMS_FETCH_NO_AUDIT")]
+    pub no_audit: bool,
+
+    /// Serve only what is already cached: no network requests and no cache writes
+    #[arg(long, env = "LLMS_FETCH_READ_ONLY")]
+    pub read_only: bool,
+
+    /// Keep fetched content in memory until the server exits instead of
+    /// writing anything to disk, as with a cache directory of `:memory:`
+    #[arg(long, env = "LLMS_FETCH_EPHEMERAL")]
+    pub ephemeral: bool,
+
+    /// Report cached files by paths relative to the client root holding the
+    /// cache directory, or else to the cache directory, for servers whose
+    /// absolute paths don't exist where the client reads files
+    #[arg(long, env = "LLMS_FETCH_RELATIVE_PATHS")]
+    pub relative_paths: bool,
+
+    /// Show paths under FROM in tool results under TO instead, e.g. for a
+    /// cache bind-mounted into a container (repeatable)
+    #[arg(
+        long,
+        value_name = "FROM=TO",
+        value_parser = parse_path_map,
+        env = "LLMS_FETCH_PATH_MAP",
+        value_delimiter = ','
+    )]
+    pub path_map: Vec<(PathBuf, PathBuf)>,
+
+    /// Save every HTTP request and its response to a file in DIR, to replay
+    /// with `--replay`
+    #[arg(
+        long,
+        value_name = "DIR",
+        env = "LLMS_FETCH_RECORD",
+        conflicts_with = "replay"
+    )]
+    pub record: Option<PathBuf>,
+
+    /// Serve HTTP responses recorded with `--record` in DIR instead of making
+    /// requests
+    #[arg(long, value_name = "DIR", env = "LLMS_FETCH_REPLAY")]
+    pub replay: Option<PathBuf>,
+
+    /// At startup, ask GitHub whether a newer release exists, and log it and
+    /// report it in `info`. Nothing is downloaded
+    #[arg(long, env = "LLMS_FETCH_UPDATE_CHECK")]
+    pub update_check: bool,
+
+    /// Check fetched content against the hashes in this lockfile, as written
+    /// by the `lock` subcommand
+    #[arg(long, value_name = "PATH", env = "LLMS_FETCH_LOCKFILE")]
+    pub lockfile: Option<PathBuf>,
+
+    /// What to do with fetched content that differs from the lockfile
+    /// (default: warn)
+    #[arg(long, value_enum, env = "LLMS_FETCH_LOCKFILE_DRIFT")]
+    pub lockfile_drift: Option<Drift>,
+
+    /// Allow file:// URLs under this directory, in addition to the cache
+    /// directory and the client's roots (repeatable)
+    #[arg(
+        long,
+        value_name = "DIR",
+        env = "LLMS_FETCH_ALLOW_PATH",
+        value_delimiter = ','
+    )]
+    pub allow_path: Vec<PathBuf>,
+
+    /// Preferred language tag (e.g. `de`, `pt-BR`): pages listing `hreflang`
+    /// versions are fetched in this language
+    #[arg(long, value_name = "TAG", value_parser = parse_language, env = "LLMS_FETCH_LANGUAGE")]
+    pub language: Option<String>,
+
+    /// Let `fetch` callers set this request header, e.g. `Authorization`
+    /// (repeatable; none are allowed by default)
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_parser = parse_header_name,
+        env = "LLMS_FETCH_ALLOW_HEADER",
+        value_delimiter = ','
+    )]
+    pub allow_header: Vec<String>,
+
+    /// Send these cookies to DOMAIN and its subdomains, e.g. a session copied
+    /// from a browser (repeatable)
+    #[arg(
+        long,
+        value_name = "DOMAIN=NAME=VALUE[; NAME=VALUE...]",
+        value_parser = parse_cookie,
+        env = "LLMS_FETCH_COOKIE",
+        hide_env_values = true
+    )]
+    pub cookie: Vec<(String, String)>,
+
+    /// Keep cookies across restarts, encrypted with this key, in the cache directory
+    #[arg(long, env = "LLMS_FETCH_COOKIE_KEY", hide_env_values = true)]
+    pub cookie_key: Option<String>,
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Check the cache for leftovers from interrupted writes and repair them,
+    /// then check cached files against their recorded hashes, and exit
+    Verify {
+        /// Also check the cached files against this lockfile's hashes
+        /// (default: the configured `--lockfile`)
+        #[arg(long, value_name = "PATH")]
+        lockfile: Option<PathBuf>,
+    },
+    /// Write a lockfile of the hash and `ETag` of every cached file, and exit
+    Lock {
+        /// Where to write it
+        #[arg(long, default_value = crate::lockfile::LOCKFILE)]
+        output: PathBuf,
+    },
+    /// Move cached files to the paths this version caches their URLs at, and
+    /// exit
+    Migrate {
+        /// Only report what would be moved
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Rebuild the search index from the cached files, and exit
+    #[cfg(feature = "search-index")]
+    Reindex,
+    /// Crawl a docs site from URL and write an offline Markdown copy of it to the cache
+    Mirror {
+        /// Page to start from; the crawl stays under its directory
+        url: String,
+        /// Link hops to follow from the start page
+        #[arg(long, default_value_t = crate::crawl::DEFAULT_MAX_DEPTH)]
+        depth: usize,
+        /// Most pages to fetch
+        #[arg(long, default_value_t = crate::crawl::MAX_PAGES)]
+        max_pages: usize,
+    },
+    /// Ask GitHub whether a newer release exists, and exit. Nothing is
+    /// downloaded
+    UpdateCheck,
+}
+
+/// Accepts names made of ASCII letters, digits, `-`, `_` and `.`, not starting
+/// with `.`, so a namespace is always a single plain directory name.
+pub fn parse_namespace(value: &str) -> Result<String, String> {
+    let valid = !value.is_empty()
+        && value.len() <= 64
+        && !value.starts_with('.')
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(value.to_string())
+    } else {
+        Err(format!(
+            "invalid namespace \"{value}\" (use up to 64 letters, digits, '-', '_' or '.')"
+        ))
+    }
+}
+
+/// Accepts BCP 47-shaped tags: subtags of ASCII letters and digits joined
+/// by `-`, starting with a letter.
+pub fn parse_language(value: &str) -> Result<String, String> {
+    let valid = value.len() <= 35
+        && value.starts_with(|c: char| c.is_ascii_alphabetic())
+        && value
+            .split('-')
+            .all(|subtag| !subtag.is_empty() && subtag.chars().all(|c| c.is_ascii_alphanumeric()));
+    if valid {
+        Ok(value.to_string())
+    } else {
+        Err(format!(
+            "invalid language tag \"{value}\" (expected e.g. \"en\" or \"pt-BR\")"
+        ))
+    }
+}
+
+/// Parses `DOMAIN=COOKIES`, where COOKIES is a `Cookie` header value.
+pub fn parse_cookie(value: &str) -> Result<(String, String), String> {
+    let (domain, cookies) = value
+        .split_once('=')
+        .ok_or_else(|| "expected DOMAIN=NAME=VALUE".to_string())?;
+    if domain.is_empty() || !cookies.contains('=') {
+        return Err("expected DOMAIN=NAME=VALUE".to_string());
+    }
+    Ok((domain.to_lowercase(), cookies.to_string()))
+}
+
+/// Parses `URL=SECONDS`, splitting at the last `=` so query strings survive.
+pub fn parse_pin(value: &str) -> Result<(String, u64), String> {
+    let (url, interval) = value
+        .rsplit_once('=')
+        .ok_or_else(|| "expected URL=SECONDS".to_string())?;
+    let interval = interval
+        .parse()
+        .map_err(|_| format!("invalid refresh interval \"{interval}\""))?;
+    pins::validate(url, interval)?;
+    Ok((url.to_string(), interval))
+}
+
+/// Accepts valid HTTP header names, lowercased for case-insensitive matching.
+pub fn parse_header_name(value: &str) -> Result<String, String> {
+    reqwest::header::HeaderName::from_bytes(value.trim().as_bytes())
+        .map(|name| name.as_str().to_string())
+        .map_err(|_| format!("invalid header name \"{value}\""))
+}
+
+/// Parses a `--toc-separator`, turning `\t` into a tab.
+pub fn parse_separator(value: &str) -> Result<String, String> {
+    let separator = value.replace("\\t", "\t");
+    if separator.is_empty() || separator.contains(['\n', '\r']) {
+        return Err(format!(
+            "invalid separator \"{value}\" (expected text without line breaks)"
+        ));
+    }
+    Ok(separator)
+}
+
+/// Parses a domain for `--allow-site`, lowercased.
+pub fn parse_site(value: &str) -> Result<String, String> {
+    let domain = value.trim().trim_end_matches('.').to_lowercase();
+    let valid = !domain.is_empty()
+        && domain.split('.').all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+        });
+    if valid {
+        Ok(domain)
+    } else {
+        Err(format!(
+            "invalid domain \"{value}\" (expected e.g. \"example.com\")"
+        ))
+    }
+}
+
+/// Parses curl-style `host:port:address`; IPv6 addresses may be bracketed.
+pub fn parse_resolve(value: &str) -> Result<(String, SocketAddr), String> {
+    let mut parts = value.splitn(3, ':');
+    let (Some(host), Some(port), Some(address)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err("expected HOST:PORT:ADDRESS".to_string());
+    };
+    if host.is_empty() {
+        return Err("host must not be empty".to_string());
+    }
+    let port: u16 = port
+        .parse()
+        .map_err(|_| format!("invalid port \"{port}\""))?;
+    let address: IpAddr = address
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .map_err(|_| format!("invalid IP address \"{address}\""))?;
+    Ok((host.to_lowercase(), SocketAddr::new(address, port)))
+}
+
+/// Parses `FROM=TO`, where FROM is an absolute path on the server and TO is
+/// how the client sees it.
+pub fn parse_path_map(value: &str) -> Result<(PathBuf, PathBuf), String> {
+    let (from, to) = value
+        .split_once('=')
+        .ok_or_else(|| "expected FROM=TO".to_string())?;
+    if !Path::new(from).is_absolute() {
+        return Err(format!("\"{from}\" is not an absolute path"));
+    }
+    if to.is_empty() {
+        return Err("TO must not be empty".to_string());
+    }
+    Ok((PathBuf::from(from), PathBuf::from(to)))
+}
+
+pub fn parse_host_header(value: &str) -> Result<(String, String), String> {
+    let (domain, header) = value
+        .split_once('=')
+        .ok_or_else(|| "expected DOMAIN=HEADER".to_string())?;
+    if domain.is_empty() || header.is_empty() {
+        return Err("domain and header must not be empty".to_string());
+    }
+    Ok((domain.to_lowercase(), header.to_string()))
+}
+
+/// Contents of the TOML config file. Keys match the long flag names.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FileConfig {
+    pub cache_dir: Option<PathBuf>,
+    pub namespace: Option<String>,
+    pub toc_budget: Option<usize>,
+    pub toc_threshold: Option<usize>,
+    #[serde(default)]
+    pub toc_offsets: bool,
+    pub toc_style: Option<toc::TocStyle>,
+    pub toc_separator: Option<String>,
+    pub toc_padding: Option<toc::TocPadding>,
+    pub toc_text: Option<toc::TocText>,
+    #[serde(default)]
+    pub bold_headings: bool,
+    pub normalize: Option<Normalize>,
+    pub preview_threshold: Option<usize>,
+    pub preview_lines: Option<usize>,
+    pub github_token: Option<String>,
+    /// `HOST:PORT:ADDRESS` entries
+    #[serde(default)]
+    pub resolve: Vec<String>,
+    /// Domain to `Host` header value
+    #[serde(default)]
+    pub host_header: HashMap<String, String>,
+    pub ip_version: Option<dns::IpVersion>,
+    /// `IP[:PORT]` entries
+    #[serde(default)]
+    pub dns_server: Vec<String>,
+    pub connect_timeout: Option<u64>,
+    pub read_timeout: Option<u64>,
+    pub variation_timeout: Option<u64>,
+    pub fetch_timeout: Option<u64>,
+    pub max_redirects: Option<usize>,
+    #[serde(default)]
+    pub confirm_cross_origin_redirects: bool,
+    #[serde(default)]
+    pub same_site: bool,
+    #[serde(default)]
+    pub allow_site: Vec<String>,
+    pub max_age: Option<u64>,
+    pub stale_while_revalidate: Option<u64>,
+    /// URL to refresh interval in seconds
+    #[serde(default)]
+    pub pin: BTreeMap<String, u64>,
+    pub negative_cache_ttl: Option<u64>,
+    pub dedup_scope: Option<DedupScope>,
+    pub max_requests: Option<u64>,
+    pub max_bytes: Option<u64>,
+    #[serde(default)]
+    pub no_audit: bool,
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default)]
+    pub ephemeral: bool,
+    #[serde(default)]
+    pub relative_paths: bool,
+    /// `FROM=TO` entries
+    #[serde(default)]
+    pub path_map: Vec<String>,
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    #[serde(default)]
+    pub update_check: bool,
+    pub lockfile: Option<PathBuf>,
+    pub lockfile_drift: Option<Drift>,
+    /// Directories `file://` URLs may read from
+    #[serde(default)]
+    pub allow_path: Vec<PathBuf>,
+    pub language: Option<String>,
+    /// Header names `fetch` callers may set
+    #[serde(default)]
+    pub allow_header: Vec<String>,
+    /// Domain to `Cookie` header value
+    #[serde(default)]
+    pub cookie: HashMap<String, String>,
+    pub cookie_key: Option<String>,
+    /// Per-domain policies (config file only)
+    #[serde(default)]
+    pub domains: BTreeMap<String, DomainPolicy>,
+}
+
+impl FileConfig {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file {}: {e}", path.display()))?;
+        toml::from_str(&text)
+            .map_err(|e| format!("Invalid config file {}: {e}", path.display()).into())
+    }
+}
+
+/// Network deadlines, in increasing scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Establishing a connection, TLS included
+    pub connect: Duration,
+    /// Between chunks of a response
+    pub read: Duration,
+    /// One request from start to complete response
+    pub variation: Duration,
+    /// All variations of a fetch; those still running are abandoned
+    pub fetch: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_secs(10),
+            read: Duration::from_secs(30),
+            variation: Duration::from_secs(30),
+            fetch: Duration::from_mins(1),
+        }
+    }
+}
+
+impl Timeouts {
+    fn merge(cli: &Cli, file: &FileConfig) -> Result<Self, String> {
+        let defaults = Self::default();
+        let seconds = |name: &str, cli: Option<u64>, file: Option<u64>, default| match cli.or(file)
+        {
+            Some(0) => Err(format!("{name}: must be at least 1 second")),
+            Some(secs) => Ok(Duration::from_secs(secs)),
+            None => Ok(default),
+        };
+        Ok(Self {
+            connect: seconds(
+                "connect-timeout",
+                cli.connect_timeout,
+                file.connect_timeout,
+                defaults.connect,
+            )?,
+            read: seconds(
+                "read-timeout",
+                cli.read_timeout,
+                file.read_timeout,
+                defaults.read,
+            )?,
+            variation: seconds(
+                "variation-timeout",
+                cli.variation_timeout,
+                file.variation_timeout,
+                defaults.variation,
+            )?,
+            fetch: seconds(
+                "fetch-timeout",
+                cli.fetch_timeout,
+                file.fetch_timeout,
+                defaults.fetch,
+            )?,
+        })
+    }
+}
+
+/// How redirects are followed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirects {
+    /// Redirects followed per request; the next one is reported instead
+    pub max: usize,
+    /// Stop at redirects to another origin instead of following them
+    pub confirm_cross_origin: bool,
+    /// Stop at redirects off the requested site, and don't swap in alternate
+    /// versions from other sites
+    pub same_site: Option<SameSite>,
+}
+
+/// `ToC` settings from the command line, or else the config file.
+fn toc_config(cli: &Cli, file: &FileConfig) -> Result<toc::TocConfig, String> {
+    Ok(toc::TocConfig {
+        toc_budget: cli
+            .toc_budget
+            .or(file.toc_budget)
+            .unwrap_or(toc::DEFAULT_TOC_BUDGET),
+        full_content_threshold: cli
+            .toc_threshold
+            .or(file.toc_threshold)
+            .unwrap_or(toc::DEFAULT_TOC_THRESHOLD),
+        offsets: cli.toc_offsets || file.toc_offsets,
+        style: cli.toc_style.or(file.toc_style).unwrap_or_default(),
+        separator: match cli.toc_separator.clone() {
+            Some(separator) => separator,
+            None => file
+                .toc_separator
+                .as_deref()
+                .map_or_else(
+                    || Ok(toc::DEFAULT_TOC_SEPARATOR.to_string()),
+                    parse_separator,
+                )
+                .map_err(|e| format!("toc-separator: {e}"))?,
+        },
+        padding: cli.toc_padding.or(file.toc_padding).unwrap_or_default(),
+        text: cli.toc_text.or(file.toc_text).unwrap_or_default(),
+    })
+}
+
+impl Redirects {
+    fn merge(cli: &Cli, file: &FileConfig) -> Result<Self, String> {
+        let allowed = repeatable(&cli.allow_site, &file.allow_site, "allow-site", parse_site)?;
+        let same_site = cli.same_site || file.same_site;
+        if !same_site && !allowed.is_empty() {
+            return Err("allow-site only applies with same-site".to_string());
+        }
+        Ok(Self {
+            max: cli
+                .max_redirects
+                .or(file.max_redirects)
+                .unwrap_or(Self::default().max),
+            confirm_cross_origin: cli.confirm_cross_origin_redirects
+                || file.confirm_cross_origin_redirects,
+            same_site: same_site.then_some(SameSite { allowed }),
+        })
+    }
+}
+
+impl Default for Redirects {
+    fn default() -> Self {
+        Self {
+            max: 10,
+            confirm_cross_origin: false,
+            same_site: None,
+        }
+    }
+}
+
+/// Fully resolved settings.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub cache_dir: PathBuf,
+    pub namespace: Option<String>,
+    pub toc: toc::TocConfig,
+    /// Promote bold pseudo-headings, unless a domain's policy says otherwise
+    pub bold_headings: bool,
+    /// Normalization of converted pages, unless a domain's policy says otherwise
+    pub normalize: Normalize,
+    pub preview_threshold: usize,
+    pub preview_lines: usize,
+    pub github_token: Option<String>,
+    pub resolve: Vec<(String, SocketAddr)>,
+    pub host_headers: HashMap<String, String>,
+    pub ip_version: dns::IpVersion,
+    pub dns_servers: Vec<SocketAddr>,
+    pub timeouts: Timeouts,
+    pub redirects: Redirects,
+    pub max_age: Option<u64>,
+    pub stale_while_revalidate: Option<u64>,
+    /// URL → refresh interval in seconds
+    pub pins: BTreeMap<String, u64>,
+    pub negative_cache_ttl: Option<u64>,
+    pub dedup_scope: DedupScope,
+    pub quota: quota::Limits,
+    pub audit: bool,
+    pub read_only: bool,
+    /// Cached content is kept in memory, under [`memory::ROOT`]
+    pub ephemeral: bool,
+    pub relative_paths: bool,
+    /// Server path prefix → the prefix shown in tool results
+    pub path_maps: Vec<(PathBuf, PathBuf)>,
+    /// HTTP traffic is recorded to or replayed from this cassette
+    pub cassette: Option<Cassette>,
+    /// Look for a newer release at startup
+    pub update_check: bool,
+    pub lockfile: Option<PathBuf>,
+    pub lockfile_drift: Drift,
+    pub allow_paths: Vec<PathBuf>,
+    pub language: Option<String>,
+    pub allow_headers: Vec<String>,
+    /// Lowercase domain → cookies to send, as a `Cookie` header value
+    pub cookies: HashMap<String, String>,
+    pub cookie_key: Option<String>,
+    pub policies: Policies,
+}
+
+impl Settings {
+    /// Loads the config file named by `cli` (if any) and layers `cli` over it.
+    pub fn load(cli: &Cli) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = match &cli.config {
+            Some(path) => FileConfig::load(path)?,
+            None => FileConfig::default(),
+        };
+        Self::merge(cli, file)
+    }
+
+    /// Layers `cli` (which already includes environment variables) over `file`.
+    ///
+    /// Repeatable options replace the file's list rather than extending it, so
+    /// `--resolve` on the command line fully overrides the configured entries.
+    pub fn merge(cli: &Cli, file: FileConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let resolve = repeatable(&cli.resolve, &file.resolve, "resolve", parse_resolve)?;
+        let dns_servers = repeatable(
+            &cli.dns_server,
+            &file.dns_server,
+            "dns-server",
+            dns::parse_dns_server,
+        )?;
+        let allow_headers = repeatable(
+            &cli.allow_header,
+            &file.allow_header,
+            "allow-header",
+            parse_header_name,
+        )?;
+
+        let timeouts = Timeouts::merge(cli, &file)?;
+        let redirects = Redirects::merge(cli, &file)?;
+        let toc = toc_config(cli, &file)?;
+        let (cache_dir, ephemeral, read_only) = cache_mode(cli, &file)?;
+        let cassette = cassette(cli, &file)?;
+
+        Ok(Self {
+            cache_dir,
+            namespace: match cli.namespace.clone() {
+                Some(namespace) => Some(namespace),
+                None => file
+                    .namespace
+                    .as_deref()
+                    .map(parse_namespace)
+                    .transpose()
+                    .map_err(|e| format!("namespace: {e}"))?,
+            },
+            toc,
+            bold_headings: cli.bold_headings || file.bold_headings,
+            normalize: cli.normalize.or(file.normalize).unwrap_or_default(),
+            preview_threshold: cli
+                .preview_threshold
+                .or(file.preview_threshold)
+                .unwrap_or(DEFAULT_PREVIEW_THRESHOLD),
+            preview_lines: cli
+                .preview_lines
+                .or(file.preview_lines)
+                .unwrap_or(DEFAULT_PREVIEW_LINES),
+            github_token: cli.github_token.clone().or(file.github_token),
+            resolve,
+            host_headers: domain_map(&cli.host_header, file.host_header),
+            ip_version: cli.ip_version.or(file.ip_version).unwrap_or_default(),
+            dns_servers,
+            timeouts,
+            redirects,
+            max_age: cli.max_age.or(file.max_age),
+            stale_while_revalidate: cli.stale_while_revalidate.or(file.stale_while_revalidate),
+            pins: pin_map(&cli.pin, file.pin)?,
+            negative_cache_ttl: cli.negative_cache_ttl.or(file.negative_cache_ttl),
+            dedup_scope: cli.dedup_scope.or(file.dedup_scope).unwrap_or_default(),
+            quota: quota::Limits {
+                max_requests: cli.max_requests.or(file.max_requests),
+                max_bytes: cli.max_bytes.or(file.max_bytes),
+            },
+            audit: !(cli.no_audit || file.no_audit),
+            read_only,
+            ephemeral,
+            relative_paths: cli.relative_paths || file.relative_paths,
+            path_maps: repeatable(&cli.path_map, &file.path_map, "path-map", parse_path_map)?,
+            cassette,
+            update_check: cli.update_check || file.update_check,
+            lockfile: cli.lockfile.clone().or(file.lockfile),
+            lockfile_drift: cli
+                .lockfile_drift
+                .or(file.lockfile_drift)
+                .unwrap_or_default(),
+            allow_paths: if cli.allow_path.is_empty() {
+                file.allow_path
+            } else {
+                cli.allow_path.clone()
+            },
+            language: match cli.language.clone() {
+                Some(language) => Some(language),
+                None => file
+                    .language
+                    .as_deref()
+                    .map(parse_language)
+                    .transpose()
+                    .map_err(|e| format!("language: {e}"))?,
+            },
+            allow_headers,
+            cookies: domain_map(&cli.cookie, file.cookie),
+            cookie_key: cli
+                .cookie_key
+                .clone()
+                .or(file.cookie_key)
+                .filter(|key| !key.is_empty()),
+            policies: Policies::new(file.domains)?,
+        })
+    }
+}
+
+/// The cache directory, and whether the cache is kept in memory and whether
+/// it's read-only.
+fn cache_mode(cli: &Cli, file: &FileConfig) -> Result<(PathBuf, bool, bool), String> {
+    let cache_dir = cli
+        .cache_dir
+        .clone()
+        .or_else(|| file.cache_dir.clone())
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_CACHE_DIR));
+    let ephemeral = cli.ephemeral || file.ephemeral || cache_dir == Path::new(memory::ROOT);
+    let read_only = cli.read_only || file.read_only;
+    if ephemeral && read_only {
+        return Err("--read-only can't be used with --ephemeral, whose cache starts empty".into());
+    }
+    if ephemeral {
+        return Ok((PathBuf::from(memory::ROOT), true, false));
+    }
+    Ok((cache_dir, false, read_only))
+}
+
+/// The cassette of `--record` or `--replay`.
+fn cassette(cli: &Cli, file: &FileConfig) -> Result<Option<Cassette>, String> {
+    let record = cli.record.clone().or_else(|| file.record.clone());
+    let replay = cli.replay.clone().or_else(|| file.replay.clone());
+    match (record, replay) {
+        (Some(_), Some(_)) => Err("--record can't be used with --replay".into()),
+        (Some(dir), None) => Ok(Some(Cassette {
+            dir,
+            mode: cassette::Mode::Record,
+        })),
+        (None, Some(dir)) => Ok(Some(Cassette {
+            dir,
+            mode: cassette::Mode::Replay,
+        })),
+        (None, None) => Ok(None),
+    }
+}
+
+/// A repeatable option from the command line, or else parsed from the config
+/// file's list.
+fn repeatable<T: Clone>(
+    cli: &[T],
+    file: &[String],
+    name: &str,
+    parse: fn(&str) -> Result<T, String>,
+) -> Result<Vec<T>, String> {
+    if !cli.is_empty() {
+        return Ok(cli.to_vec());
+    }
+    file.iter()
+        .map(|entry| parse(entry).map_err(|e| format!("{name} \"{entry}\": {e}")))
+        .collect()
+}
+
+/// Pins from the command line, or else the config file's, validated.
+fn pin_map(
+    cli: &[(String, u64)],
+    file: BTreeMap<String, u64>,
+) -> Result<BTreeMap<String, u64>, String> {
+    if !cli.is_empty() {
+        return Ok(cli.iter().cloned().collect());
+    }
+    for (url, &interval) in &file {
+        pins::validate(url, interval).map_err(|e| format!("pin: {e}"))?;
+    }
+    Ok(file)
+}
+
+/// A per-domain table from the command line, or else from the config file
+/// with its domains lowercased.
+fn domain_map(cli: &[(String, String)], file: HashMap<String, String>) -> HashMap<String, String> {
+    if cli.is_empty() {
+        file.into_iter()
+            .map(|(domain, value)| (domain.to_lowercase(), value))
+            .collect()
+    } else {
+        cli.iter().cloned().collect()
+    }
+}
+
+/// How often [`watch`] checks the config file for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Polls the config file and calls `on_change` with freshly merged settings
+/// whenever its modification time changes.
+///
+/// Invalid edits are reported on stderr and otherwise ignored, so a typo
+/// while editing keeps the last good settings in place.
+pub async fn watch(cli: Cli, path: PathBuf, mut on_change: impl FnMut(Settings)) {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut last_modified = modified(&path);
+    loop {
+        tokio::time::sleep(WATCH_INTERVAL).await;
+        let current = modified(&path);
+        if current == last_modified {
+            continue;
+        }
+        last_modified = current;
+        match Settings::load(&cli) {
+            Ok(settings) => on_change(settings),
+            Err(e) => eprintln!("warning: keeping previous settings: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("llms-fetch-mcp").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn test_parse_resolve() {
+        assert_eq!(
+            parse_resolve("Docs.Example.com:443:10.0.0.5").unwrap(),
+            (
+                "docs.example.com".to_string(),
+                "10.0.0.5:443".parse().unwrap()
+            )
+        );
+        assert_eq!(
+            parse_resolve("example.com:80:[::1]").unwrap(),
+            ("example.com".to_string(), "[::1]:80".parse().unwrap())
+        );
+        assert!(parse_resolve("example.com:443").is_err());
+        assert!(parse_resolve("example.com:https:10.0.0.5").is_err());
+        assert!(parse_resolve("example.com:443:not-an-ip").is_err());
+    }
+
+    #[test]
+    fn test_parse_host_header() {
+        assert_eq!(
+            parse_host_header("10.0.0.5=docs.internal").unwrap(),
+            ("10.0.0.5".to_string(), "docs.internal".to_string())
+        );
+        assert!(parse_host_header("docs.internal").is_err());
+        assert!(parse_host_header("=docs.internal").is_err());
+    }
+
+    #[test]
+    fn test_parse_path_map() {
+        assert_eq!(
+            parse_path_map("/cache=/Users/me/project/.llms-fetch-mcp").unwrap(),
+            (
+                PathBuf::from("/cache"),
+                PathBuf::from("/Users/me/project/.llms-fetch-mcp")
+            )
+        );
+        assert!(parse_path_map("/cache").is_err());
+        assert!(parse_path_map("cache=/host/cache").is_err());
+        assert!(parse_path_map("/cache=").is_err());
+    }
+
+    #[test]
+    fn test_parse_cookie() {
+        assert_eq!(
+            parse_cookie("Example.com=session=abc; theme=dark").unwrap(),
+            (
+                "example.com".to_string(),
+                "session=abc; theme=dark".to_string()
+            )
+        );
+        assert!(parse_cookie("example.com=session").is_err());
+        assert!(parse_cookie("=session=abc").is_err());
+    }
+
+    #[test]
+    fn test_parse_pin() {
+        assert_eq!(
+            parse_pin("https://example.com/news?tab=releases=3600").unwrap(),
+            ("https://example.com/news?tab=releases".to_string(), 3600)
+        );
+        assert!(parse_pin("https://example.com/news").is_err());
+        assert!(parse_pin("https://example.com/news=5").is_err());
+    }
+
+    #[test]
+    fn test_parse_namespace() {
+        assert_eq!(parse_namespace("project-a").unwrap(), "project-a");
+        assert_eq!(parse_namespace("agent_2.docs").unwrap(), "agent_2.docs");
+        for invalid in ["", "..", ".hidden", "a/b", "a b", "ünï"] {
+            assert!(parse_namespace(invalid).is_err(), "{invalid:?}");
+        }
+        let file: FileConfig = toml::from_str(r#"namespace = "../escape""#).unwrap();
+        assert!(Settings::merge(&cli(&[]), file).is_err());
+    }
+
+    #[test]
+    fn test_parse_language() {
+        for valid in ["en", "pt-BR", "zh-Hant-TW", "es-419"] {
+            assert_eq!(parse_language(valid).unwrap(), valid);
+        }
+        for invalid in ["", "-en", "en-", "en_US", "de,fr", "1a"] {
+            assert!(parse_language(invalid).is_err(), "{invalid:?}");
+        }
+        let file: FileConfig = toml::from_str(r#"language = "de""#).unwrap();
+        let settings = Settings::merge(&cli(&[]), file).unwrap();
+        assert_eq!(settings.language.as_deref(), Some("de"));
+    }
+
+    #[test]
+    fn test_verify_subcommand() {
+        let verify = cli(&["verify"]);
+        assert_eq!(verify.command, Some(Command::Verify { lockfile: None }));
+        assert!(verify.cache_dir.is_none());
+        let serve = cli(&["/tmp/cache"]);
+        assert_eq!(serve.command, None);
+        assert_eq!(serve.cache_dir, Some(PathBuf::from("/tmp/cache")));
+        let both = cli(&["/tmp/cache", "verify", "--lockfile", "llms-fetch.lock"]);
+        assert_eq!(
+            both.command,
+            Some(Command::Verify {
+                lockfile: Some(PathBuf::from("llms-fetch.lock"))
+            })
+        );
+        assert_eq!(both.cache_dir, Some(PathBuf::from("/tmp/cache")));
+        assert_eq!(
+            cli(&["lock"]).command,
+            Some(Command::Lock {
+                output: PathBuf::from("llms-fetch.lock")
+            })
+        );
+    }
+
+    #[test]
+    fn test_defaults_without_config() {
+        let settings = Settings::merge(&cli(&[]), FileConfig::default()).unwrap();
+        assert_eq!(settings.cache_dir, PathBuf::from(DEFAULT_CACHE_DIR));
+        assert_eq!(settings.toc.toc_budget, toc::DEFAULT_TOC_BUDGET);
+        assert_eq!(
+            settings.toc.full_content_threshold,
+            toc::DEFAULT_TOC_THRESHOLD
+        );
+        assert!(!settings.toc.offsets);
+        assert_eq!(settings.toc.separator, toc::DEFAULT_TOC_SEPARATOR);
+        assert!(!settings.bold_headings);
+        assert_eq!(settings.normalize, Normalize::Text);
+        assert_eq!(settings.preview_threshold, DEFAULT_PREVIEW_THRESHOLD);
+        assert_eq!(settings.preview_lines, DEFAULT_PREVIEW_LINES);
+        assert_eq!(settings.ip_version, dns::IpVersion::Auto);
+        assert!(settings.resolve.is_empty());
+        assert!(settings.negative_cache_ttl.is_none());
+        assert_eq!(settings.dedup_scope, DedupScope::Call);
+        assert!(settings.quota.is_unlimited());
+        assert!(settings.audit);
+        assert!(!settings.read_only);
+        assert!(!settings.relative_paths);
+        assert!(!settings.update_check);
+        assert_eq!(settings.cassette, None);
+        assert_eq!(settings.lockfile, None);
+        assert_eq!(settings.lockfile_drift, Drift::Warn);
+        assert!(settings.allow_paths.is_empty());
+        assert!(settings.allow_headers.is_empty());
+        assert_eq!(settings.timeouts, Timeouts::default());
+        assert_eq!(settings.redirects, Redirects::default());
+    }
+
+    #[test]
+    fn test_cli_overrides_config_file() {
+        let file: FileConfig = toml::from_str(
+            r#"
+            cache-dir = "/var/cache/docs"
+            toc-budget = 2000
+            toc-threshold = 4000
+            bold-headings = true
+            ip-version = "6"
+            resolve = ["docs.example.com:443:10.0.0.5"]
+            dns-server = ["1.1.1.1"]
+            negative-cache-ttl = 600
+            connect-timeout = 5
+            max-age = 3600
+            stale-while-revalidate = 86400
+            fetch-timeout = 20
+            max-redirects = 3
+            confirm-cross-origin-redirects = true
+            same-site = true
+            allow-site = ["CDN.example.net"]
+            max-requests = 100
+            max-bytes = 5000000
+            no-audit = true
+            read-only = true
+            cookie-key = "from file"
+
+            [host-header]
+            "Docs.Example.com" = "staging.example.com"
+
+            [cookie]
+            "Example.com" = "session=abc"
+            "#,
+        )
+        .unwrap();
+
+        let settings = Settings::merge(
+            &cli(&[
+                "--toc-budget",
+                "1000",
+                "--ip-version",
+                "4",
+                "--fetch-timeout",
+                "45",
+                "--max-redirects",
+                "5",
+            ]),
+            file,
+        )
+        .unwrap();
+        assert_eq!(settings.cache_dir, PathBuf::from("/var/cache/docs"));
+        assert_eq!(settings.toc.toc_budget, 1000);
+        assert_eq!(settings.toc.full_content_threshold, 4000);
+        assert!(settings.bold_headings);
+        assert_eq!(settings.ip_version, dns::IpVersion::V4);
+        assert_eq!(
+            settings.resolve,
+            vec![(
+                "docs.example.com".to_string(),
+                "10.0.0.5:443".parse().unwrap()
+            )]
+        );
+        assert_eq!(settings.dns_servers, vec!["1.1.1.1:53".parse().unwrap()]);
+        assert_eq!(
+            settings
+                .host_headers
+                .get("docs.example.com")
+                .map(String::as_str),
+            Some("staging.example.com")
+        );
+        assert_eq!(settings.negative_cache_ttl, Some(600));
+        assert_eq!(settings.max_age, Some(3600));
+        assert_eq!(
+            settings.redirects,
+            Redirects {
+                max: 5,
+                confirm_cross_origin: true,
+                same_site: Some(SameSite {
+                    allowed: vec!["cdn.example.net".to_string()],
+                }),
+            }
+        );
+        assert_eq!(settings.stale_while_revalidate, Some(86400));
+        assert_eq!(
+            settings.timeouts,
+            Timeouts {
+                connect: Duration::from_secs(5),
+                fetch: Duration::from_secs(45),
+                ..Timeouts::default()
+            }
+        );
+        assert_eq!(
+            settings.quota,
+            quota::Limits {
+                max_requests: Some(100),
+                max_bytes: Some(5_000_000),
+            }
+        );
+        assert!(!settings.audit);
+        assert!(settings.read_only);
+        assert_eq!(settings.cookie_key.as_deref(), Some("from file"));
+        assert_eq!(
+            settings.cookies.get("example.com").map(String::as_str),
+            Some("session=abc")
+        );
+    }
+
+    #[test]
+    fn test_toc_format_settings() {
+        let file: FileConfig = toml::from_str(
+            r#"
+            toc-offsets = true
+            toc-style = "annotated"
+            toc-separator = '\t'
+            toc-padding = "none"
+            toc-text = "clean"
+            "#,
+        )
+        .unwrap();
+        let settings = Settings::merge(&cli(&[]), file).unwrap();
+        assert!(settings.toc.offsets);
+        assert_eq!(settings.toc.style, toc::TocStyle::Annotated);
+        assert_eq!(settings.toc.separator, "\t");
+        assert_eq!(settings.toc.padding, toc::TocPadding::None);
+        assert_eq!(settings.toc.text, toc::TocText::Clean);
+        let settings = Settings::merge(
+            &cli(&[
+                "--toc-offsets",
+                "--toc-style",
+                "plain",
+                "--toc-separator",
+                " | ",
+            ]),
+            FileConfig::default(),
+        )
+        .unwrap();
+        assert!(settings.toc.offsets);
+        assert_eq!(settings.toc.style, toc::TocStyle::Plain);
+        assert_eq!(settings.toc.separator, " | ");
+        assert_eq!(settings.toc.padding, toc::TocPadding::Aligned);
+
+        let file: FileConfig = toml::from_str("toc-separator = \"\"").unwrap();
+        assert!(Settings::merge(&cli(&[]), file).is_err());
+        assert!(Cli::try_parse_from(["llms-fetch-mcp", "--toc-separator", "a\nb"]).is_err());
+    }
+
+    #[test]
+    fn test_lockfile_settings() {
+        let file: FileConfig = toml::from_str(
+            r#"
+            lockfile = "docs/llms-fetch.lock"
+            lockfile-drift = "fail"
+            "#,
+        )
+        .unwrap();
+        let settings = Settings::merge(&cli(&["--lockfile", "team.lock"]), file).unwrap();
+        assert_eq!(settings.lockfile, Some(PathBuf::from("team.lock")));
+        assert_eq!(settings.lockfile_drift, Drift::Fail);
+    }
+
+    #[test]
+    fn test_ephemeral_settings() {
+        let merge =
+            |args: &[&str], toml: &str| Settings::merge(&cli(args), toml::from_str(toml).unwrap());
+        let settings = merge(&[":memory:"], "").unwrap();
+        assert!(settings.ephemeral);
+        let settings = merge(&["--ephemeral"], "cache-dir = \"/var/cache/docs\"").unwrap();
+        assert!(settings.ephemeral);
+        assert_eq!(settings.cache_dir, PathBuf::from(memory::ROOT));
+        assert!(merge(&[], "ephemeral = true").unwrap().ephemeral);
+        assert!(!merge(&[], "").unwrap().ephemeral);
+        assert!(merge(&["--ephemeral", "--read-only"], "").is_err());
+    }
+
+    #[test]
+    fn test_repeatable_cli_options_replace_config_lists() {
+        let file: FileConfig = toml::from_str(
+            r#"
+            resolve = ["a.example.com:443:10.0.0.1"]
+            allow-path = ["/srv/docs", "/opt/vendor"]
+            allow-header = ["Authorization"]
+            "#,
+        )
+        .unwrap();
+        let settings = Settings::merge(
+            &cli(&[
+                "--resolve",
+                "b.example.com:443:10.0.0.2",
+                "--allow-path",
+                "/home/docs",
+                "--allow-header",
+                "X-API-Version,Accept-Language",
+            ]),
+            file,
+        )
+        .unwrap();
+        assert_eq!(settings.resolve.len(), 1);
+        assert_eq!(settings.resolve[0].0, "b.example.com");
+        assert_eq!(settings.allow_paths, vec![PathBuf::from("/home/docs")]);
+        assert_eq!(settings.allow_headers, ["x-api-version", "accept-language"]);
+    }
+
+    #[test]
+    fn test_config_file_rejects_unknown_and_invalid_entries() {
+        assert!(toml::from_str::<FileConfig>("toc_budget = 1").is_err());
+        let file: FileConfig = toml::from_str(r#"resolve = ["example.com:443"]"#).unwrap();
+        assert!(Settings::merge(&cli(&[]), file).is_err());
+        let file: FileConfig = toml::from_str(r#"allow-header = ["Bad Header"]"#).unwrap();
+        assert!(Settings::merge(&cli(&[]), file).is_err());
+        let file: FileConfig = toml::from_str("read-timeout = 0").unwrap();
+        assert!(Settings::merge(&cli(&[]), file).is_err());
+        let file: FileConfig = toml::from_str("[pin]\n\"ftp://example.com/\" = 3600").unwrap();
+        assert!(Settings::merge(&cli(&[]), file).is_err());
+        let file: FileConfig = toml::from_str(r#"allow-site = ["example.com"]"#).unwrap();
+        assert!(Settings::merge(&cli(&[]), file).is_err());
+        let file: FileConfig = toml::from_str("same-site = true\nallow-site = [\"a/b\"]").unwrap();
+        assert!(Settings::merge(&cli(&[]), file).is_err());
+    }
+}
@@ -0,0 +1,338 @@
+//! Cookie jar shared by all page requests.
+//!
+//! Some sites only serve content once a cookie is set: a consent banner's
+//! choice, a load balancer's affinity cookie set on a redirect, or a session
+//! copied from a browser into the config. The jar keeps cookies across
+//! variations and calls and, given a key, across restarts in an encrypted file
+//! in the cache directory.
+//!
+//! Cookies are kept in a [`CookieStore`], the store behind reqwest's own
+//! cookie jar, which follows RFC 6265 and checks `Domain` attributes against
+//! the public suffix list: a page on `evil.co.uk` can't set a cookie for every
+//! `*.co.uk` host. Only the serialized store is encrypted.
+
+use cookie_store::CookieStore;
+use reqwest::header::{HeaderMap, SET_COOKIE};
+use ring::aead::{Aad, CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// File name of the encrypted jar inside the cache directory.
+pub const COOKIE_FILE: &str = ".cookies.enc";
+
+const SALT_LEN: usize = 16;
+
+/// PBKDF2-HMAC-SHA256 rounds turning the key into an encryption key.
+const PBKDF2_ROUNDS: u32 = 100_000;
+
+/// Where and how the jar is saved; absent when no key is configured.
+struct Persist {
+    path: PathBuf,
+    salt: [u8; SALT_LEN],
+    key: LessSafeKey,
+}
+
+pub struct CookieJar {
+    store: Mutex<CookieStore>,
+    /// Cookies changed since the jar was last saved
+    dirty: AtomicBool,
+    persist: Option<Persist>,
+}
+
+impl CookieJar {
+    /// Opens the jar saved in `cache_dir` with `key`, or an in-memory jar if
+    /// `key` is `None`. Fails if a saved jar can't be decrypted with `key`.
+    pub fn load(cache_dir: &Path, key: Option<&str>) -> Result<Self, String> {
+        let Some(key) = key else {
+            return Ok(Self::with_store(CookieStore::default(), None));
+        };
+        let path = cache_dir.join(COOKIE_FILE);
+        match std::fs::read(&path) {
+            Ok(data) => {
+                let (persist, store) = open(path, key, &data).map_err(|e| {
+                    format!(
+                        "Failed to read {COOKIE_FILE} in the cache directory: {e}; \
+                         check --cookie-key or delete the file"
+                    )
+                })?;
+                Ok(Self::with_store(store, Some(persist)))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let mut salt = [0; SALT_LEN];
+                SystemRandom::new()
+                    .fill(&mut salt)
+                    .map_err(|_| "No secure random source for the cookie jar")?;
+                let persist = Persist {
+                    key: derive_key(key, &salt),
+                    path,
+                    salt,
+                };
+                Ok(Self::with_store(CookieStore::default(), Some(persist)))
+            }
+            Err(e) => Err(format!("Failed to read {}: {e}", path.display())),
+        }
+    }
+
+    fn with_store(store: CookieStore, persist: Option<Persist>) -> Self {
+        let list: publicsuffix::List = crate::site::PUBLIC_SUFFIXES.clone();
+        Self {
+            store: Mutex::new(store.with_suffix_list(list)),
+            dirty: AtomicBool::new(false),
+            persist,
+        }
+    }
+
+    /// Adds cookies pasted into the config: domain → `name=value; name=value`,
+    /// sent to the domain and its subdomains.
+    pub fn seed(&self, configured: &HashMap<String, String>) {
+        let mut store = self.store.lock().unwrap();
+        for (domain, header) in configured {
+            let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+            let Ok(url) = url::Url::parse(&format!("https://{domain}/")) else {
+                continue;
+            };
+            for pair in header.split(';') {
+                let Some((name, value)) = pair.split_once('=') else {
+                    continue;
+                };
+                let (name, value) = (name.trim(), value.trim());
+                if !name.is_empty() {
+                    // A domain that's a public suffix keeps the cookie to itself
+                    let _ = store.parse(&format!("{name}={value}; Domain={domain}; Path=/"), &url);
+                }
+            }
+        }
+    }
+
+    /// Records the `Set-Cookie` headers of a response from `url`. Cookies the
+    /// store refuses, such as those for another domain or a public suffix,
+    /// are dropped.
+    pub fn store(&self, url: &url::Url, headers: &HeaderMap) {
+        let values: Vec<&str> = headers
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect();
+        if values.is_empty() {
+            return;
+        }
+        let mut store = self.store.lock().unwrap();
+        for value in values {
+            let _ = store.parse(value, url);
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// The `Cookie` header for a request to `url`, if any cookie applies.
+    /// Longer paths come first, as RFC 6265 recommends, then names in order.
+    pub fn header(&self, url: &url::Url) -> Option<String> {
+        let store = self.store.lock().unwrap();
+        let mut matching = store.matches(url);
+        matching.sort_by_cached_key(|c| {
+            (
+                std::cmp::Reverse(String::from(&c.path).len()),
+                c.name().to_string(),
+            )
+        });
+        let pairs: Vec<String> = matching
+            .iter()
+            .map(|c| format!("{}={}", c.name(), c.value()))
+            .collect();
+        (!pairs.is_empty()).then(|| pairs.join("; "))
+    }
+
+    /// Saves the jar if it is persistent and changed since the last save.
+    pub async fn save(&self) -> std::io::Result<()> {
+        let Some(persist) = &self.persist else {
+            return Ok(());
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let mut json = Vec::new();
+        // Expired cookies are skipped when the jar is loaded
+        cookie_store::serde::json::save_incl_expired_and_nonpersistent(
+            &self.store.lock().unwrap(),
+            &mut json,
+        )
+        .map_err(std::io::Error::other)?;
+        let data = seal(persist, json).map_err(std::io::Error::other)?;
+        if let Some(parent) = persist.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let temp_path = crate::sidecar::temp_path(&persist.path);
+        tokio::fs::write(&temp_path, data).await?;
+        tokio::fs::rename(&temp_path, &persist.path).await
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> LessSafeKey {
+    let mut key = [0; 32];
+    ring::pbkdf2::derive(
+        ring::pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(PBKDF2_ROUNDS).expect("rounds are non-zero"),
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, &key).expect("key has the right length"))
+}
+
+/// Encrypts `plaintext` as `salt ‖ nonce ‖ ciphertext ‖ tag`.
+fn seal(persist: &Persist, mut plaintext: Vec<u8>) -> Result<Vec<u8>, String> {
+    let mut nonce = [0; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| "No secure random source for the cookie jar")?;
+    persist
+        .key
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut plaintext,
+        )
+        .map_err(|_| "Failed to encrypt the cookie jar")?;
+    let mut data = Vec::with_capacity(SALT_LEN + NONCE_LEN + plaintext.len());
+    data.extend_from_slice(&persist.salt);
+    data.extend_from_slice(&nonce);
+    data.extend_from_slice(&plaintext);
+    Ok(data)
+}
+
+/// Decrypts a file written by [`seal`], keeping its salt for later saves.
+fn open(path: PathBuf, passphrase: &str, data: &[u8]) -> Result<(Persist, CookieStore), String> {
+    if data.len() < SALT_LEN + NONCE_LEN {
+        return Err("file is truncated".to_string());
+    }
+    let (salt, rest) = data.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let persist = Persist {
+        path,
+        salt: salt.try_into().expect("split at SALT_LEN"),
+        key: derive_key(passphrase, salt),
+    };
+    let mut buffer = ciphertext.to_vec();
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| "invalid nonce")?;
+    let plaintext = persist
+        .key
+        .open_in_place(nonce, Aad::empty(), &mut buffer)
+        .map_err(|_| "wrong key or corrupted file")?;
+    let store = cookie_store::serde::json::load(&*plaintext).map_err(|e| e.to_string())?;
+    Ok((persist, store))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> url::Url {
+        url::Url::parse(s).unwrap()
+    }
+
+    fn set_cookies(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(SET_COOKIE, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_store_and_send() {
+        let jar = CookieJar::load(Path::new("/nonexistent"), None).unwrap();
+        jar.store(
+            &url("https://docs.example.com/guide/intro"),
+            &set_cookies(&[
+                "consent=yes; Domain=.example.com; Path=/",
+                "lb=node3",
+                "admin=1; Path=/admin",
+                "tracker=x; Domain=other.com",
+                "tld=x; Domain=com",
+                "token=t; Secure",
+            ]),
+        );
+
+        let header = |u| jar.header(&url(u));
+        assert_eq!(
+            header("https://docs.example.com/guide/setup").as_deref(),
+            Some("lb=node3; token=t; consent=yes")
+        );
+        // Host-only and secure cookies stay where they were set
+        assert_eq!(
+            header("http://api.example.com/guide/x").as_deref(),
+            Some("consent=yes")
+        );
+        assert_eq!(header("https://other.com/").as_deref(), None);
+        assert_eq!(
+            header("https://docs.example.com/admin/users").as_deref(),
+            Some("admin=1; consent=yes")
+        );
+        assert_eq!(
+            header("https://docs.example.com/administrator").as_deref(),
+            Some("consent=yes")
+        );
+
+        // Expiring a cookie deletes it
+        jar.store(
+            &url("https://docs.example.com/"),
+            &set_cookies(&["consent=; Domain=example.com; Path=/; Max-Age=0"]),
+        );
+        assert_eq!(header("http://api.example.com/"), None);
+    }
+
+    #[test]
+    fn test_public_suffix_domains_rejected() {
+        let jar = CookieJar::load(Path::new("/nonexistent"), None).unwrap();
+        jar.store(
+            &url("https://evil.co.uk/"),
+            &set_cookies(&["wide=1; Domain=co.uk", "pages=1; Domain=github.io"]),
+        );
+        jar.store(
+            &url("https://evil.github.io/"),
+            &set_cookies(&["pages=1; Domain=github.io", "own=1; Domain=evil.github.io"]),
+        );
+        assert_eq!(jar.header(&url("https://bank.co.uk/")), None);
+        assert_eq!(jar.header(&url("https://other.github.io/")), None);
+        assert_eq!(
+            jar.header(&url("https://evil.github.io/")).as_deref(),
+            Some("own=1")
+        );
+    }
+
+    #[test]
+    fn test_seed_from_config() {
+        let jar = CookieJar::load(Path::new("/nonexistent"), None).unwrap();
+        jar.seed(&HashMap::from([(
+            "example.com".to_string(),
+            "session=abc; theme=dark".to_string(),
+        )]));
+        assert_eq!(
+            jar.header(&url("https://docs.example.com/a")).as_deref(),
+            Some("session=abc; theme=dark")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_persistence() {
+        let dir = tempfile::tempdir().unwrap();
+        let jar = CookieJar::load(dir.path(), Some("correct horse")).unwrap();
+        jar.store(
+            &url("https://example.com/"),
+            &set_cookies(&["session=abc; Max-Age=3600", "tab=1"]),
+        );
+        jar.save().await.unwrap();
+
+        let saved = std::fs::read(dir.path().join(COOKIE_FILE)).unwrap();
+        assert!(!saved.windows(7).any(|w| w == b"session"));
+        let reopened = CookieJar::load(dir.path(), Some("correct horse")).unwrap();
+        assert_eq!(
+            reopened.header(&url("https://example.com/")).as_deref(),
+            Some("session=abc; tab=1")
+        );
+        assert!(CookieJar::load(dir.path(), Some("wrong")).is_err());
+    }
+}
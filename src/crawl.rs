@@ -0,0 +1,356 @@
+//! Bounded crawls of documentation sites.
+//!
+//! A crawl starts at a page and follows links breadth first, staying on the
+//! same host and under the start page's directory, until it runs out of links
+//! or reaches its page or depth limit. The pages found can be summarized as an
+//! `llms.txt` index for sites that don't publish one.
+
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use url::Url;
+
+/// Pages fetched when the caller doesn't give a limit.
+pub const DEFAULT_MAX_PAGES: usize = 50;
+
+/// Upper bound on `max_pages`, so a single call can't crawl a whole site.
+pub const MAX_PAGES: usize = 500;
+
+/// Link hops from the start page followed when the caller doesn't give a limit.
+pub const DEFAULT_MAX_DEPTH: usize = 3;
+
+/// Upper bound on `max_depth` for tools.
+pub const MAX_DEPTH: usize = 10;
+
+/// Descriptions longer than this are cut at a word boundary.
+const MAX_DESCRIPTION_CHARS: usize = 200;
+
+/// How far a crawl may go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_pages: usize,
+    pub max_depth: usize,
+}
+
+/// The part of a site a crawl stays in: the start page's host and directory.
+#[derive(Debug, Clone)]
+pub struct Scope {
+    origin: url::Origin,
+    prefix: String,
+}
+
+impl Scope {
+    /// The scope of a crawl from `start`; `https://example.com/docs/intro`
+    /// covers everything under `https://example.com/docs/`.
+    pub fn new(start: &Url) -> Self {
+        let path = start.path();
+        let prefix = &path[..=path.rfind('/').unwrap_or(0)];
+        Self {
+            origin: start.origin(),
+            prefix: prefix.to_string(),
+        }
+    }
+
+    /// The in-scope page `href` points to from `base`, without its fragment.
+    pub fn resolve(&self, base: &Url, href: &str) -> Option<Url> {
+        let mut url = base.join(href.trim()).ok()?;
+        url.set_fragment(None);
+        (url.origin() == self.origin && url.path().starts_with(&self.prefix)).then_some(url)
+    }
+
+    /// Path of `url` below the scope's directory.
+    pub fn relative<'a>(&self, url: &'a Url) -> &'a str {
+        url.path().strip_prefix(&self.prefix).unwrap_or("")
+    }
+}
+
+/// What a crawled page says about itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Page {
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// In-scope links in document order, without duplicates
+    pub links: Vec<String>,
+}
+
+impl Page {
+    /// Reads an HTML page: the title from its first `<h1>` or `<title>`, the
+    /// description from its meta description or first paragraph.
+    pub fn from_html(html: &str, url: &Url, scope: &Scope) -> Self {
+        let document = dom_query::Document::from(html);
+        let text = |selector: &str| {
+            document
+                .select(selector)
+                .iter()
+                .map(|node| collapse_whitespace(&node.text()))
+                .find(|text| !text.is_empty())
+        };
+        let title = text("h1").or_else(|| text("title"));
+        let description = document
+            .select("meta[name='description' i], meta[property='og:description']")
+            .iter()
+            .filter_map(|meta| meta.attr("content"))
+            .map(|content| collapse_whitespace(&content))
+            .find(|content| !content.is_empty())
+            .or_else(|| text("main p, article p"))
+            .or_else(|| text("p"));
+        let hrefs = document
+            .select("a[href]")
+            .iter()
+            .filter_map(|link| link.attr("href").map(|href| href.to_string()))
+            .collect::<Vec<_>>();
+        Self {
+            url: url.to_string(),
+            title,
+            description: description.map(|d| truncate(&d)),
+            links: in_scope_links(hrefs.iter().map(String::as_str), url, scope),
+        }
+    }
+
+    /// Reads a Markdown (or plain text) page: the title from its first
+    /// heading, the description from its first paragraph.
+    pub fn from_markdown(markdown: &str, url: &Url, scope: &Scope) -> Self {
+        let mut title = None;
+        let mut description = None;
+        let mut hrefs = Vec::new();
+        let mut current: Option<String> = None;
+        for event in Parser::new(markdown) {
+            match event {
+                Event::Start(Tag::Heading { .. } | Tag::Paragraph) => {
+                    current = Some(String::new());
+                }
+                Event::Text(text) | Event::Code(text) => {
+                    if let Some(current) = &mut current {
+                        current.push_str(&text);
+                    }
+                }
+                Event::SoftBreak | Event::HardBreak => {
+                    if let Some(current) = &mut current {
+                        current.push(' ');
+                    }
+                }
+                Event::End(TagEnd::Heading(_)) if title.is_none() => {
+                    title = current.take().map(|t| collapse_whitespace(&t));
+                }
+                Event::End(TagEnd::Paragraph) if description.is_none() => {
+                    description = current
+                        .take()
+                        .map(|d| collapse_whitespace(&d))
+                        .filter(|d| !d.is_empty());
+                }
+                Event::End(TagEnd::Heading(_) | TagEnd::Paragraph) => current = None,
+                Event::Start(Tag::Link { dest_url, .. }) => hrefs.push(dest_url.to_string()),
+                _ => {}
+            }
+        }
+        Self {
+            url: url.to_string(),
+            title: title.filter(|t| !t.is_empty()),
+            description: description.map(|d| truncate(&d)),
+            links: in_scope_links(hrefs.iter().map(String::as_str), url, scope),
+        }
+    }
+}
+
+fn in_scope_links<'a>(
+    hrefs: impl Iterator<Item = &'a str>,
+    base: &Url,
+    scope: &Scope,
+) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    for href in hrefs {
+        if let Some(url) = scope.resolve(base, href)
+            && url != *base
+            && !links.contains(&url.to_string())
+        {
+            links.push(url.to_string());
+        }
+    }
+    links
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn truncate(text: &str) -> String {
+    if text.chars().count() <= MAX_DESCRIPTION_CHARS {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(MAX_DESCRIPTION_CHARS).collect();
+    let cut = cut.rfind(' ').map_or(cut.as_str(), |space| &cut[..space]);
+    format!("{}…", cut.trim_end_matches([',', '.', ';', ':']))
+}
+
+/// Section heading for a page: its first directory below the scope,
+/// `getting-started/install` → "Getting started". Pages directly in the
+/// scope's directory go under "Docs".
+fn section(relative_path: &str) -> String {
+    let Some((directory, _)) = relative_path.split_once('/') else {
+        return "Docs".to_string();
+    };
+    let words = directory.replace(['-', '_'], " ");
+    let mut chars = words.chars();
+    chars.next().map_or_else(
+        || "Docs".to_string(),
+        |first| first.to_uppercase().chain(chars).collect(),
+    )
+}
+
+/// Writes an `llms.txt` index of `pages`, a crawl from `pages[0]`: the start
+/// page's title and description, then a list of links per section.
+pub fn llms_txt(pages: &[Page], scope: &Scope) -> String {
+    let Some((start, rest)) = pages.split_first() else {
+        return String::new();
+    };
+    let mut sections: BTreeMap<String, Vec<&Page>> = BTreeMap::new();
+    for page in rest {
+        let Ok(url) = Url::parse(&page.url) else {
+            continue;
+        };
+        sections
+            .entry(section(scope.relative(&url)))
+            .or_default()
+            .push(page);
+    }
+
+    let mut output = String::new();
+    writeln!(output, "# {}", start.title.as_deref().unwrap_or(&start.url)).unwrap();
+    if let Some(description) = &start.description {
+        writeln!(output, "\n> {description}").unwrap();
+    }
+    writeln!(
+        output,
+        "\nGenerated by crawling {} pages from {}; not published by the site.",
+        pages.len(),
+        start.url
+    )
+    .unwrap();
+    // "Docs" first, the rest alphabetically
+    let docs = sections.remove_entry("Docs");
+    for (name, pages) in docs.into_iter().chain(sections) {
+        writeln!(output, "\n## {name}\n").unwrap();
+        for page in pages {
+            let title = page.title.as_deref().unwrap_or(&page.url);
+            match &page.description {
+                Some(description) => {
+                    writeln!(output, "- [{title}]({}): {description}", page.url).unwrap();
+                }
+                None => writeln!(output, "- [{title}]({})", page.url).unwrap(),
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start() -> (Url, Scope) {
+        let url = Url::parse("https://example.com/docs/intro").unwrap();
+        let scope = Scope::new(&url);
+        (url, scope)
+    }
+
+    #[test]
+    fn test_scope() {
+        let (url, scope) = start();
+        let resolve = |href| scope.resolve(&url, href).map(|u| u.to_string());
+        assert_eq!(
+            resolve("guide/setup#install").as_deref(),
+            Some("https://example.com/docs/guide/setup")
+        );
+        assert_eq!(
+            resolve("/docs/api?v=2").as_deref(),
+            Some("https://example.com/docs/api?v=2")
+        );
+        assert_eq!(resolve("/blog/"), None);
+        assert_eq!(resolve("https://other.example.com/docs/x"), None);
+        assert_eq!(resolve("mailto:docs@example.com"), None);
+    }
+
+    #[test]
+    fn test_page_from_html() {
+        let (url, scope) = start();
+        let html = r##"<html><head><title>Intro | Example</title>
+            <meta name="description" content="  Getting   started with Example. ">
+            </head><body><h1>Introduction</h1>
+            <a href="guide/setup">Setup</a> <a href="guide/setup#more">Again</a>
+            <a href="#top">Top</a> <a href="/pricing">Pricing</a></body></html>"##;
+        assert_eq!(
+            Page::from_html(html, &url, &scope),
+            Page {
+                url: url.to_string(),
+                title: Some("Introduction".to_string()),
+                description: Some("Getting started with Example.".to_string()),
+                links: vec!["https://example.com/docs/guide/setup".to_string()],
+            }
+        );
+
+        let bare = "<title>Bare</title><p></p><p>First  paragraph.</p>";
+        let page = Page::from_html(bare, &url, &scope);
+        assert_eq!(page.title.as_deref(), Some("Bare"));
+        assert_eq!(page.description.as_deref(), Some("First paragraph."));
+    }
+
+    #[test]
+    fn test_page_from_markdown() {
+        let (url, scope) = start();
+        let markdown =
+            "# Setup `cli`\n\nInstall the\ncli first. See [API](api.md).\n\n## Next\n\nMore.";
+        let page = Page::from_markdown(markdown, &url, &scope);
+        assert_eq!(page.title.as_deref(), Some("Setup cli"));
+        assert_eq!(
+            page.description.as_deref(),
+            Some("Install the cli first. See API.")
+        );
+        assert_eq!(page.links, ["https://example.com/docs/api.md"]);
+    }
+
+    #[test]
+    fn test_truncate() {
+        let long = "word ".repeat(100);
+        let cut = truncate(long.trim());
+        assert!(cut.ends_with("word…"));
+        assert!(cut.chars().count() <= MAX_DESCRIPTION_CHARS + 1);
+    }
+
+    #[test]
+    fn test_llms_txt() {
+        let (_, scope) = start();
+        let page = |path: &str, title: Option<&str>, description: Option<&str>| Page {
+            url: format!("https://example.com/docs/{path}"),
+            title: title.map(str::to_string),
+            description: description.map(str::to_string),
+            links: Vec::new(),
+        };
+        let pages = [
+            page("intro", Some("Example"), Some("Docs for Example.")),
+            page("api-reference/client", Some("Client"), None),
+            page("faq", Some("FAQ"), Some("Common questions.")),
+            page("getting-started/install", None, Some("Install it.")),
+        ];
+        insta::assert_snapshot!(llms_txt(&pages, &scope), @r"
+        # Example
+
+        > Docs for Example.
+
+        Generated by crawling 4 pages from https://example.com/docs/intro; not published by the site.
+
+        ## Docs
+
+        - [FAQ](https://example.com/docs/faq): Common questions.
+
+        ## Api reference
+
+        - [Client](https://example.com/docs/api-reference/client)
+
+        ## Getting started
+
+        - [https://example.com/docs/getting-started/install](https://example.com/docs/getting-started/install): Install it.
+        ");
+    }
+}
@@ -0,0 +1,186 @@
+//! `data:` URLs (RFC 2397), for content an agent already has inline.
+//!
+//! The decoded content goes through the same conversion, caching and `ToC`
+//! pipeline as fetched pages. Cached copies are keyed by a hash of the
+//! content, since the URL itself can be megabytes long.
+
+use base64::Engine;
+use std::hash::{DefaultHasher, Hasher};
+
+/// Media types that can be converted to Markdown.
+const SUPPORTED_MEDIA_TYPES: [&str; 4] = [
+    "text/markdown",
+    "text/x-markdown",
+    "text/html",
+    "text/plain",
+];
+
+/// Cache directory for `data:` URL content, alongside the host directories.
+pub const CACHE_DIR: &str = "_data";
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DataUrl {
+    /// Lowercase media type without parameters, e.g. `text/html`
+    pub media_type: String,
+    pub content: String,
+}
+
+impl DataUrl {
+    /// Parses `url` if it is a `data:` URL, returning `None` for any other scheme.
+    pub fn parse(url: &str) -> Option<Result<Self, String>> {
+        let rest = url
+            .get(..5)
+            .filter(|scheme| scheme.eq_ignore_ascii_case("data:"))
+            .map(|_| &url[5..])?;
+        Some(Self::parse_body(rest))
+    }
+
+    fn parse_body(rest: &str) -> Result<Self, String> {
+        let (header, data) = rest
+            .split_once(',')
+            .ok_or("Invalid data URL: missing ',' before the data")?;
+        let mut params = header.split(';').map(str::trim);
+        let media_type = match params.next() {
+            Some("") | None => "text/plain".to_string(),
+            Some(media_type) => media_type.to_ascii_lowercase(),
+        };
+        if !SUPPORTED_MEDIA_TYPES.contains(&media_type.as_str()) {
+            return Err(format!(
+                "Unsupported data URL media type {media_type}; expected one of {}",
+                SUPPORTED_MEDIA_TYPES.join(", ")
+            ));
+        }
+
+        let mut base64 = false;
+        for param in params {
+            if param.eq_ignore_ascii_case("base64") {
+                base64 = true;
+            } else if let Some((name, value)) = param.split_once('=')
+                && name.trim().eq_ignore_ascii_case("charset")
+                && !matches!(
+                    value.trim().to_ascii_lowercase().as_str(),
+                    "utf-8" | "utf8" | "us-ascii"
+                )
+            {
+                return Err(format!(
+                    "Unsupported data URL charset {value}; only UTF-8 is supported"
+                ));
+            }
+        }
+
+        let bytes: Vec<u8> = percent_encoding::percent_decode_str(data).collect();
+        let bytes = if base64 {
+            // Whitespace is common in hand-written or wrapped base64
+            let compact: Vec<u8> = bytes
+                .into_iter()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect();
+            base64::engine::general_purpose::STANDARD
+                .decode(compact)
+                .map_err(|e| format!("Invalid base64 in data URL: {e}"))?
+        } else {
+            bytes
+        };
+        let content =
+            String::from_utf8(bytes).map_err(|_| "Data URL content is not valid UTF-8")?;
+
+        Ok(Self {
+            media_type,
+            content,
+        })
+    }
+
+    pub fn is_html(&self) -> bool {
+        self.media_type == "text/html"
+    }
+
+    pub fn is_markdown(&self) -> bool {
+        matches!(
+            self.media_type.as_str(),
+            "text/markdown" | "text/x-markdown"
+        )
+    }
+
+    /// Short stand-in for the URL in results, the audit log and the cache:
+    /// identical content always gets the same label.
+    pub fn label(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(self.media_type.as_bytes());
+        hasher.write(self.content.as_bytes());
+        format!("data:{};hash={:016x},", self.media_type, hasher.finish())
+    }
+}
+
+/// Name of the cached file for a `data:` URL under [`CACHE_DIR`].
+pub fn cache_file_name(url: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(url.as_bytes());
+    format!("{:016x}.md", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_base64_markdown() {
+        let data = DataUrl::parse("data:text/markdown;base64,IyBUaXRsZQoKQm9keQo=")
+            .unwrap()
+            .unwrap();
+        assert_eq!(data.media_type, "text/markdown");
+        assert_eq!(data.content, "# Title\n\nBody\n");
+        assert!(data.is_markdown());
+    }
+
+    #[test]
+    fn test_parse_percent_encoded_html() {
+        let data = DataUrl::parse("DATA:Text/HTML;charset=UTF-8,%3Ch1%3EHi%3C%2Fh1%3E")
+            .unwrap()
+            .unwrap();
+        assert_eq!(data.media_type, "text/html");
+        assert_eq!(data.content, "<h1>Hi</h1>");
+        assert!(data.is_html());
+    }
+
+    #[test]
+    fn test_default_media_type_is_plain_text() {
+        let data = DataUrl::parse("data:,hello%20world").unwrap().unwrap();
+        assert_eq!(data.media_type, "text/plain");
+        assert_eq!(data.content, "hello world");
+    }
+
+    #[test]
+    fn test_rejects_unsupported_and_malformed() {
+        assert!(DataUrl::parse("https://example.com").is_none());
+        assert!(
+            DataUrl::parse("data:image/png;base64,iVBORw0KGgo=")
+                .unwrap()
+                .is_err()
+        );
+        assert!(
+            DataUrl::parse("data:text/plain;charset=latin1,abc")
+                .unwrap()
+                .is_err()
+        );
+        assert!(
+            DataUrl::parse("data:text/plain;base64,***")
+                .unwrap()
+                .is_err()
+        );
+        assert!(DataUrl::parse("data:text/plain").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_label_depends_only_on_content() {
+        let a = DataUrl::parse("data:text/markdown,%23%20Hi")
+            .unwrap()
+            .unwrap();
+        let b = DataUrl::parse("data:text/markdown;base64,IyBIaQ==")
+            .unwrap()
+            .unwrap();
+        let c = DataUrl::parse("data:text/plain,%23%20Hi").unwrap().unwrap();
+        assert_eq!(a.label(), b.label());
+        assert_ne!(a.label(), c.label());
+        assert!(a.label().starts_with("data:text/markdown;hash="));
+    }
+}
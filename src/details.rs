@@ -0,0 +1,88 @@
+//! Collapsible `<details>` blocks, expanded so their content survives
+//! conversion.
+//!
+//! Docs hide FAQ answers, advanced options and long examples in
+//! `<details>`, with a `<summary>` as the only visible line. Converted as
+//! they are, the summary runs into the first paragraph of the answer, and
+//! readability may leave the block out as hidden. Each block is rewritten
+//! before conversion as its summary, on a bold line of its own, followed by
+//! its content. A summary holding a heading keeps the heading instead.
+//! Collapsible admonitions (`<details class="note">`) are taken by
+//! [`crate::admonitions`] first.
+
+use std::borrow::Cow;
+
+/// `html` with its `<details>` blocks rewritten as their summary and content.
+pub fn prepare(html: &str) -> Cow<'_, str> {
+    let document = dom_query::Document::from(html);
+    let blocks: Vec<_> = document.select("details").iter().collect();
+    if blocks.is_empty() {
+        return Cow::Borrowed(html);
+    }
+    // Innermost first, so an outer block takes in the inner one's expansion
+    for block in blocks.into_iter().rev() {
+        let summary = block.select("summary").first();
+        let mut expanded = String::new();
+        if summary.exists() && !summary.text().trim().is_empty() {
+            let inner = summary.inner_html();
+            if summary.select("h1, h2, h3, h4, h5, h6").exists() {
+                expanded.push_str(&inner);
+            } else {
+                expanded.push_str("<p><strong>");
+                expanded.push_str(inner.trim());
+                expanded.push_str("</strong></p>");
+            }
+        }
+        summary.remove();
+        expanded.push_str("<div>");
+        expanded.push_str(&block.inner_html());
+        expanded.push_str("</div>");
+        block.replace_with_html(expanded);
+    }
+    Cow::Owned(document.html().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(html: &str) -> String {
+        html2md::parse_html(&prepare(html))
+    }
+
+    #[test]
+    fn test_prepare() {
+        let markdown = convert(
+            "<details><summary>How do I reset it?</summary>\
+             <p>Run <code>reset</code>.</p></details>",
+        );
+        assert!(
+            markdown.starts_with("**How do I reset it?**\n\n"),
+            "{markdown:?}"
+        );
+        assert!(markdown.contains("Run `reset`."), "{markdown:?}");
+
+        // Markup in the summary is kept
+        let markdown = convert(
+            "<details open><summary><code>timeout</code> option</summary>\
+             <p>Seconds.</p></details>",
+        );
+        assert!(markdown.contains("**`timeout` option**"), "{markdown:?}");
+
+        // A heading summary stays a heading, and nested blocks expand too
+        let markdown = convert(
+            "<details><summary><h3>Advanced</h3></summary>\
+             <details><summary>Proxy</summary><p>Set it.</p></details></details>",
+        );
+        assert!(markdown.contains("### Advanced"), "{markdown:?}");
+        assert!(markdown.contains("**Proxy**"), "{markdown:?}");
+        assert!(markdown.contains("Set it."), "{markdown:?}");
+
+        // Without a summary, only the content is left
+        let markdown = convert("<details><p>Hidden.</p></details>");
+        assert_eq!(markdown.trim(), "Hidden.");
+
+        let plain = "<p>Nothing collapsed.</p>";
+        assert!(matches!(prepare(plain), Cow::Borrowed(_)));
+    }
+}
@@ -0,0 +1,253 @@
+//! DNS resolution for the shared HTTP client.
+//!
+//! By default reqwest resolves hosts with the system resolver and connects over
+//! whichever address family answers first. This module provides a resolver that
+//! can restrict lookups to IPv4 or IPv6 and query specific DNS servers instead.
+//!
+//! Either way, lookups go through a short-lived cache: a fetch sends its URL
+//! variations to the same host at once, and they should share one lookup.
+
+use hickory_resolver::TokioResolver;
+use hickory_resolver::config::{LookupIpStrategy, NameServerConfig, ResolverConfig};
+use hickory_resolver::net::NetError;
+use hickory_resolver::net::runtime::TokioRuntimeProvider;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+type LookupError = Box<dyn std::error::Error + Send + Sync>;
+
+/// How long resolved addresses are reused. Short, so hosts that move are
+/// picked up quickly by a long-running server.
+const CACHE_TTL: Duration = Duration::from_mins(1);
+
+/// Address family used when connecting to hosts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpVersion {
+    /// Use whatever the resolver returns
+    #[default]
+    Auto,
+    /// Only connect over IPv4
+    #[value(name = "4")]
+    #[serde(rename = "4")]
+    V4,
+    /// Only connect over IPv6
+    #[value(name = "6")]
+    #[serde(rename = "6")]
+    V6,
+}
+
+impl IpVersion {
+    fn allows(self, ip: IpAddr) -> bool {
+        match self {
+            Self::Auto => true,
+            Self::V4 => ip.is_ipv4(),
+            Self::V6 => ip.is_ipv6(),
+        }
+    }
+
+    fn strategy(self) -> LookupIpStrategy {
+        match self {
+            Self::Auto => LookupIpStrategy::Ipv4thenIpv6,
+            Self::V4 => LookupIpStrategy::Ipv4Only,
+            Self::V6 => LookupIpStrategy::Ipv6Only,
+        }
+    }
+}
+
+/// Parses `IP` or `IP:PORT`; IPv6 addresses with a port must be bracketed.
+pub fn parse_dns_server(value: &str) -> Result<SocketAddr, String> {
+    if let Ok(address) = value.parse::<SocketAddr>() {
+        return Ok(address);
+    }
+    value
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, 53))
+        .map_err(|_| format!("invalid DNS server \"{value}\""))
+}
+
+/// Resolver used by the HTTP client when `--ip-version` or `--dns-server` is set.
+#[derive(Clone)]
+pub struct Resolver {
+    inner: TokioResolver,
+    ip_version: IpVersion,
+}
+
+impl Resolver {
+    /// Builds a resolver querying `servers`, or the system configuration when empty.
+    pub fn new(servers: &[SocketAddr], ip_version: IpVersion) -> Result<Self, NetError> {
+        let mut builder = if servers.is_empty() {
+            TokioResolver::builder_tokio()?
+        } else {
+            let name_servers = servers
+                .iter()
+                .map(|server| {
+                    let mut config = NameServerConfig::udp_and_tcp(server.ip());
+                    for connection in &mut config.connections {
+                        connection.port = server.port();
+                    }
+                    config
+                })
+                .collect();
+            TokioResolver::builder_with_config(
+                ResolverConfig::from_name_servers(name_servers),
+                TokioRuntimeProvider::default(),
+            )
+        };
+        builder.options_mut().ip_strategy = ip_version.strategy();
+        Ok(Self {
+            inner: builder.build()?,
+            ip_version,
+        })
+    }
+
+    async fn lookup(&self, host: &str) -> Result<Vec<SocketAddr>, LookupError> {
+        let lookup = self.inner.lookup_ip(host).await?;
+        let addresses = filter_addresses(lookup.iter(), self.ip_version);
+        if addresses.is_empty() {
+            let family = if self.ip_version == IpVersion::V6 {
+                "IPv6"
+            } else {
+                "IPv4"
+            };
+            return Err(format!("no {family} addresses found for {host}").into());
+        }
+        Ok(addresses)
+    }
+}
+
+/// The HTTP client's resolver: [`Resolver`] when configured, the system
+/// resolver otherwise, behind a per-host cache.
+pub struct CachingResolver {
+    resolver: Option<Resolver>,
+    /// Lowercase host → lookup, shared by concurrent requests while in flight
+    entries: Mutex<HashMap<String, Arc<Entry>>>,
+}
+
+struct Entry {
+    created: Instant,
+    /// Left empty by a failed lookup, so the next request retries
+    addresses: OnceCell<Vec<SocketAddr>>,
+}
+
+impl CachingResolver {
+    fn new(resolver: Option<Resolver>) -> Self {
+        Self {
+            resolver,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn entry(&self, host: &str) -> Arc<Entry> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| entry.created.elapsed() < CACHE_TTL);
+        let entry = entries.entry(host.to_ascii_lowercase()).or_insert_with(|| {
+            Arc::new(Entry {
+                created: Instant::now(),
+                addresses: OnceCell::new(),
+            })
+        });
+        Arc::clone(entry)
+    }
+}
+
+impl Resolve for CachingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let entry = self.entry(name.as_str());
+        let resolver = self.resolver.clone();
+        Box::pin(async move {
+            let host = name.as_str();
+            let addresses = entry
+                .addresses
+                .get_or_try_init(|| async {
+                    match &resolver {
+                        Some(resolver) => resolver.lookup(host).await,
+                        None => Ok(tokio::net::lookup_host((host, 0)).await?.collect()),
+                    }
+                })
+                .await?;
+            let addrs: Addrs = Box::new(addresses.clone().into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Keeps addresses of the requested family; the port is filled in by the client.
+fn filter_addresses(ips: impl Iterator<Item = IpAddr>, ip_version: IpVersion) -> Vec<SocketAddr> {
+    ips.filter(|ip| ip_version.allows(*ip))
+        .map(|ip| SocketAddr::new(ip, 0))
+        .collect()
+}
+
+/// Returns the resolver for the client, using the system resolver when
+/// neither option is set.
+pub fn resolver(
+    servers: &[SocketAddr],
+    ip_version: IpVersion,
+) -> Result<Arc<CachingResolver>, NetError> {
+    let resolver = if servers.is_empty() && ip_version == IpVersion::Auto {
+        None
+    } else {
+        Some(Resolver::new(servers, ip_version)?)
+    };
+    Ok(Arc::new(CachingResolver::new(resolver)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dns_server() {
+        assert_eq!(
+            parse_dns_server("1.1.1.1").unwrap(),
+            "1.1.1.1:53".parse().unwrap()
+        );
+        assert_eq!(
+            parse_dns_server("10.0.0.2:5353").unwrap(),
+            "10.0.0.2:5353".parse().unwrap()
+        );
+        assert_eq!(
+            parse_dns_server("2606:4700::1111").unwrap(),
+            "[2606:4700::1111]:53".parse().unwrap()
+        );
+        assert_eq!(
+            parse_dns_server("[::1]:5353").unwrap(),
+            "[::1]:5353".parse().unwrap()
+        );
+        assert!(parse_dns_server("dns.example.com").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lookups_are_shared() {
+        let resolver = resolver(&[], IpVersion::Auto).unwrap();
+        let lookup = |host: &str| resolver.resolve(host.parse().unwrap());
+        let (first, second) = tokio::join!(lookup("localhost"), lookup("LOCALHOST"));
+        let first: Vec<SocketAddr> = first.unwrap().collect();
+        assert!(!first.is_empty());
+        assert_eq!(first, second.unwrap().collect::<Vec<_>>());
+        assert_eq!(resolver.entries.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_filter_addresses_by_family() {
+        let ips: Vec<IpAddr> = vec![
+            "93.184.216.34".parse().unwrap(),
+            "2606:2800::1".parse().unwrap(),
+        ];
+        assert_eq!(
+            filter_addresses(ips.iter().copied(), IpVersion::Auto).len(),
+            2
+        );
+        let v4 = filter_addresses(ips.iter().copied(), IpVersion::V4);
+        assert_eq!(v4, vec!["93.184.216.34:0".parse().unwrap()]);
+        let v6 = filter_addresses(ips.iter().copied(), IpVersion::V6);
+        assert_eq!(v6, vec!["[2606:2800::1]:0".parse().unwrap()]);
+    }
+}
@@ -0,0 +1,131 @@
+//! Recorded HTTP exchanges for testing site handlers without the network.
+//!
+//! A handler's cases live in `test-fixtures/<handler>/`, one TOML cassette
+//! per case: the URL to fetch and the responses the site gave, replayed by a
+//! local server the handler is pointed at instead of the real API.
+//!
+//! ```toml
+//! url = "https://github.com/o/r/releases/tag/v1.0.0"
+//!
+//! [[exchange]]
+//! path = "/repos/o/r/releases/tags/v1.0.0"
+//! headers = { x-ratelimit-remaining = "4999" }
+//! body = '{"tag_name": "v1.0.0", "body": "First release"}'
+//! ```
+//!
+//! `method` defaults to `GET` and `status` to 200, and a body that isn't
+//! UTF-8 goes in `body_base64`; files `--record` saves are in this format
+//! too. Each case's output is compared with an insta snapshot named after
+//! the file, so a regression shows up as a snapshot diff, and adding a case
+//! is adding a cassette and accepting its snapshot. Requests no exchange matches get a 501 and are
+//! reported by [`Replay::unmatched`].
+
+use axum::Router;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{Method, StatusCode, Uri};
+use axum::response::Response;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+pub use crate::cassette::Exchange;
+
+/// One recorded case of a handler.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Cassette {
+    /// URL the handler is asked for
+    pub url: String,
+    /// Token the handler is configured with, for APIs that need one
+    pub token: Option<String>,
+    #[serde(default)]
+    pub exchange: Vec<Exchange>,
+}
+
+/// The cassettes of `handler`, by case name, sorted.
+pub fn cassettes(handler: &str) -> Vec<(String, Cassette)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("test-fixtures")
+        .join(handler);
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("no fixtures in {}: {e}", dir.display()))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            let text = std::fs::read_to_string(&path).unwrap();
+            let cassette = toml::from_str(&text)
+                .unwrap_or_else(|e| panic!("invalid cassette {}: {e}", path.display()));
+            (name, cassette)
+        })
+        .collect()
+}
+
+type Shared = Arc<(Vec<Exchange>, Mutex<Vec<String>>)>;
+
+/// A cassette served on a localhost port, stopped on drop.
+pub struct Replay {
+    base: String,
+    shared: Shared,
+    handle: JoinHandle<()>,
+}
+
+impl Replay {
+    pub async fn start(exchanges: Vec<Exchange>) -> Self {
+        let shared: Shared = Arc::new((exchanges, Mutex::default()));
+        let app = Router::new()
+            .fallback(respond)
+            .with_state(Arc::clone(&shared));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        Self {
+            base,
+            shared,
+            handle,
+        }
+    }
+
+    /// Base URL to point the handler at.
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    /// Requests no exchange matched, as `METHOD path`.
+    pub fn unmatched(&self) -> Vec<String> {
+        self.shared.1.lock().unwrap().clone()
+    }
+}
+
+impl Drop for Replay {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn respond(State(shared): State<Shared>, method: Method, uri: Uri) -> Response {
+    let path = uri.path_and_query().map_or("/", |path| path.as_str());
+    let (exchanges, unmatched) = &*shared;
+    let Some(exchange) = exchanges.iter().find(|exchange| {
+        exchange.method.eq_ignore_ascii_case(method.as_str()) && exchange.path == path
+    }) else {
+        unmatched.lock().unwrap().push(format!("{method} {path}"));
+        let mut response = Response::new(Body::from("no recorded exchange"));
+        *response.status_mut() = StatusCode::NOT_IMPLEMENTED;
+        return response;
+    };
+    let mut response = Response::builder().status(exchange.status);
+    for (name, value) in &exchange.headers {
+        response = response.header(name, value);
+    }
+    response.body(Body::from(exchange.body().unwrap())).unwrap()
+}
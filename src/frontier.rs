@@ -0,0 +1,234 @@
+//! Saved state of crawls, kept in the cache next to the pages.
+//!
+//! A crawl records its frontier (the pages found so far with their depth, and
+//! which are done) after every page. When a crawl from the same URL runs
+//! again before the last one finished (it was interrupted or ran out of
+//! quota), it resumes instead of starting over. What each page said about
+//! itself outlives the crawl, so the next one can ask for pages only if they
+//! changed and reuse the stored title, description and links when they didn't.
+
+use crate::crawl::{Limits, Page};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Directory of frontiers inside the cache root.
+pub const CACHE_DIR: &str = ".crawls";
+
+/// A page waiting to be fetched: its position in the frontier, URL and depth.
+pub type Pending = (usize, String, usize);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Found {
+    url: String,
+    /// Link hops from the start page
+    depth: usize,
+}
+
+/// An unfinished crawl.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct Progress {
+    /// In the order they were found, starting with the start page
+    found: Vec<Found>,
+    /// Positions in `found` that were fetched, or failed
+    done: BTreeSet<usize>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct FrontierData {
+    /// The page each URL returned when last fetched
+    #[serde(default)]
+    pages: BTreeMap<String, Page>,
+    #[serde(default)]
+    progress: Option<Progress>,
+}
+
+/// The saved frontier of crawls from one URL, persisted with [`Frontier::save`].
+#[derive(Debug)]
+pub struct Frontier {
+    path: PathBuf,
+    data: Mutex<FrontierData>,
+}
+
+impl Frontier {
+    /// Loads the frontier stored at `path`, starting empty if it's missing or
+    /// unreadable.
+    pub fn load(path: PathBuf) -> Self {
+        let data = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            data: Mutex::new(data),
+        }
+    }
+
+    /// Starts a crawl from `start`, or resumes the unfinished one.
+    ///
+    /// Returns the pages still to fetch, and the pages already done by the
+    /// resumed crawl (position and page; failed ones are left out).
+    pub fn begin(&self, start: &str) -> (Vec<Pending>, Vec<(usize, Page)>) {
+        let mut data = self.data.lock().unwrap();
+        let FrontierData { pages, progress } = &mut *data;
+        let progress = match progress {
+            Some(p) if p.found.first().is_some_and(|f| f.url == start) => p,
+            _ => progress.insert(Progress {
+                found: vec![Found {
+                    url: start.to_string(),
+                    depth: 0,
+                }],
+                done: BTreeSet::new(),
+            }),
+        };
+        let mut pending = Vec::new();
+        let mut done = Vec::new();
+        for (position, found) in progress.found.iter().enumerate() {
+            if !progress.done.contains(&position) {
+                pending.push((position, found.url.clone(), found.depth));
+            } else if let Some(page) = pages.get(&found.url) {
+                done.push((position, page.clone()));
+            }
+        }
+        (pending, done)
+    }
+
+    /// The page `url` returned when last fetched, for when it hasn't changed.
+    pub fn known(&self, url: &str) -> Option<Page> {
+        self.data.lock().unwrap().pages.get(url).cloned()
+    }
+
+    /// Marks the page at `position` done, with what it returned (`None` if it
+    /// failed), and adds its links to the frontier within `limits`.
+    ///
+    /// Returns the newly found pages.
+    pub fn complete(&self, position: usize, page: Option<&Page>, limits: Limits) -> Vec<Pending> {
+        let mut data = self.data.lock().unwrap();
+        let FrontierData { pages, progress } = &mut *data;
+        let Some(progress) = progress else {
+            return Vec::new();
+        };
+        progress.done.insert(position);
+        let Some(page) = page else {
+            return Vec::new();
+        };
+        pages.insert(page.url.clone(), page.clone());
+        let depth = progress.found[position].depth + 1;
+        if depth > limits.max_depth {
+            return Vec::new();
+        }
+        let mut new = Vec::new();
+        for link in &page.links {
+            if progress.found.len() >= limits.max_pages {
+                break;
+            }
+            if !progress.found.iter().any(|found| found.url == *link) {
+                new.push((progress.found.len(), link.clone(), depth));
+                progress.found.push(Found {
+                    url: link.clone(),
+                    depth,
+                });
+            }
+        }
+        new
+    }
+
+    /// Ends the crawl, so the next one starts from the beginning.
+    pub fn finish(&self) {
+        self.data.lock().unwrap().progress = None;
+    }
+
+    /// Writes the frontier atomically (temp file + rename).
+    pub async fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_string(&*self.data.lock().unwrap())?;
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let temp_path = crate::sidecar::temp_path(&self.path);
+        tokio::fs::write(&temp_path, json).await?;
+        tokio::fs::rename(&temp_path, &self.path).await
+    }
+}
+
+/// Where the frontier of crawls from a URL is stored, given the path the URL
+/// would be cached at under [`CACHE_DIR`].
+pub fn path_for(cached_path: &Path) -> PathBuf {
+    let mut name = cached_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".json");
+    cached_path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: &str = "https://example.com/docs/";
+    const LIMITS: Limits = Limits {
+        max_pages: 3,
+        max_depth: 1,
+    };
+
+    fn page(url: &str, links: &[&str]) -> Page {
+        Page {
+            url: url.to_string(),
+            links: links.iter().map(|link| format!("{START}{link}")).collect(),
+            ..Page::default()
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let frontier = Frontier::load(PathBuf::from("unused.json"));
+        let (pending, done) = frontier.begin(START);
+        assert_eq!(pending, [(0, START.to_string(), 0)]);
+        assert!(done.is_empty());
+
+        let start = page(START, &["a", "b", "c"]);
+        let found = frontier.complete(0, Some(&start), LIMITS);
+        let urls: Vec<&str> = found.iter().map(|(_, url, _)| url.as_str()).collect();
+        assert_eq!(urls, [format!("{START}a"), format!("{START}b")]);
+
+        // Past max_depth, links aren't followed
+        assert!(
+            frontier
+                .complete(1, Some(&page("a", &["d"])), LIMITS)
+                .is_empty()
+        );
+        assert_eq!(frontier.known("a"), Some(page("a", &["d"])));
+    }
+
+    #[tokio::test]
+    async fn test_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("frontier.json");
+        let frontier = Frontier::load(path.clone());
+        frontier.begin(START);
+        let start = page(START, &["a", "b"]);
+        frontier.complete(0, Some(&start), LIMITS);
+        frontier.complete(2, None, LIMITS);
+        frontier.save().await.unwrap();
+
+        let reloaded = Frontier::load(path.clone());
+        let (pending, done) = reloaded.begin(START);
+        assert_eq!(pending, [(1, format!("{START}a"), 1)]);
+        assert_eq!(done, [(0, start.clone())]);
+
+        // A finished crawl starts over but remembers its pages
+        reloaded.finish();
+        reloaded.save().await.unwrap();
+        let reloaded = Frontier::load(path);
+        let (pending, done) = reloaded.begin(START);
+        assert_eq!(pending.len(), 1);
+        assert!(done.is_empty());
+        assert_eq!(reloaded.known(START), Some(start));
+    }
+
+    #[test]
+    fn test_path_for() {
+        assert_eq!(
+            path_for(Path::new("/cache/.crawls/example.com/docs/index")),
+            PathBuf::from("/cache/.crawls/example.com/docs/index.json")
+        );
+    }
+}
@@ -0,0 +1,251 @@
+//! Minimal FTP client for retrieving single files (RFC 959, passive mode).
+//!
+//! Enough for the anonymous archives that still host some specifications:
+//! log in, switch to binary, open a passive data connection and `RETR` the
+//! file. Directory listings and FTPS are not supported.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+
+/// Whole-transfer deadline, matching the HTTP client's timeout.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Downloads the file named by an `ftp://` URL.
+pub async fn retrieve(url: &url::Url) -> Result<Vec<u8>, String> {
+    tokio::time::timeout(TIMEOUT, retrieve_inner(url))
+        .await
+        .map_err(|_| format!("FTP transfer timed out after {}s", TIMEOUT.as_secs()))?
+}
+
+async fn retrieve_inner(url: &url::Url) -> Result<Vec<u8>, String> {
+    let host = url.host_str().ok_or("No host in FTP URL")?;
+    let port = url.port().unwrap_or(21);
+    let path = percent_encoding::percent_decode_str(url.path())
+        .decode_utf8()
+        .map_err(|_| "FTP path is not valid UTF-8")?;
+    if path.ends_with('/') {
+        return Err("FTP directory listings are not supported; link to a file".to_string());
+    }
+
+    let stream = TcpStream::connect((host, port))
+        .await
+        .map_err(|e| format!("Failed to connect to {host}:{port}: {e}"))?;
+    let peer = stream.peer_addr().map_err(|e| e.to_string())?;
+    let (read, write) = stream.into_split();
+    let mut control = Control {
+        reader: BufReader::new(read),
+        writer: write,
+    };
+
+    control.expect(&[220]).await?;
+    let user = match url.username() {
+        "" => "anonymous",
+        user => user,
+    };
+    let password = url.password().unwrap_or("anonymous@");
+    if control
+        .command(&format!("USER {user}"), &[230, 331])
+        .await?
+        == 331
+    {
+        control.command(&format!("PASS {password}"), &[230]).await?;
+    }
+    control.command("TYPE I", &[200]).await?;
+
+    let data_addr = control.passive(peer).await?;
+    let mut data = TcpStream::connect(data_addr)
+        .await
+        .map_err(|e| format!("Failed to open FTP data connection: {e}"))?;
+    control
+        .command(&format!("RETR {path}"), &[125, 150])
+        .await?;
+    let mut content = Vec::new();
+    data.read_to_end(&mut content)
+        .await
+        .map_err(|e| format!("FTP transfer failed: {e}"))?;
+    control.expect(&[226, 250]).await?;
+    // Best effort: the file is already complete
+    let _ = control.command("QUIT", &[221]).await;
+    Ok(content)
+}
+
+struct Control {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+}
+
+impl Control {
+    async fn command(&mut self, command: &str, expected: &[u16]) -> Result<u16, String> {
+        self.writer
+            .write_all(format!("{command}\r\n").as_bytes())
+            .await
+            .map_err(|e| format!("FTP connection failed: {e}"))?;
+        let verb = command.split(' ').next().unwrap_or(command);
+        self.expect(expected)
+            .await
+            .map_err(|e| format!("{verb}: {e}"))
+    }
+
+    /// Reads one reply and fails unless its code is in `expected`.
+    async fn expect(&mut self, expected: &[u16]) -> Result<u16, String> {
+        let (code, text) = self.reply().await?;
+        if expected.contains(&code) {
+            Ok(code)
+        } else {
+            Err(format!("FTP server replied {code} {text}"))
+        }
+    }
+
+    /// Reads a possibly multi-line reply, returning its code and last line.
+    async fn reply(&mut self) -> Result<(u16, String), String> {
+        let mut first_code = None;
+        loop {
+            let mut line = String::new();
+            let read = self
+                .reader
+                .read_line(&mut line)
+                .await
+                .map_err(|e| format!("FTP connection failed: {e}"))?;
+            if read == 0 {
+                return Err("FTP server closed the connection".to_string());
+            }
+            let line = line.trim_end();
+            let code = line.get(..3).and_then(|code| code.parse::<u16>().ok());
+            if first_code.is_none() {
+                first_code = Some(code.ok_or_else(|| format!("Malformed FTP reply: {line}"))?);
+            }
+            // A multi-line reply ends with its code followed by a space
+            if code == first_code && line.as_bytes().get(3) != Some(&b'-') {
+                return Ok((code.unwrap_or_default(), line[3..].trim().to_string()));
+            }
+        }
+    }
+
+    /// Enters passive mode, preferring `EPSV` and falling back to `PASV`.
+    ///
+    /// The data connection always goes to the control connection's address:
+    /// the address in a `PASV` reply is ignored, as it is often a private
+    /// address behind NAT and would let a server redirect the client.
+    async fn passive(&mut self, peer: SocketAddr) -> Result<SocketAddr, String> {
+        self.writer
+            .write_all(b"EPSV\r\n")
+            .await
+            .map_err(|e| format!("FTP connection failed: {e}"))?;
+        let (code, text) = self.reply().await?;
+        let port = if code == 229 {
+            parse_epsv(&text)
+        } else {
+            self.writer
+                .write_all(b"PASV\r\n")
+                .await
+                .map_err(|e| format!("FTP connection failed: {e}"))?;
+            let (code, text) = self.reply().await?;
+            if code != 227 {
+                return Err(format!("PASV: FTP server replied {code} {text}"));
+            }
+            parse_pasv(&text)
+        };
+        let port = port.ok_or("Could not parse the FTP passive mode reply")?;
+        Ok(SocketAddr::new(peer.ip(), port))
+    }
+}
+
+/// Port from a `229 Entering Extended Passive Mode (|||6446|)` reply.
+fn parse_epsv(text: &str) -> Option<u16> {
+    let start = text.find("(|||")? + 4;
+    let end = start + text[start..].find('|')?;
+    text[start..end].parse().ok()
+}
+
+/// Port from a `227 Entering Passive Mode (h1,h2,h3,h4,p1,p2)` reply.
+fn parse_pasv(text: &str) -> Option<u16> {
+    let start = text.find('(')? + 1;
+    let end = start + text[start..].find(')')?;
+    let numbers: Vec<u16> = text[start..end]
+        .split(',')
+        .map(|n| n.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    match numbers[..] {
+        [_, _, _, _, high, low] if high < 256 && low < 256 => Some(high * 256 + low),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_parse_passive_replies() {
+        assert_eq!(
+            parse_epsv("Entering Extended Passive Mode (|||6446|)"),
+            Some(6446)
+        );
+        assert_eq!(
+            parse_pasv("Entering Passive Mode (192,168,1,2,25,46)."),
+            Some(25 * 256 + 46)
+        );
+        assert_eq!(parse_pasv("Entering Passive Mode (1,2,3)"), None);
+    }
+
+    /// Serves `content` for one `RETR` over a scripted control connection.
+    async fn serve_once(content: &'static [u8]) -> u16 {
+        let control = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = control.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (stream, _) = control.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+            write
+                .write_all(b"220-Welcome\r\n220 Ready\r\n")
+                .await
+                .unwrap();
+            let data = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let data_port = data.local_addr().unwrap().port();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let reply = match line.split(' ').next().unwrap() {
+                    "USER" => "331 Password required\r\n".to_string(),
+                    "PASS" => "230 Logged in\r\n".to_string(),
+                    "TYPE" => "200 Binary\r\n".to_string(),
+                    "EPSV" => "500 Unknown command\r\n".to_string(),
+                    "PASV" => format!(
+                        "227 Entering Passive Mode (10,0,0,1,{},{})\r\n",
+                        data_port / 256,
+                        data_port % 256
+                    ),
+                    "RETR" => {
+                        assert_eq!(line, "RETR /pub/rfc 1.txt");
+                        write.write_all(b"150 Opening\r\n").await.unwrap();
+                        let (mut stream, _) = data.accept().await.unwrap();
+                        stream.write_all(content).await.unwrap();
+                        drop(stream);
+                        "226 Done\r\n".to_string()
+                    }
+                    _ => {
+                        write.write_all(b"221 Bye\r\n").await.unwrap();
+                        break;
+                    }
+                };
+                write.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_over_pasv() {
+        let port = serve_once(b"Request for Comments\n").await;
+        let url = url::Url::parse(&format!("ftp://127.0.0.1:{port}/pub/rfc%201.txt")).unwrap();
+        assert_eq!(retrieve(&url).await.unwrap(), b"Request for Comments\n");
+    }
+
+    #[tokio::test]
+    async fn test_directory_urls_rejected() {
+        let url = url::Url::parse("ftp://127.0.0.1:1/pub/").unwrap();
+        assert!(retrieve(&url).await.unwrap_err().contains("directory"));
+    }
+}
@@ -0,0 +1,259 @@
+//! Gemini protocol client and gemtext-to-Markdown conversion.
+//!
+//! Gemini capsules almost always use self-signed certificates and expect
+//! clients to trust on first use. The cache has no place to remember
+//! certificates across sessions, so any certificate is accepted; the
+//! handshake signature is still checked so the server must hold the key.
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+
+/// Whole-request deadline, matching the HTTP client's timeout.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Redirects followed before giving up, as the specification suggests.
+const MAX_REDIRECTS: usize = 5;
+
+/// Fetches a `gemini://` URL, returning the media type of the returned
+/// content and the content itself. Gemtext is converted to Markdown.
+pub async fn retrieve(url: &url::Url) -> Result<(String, String), String> {
+    tokio::time::timeout(TIMEOUT, retrieve_following_redirects(url))
+        .await
+        .map_err(|_| format!("Gemini request timed out after {}s", TIMEOUT.as_secs()))?
+}
+
+async fn retrieve_following_redirects(url: &url::Url) -> Result<(String, String), String> {
+    let mut url = url.clone();
+    for _ in 0..=MAX_REDIRECTS {
+        let (status, meta, body) = request(&url).await?;
+        match status / 10 {
+            2 => {
+                let media_type = if meta.is_empty() {
+                    "text/gemini".to_string()
+                } else {
+                    meta
+                };
+                let text = String::from_utf8_lossy(&body).into_owned();
+                return Ok(if media_type.starts_with("text/gemini") {
+                    (
+                        "text/markdown".to_string(),
+                        gemtext_to_markdown(&text, &url),
+                    )
+                } else if media_type.starts_with("text/") {
+                    (media_type, text)
+                } else {
+                    return Err(format!("Unsupported Gemini content type {media_type}"));
+                });
+            }
+            3 => {
+                url = url
+                    .join(&meta)
+                    .map_err(|e| format!("Invalid Gemini redirect to {meta}: {e}"))?;
+                if url.scheme() != "gemini" {
+                    return Err(format!("Gemini server redirected to non-Gemini URL {url}"));
+                }
+            }
+            1 => return Err(format!("Gemini server asks for input ({meta})")),
+            6 => {
+                return Err(format!(
+                    "Gemini server requires a client certificate ({meta})"
+                ));
+            }
+            _ => return Err(format!("Gemini server replied {status} {meta}")),
+        }
+    }
+    Err(format!("Gemini redirect limit ({MAX_REDIRECTS}) exceeded"))
+}
+
+/// Sends one request, returning the status, meta and body.
+async fn request(url: &url::Url) -> Result<(u16, String, Vec<u8>), String> {
+    let host = url.host_str().ok_or("No host in Gemini URL")?;
+    let port = url.port().unwrap_or(1965);
+    let config = rustls::ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))))
+        .with_no_client_auth();
+    let server_name = ServerName::try_from(host.to_string())
+        .map_err(|e| format!("Invalid Gemini host {host}: {e}"))?;
+
+    let stream = TcpStream::connect((host, port))
+        .await
+        .map_err(|e| format!("Failed to connect to {host}:{port}: {e}"))?;
+    let mut stream = tokio_rustls::TlsConnector::from(Arc::new(config))
+        .connect(server_name, stream)
+        .await
+        .map_err(|e| format!("TLS handshake with {host} failed: {e}"))?;
+    stream
+        .write_all(format!("{url}\r\n").as_bytes())
+        .await
+        .map_err(|e| format!("Gemini request failed: {e}"))?;
+
+    let mut response = Vec::new();
+    // Many servers close without a TLS close_notify; what was read is complete
+    if let Err(e) = stream.read_to_end(&mut response).await
+        && e.kind() != std::io::ErrorKind::UnexpectedEof
+    {
+        return Err(format!("Gemini response failed: {e}"));
+    }
+    let header_end = response
+        .windows(2)
+        .position(|w| w == b"\r\n")
+        .ok_or("Malformed Gemini response: no header line")?;
+    let header = String::from_utf8_lossy(&response[..header_end]).into_owned();
+    let (status, meta) = header.split_once(' ').unwrap_or((&header, ""));
+    let status = status
+        .parse()
+        .map_err(|_| format!("Malformed Gemini response header: {header}"))?;
+    Ok((
+        status,
+        meta.trim().to_string(),
+        response[header_end + 2..].to_vec(),
+    ))
+}
+
+/// Converts gemtext to Markdown, resolving links against `base`.
+///
+/// Gemtext has one element per line, so each text line becomes its own
+/// paragraph; preformatted blocks become fenced code blocks.
+pub fn gemtext_to_markdown(text: &str, base: &url::Url) -> String {
+    use std::fmt::Write;
+
+    let mut output = String::new();
+    let mut preformatted = false;
+    let mut previous_was_text = false;
+    for line in text.lines() {
+        if line.starts_with("```") {
+            output.push_str(if preformatted { "```" } else { line });
+            output.push('\n');
+            preformatted = !preformatted;
+            previous_was_text = false;
+            continue;
+        }
+        if preformatted {
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        }
+
+        let is_text = !line.is_empty()
+            && !["=>", "#", "* ", ">"]
+                .iter()
+                .any(|prefix| line.starts_with(prefix));
+        if is_text && previous_was_text {
+            output.push('\n');
+        }
+        previous_was_text = is_text;
+
+        if let Some(link) = line.strip_prefix("=>") {
+            let link = link.trim();
+            let (target, label) = link
+                .split_once(char::is_whitespace)
+                .map_or((link, ""), |(target, label)| (target, label.trim()));
+            let target = base
+                .join(target)
+                .map_or_else(|_| target.to_string(), |url| url.to_string());
+            let label = if label.is_empty() { &target } else { label };
+            writeln!(output, "- [{label}]({target})").unwrap();
+        } else if let Some(item) = line.strip_prefix("* ") {
+            writeln!(output, "- {}", item.trim()).unwrap();
+        } else {
+            output.push_str(line);
+            output.push('\n');
+        }
+    }
+    if preformatted {
+        output.push_str("```\n");
+    }
+    output
+}
+
+/// Trust-on-first-use without the memory: see the module documentation.
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<rustls::crypto::CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gemtext_to_markdown() {
+        let base = url::Url::parse("gemini://example.org/docs/").unwrap();
+        let gemtext = "# Manual\nFirst line.\nSecond line.\n\n=> intro.gmi Introduction\n=> gemini://other.org/\n* one\n* two\n> quoted\n```rust\nfn main() {}\n=> not a link\n```\n";
+        insta::assert_snapshot!(gemtext_to_markdown(gemtext, &base), @r"
+        # Manual
+        First line.
+
+        Second line.
+
+        - [Introduction](gemini://example.org/docs/intro.gmi)
+        - [gemini://other.org/](gemini://other.org/)
+        - one
+        - two
+        > quoted
+        ```rust
+        fn main() {}
+        => not a link
+        ```
+        ");
+    }
+
+    #[test]
+    fn test_unterminated_preformatted_block_is_closed() {
+        let base = url::Url::parse("gemini://example.org/").unwrap();
+        assert_eq!(gemtext_to_markdown("```\ncode", &base), "```\ncode\n```\n");
+    }
+}
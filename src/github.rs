@@ -0,0 +1,1105 @@
+//! GitHub API access for pages that convert poorly from HTML.
+//!
+//! Releases and tags pages are rendered client-side and truncate badly when
+//! converted, and issue/PR/discussion threads hide most comments behind lazy
+//! loading, so these are fetched from the API and rendered as markdown.
+
+use crate::cassette::Cassette;
+use serde::Deserialize;
+use std::fmt::Write;
+
+const API_BASE: &str = "https://api.github.com";
+
+/// Releases per page when fetching a listing for the `fetch` tool (GitHub's default).
+pub const DEFAULT_PER_PAGE: u32 = 30;
+
+/// A GitHub page that is served from the API instead of HTML conversion.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GithubPage {
+    /// `github.com/{owner}/{repo}/releases[?page=N]`
+    Releases {
+        owner: String,
+        repo: String,
+        page: u32,
+    },
+    /// `github.com/{owner}/{repo}/releases/tag/{tag}`, where the tag may
+    /// contain `/`
+    Release {
+        owner: String,
+        repo: String,
+        tag: String,
+    },
+    /// `github.com/{owner}/{repo}/tags[?page=N]`
+    Tags {
+        owner: String,
+        repo: String,
+        page: u32,
+    },
+    /// `github.com/{owner}/{repo}/issues/{number}` or `.../pull/{number}`
+    Issue {
+        owner: String,
+        repo: String,
+        number: u64,
+        pull: bool,
+    },
+    /// `github.com/{owner}/{repo}/discussions/{number}` (GraphQL, needs a token)
+    Discussion {
+        owner: String,
+        repo: String,
+        number: u64,
+    },
+}
+
+impl GithubPage {
+    pub fn parse(url: &str) -> Option<Self> {
+        let parsed = url::Url::parse(url).ok()?;
+        if parsed.host_str()? != "github.com" {
+            return None;
+        }
+        let segments: Vec<&str> = parsed.path_segments()?.filter(|s| !s.is_empty()).collect();
+        let page = parsed
+            .query_pairs()
+            .find(|(k, _)| k == "page")
+            .and_then(|(_, v)| v.parse().ok())
+            .filter(|&p| p > 0)
+            .unwrap_or(1);
+
+        match segments.as_slice() {
+            [owner, repo, "releases"] => Some(Self::Releases {
+                owner: (*owner).to_string(),
+                repo: (*repo).to_string(),
+                page,
+            }),
+            [owner, repo, "releases", "tag", tag @ ..] if !tag.is_empty() => Some(Self::Release {
+                owner: (*owner).to_string(),
+                repo: (*repo).to_string(),
+                tag: tag.join("/"),
+            }),
+            [owner, repo, "tags"] => Some(Self::Tags {
+                owner: (*owner).to_string(),
+                repo: (*repo).to_string(),
+                page,
+            }),
+            [owner, repo, kind @ ("issues" | "pull"), number] => Some(Self::Issue {
+                owner: (*owner).to_string(),
+                repo: (*repo).to_string(),
+                number: number.parse().ok()?,
+                pull: *kind == "pull",
+            }),
+            [owner, repo, "discussions", number] => Some(Self::Discussion {
+                owner: (*owner).to_string(),
+                repo: (*repo).to_string(),
+                number: number.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Canonical github.com URL for this page, used as the cache key.
+    pub fn url(&self) -> String {
+        match self {
+            Self::Releases { owner, repo, page } if *page > 1 => {
+                format!("https://github.com/{owner}/{repo}/releases?page={page}")
+            }
+            Self::Releases { owner, repo, .. } => {
+                format!("https://github.com/{owner}/{repo}/releases")
+            }
+            Self::Release { owner, repo, tag } => {
+                format!("https://github.com/{owner}/{repo}/releases/tag/{tag}")
+            }
+            Self::Tags { owner, repo, page } if *page > 1 => {
+                format!("https://github.com/{owner}/{repo}/tags?page={page}")
+            }
+            Self::Tags { owner, repo, .. } => format!("https://github.com/{owner}/{repo}/tags"),
+            Self::Issue {
+                owner,
+                repo,
+                number,
+                pull,
+            } => {
+                let kind = if *pull { "pull" } else { "issues" };
+                format!("https://github.com/{owner}/{repo}/{kind}/{number}")
+            }
+            Self::Discussion {
+                owner,
+                repo,
+                number,
+            } => format!("https://github.com/{owner}/{repo}/discussions/{number}"),
+        }
+    }
+}
+
+/// A markdown document rendered from the API, keyed by the github.com URL it replaces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub url: String,
+    pub markdown: String,
+}
+
+/// Result of fetching a [`GithubPage`].
+#[derive(Debug)]
+pub struct Fetched {
+    /// The page itself (release listing, single release, or tag list)
+    pub main: Document,
+    /// One document per release on a listing page, for individual caching
+    pub releases: Vec<Document>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    name: Option<String>,
+    body: Option<String>,
+    published_at: Option<String>,
+    #[serde(default)]
+    prerelease: bool,
+    #[serde(default)]
+    draft: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Tag {
+    name: String,
+    commit: TagCommit,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagCommit {
+    sha: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct User {
+    login: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Issue {
+    title: String,
+    body: Option<String>,
+    state: String,
+    user: Option<User>,
+    created_at: String,
+    #[serde(default)]
+    comments: u64,
+    /// Present when the issue is a pull request
+    #[serde(default)]
+    pull_request: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IssueComment {
+    body: Option<String>,
+    user: Option<User>,
+    created_at: String,
+}
+
+/// A comment on a line of a pull request's diff.
+#[derive(Debug, Deserialize)]
+struct ReviewComment {
+    body: Option<String>,
+    user: Option<User>,
+    created_at: String,
+    path: String,
+    /// Line of the file commented on, absent when the diff moved on
+    line: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphqlResponse<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphqlError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphqlError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DiscussionData {
+    repository: Option<DiscussionRepository>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DiscussionRepository {
+    discussion: Option<Discussion>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Discussion {
+    title: String,
+    body: String,
+    created_at: String,
+    author: Option<User>,
+    answer: Option<NodeId>,
+    comments: Nodes<DiscussionComment>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NodeId {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Nodes<T> {
+    nodes: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DiscussionComment {
+    id: String,
+    body: String,
+    created_at: String,
+    author: Option<User>,
+    replies: Nodes<DiscussionReply>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DiscussionReply {
+    body: String,
+    created_at: String,
+    author: Option<User>,
+}
+
+const DISCUSSION_QUERY: &str = "query($owner: String!, $repo: String!, $number: Int!) {
+  repository(owner: $owner, name: $repo) {
+    discussion(number: $number) {
+      title body createdAt author { login } answer { id }
+      comments(first: 100) {
+        nodes {
+          id body createdAt author { login }
+          replies(first: 50) { nodes { body createdAt author { login } } }
+        }
+      }
+    }
+  }
+}";
+
+/// Comment pages fetched per issue, and review comment pages per pull
+/// request (100 comments each).
+const MAX_COMMENT_PAGES: u32 = 10;
+
+/// Remaining-request count at or below which a warning is surfaced.
+const RATE_LIMIT_WARNING_THRESHOLD: u64 = 10;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Rate-limit state reported by the API's `x-ratelimit-*` headers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub remaining: u64,
+    pub limit: u64,
+    /// Unix timestamp when the window resets
+    pub reset: Option<u64>,
+}
+
+impl RateLimit {
+    fn from_headers(headers: &reqwest::header::HeaderMap) -> Option<Self> {
+        let get = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok())
+        };
+        Some(Self {
+            remaining: get("x-ratelimit-remaining")?,
+            limit: get("x-ratelimit-limit")?,
+            reset: get("x-ratelimit-reset"),
+        })
+    }
+
+    /// Human-readable time until reset, e.g. "in 12 min".
+    pub fn resets_in(&self) -> String {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        match self.reset {
+            Some(reset) if reset > now => format!("in {} min", (reset - now).div_ceil(60)),
+            Some(_) => "now".to_string(),
+            None => "at an unknown time".to_string(),
+        }
+    }
+
+    /// Warning text when the remaining budget is nearly spent.
+    pub fn warning(&self) -> Option<String> {
+        (self.remaining <= RATE_LIMIT_WARNING_THRESHOLD).then(|| {
+            format!(
+                "GitHub API rate limit low: {}/{} requests remaining, resets {}{}",
+                self.remaining,
+                self.limit,
+                self.resets_in(),
+                if self.limit <= 60 {
+                    " (set GITHUB_TOKEN for a higher limit)"
+                } else {
+                    ""
+                }
+            )
+        })
+    }
+}
+
+/// The API refused the request because the rate limit is exhausted.
+///
+/// Callers downcast to this to fall back to scraping the HTML page.
+#[derive(Debug)]
+pub struct RateLimited(pub Option<RateLimit>);
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GitHub API rate limit exhausted")?;
+        if let Some(limit) = &self.0 {
+            write!(f, " (limit {}, resets {})", limit.limit, limit.resets_in())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Authenticated (when a token is configured) access to the GitHub API that
+/// remembers the most recent rate-limit headers and how much it downloaded.
+pub struct Api<'a> {
+    client: &'a reqwest::Client,
+    token: Option<&'a str>,
+    /// [`API_BASE`], or a replay server in tests
+    base: &'a str,
+    /// Where requests are recorded to or replayed from, if anywhere
+    cassette: Option<&'a Cassette>,
+    rate_limit: std::sync::Mutex<Option<RateLimit>>,
+    /// Requests sent and response bytes received
+    usage: std::sync::Mutex<(u64, u64)>,
+}
+
+impl<'a> Api<'a> {
+    pub fn new(client: &'a reqwest::Client, token: Option<&'a str>) -> Self {
+        Self {
+            client,
+            token,
+            base: API_BASE,
+            cassette: None,
+            rate_limit: std::sync::Mutex::new(None),
+            usage: std::sync::Mutex::new((0, 0)),
+        }
+    }
+
+    /// Records requests to `cassette`, or replays them from it.
+    pub fn with_cassette(mut self, cassette: Option<&'a Cassette>) -> Self {
+        self.cassette = cassette;
+        self
+    }
+
+    /// Sends requests to `base` instead of the GitHub API.
+    #[cfg(test)]
+    pub fn with_base(mut self, base: &'a str) -> Self {
+        self.base = base;
+        self
+    }
+
+    /// Rate-limit state from the last response, if the API reported one.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        *self.rate_limit.lock().unwrap()
+    }
+
+    /// Requests sent and response bytes received so far, as `(requests, bytes)`.
+    pub fn usage(&self) -> (u64, u64) {
+        *self.usage.lock().unwrap()
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<String, Error> {
+        let request = match self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        };
+        let request = request.header("Accept", "application/vnd.github+json");
+        let response = match self.cassette {
+            Some(cassette) => cassette.send(self.client, request.build()?).await?,
+            None => request.send().await?,
+        };
+        self.usage.lock().unwrap().0 += 1;
+
+        let rate_limit = RateLimit::from_headers(response.headers());
+        if rate_limit.is_some() {
+            *self.rate_limit.lock().unwrap() = rate_limit;
+        }
+
+        let status = response.status();
+        let exhausted = rate_limit.is_some_and(|r| r.remaining == 0)
+            || response.headers().contains_key("retry-after");
+        if (status == reqwest::StatusCode::FORBIDDEN
+            || status == reqwest::StatusCode::TOO_MANY_REQUESTS)
+            && exhausted
+        {
+            return Err(Box::new(RateLimited(rate_limit)));
+        }
+
+        let body = response.error_for_status()?.text().await?;
+        self.usage.lock().unwrap().1 += body.len() as u64;
+        Ok(body)
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T, Error> {
+        let body = self
+            .send(self.client.get(format!("{}{path}", self.base)))
+            .await?;
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    async fn post_graphql<T: serde::de::DeserializeOwned>(
+        &self,
+        query: &str,
+        variables: serde_json::Value,
+    ) -> Result<T, Error> {
+        if self.token.is_none() {
+            return Err("discussions require a GitHub token (GraphQL API)".into());
+        }
+        let body = self
+            .send(
+                self.client.post(format!("{}/graphql", self.base)).body(
+                    serde_json::json!({ "query": query, "variables": variables }).to_string(),
+                ),
+            )
+            .await?;
+        let response: GraphqlResponse<T> = serde_json::from_str(&body)?;
+        if let Some(error) = response.errors.first() {
+            return Err(error.message.clone().into());
+        }
+        response
+            .data
+            .ok_or_else(|| "GraphQL response has no data".into())
+    }
+}
+
+/// Fetches `page` from the GitHub API and renders it as markdown.
+pub async fn fetch(api: &Api<'_>, page: &GithubPage, per_page: u32) -> Result<Fetched, Error> {
+    match page {
+        GithubPage::Releases {
+            owner,
+            repo,
+            page: page_number,
+        } => {
+            let releases: Vec<Release> = api
+                .get_json(&format!(
+                    "/repos/{owner}/{repo}/releases?per_page={per_page}&page={page_number}"
+                ))
+                .await?;
+            let documents = releases
+                .iter()
+                .filter(|r| !r.draft)
+                .map(|release| Document {
+                    url: GithubPage::Release {
+                        owner: owner.clone(),
+                        repo: repo.clone(),
+                        tag: release.tag_name.clone(),
+                    }
+                    .url(),
+                    markdown: format!("# {owner}/{repo}\n{}", render_release(release)),
+                })
+                .collect();
+            Ok(Fetched {
+                main: Document {
+                    url: page.url(),
+                    markdown: render_releases(owner, repo, &releases),
+                },
+                releases: documents,
+            })
+        }
+        GithubPage::Release { owner, repo, tag } => {
+            // The tag is a single path segment to the API
+            let tag = tag.replace('/', "%2F");
+            let release: Release = api
+                .get_json(&format!("/repos/{owner}/{repo}/releases/tags/{tag}"))
+                .await?;
+            Ok(Fetched {
+                main: Document {
+                    url: page.url(),
+                    markdown: format!("# {owner}/{repo}\n{}", render_release(&release)),
+                },
+                releases: Vec::new(),
+            })
+        }
+        GithubPage::Tags {
+            owner,
+            repo,
+            page: page_number,
+        } => {
+            let tags: Vec<Tag> = api
+                .get_json(&format!(
+                    "/repos/{owner}/{repo}/tags?per_page={per_page}&page={page_number}"
+                ))
+                .await?;
+            Ok(Fetched {
+                main: Document {
+                    url: page.url(),
+                    markdown: render_tags(owner, repo, &tags),
+                },
+                releases: Vec::new(),
+            })
+        }
+        GithubPage::Issue {
+            owner,
+            repo,
+            number,
+            ..
+        } => Ok(Fetched {
+            main: Document {
+                url: page.url(),
+                markdown: fetch_issue(api, &page.url(), owner, repo, *number).await?,
+            },
+            releases: Vec::new(),
+        }),
+        GithubPage::Discussion {
+            owner,
+            repo,
+            number,
+        } => Ok(Fetched {
+            main: Document {
+                url: page.url(),
+                markdown: fetch_discussion(api, &page.url(), owner, repo, *number).await?,
+            },
+            releases: Vec::new(),
+        }),
+    }
+}
+
+/// Tag of the latest published release of `owner/repo`, skipping drafts and
+/// pre-releases.
+pub async fn latest_release(api: &Api<'_>, owner: &str, repo: &str) -> Result<String, Error> {
+    let release: Release = api
+        .get_json(&format!("/repos/{owner}/{repo}/releases/latest"))
+        .await?;
+    Ok(release.tag_name)
+}
+
+async fn fetch_issue(
+    api: &Api<'_>,
+    url: &str,
+    owner: &str,
+    repo: &str,
+    number: u64,
+) -> Result<String, Error> {
+    // The issues endpoint serves pull requests too
+    let issue: Issue = api
+        .get_json(&format!("/repos/{owner}/{repo}/issues/{number}"))
+        .await?;
+    let comments: Vec<IssueComment> = fetch_comments(
+        api,
+        &format!("/repos/{owner}/{repo}/issues/{number}/comments"),
+        Some(issue.comments),
+    )
+    .await?;
+    // Comments on the diff are only served by the pulls endpoint
+    let review_comments: Vec<ReviewComment> = if issue.pull_request.is_some() {
+        fetch_comments(
+            api,
+            &format!("/repos/{owner}/{repo}/pulls/{number}/comments"),
+            None,
+        )
+        .await?
+    } else {
+        Vec::new()
+    };
+    Ok(render_issue(
+        url,
+        number,
+        &issue,
+        &comments,
+        &review_comments,
+    ))
+}
+
+/// Fetches the comments at `path` a page of 100 at a time, up to
+/// [`MAX_COMMENT_PAGES`], until `expected` have arrived or a page is short.
+async fn fetch_comments<T: serde::de::DeserializeOwned>(
+    api: &Api<'_>,
+    path: &str,
+    expected: Option<u64>,
+) -> Result<Vec<T>, Error> {
+    let mut comments: Vec<T> = Vec::new();
+    for comment_page in 1..=MAX_COMMENT_PAGES {
+        if expected.is_some_and(|expected| comments.len() as u64 >= expected) {
+            break;
+        }
+        let batch: Vec<T> = api
+            .get_json(&format!("{path}?per_page=100&page={comment_page}"))
+            .await?;
+        let done = batch.len() < 100;
+        comments.extend(batch);
+        if done {
+            break;
+        }
+    }
+    Ok(comments)
+}
+
+async fn fetch_discussion(
+    api: &Api<'_>,
+    url: &str,
+    owner: &str,
+    repo: &str,
+    number: u64,
+) -> Result<String, Error> {
+    let data: DiscussionData = api
+        .post_graphql(
+            DISCUSSION_QUERY,
+            serde_json::json!({ "owner": owner, "repo": repo, "number": number }),
+        )
+        .await?;
+    let discussion = data
+        .repository
+        .and_then(|r| r.discussion)
+        .ok_or("discussion not found")?;
+    Ok(render_discussion(url, number, &discussion))
+}
+
+fn render_releases(owner: &str, repo: &str, releases: &[Release]) -> String {
+    let mut output = format!("# Releases: {owner}/{repo}\n");
+    for release in releases.iter().filter(|r| !r.draft) {
+        output.push_str(&render_release(release));
+    }
+    output
+}
+
+/// Renders one release as a `##` section, preceded by a blank line.
+fn render_release(release: &Release) -> String {
+    let mut output = format!("\n## {}", release.tag_name);
+    if let Some(name) = release.name.as_deref().map(str::trim)
+        && !name.is_empty()
+        && name != release.tag_name
+    {
+        write!(output, " — {name}").unwrap();
+    }
+    if let Some(date) = &release.published_at {
+        write!(output, " ({})", date.get(..10).unwrap_or(date)).unwrap();
+    }
+    if release.prerelease {
+        output.push_str(" [pre-release]");
+    }
+    output.push('\n');
+
+    if let Some(body) = release.body.as_deref().map(str::trim)
+        && !body.is_empty()
+    {
+        writeln!(output, "\n{}", demote_headings(body, 2)).unwrap();
+    }
+    output
+}
+
+fn render_tags(owner: &str, repo: &str, tags: &[Tag]) -> String {
+    let mut output = format!("# Tags: {owner}/{repo}\n\n");
+    for tag in tags {
+        let short_sha = tag.commit.sha.get(..7).unwrap_or(&tag.commit.sha);
+        writeln!(output, "- {} ({short_sha})", tag.name).unwrap();
+    }
+    output
+}
+
+fn login(user: Option<&User>) -> &str {
+    user.map_or("ghost", |u| u.login.as_str())
+}
+
+fn date(timestamp: &str) -> &str {
+    timestamp.get(..10).unwrap_or(timestamp)
+}
+
+fn render_issue(
+    url: &str,
+    number: u64,
+    issue: &Issue,
+    comments: &[IssueComment],
+    review_comments: &[ReviewComment],
+) -> String {
+    let mut output = format!("# {} (#{number})\n\n", issue.title.trim());
+    writeln!(
+        output,
+        "**{}** · opened by @{} on {} · {url}",
+        issue.state,
+        login(issue.user.as_ref()),
+        date(&issue.created_at)
+    )
+    .unwrap();
+    if let Some(body) = issue.body.as_deref().map(str::trim)
+        && !body.is_empty()
+    {
+        writeln!(output, "\n{}", demote_headings(body, 2)).unwrap();
+    }
+
+    if !comments.is_empty() {
+        writeln!(output, "\n## Comments ({})", comments.len()).unwrap();
+    }
+    for comment in comments {
+        writeln!(
+            output,
+            "\n### @{} — {}",
+            login(comment.user.as_ref()),
+            date(&comment.created_at)
+        )
+        .unwrap();
+        if let Some(body) = comment.body.as_deref().map(str::trim)
+            && !body.is_empty()
+        {
+            writeln!(output, "\n{}", demote_headings(body, 3)).unwrap();
+        }
+    }
+
+    if !review_comments.is_empty() {
+        writeln!(output, "\n## Review comments ({})", review_comments.len()).unwrap();
+    }
+    for comment in review_comments {
+        let location = match comment.line {
+            Some(line) => format!("{}:{line}", comment.path),
+            None => comment.path.clone(),
+        };
+        writeln!(
+            output,
+            "\n### @{} on `{location}` — {}",
+            login(comment.user.as_ref()),
+            date(&comment.created_at)
+        )
+        .unwrap();
+        if let Some(body) = comment.body.as_deref().map(str::trim)
+            && !body.is_empty()
+        {
+            writeln!(output, "\n{}", demote_headings(body, 3)).unwrap();
+        }
+    }
+    output
+}
+
+fn render_discussion(url: &str, number: u64, discussion: &Discussion) -> String {
+    let mut output = format!("# {} (#{number})\n\n", discussion.title.trim());
+    writeln!(
+        output,
+        "**discussion** · opened by @{} on {} · {url}",
+        login(discussion.author.as_ref()),
+        date(&discussion.created_at)
+    )
+    .unwrap();
+    let body = discussion.body.trim();
+    if !body.is_empty() {
+        writeln!(output, "\n{}", demote_headings(body, 2)).unwrap();
+    }
+
+    let comments = &discussion.comments.nodes;
+    if !comments.is_empty() {
+        writeln!(output, "\n## Comments ({})", comments.len()).unwrap();
+    }
+    let answer_id = discussion.answer.as_ref().map(|a| a.id.as_str());
+    for comment in comments {
+        let answer = if answer_id == Some(comment.id.as_str()) {
+            " (accepted answer)"
+        } else {
+            ""
+        };
+        writeln!(
+            output,
+            "\n### @{} — {}{answer}",
+            login(comment.author.as_ref()),
+            date(&comment.created_at)
+        )
+        .unwrap();
+        let body = comment.body.trim();
+        if !body.is_empty() {
+            writeln!(output, "\n{}", demote_headings(body, 4)).unwrap();
+        }
+        for reply in &comment.replies.nodes {
+            writeln!(
+                output,
+                "\n#### Reply by @{} — {}",
+                login(reply.author.as_ref()),
+                date(&reply.created_at)
+            )
+            .unwrap();
+            let body = reply.body.trim();
+            if !body.is_empty() {
+                writeln!(output, "\n{}", demote_headings(body, 4)).unwrap();
+            }
+        }
+    }
+    output
+}
+
+/// Pushes user-authored headings down `by` levels so they nest under ours.
+fn demote_headings(body: &str, by: usize) -> String {
+    body.lines()
+        .map(|line| {
+            if line.starts_with('#') && line.len() < 200 {
+                let hashes = line.chars().take_while(|&c| c == '#').count();
+                if hashes < 6 && line[hashes..].starts_with(' ') {
+                    return format!("{}{}", "#".repeat((hashes + by).min(6)), &line[hashes..]);
+                }
+            }
+            line.to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_github_pages() {
+        assert_eq!(
+            GithubPage::parse("https://github.com/tokio-rs/tokio/releases"),
+            Some(GithubPage::Releases {
+                owner: "tokio-rs".to_string(),
+                repo: "tokio".to_string(),
+                page: 1
+            })
+        );
+        assert_eq!(
+            GithubPage::parse("https://github.com/tokio-rs/tokio/releases/?page=3"),
+            Some(GithubPage::Releases {
+                owner: "tokio-rs".to_string(),
+                repo: "tokio".to_string(),
+                page: 3
+            })
+        );
+        assert_eq!(
+            GithubPage::parse("https://github.com/tokio-rs/tokio/releases/tag/tokio-1.47.1"),
+            Some(GithubPage::Release {
+                owner: "tokio-rs".to_string(),
+                repo: "tokio".to_string(),
+                tag: "tokio-1.47.1".to_string()
+            })
+        );
+        assert_eq!(
+            GithubPage::parse("https://github.com/o/r/releases/tag/release/v1.0"),
+            Some(GithubPage::Release {
+                owner: "o".to_string(),
+                repo: "r".to_string(),
+                tag: "release/v1.0".to_string()
+            })
+        );
+        assert_eq!(
+            GithubPage::parse("https://github.com/o/r/releases/tag"),
+            None
+        );
+        assert_eq!(
+            GithubPage::parse("https://github.com/tokio-rs/tokio/tags?page=0"),
+            Some(GithubPage::Tags {
+                owner: "tokio-rs".to_string(),
+                repo: "tokio".to_string(),
+                page: 1
+            })
+        );
+        assert_eq!(GithubPage::parse("https://github.com/tokio-rs/tokio"), None);
+        assert_eq!(
+            GithubPage::parse("https://github.com/tokio-rs/tokio/blob/main/README.md"),
+            None
+        );
+        assert_eq!(
+            GithubPage::parse("https://gitlab.com/tokio-rs/tokio/releases"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_threads() {
+        assert_eq!(
+            GithubPage::parse("https://github.com/o/r/issues/42"),
+            Some(GithubPage::Issue {
+                owner: "o".to_string(),
+                repo: "r".to_string(),
+                number: 42,
+                pull: false
+            })
+        );
+        assert_eq!(
+            GithubPage::parse("https://github.com/o/r/pull/7#issuecomment-1"),
+            Some(GithubPage::Issue {
+                owner: "o".to_string(),
+                repo: "r".to_string(),
+                number: 7,
+                pull: true
+            })
+        );
+        assert_eq!(
+            GithubPage::parse("https://github.com/o/r/discussions/3"),
+            Some(GithubPage::Discussion {
+                owner: "o".to_string(),
+                repo: "r".to_string(),
+                number: 3
+            })
+        );
+        assert_eq!(GithubPage::parse("https://github.com/o/r/issues"), None);
+        assert_eq!(GithubPage::parse("https://github.com/o/r/issues/new"), None);
+        assert_eq!(
+            GithubPage::parse("https://github.com/o/r/pull/7/files"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_render_issue() {
+        let issue = Issue {
+            title: "Crash on startup ".to_string(),
+            body: Some("# Steps\nRun it".to_string()),
+            state: "open".to_string(),
+            user: Some(User {
+                login: "alice".to_string(),
+            }),
+            created_at: "2024-05-01T12:00:00Z".to_string(),
+            comments: 1,
+            pull_request: None,
+        };
+        let comments = vec![IssueComment {
+            body: Some("Workaround: set `FOO=1`".to_string()),
+            user: None,
+            created_at: "2024-05-02T08:00:00Z".to_string(),
+        }];
+        assert_eq!(
+            render_issue("https://github.com/o/r/issues/1", 1, &issue, &comments, &[]),
+            "# Crash on startup (#1)\n\n**open** · opened by @alice on 2024-05-01 · https://github.com/o/r/issues/1\n\n### Steps\nRun it\n\n## Comments (1)\n\n### @ghost — 2024-05-02\n\nWorkaround: set `FOO=1`\n"
+        );
+    }
+
+    #[test]
+    fn test_render_discussion_marks_answer() {
+        let discussion: Discussion = serde_json::from_value(serde_json::json!({
+            "title": "How do I X?",
+            "body": "Question",
+            "createdAt": "2024-01-01T00:00:00Z",
+            "author": { "login": "bob" },
+            "answer": { "id": "c2" },
+            "comments": { "nodes": [
+                { "id": "c1", "body": "Maybe Y", "createdAt": "2024-01-02T00:00:00Z",
+                  "author": null, "replies": { "nodes": [] } },
+                { "id": "c2", "body": "Do Z", "createdAt": "2024-01-03T00:00:00Z",
+                  "author": { "login": "carol" },
+                  "replies": { "nodes": [
+                    { "body": "Thanks!", "createdAt": "2024-01-04T00:00:00Z",
+                      "author": { "login": "bob" } }
+                  ] } }
+            ] }
+        }))
+        .unwrap();
+        let md = render_discussion("https://github.com/o/r/discussions/3", 3, &discussion);
+        assert!(md.contains("### @carol — 2024-01-03 (accepted answer)"));
+        assert!(md.contains("### @ghost — 2024-01-02\n"));
+        assert!(md.contains("#### Reply by @bob — 2024-01-04\n\nThanks!"));
+    }
+
+    #[test]
+    fn test_page_url_round_trip() {
+        for url in [
+            "https://github.com/o/r/releases",
+            "https://github.com/o/r/releases?page=2",
+            "https://github.com/o/r/releases/tag/v1.0.0",
+            "https://github.com/o/r/releases/tag/release/v1.0",
+            "https://github.com/o/r/tags",
+            "https://github.com/o/r/issues/1",
+            "https://github.com/o/r/pull/2",
+            "https://github.com/o/r/discussions/3",
+        ] {
+            assert_eq!(GithubPage::parse(url).unwrap().url(), url);
+        }
+    }
+
+    #[test]
+    fn test_render_releases() {
+        let releases = vec![
+            Release {
+                tag_name: "v1.1.0".to_string(),
+                name: Some("Spring release".to_string()),
+                body: Some("## Fixes\n- bug".to_string()),
+                published_at: Some("2024-03-01T10:00:00Z".to_string()),
+                prerelease: false,
+                draft: false,
+            },
+            Release {
+                tag_name: "v1.1.0-rc.1".to_string(),
+                name: Some("v1.1.0-rc.1".to_string()),
+                body: None,
+                published_at: None,
+                prerelease: true,
+                draft: false,
+            },
+            Release {
+                tag_name: "v2.0.0".to_string(),
+                name: None,
+                body: None,
+                published_at: None,
+                prerelease: false,
+                draft: true,
+            },
+        ];
+        let md = render_releases("o", "r", &releases);
+        assert_eq!(
+            md,
+            "# Releases: o/r\n\n## v1.1.0 — Spring release (2024-03-01)\n\n#### Fixes\n- bug\n\n## v1.1.0-rc.1 [pre-release]\n"
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_headers() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("x-ratelimit-remaining", "3".parse().unwrap());
+        headers.insert("x-ratelimit-limit", "60".parse().unwrap());
+        headers.insert("x-ratelimit-reset", "0".parse().unwrap());
+        let limit = RateLimit::from_headers(&headers).unwrap();
+        assert_eq!(
+            limit,
+            RateLimit {
+                remaining: 3,
+                limit: 60,
+                reset: Some(0)
+            }
+        );
+        assert_eq!(
+            limit.warning().unwrap(),
+            "GitHub API rate limit low: 3/60 requests remaining, resets now (set GITHUB_TOKEN for a higher limit)"
+        );
+
+        let plenty = RateLimit {
+            remaining: 4000,
+            limit: 5000,
+            reset: None,
+        };
+        assert!(plenty.warning().is_none());
+        assert!(RateLimit::from_headers(&reqwest::header::HeaderMap::new()).is_none());
+    }
+
+    #[test]
+    fn test_render_tags() {
+        let tags = vec![Tag {
+            name: "v1.0.0".to_string(),
+            commit: TagCommit {
+                sha: "0123456789abcdef".to_string(),
+            },
+        }];
+        assert_eq!(
+            render_tags("o", "r", &tags),
+            "# Tags: o/r\n\n- v1.0.0 (0123456)\n"
+        );
+    }
+
+    /// Replays each cassette in `test-fixtures/github` and snapshots the
+    /// documents fetched from it, or the error.
+    #[tokio::test]
+    async fn test_fixtures() {
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        for (name, cassette) in crate::fixtures::cassettes("github") {
+            let page = GithubPage::parse(&cassette.url)
+                .unwrap_or_else(|| panic!("{name}: {} isn't served by the API", cassette.url));
+            let replay = crate::fixtures::Replay::start(cassette.exchange).await;
+            let api = Api::new(&client, cassette.token.as_deref()).with_base(replay.base());
+            let output = match fetch(&api, &page, DEFAULT_PER_PAGE).await {
+                Ok(fetched) => std::iter::once(&fetched.main)
+                    .chain(&fetched.releases)
+                    .map(|document| format!("<!-- {} -->\n{}", document.url, document.markdown))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                Err(e) => format!("error: {e}"),
+            };
+            assert_eq!(replay.unmatched(), Vec::<String>::new(), "{name}");
+            insta::assert_snapshot!(name, output);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Gopher client (RFC 1436) for text documents and menus.
+//!
+//! A URL's path is the item type followed by the selector
+//! (`gopher://host/0/docs/readme.txt`). Text and HTML items are returned as
+//! they are; menus become Markdown link lists.
+
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Characters escaped in selectors turned into URLs: those that end a URL
+/// path or would break a Markdown link.
+const SELECTOR_ESCAPES: &percent_encoding::AsciiSet = &percent_encoding::CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'(')
+    .add(b')')
+    .add(b'[')
+    .add(b']');
+
+/// Whole-request deadline, matching the HTTP client's timeout.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Fetches a `gopher://` URL, returning the media type of the returned
+/// content and the content itself.
+pub async fn retrieve(url: &url::Url) -> Result<(String, String), String> {
+    tokio::time::timeout(TIMEOUT, retrieve_inner(url))
+        .await
+        .map_err(|_| format!("Gopher request timed out after {}s", TIMEOUT.as_secs()))?
+}
+
+async fn retrieve_inner(url: &url::Url) -> Result<(String, String), String> {
+    let host = url.host_str().ok_or("No host in Gopher URL")?;
+    let port = url.port().unwrap_or(70);
+    let path = percent_encoding::percent_decode_str(url.path())
+        .decode_utf8()
+        .map_err(|_| "Gopher selector is not valid UTF-8")?;
+    // The root menu has no item type
+    let mut chars = path.trim_start_matches('/').chars();
+    let item_type = chars.next().unwrap_or('1');
+    let selector = chars.as_str();
+
+    let media_type = match item_type {
+        '0' => "text/plain",
+        '1' => "text/markdown",
+        'h' => "text/html",
+        other => return Err(format!("Unsupported Gopher item type '{other}'")),
+    };
+
+    let mut stream = TcpStream::connect((host, port))
+        .await
+        .map_err(|e| format!("Failed to connect to {host}:{port}: {e}"))?;
+    stream
+        .write_all(format!("{selector}\r\n").as_bytes())
+        .await
+        .map_err(|e| format!("Gopher request failed: {e}"))?;
+    let mut response = Vec::new();
+    stream
+        .read_to_end(&mut response)
+        .await
+        .map_err(|e| format!("Gopher response failed: {e}"))?;
+    let text = String::from_utf8_lossy(&response);
+    let text = strip_terminator(&text);
+
+    let content = if item_type == '1' {
+        menu_to_markdown(text)
+    } else {
+        text.to_string()
+    };
+    Ok((media_type.to_string(), content))
+}
+
+/// Removes the `.` line that ends text and menu responses.
+fn strip_terminator(text: &str) -> &str {
+    text.strip_suffix(".\r\n")
+        .or_else(|| text.strip_suffix(".\n"))
+        .unwrap_or(text)
+}
+
+/// Renders a menu: info lines (`i`) as text, everything else as links.
+pub fn menu_to_markdown(menu: &str) -> String {
+    use std::fmt::Write;
+
+    let mut output = String::new();
+    for line in menu.lines() {
+        let mut chars = line.chars();
+        let Some(item_type) = chars.next() else {
+            continue;
+        };
+        let mut fields = chars.as_str().split('\t');
+        let display = fields.next().unwrap_or_default();
+        let selector = fields.next().unwrap_or_default();
+        let host = fields.next().unwrap_or_default();
+        let port = fields.next().unwrap_or("70").trim();
+
+        if item_type == 'i' || host.is_empty() {
+            output.push_str(display);
+            output.push('\n');
+            continue;
+        }
+        let target = if item_type == 'h'
+            && let Some(url) = selector.strip_prefix("URL:")
+        {
+            url.to_string()
+        } else {
+            let port = if port == "70" {
+                String::new()
+            } else {
+                format!(":{port}")
+            };
+            let selector = percent_encoding::utf8_percent_encode(selector, SELECTOR_ESCAPES);
+            format!("gopher://{host}{port}/{item_type}{selector}")
+        };
+        writeln!(output, "- [{display}]({target})").unwrap();
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_menu_to_markdown() {
+        let menu = "iWelcome to the archive\tfake\t(NULL)\t0\r\n\
+                    0About this server\t/about.txt\texample.org\t70\r\n\
+                    1Specifications\t/specs\texample.org\t7070\r\n\
+                    hProject site\tURL:https://example.org/\texample.org\t70\r\n\
+                    .\r\n";
+        insta::assert_snapshot!(menu_to_markdown(strip_terminator(menu)), @r"
+        Welcome to the archive
+        - [About this server](gopher://example.org/0/about.txt)
+        - [Specifications](gopher://example.org:7070/1/specs)
+        - [Project site](https://example.org/)
+        ");
+    }
+}
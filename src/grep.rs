@@ -0,0 +1,161 @@
+//! Regex search over the cached files, for exact lookups like error codes or
+//! function names that ranked search would bury.
+
+use crate::memory::Store;
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+/// Matches returned when the caller doesn't give a limit.
+pub const DEFAULT_MAX_MATCHES: usize = 50;
+
+/// Upper bound on `max_matches`.
+pub const MAX_MATCHES: usize = 500;
+
+/// Upper bound on the context lines before or after a match.
+pub const MAX_CONTEXT: usize = 20;
+
+/// Lines longer than this are cut, so minified pages don't flood the output.
+const MAX_LINE_CHARS: usize = 500;
+
+/// Which files to search: paths relative to the cache root matching a glob,
+/// where `*` stays within a directory and `**` crosses them.
+pub fn path_matcher(glob: &str) -> Result<GlobMatcher, globset::Error> {
+    Ok(GlobBuilder::new(glob)
+        .literal_separator(true)
+        .build()?
+        .compile_matcher())
+}
+
+/// A matching line with its context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub path: PathBuf,
+    pub source_url: Option<String>,
+    /// 1-based line number
+    pub line: usize,
+    pub text: String,
+    /// Lines just before the match, in order
+    pub before: Vec<String>,
+    /// Lines just after the match, in order
+    pub after: Vec<String>,
+}
+
+/// Result of a search: the matches, and whether there were more than the limit.
+#[derive(Debug, Default)]
+pub struct Matches {
+    pub matches: Vec<Match>,
+    pub truncated: bool,
+}
+
+fn cut(line: &str) -> String {
+    match line.char_indices().nth(MAX_LINE_CHARS) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+/// Searches the lines of the files cached in `store` under `root` (only those matching
+/// `paths`, if given) for `pattern`, with `before` and `after` lines of
+/// context, stopping after `max_matches` matches.
+pub fn grep(
+    store: &Store,
+    root: &Path,
+    pattern: &Regex,
+    paths: Option<&GlobMatcher>,
+    (before, after): (usize, usize),
+    max_matches: usize,
+) -> std::io::Result<Matches> {
+    let mut result = Matches::default();
+    for entry in store.list(root)? {
+        if let Some(paths) = paths
+            && !paths.is_match(entry.path.strip_prefix(root).unwrap_or(&entry.path))
+        {
+            continue;
+        }
+        let Ok(content) = store.read(&entry.path) else {
+            continue;
+        };
+        let lines: Vec<&str> = content.lines().collect();
+        let source_url = entry.sidecar.map(|meta| meta.url);
+        for (index, line) in lines.iter().enumerate() {
+            if !pattern.is_match(line) {
+                continue;
+            }
+            if result.matches.len() == max_matches {
+                result.truncated = true;
+                return Ok(result);
+            }
+            let lines_in = |range: std::ops::Range<usize>| -> Vec<String> {
+                lines[range].iter().map(|line| cut(line)).collect()
+            };
+            result.matches.push(Match {
+                path: entry.path.clone(),
+                source_url: source_url.clone(),
+                line: index + 1,
+                text: cut(line),
+                before: lines_in(index.saturating_sub(before)..index),
+                after: lines_in(index + 1..(index + 1 + after).min(lines.len())),
+            });
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_matcher() {
+        let matcher = path_matcher("docs.rs/*/index.md").unwrap();
+        assert!(matcher.is_match("docs.rs/tokio/index.md"));
+        assert!(!matcher.is_match("docs.rs/tokio/sync/index.md"));
+        assert!(
+            path_matcher("docs.rs/**")
+                .unwrap()
+                .is_match("docs.rs/tokio/sync/index.md")
+        );
+        assert!(path_matcher("[").is_err());
+    }
+
+    #[test]
+    fn test_grep() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("a")).unwrap();
+        std::fs::write(
+            dir.path().join("a/errors.md"),
+            "intro\nE0382 moved\nfix\n\nE0499 twice",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("b.md"), "E0382 again").unwrap();
+        let pattern = Regex::new(r"E0\d{3}").unwrap();
+
+        let result = grep(&Store::Disk, dir.path(), &pattern, None, (1, 1), 10).unwrap();
+        assert!(!result.truncated);
+        let first = &result.matches[0];
+        assert_eq!(
+            (first.line, first.text.as_str(), &first.before, &first.after),
+            (
+                2,
+                "E0382 moved",
+                &vec!["intro".to_string()],
+                &vec!["fix".to_string()]
+            )
+        );
+        assert_eq!(result.matches[1].after, Vec::<String>::new());
+        assert_eq!(result.matches.len(), 3);
+
+        let only_a = path_matcher("a/*.md").unwrap();
+        let result = grep(&Store::Disk, dir.path(), &pattern, Some(&only_a), (0, 0), 1).unwrap();
+        assert_eq!(result.matches.len(), 1);
+        assert!(result.truncated);
+    }
+
+    #[test]
+    fn test_long_lines_are_cut() {
+        let long = "x".repeat(MAX_LINE_CHARS + 10);
+        assert_eq!(cut(&long).chars().count(), MAX_LINE_CHARS + 1);
+        assert_eq!(cut("short"), "short");
+    }
+}
@@ -0,0 +1,152 @@
+//! The ids pages give their headings, kept in the converted Markdown.
+//!
+//! A page's fragment identifiers (`<h2 id="configure-the-runtime">`) are the
+//! anchors people link to, but the conversion drops attributes, and the slug
+//! of a heading's text often isn't the same id: sites number repeated
+//! headings, shorten them or name them by hand. Headings whose id differs
+//! from the slug get it back as a `{#id}` suffix, which citations link to and
+//! the table of contents leaves out.
+
+use crate::cite;
+use crate::toc;
+use std::fmt::Write;
+
+/// A heading of a page with an id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadingId {
+    pub text: String,
+    pub id: String,
+}
+
+/// The headings of `html` with an id, in order: the heading's own, or that
+/// of an anchor inside it, like `<h2><a id="setup"></a>Setup</h2>`.
+pub fn extract(html: &str) -> Vec<HeadingId> {
+    let document = dom_query::Document::from(html);
+    document
+        .select("h1, h2, h3, h4, h5, h6")
+        .iter()
+        .filter_map(|heading| {
+            let id = heading
+                .attr("id")
+                .or_else(|| heading.select("[id]").attr("id"))
+                .or_else(|| heading.select("a[name]").attr("name"))?;
+            let id = id.trim();
+            let text = heading
+                .text()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            (!id.is_empty() && !id.contains(char::is_whitespace) && !text.is_empty()).then(|| {
+                HeadingId {
+                    text,
+                    id: id.to_string(),
+                }
+            })
+        })
+        .collect()
+}
+
+/// Text for comparing a page's headings with the Markdown's: formatting,
+/// escapes, permalink signs and case don't count.
+fn key(text: &str) -> String {
+    cite::plain_text(text)
+        .replace('\\', "")
+        .trim_end_matches(['¶', '#', '§'])
+        .trim()
+        .to_lowercase()
+}
+
+/// `markdown` with the `ids` of its headings added as `{#id}` suffixes,
+/// matching headings to ids by text in order. Headings that kept a permalink
+/// or whose slug is already the id are left as they are.
+pub fn apply(markdown: &str, ids: &[HeadingId]) -> String {
+    if ids.is_empty() {
+        return markdown.to_string();
+    }
+    let mut lines: Vec<String> = markdown.lines().map(str::to_string).collect();
+    let mut cursor = 0;
+    let mut changed = false;
+    for heading in toc::extract_headings(markdown) {
+        let wanted = key(&heading.text);
+        let Some(index) = (cursor..ids.len()).find(|&index| key(&ids[index].text) == wanted) else {
+            continue;
+        };
+        cursor = index + 1;
+        let line = &mut lines[heading.line_number - 1];
+        let id = &ids[index].id;
+        if cite::explicit_id(line).is_none() && cite::slug(&cite::plain_text(&heading.text)) != *id
+        {
+            write!(line, " {{#{id}}}").unwrap();
+            changed = true;
+        }
+    }
+    if !changed {
+        return markdown.to_string();
+    }
+    let mut joined = lines.join("\n");
+    if markdown.ends_with('\n') {
+        joined.push('\n');
+    }
+    joined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract() {
+        let html = r#"<h1>Guide</h1>
+            <h2 id="setup-1"> Setup
+              the tool </h2>
+            <h3><a id="opts"></a>Options</h3>
+            <h3><a name="env">Environment</a></h3>
+            <h2 id="">Empty</h2>"#;
+        assert_eq!(
+            extract(html),
+            [
+                HeadingId {
+                    text: "Setup the tool".to_string(),
+                    id: "setup-1".to_string(),
+                },
+                HeadingId {
+                    text: "Options".to_string(),
+                    id: "opts".to_string(),
+                },
+                HeadingId {
+                    text: "Environment".to_string(),
+                    id: "env".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        let ids = |pairs: &[(&str, &str)]| -> Vec<HeadingId> {
+            pairs
+                .iter()
+                .map(|(text, id)| HeadingId {
+                    text: (*text).to_string(),
+                    id: (*id).to_string(),
+                })
+                .collect()
+        };
+        let markdown = "Guide\n==========\n\nintro\n\n## Setup `tool`\n\ntext\n\n\
+            ## Options[¶](#options)\n\n## Notes\n\n```\n## Setup tool\n```\n\n## Setup tool\n";
+        let page = ids(&[
+            ("Guide ¶", "top"),
+            ("Setup tool", "setup-1"),
+            ("Options¶", "opts"),
+            ("Notes", "notes"),
+            ("Setup tool", "setup-2"),
+        ]);
+        assert_eq!(
+            apply(markdown, &page),
+            "Guide {#top}\n==========\n\nintro\n\n## Setup `tool` {#setup-1}\n\ntext\n\n\
+             ## Options[¶](#options)\n\n## Notes\n\n```\n## Setup tool\n```\n\n\
+             ## Setup tool {#setup-2}\n"
+        );
+        assert_eq!(apply(markdown, &[]), markdown);
+    }
+}
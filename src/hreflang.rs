@@ -0,0 +1,241 @@
+//! Language versions of a page, from `hreflang` alternate links.
+//!
+//! Multilingual sites list their translations with
+//! `<link rel="alternate" hreflang="de" href="...">` in the page head or in
+//! `Link` response headers. Without a preferred language the server picks one
+//! by geo-IP or `Accept-Language`; with one, the matching version can be
+//! fetched instead.
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// One language version of a page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Alternate {
+    /// Language tag as given by the site, e.g. `de` or `pt-BR`
+    pub language: String,
+    pub url: String,
+}
+
+/// The language of a page and its listed language versions.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Languages {
+    /// From `<html lang>`, falling back to the `Content-Language` header
+    pub page: Option<String>,
+    /// In document order, then `Link` header order, one per language.
+    /// `x-default` (the language picker or fallback page) is left out.
+    pub alternates: Vec<Alternate>,
+}
+
+impl Languages {
+    /// Reads the language information of a page fetched from `page_url`.
+    /// `html` is empty for non-HTML responses.
+    pub fn discover(
+        html: &str,
+        content_language: Option<&str>,
+        link_header: Option<&str>,
+        page_url: &str,
+    ) -> Self {
+        let Ok(base) = url::Url::parse(page_url) else {
+            return Self::default();
+        };
+        let mut languages = Self::default();
+        let mut add = |language: &str, href: &str| {
+            let language = language.trim();
+            if language.is_empty()
+                || language.eq_ignore_ascii_case("x-default")
+                || languages
+                    .alternates
+                    .iter()
+                    .any(|a| a.language.eq_ignore_ascii_case(language))
+            {
+                return;
+            }
+            if let Ok(url) = base.join(href.trim())
+                && matches!(url.scheme(), "http" | "https")
+            {
+                languages.alternates.push(Alternate {
+                    language: language.to_string(),
+                    url: url.to_string(),
+                });
+            }
+        };
+
+        if !html.is_empty() {
+            let document = dom_query::Document::from(html);
+            for link in document
+                .select("link[rel~='alternate'][hreflang][href]")
+                .iter()
+            {
+                if let (Some(language), Some(href)) = (link.attr("hreflang"), link.attr("href")) {
+                    add(&language, &href);
+                }
+            }
+            languages.page = document
+                .select("html")
+                .attr("lang")
+                .map(|lang| lang.trim().to_string())
+                .filter(|lang| !lang.is_empty());
+        }
+        for (href, params) in link_header.map(parse_link_header).unwrap_or_default() {
+            let is_alternate = params.iter().any(|(name, value)| {
+                name == "rel" && value.split_whitespace().any(|rel| rel == "alternate")
+            });
+            if let Some((_, language)) = params.iter().find(|(name, _)| name == "hreflang")
+                && is_alternate
+            {
+                add(language, &href);
+            }
+        }
+
+        if languages.page.is_none() {
+            // Only a single language says which one the page is in
+            languages.page = content_language
+                .map(str::trim)
+                .filter(|lang| !lang.is_empty() && !lang.contains(','))
+                .map(str::to_string);
+        }
+        languages
+    }
+
+    /// The version to fetch instead of `page_url` to get `preferred`, if the
+    /// page isn't already in that language.
+    ///
+    /// An exact tag match wins; otherwise the primary language is enough
+    /// (`de` for `de-AT`), unless the page itself already has it.
+    pub fn preferred(&self, preferred: &str, page_url: &str) -> Option<&Alternate> {
+        let own = self
+            .alternates
+            .iter()
+            .find(|a| a.url == page_url)
+            .map(|a| a.language.as_str());
+        let current = self.page.as_deref().or(own);
+        let candidates = || self.alternates.iter().filter(|a| a.url != page_url);
+
+        if current.is_some_and(|lang| lang.eq_ignore_ascii_case(preferred)) {
+            return None;
+        }
+        if let Some(exact) = candidates().find(|a| a.language.eq_ignore_ascii_case(preferred)) {
+            return Some(exact);
+        }
+        if current.is_some_and(|lang| same_primary(lang, preferred)) {
+            return None;
+        }
+        candidates().find(|a| same_primary(&a.language, preferred))
+    }
+}
+
+/// Whether two language tags share their primary language (`de` and `de-AT`).
+pub fn same_primary(a: &str, b: &str) -> bool {
+    let primary = |tag: &str| tag.split(['-', '_']).next().unwrap_or_default().to_string();
+    primary(a).eq_ignore_ascii_case(&primary(b))
+}
+
+/// Splits a `Link` header into targets and their lowercase-named parameters:
+/// `<https://example.com/de/>; rel="alternate"; hreflang="de", <...>; ...`.
+fn parse_link_header(header: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let mut links = Vec::new();
+    let mut rest = header;
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>').map(|end| start + end) else {
+            break;
+        };
+        let target = rest[start + 1..end].to_string();
+        rest = &rest[end + 1..];
+        // Parameters run up to the next link; quoted values may contain ','
+        let mut params = Vec::new();
+        let mut remaining = rest;
+        loop {
+            let trimmed = remaining.trim_start();
+            let Some(param) = trimmed.strip_prefix(';') else {
+                break;
+            };
+            let param = param.trim_start();
+            let name_end = param.find(['=', ';', ',']).unwrap_or(param.len());
+            let name = param[..name_end].trim().to_ascii_lowercase();
+            let mut after = &param[name_end..];
+            let mut value = String::new();
+            if let Some(v) = after.strip_prefix('=') {
+                let v = v.trim_start();
+                if let Some(quoted) = v.strip_prefix('"') {
+                    let close = quoted.find('"').unwrap_or(quoted.len());
+                    value = quoted[..close].to_string();
+                    after = quoted.get(close + 1..).unwrap_or_default();
+                } else {
+                    let end = v.find([';', ',']).unwrap_or(v.len());
+                    value = v[..end].trim().to_string();
+                    after = &v[end..];
+                }
+            }
+            params.push((name, value));
+            remaining = after;
+        }
+        links.push((target, params));
+        rest = remaining;
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<html lang="en"><head>
+        <link rel="alternate" hreflang="en" href="https://example.com/en/guide">
+        <link rel="alternate" hreflang="de-DE" href="/de/guide">
+        <link rel="alternate" hreflang="pt-BR" href="https://example.com/pt-br/guide">
+        <link rel="alternate" hreflang="x-default" href="https://example.com/guide">
+        <link rel="alternate" hreflang="de-de" href="/duplicate">
+        <link rel="stylesheet" hreflang="fr" href="/style.css">
+        </head><body></body></html>"#;
+
+    #[test]
+    fn test_discover_from_html() {
+        let languages = Languages::discover(PAGE, None, None, "https://example.com/en/guide");
+        assert_eq!(languages.page.as_deref(), Some("en"));
+        let found: Vec<(&str, &str)> = languages
+            .alternates
+            .iter()
+            .map(|a| (a.language.as_str(), a.url.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("en", "https://example.com/en/guide"),
+                ("de-DE", "https://example.com/de/guide"),
+                ("pt-BR", "https://example.com/pt-br/guide"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_discover_from_link_header() {
+        let header = r#"<https://example.com/fr/>; rel="alternate"; hreflang="fr", <https://example.com/style.css>; rel=preload, <https://example.com/ja/>; hreflang=ja; rel="alternate nofollow""#;
+        let languages = Languages::discover("", Some("en"), Some(header), "https://example.com/");
+        assert_eq!(languages.page.as_deref(), Some("en"));
+        let found: Vec<&str> = languages
+            .alternates
+            .iter()
+            .map(|a| a.language.as_str())
+            .collect();
+        assert_eq!(found, ["fr", "ja"]);
+    }
+
+    #[test]
+    fn test_preferred() {
+        let url = "https://example.com/en/guide";
+        let languages = Languages::discover(PAGE, None, None, url);
+        let preferred = |lang| languages.preferred(lang, url).map(|a| a.url.as_str());
+        assert_eq!(preferred("de-DE"), Some("https://example.com/de/guide"));
+        assert_eq!(preferred("de"), Some("https://example.com/de/guide"));
+        assert_eq!(preferred("PT-br"), Some("https://example.com/pt-br/guide"));
+        assert_eq!(preferred("en"), None);
+        assert_eq!(preferred("en-US"), None);
+        assert_eq!(preferred("fr"), None);
+
+        // Without `<html lang>`, the page's own entry tells its language
+        let unlabelled = PAGE.replace(r#" lang="en""#, "");
+        let languages = Languages::discover(&unlabelled, None, None, url);
+        assert_eq!(languages.preferred("en-GB", url), None);
+    }
+}
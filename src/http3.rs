@@ -0,0 +1,160 @@
+//! HTTP/3 for hosts that advertise it.
+//!
+//! Servers announce HTTP/3 with an `Alt-Svc: h3=":443"` header on ordinary
+//! responses. Once a host has, its later requests (such as the remaining
+//! variations of a fetch) go over QUIC, saving the TCP and TLS round trips of
+//! each new connection. If the QUIC handshake fails, as it does where UDP is
+//! blocked, the host goes back to TCP for the rest of the session.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long to wait for an HTTP/3 response before falling back to TCP. QUIC
+/// packets that are silently dropped would otherwise stall until the request
+/// timeout.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// `Alt-Svc` lifetime when the header has no `ma` parameter (RFC 7838).
+const DEFAULT_MAX_AGE: Duration = Duration::from_hours(24);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Support {
+    Advertised { until: Instant },
+    Failed,
+}
+
+pub struct Http3 {
+    /// Speaks only HTTP/3
+    pub client: reqwest::Client,
+    /// Keyed by `host:port`
+    hosts: Mutex<HashMap<String, Support>>,
+}
+
+impl Http3 {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether requests to `url` should try HTTP/3 first.
+    pub fn should_try(&self, url: &url::Url) -> bool {
+        let Some(key) = host_key(url) else {
+            return false;
+        };
+        matches!(
+            self.hosts.lock().unwrap().get(&key),
+            Some(Support::Advertised { until }) if *until > Instant::now()
+        )
+    }
+
+    /// Stops trying HTTP/3 with the host of `url` for the rest of the session.
+    pub fn mark_failed(&self, url: &url::Url) {
+        if let Some(key) = host_key(url) {
+            self.hosts.lock().unwrap().insert(key, Support::Failed);
+        }
+    }
+
+    /// Records the host's `Alt-Svc` header from a response to `url`.
+    pub fn note_response(&self, url: &url::Url, headers: &reqwest::header::HeaderMap) {
+        let Some(key) = host_key(url) else {
+            return;
+        };
+        let Some(alt_svc) = headers
+            .get(reqwest::header::ALT_SVC)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return;
+        };
+        let mut hosts = self.hosts.lock().unwrap();
+        if hosts.get(&key) == Some(&Support::Failed) {
+            return;
+        }
+        match advertised_max_age(alt_svc, url.port_or_known_default().unwrap_or(443)) {
+            Some(max_age) => {
+                hosts.insert(
+                    key,
+                    Support::Advertised {
+                        until: Instant::now() + max_age,
+                    },
+                );
+            }
+            None if alt_svc.trim() == "clear" => {
+                hosts.remove(&key);
+            }
+            None => {}
+        }
+    }
+}
+
+fn host_key(url: &url::Url) -> Option<String> {
+    if url.scheme() != "https" {
+        return None;
+    }
+    Some(format!(
+        "{}:{}",
+        url.host_str()?.to_ascii_lowercase(),
+        url.port_or_known_default()?
+    ))
+}
+
+/// The lifetime of an `h3` alternative on the same host and `port`, if the
+/// `Alt-Svc` value offers one. Alternatives on other hosts are ignored.
+fn advertised_max_age(alt_svc: &str, port: u16) -> Option<Duration> {
+    alt_svc.split(',').find_map(|entry| {
+        let mut params = entry.split(';').map(str::trim);
+        let (protocol, authority) = params.next()?.split_once('=')?;
+        let authority = authority.trim_matches('"');
+        let same_port = authority
+            .strip_prefix(':')
+            .and_then(|p| p.parse::<u16>().ok())
+            == Some(port);
+        if protocol.trim() != "h3" || !same_port {
+            return None;
+        }
+        let max_age = params
+            .find_map(|param| param.strip_prefix("ma="))
+            .and_then(|seconds| seconds.trim_matches('"').parse().ok())
+            .map_or(DEFAULT_MAX_AGE, Duration::from_secs);
+        Some(max_age)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advertised_max_age() {
+        assert_eq!(
+            advertised_max_age(r#"h3=":443"; ma=3600, h3-29=":443""#, 443),
+            Some(Duration::from_hours(1))
+        );
+        assert_eq!(
+            advertised_max_age(r#"h2=":443", h3=":443""#, 443),
+            Some(DEFAULT_MAX_AGE)
+        );
+        assert_eq!(advertised_max_age(r#"h3=":8443""#, 443), None);
+        assert_eq!(advertised_max_age(r#"h3="alt.example.com:443""#, 443), None);
+        assert_eq!(advertised_max_age("clear", 443), None);
+    }
+
+    #[test]
+    fn test_host_support() {
+        let http3 = Http3::new(reqwest::Client::new());
+        let url = url::Url::parse("https://docs.example.com/guide").unwrap();
+        assert!(!http3.should_try(&url));
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(reqwest::header::ALT_SVC, r#"h3=":443""#.parse().unwrap());
+        http3.note_response(&url, &headers);
+        assert!(http3.should_try(&url));
+        assert!(!http3.should_try(&url::Url::parse("http://docs.example.com/").unwrap()));
+
+        http3.mark_failed(&url);
+        http3.note_response(&url, &headers);
+        assert!(!http3.should_try(&url));
+    }
+}
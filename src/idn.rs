@@ -0,0 +1,64 @@
+//! Internationalized domain names.
+//!
+//! URL parsing turns a host like `bücher.example` into its ASCII (punycode)
+//! form, `xn--bcher-kva.example`, which is what cache directories are named
+//! after: it's stable however the host was written and safe on any
+//! filesystem. Agents and people read URLs though, so the URLs recorded in
+//! sidecars and shown in output use the Unicode form.
+
+use std::borrow::Cow;
+use url::{Host, Position, Url};
+
+/// `url` with its host in Unicode form, normalized as URL parsing does, when
+/// the host is an internationalized domain name. Other URLs are returned as
+/// given.
+pub fn unicode_url(url: &str) -> Cow<'_, str> {
+    let Ok(parsed) = Url::parse(url) else {
+        return Cow::Borrowed(url);
+    };
+    let Some(Host::Domain(domain)) = parsed.host() else {
+        return Cow::Borrowed(url);
+    };
+    if !domain.split('.').any(|label| label.starts_with("xn--")) {
+        return Cow::Borrowed(url);
+    }
+    let (unicode, result) = idna::domain_to_unicode(domain);
+    if result.is_err() {
+        return Cow::Borrowed(url);
+    }
+    Cow::Owned(format!(
+        "{}{unicode}{}",
+        &parsed[..Position::BeforeHost],
+        &parsed[Position::AfterHost..]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unicode_url() {
+        assert_eq!(
+            unicode_url("https://xn--bcher-kva.example/docs?q=1#top"),
+            "https://bücher.example/docs?q=1#top"
+        );
+        assert_eq!(
+            unicode_url("https://BÜCHER.example:8443/docs"),
+            "https://bücher.example:8443/docs"
+        );
+        assert_eq!(
+            unicode_url("http://user@例え.テスト"),
+            "http://user@例え.テスト/"
+        );
+        // Not IDNs, or not valid punycode: unchanged
+        for url in [
+            "https://Docs.Example.com/a",
+            "http://127.0.0.1:8000/",
+            "https://xn--a.example/",
+            "not a url",
+        ] {
+            assert!(matches!(unicode_url(url), Cow::Borrowed(_)), "{url}");
+        }
+    }
+}
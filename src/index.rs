@@ -0,0 +1,390 @@
+//! Cache index stored next to the cached files.
+//!
+//! Records what the server has learned about URLs beyond their content, such as
+//! variations that recently returned 404, so later fetches can skip them, the
+//! URLs of cached files whose paths don't spell them out, the other forms
+//! of a directory's URL fetched into its file, and under `--dedup-scope`, the
+//! files holding each content and the URLs saved once into them.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// File name of the index inside the cache directory.
+pub const INDEX_FILE: &str = ".index.json";
+
+/// Where `fetch` looks for cached files with the same content as a page
+/// before saving it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupScope {
+    /// Among the variations fetched by the same call
+    #[default]
+    Call,
+    /// Also among files cached from the same host
+    Domain,
+    /// Also among all files in the same cache directory or namespace
+    Global,
+}
+
+/// A variation that returned a "not found" status.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Missing {
+    pub status: u16,
+    /// Unix timestamp (seconds) of the response
+    pub checked_at: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IndexData {
+    #[serde(default)]
+    missing: BTreeMap<String, Missing>,
+    /// Cache-relative path → URL, for files with a shortened name or moved
+    /// aside for a directory
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    urls: BTreeMap<String, String>,
+    /// Cache-relative path → the forms of a directory's URL cached there
+    /// (`/docs`, `/docs/`, `/docs/index.html`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    aliases: BTreeMap<String, BTreeSet<String>>,
+    /// SHA-256 of cached content → cache-relative path of the file holding
+    /// it, recorded under `--dedup-scope domain` or `global`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    hashes: BTreeMap<String, String>,
+}
+
+/// In-memory view of the index, persisted with [`CacheIndex::save`].
+#[derive(Debug)]
+pub struct CacheIndex {
+    path: PathBuf,
+    data: Mutex<IndexData>,
+}
+
+/// Statuses that mean the resource doesn't exist, as opposed to a transient failure.
+pub fn is_negative_status(status: u16) -> bool {
+    matches!(status, 404 | 410)
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl CacheIndex {
+    /// Loads the index from `cache_dir`, starting empty if it's missing or unreadable.
+    pub fn load(cache_dir: &Path) -> Self {
+        let path = cache_dir.join(INDEX_FILE);
+        let data = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            data: Mutex::new(data),
+        }
+    }
+
+    /// Returns the recorded miss for `url` if it's younger than `ttl`.
+    pub fn missing(&self, url: &str, ttl: Duration, now: u64) -> Option<Missing> {
+        let data = self.data.lock().unwrap();
+        data.missing
+            .get(url)
+            .copied()
+            .filter(|m| now.saturating_sub(m.checked_at) < ttl.as_secs())
+    }
+
+    pub fn record_missing(&self, url: &str, status: u16, now: u64) {
+        self.data.lock().unwrap().missing.insert(
+            url.to_string(),
+            Missing {
+                status,
+                checked_at: now,
+            },
+        );
+    }
+
+    pub fn clear_missing(&self, url: &str) {
+        self.data.lock().unwrap().missing.remove(url);
+    }
+
+    /// Records that `url` is cached at `path`, relative to the cache
+    /// directory. Returns whether it's new.
+    pub fn record_url(&self, path: &str, url: &str) -> bool {
+        let mut data = self.data.lock().unwrap();
+        if data.urls.get(path).is_some_and(|known| known == url) {
+            return false;
+        }
+        data.urls.insert(path.to_string(), url.to_string());
+        true
+    }
+
+    /// URL recorded for `path`, relative to the cache directory.
+    pub fn url_of(&self, path: &str) -> Option<String> {
+        self.data.lock().unwrap().urls.get(path).cloned()
+    }
+
+    pub fn forget_url(&self, path: &str) {
+        let mut data = self.data.lock().unwrap();
+        data.urls.remove(path);
+        data.aliases.remove(path);
+        data.hashes.retain(|_, known| known != path);
+    }
+
+    /// Records that `urls` are all cached at `path`, relative to the cache
+    /// directory. Returns whether any is new.
+    pub fn record_aliases(&self, path: &str, urls: &[&str]) -> bool {
+        let mut data = self.data.lock().unwrap();
+        let known = data.aliases.entry(path.to_string()).or_default();
+        let mut added = false;
+        for url in urls {
+            added |= known.insert((*url).to_string());
+        }
+        added
+    }
+
+    /// Removes `url` from the aliases of files other than `path`, once it's
+    /// cached there. Returns whether the index changed.
+    pub fn forget_alias(&self, url: &str, path: &str) -> bool {
+        let mut data = self.data.lock().unwrap();
+        let mut removed = false;
+        data.aliases.retain(|known, urls| {
+            if known != path {
+                removed |= urls.remove(url);
+            }
+            !urls.is_empty()
+        });
+        removed
+    }
+
+    /// Paths, relative to the cache directory, of the files `url` is
+    /// recorded as an alias of, sorted.
+    pub fn alias_paths(&self, url: &str) -> Vec<String> {
+        let data = self.data.lock().unwrap();
+        data.aliases
+            .iter()
+            .filter(|(_, urls)| urls.contains(url))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// Records that the file at `path`, relative to the cache directory,
+    /// holds content with hash `sha256`, replacing the hash it held before.
+    /// Returns whether the index changed.
+    pub fn record_hash(&self, sha256: &str, path: &str) -> bool {
+        let mut data = self.data.lock().unwrap();
+        if data.hashes.get(sha256).is_some_and(|known| known == path) {
+            return false;
+        }
+        data.hashes.retain(|_, known| known != path);
+        data.hashes.insert(sha256.to_string(), path.to_string());
+        true
+    }
+
+    /// Path, relative to the cache directory, of the file recorded as holding
+    /// content with hash `sha256`.
+    pub fn path_with_hash(&self, sha256: &str) -> Option<String> {
+        self.data.lock().unwrap().hashes.get(sha256).cloned()
+    }
+
+    /// URLs recorded as cached at `path`, sorted.
+    pub fn aliases(&self, path: &str) -> Vec<String> {
+        let data = self.data.lock().unwrap();
+        data.aliases
+            .get(path)
+            .map(|urls| urls.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Drops misses older than `ttl` so the index doesn't grow without bound.
+    pub fn prune_missing(&self, ttl: Duration, now: u64) {
+        self.data
+            .lock()
+            .unwrap()
+            .missing
+            .retain(|_, m| now.saturating_sub(m.checked_at) < ttl.as_secs());
+    }
+
+    /// Drops what's recorded about files that are gone: those whose path,
+    /// relative to the cache directory, `exists` is false for. Returns their
+    /// paths, sorted.
+    pub fn prune_paths(&self, exists: impl Fn(&str) -> bool) -> Vec<String> {
+        let mut data = self.data.lock().unwrap();
+        let mut gone = BTreeSet::new();
+        let mut keep = |path: &String| {
+            let kept = exists(path);
+            if !kept {
+                gone.insert(path.clone());
+            }
+            kept
+        };
+        data.urls.retain(|path, _| keep(path));
+        data.aliases.retain(|path, _| keep(path));
+        data.hashes.retain(|_, path| keep(path));
+        gone.into_iter().collect()
+    }
+
+    /// Writes the index atomically (temp file + rename).
+    pub async fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(&*self.data.lock().unwrap())?;
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let temp_path = crate::sidecar::temp_path(&self.path);
+        tokio::fs::write(&temp_path, json).await?;
+        tokio::fs::rename(&temp_path, &self.path).await
+    }
+
+    /// [`CacheIndex::save`], for callers outside the runtime.
+    pub fn save_blocking(&self) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(&*self.data.lock().unwrap())?;
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let temp_path = crate::sidecar::temp_path(&self.path);
+        std::fs::write(&temp_path, json)?;
+        std::fs::rename(&temp_path, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_mins(1);
+
+    #[test]
+    fn test_missing_expires_after_ttl() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = CacheIndex::load(dir.path());
+        index.record_missing("https://example.com/llms.txt", 404, 1000);
+
+        let hit = index.missing("https://example.com/llms.txt", TTL, 1030);
+        assert_eq!(hit.map(|m| m.status), Some(404));
+        assert!(
+            index
+                .missing("https://example.com/llms.txt", TTL, 1060)
+                .is_none()
+        );
+        assert!(
+            index
+                .missing("https://example.com/index.md", TTL, 1030)
+                .is_none()
+        );
+
+        index.prune_missing(TTL, 1060);
+        assert!(index.data.lock().unwrap().missing.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_save_and_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = CacheIndex::load(dir.path());
+        index.record_missing("https://example.com/llms.txt", 410, 1000);
+        index.record_missing("https://example.com/page.md", 404, 1000);
+        index.clear_missing("https://example.com/page.md");
+        index.save().await.unwrap();
+
+        let reloaded = CacheIndex::load(dir.path());
+        assert_eq!(
+            reloaded.missing("https://example.com/llms.txt", TTL, 1001),
+            Some(Missing {
+                status: 410,
+                checked_at: 1000
+            })
+        );
+        assert!(
+            reloaded
+                .missing("https://example.com/page.md", TTL, 1001)
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_record_url() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = CacheIndex::load(dir.path());
+        let path = "example.com/long~0123456789abcdef.md";
+        assert!(index.record_url(path, "https://example.com/long.md"));
+        assert!(!index.record_url(path, "https://example.com/long.md"));
+        index.save().await.unwrap();
+
+        let reloaded = CacheIndex::load(dir.path());
+        assert_eq!(
+            reloaded.url_of(path).as_deref(),
+            Some("https://example.com/long.md")
+        );
+        reloaded.forget_url(path);
+        assert_eq!(reloaded.url_of(path), None);
+    }
+
+    #[tokio::test]
+    async fn test_record_aliases() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = CacheIndex::load(dir.path());
+        let path = "example.com/docs/index";
+        assert!(index.record_aliases(
+            path,
+            &["https://example.com/docs/", "https://example.com/docs"]
+        ));
+        assert!(!index.record_aliases(path, &["https://example.com/docs"]));
+        assert!(index.record_aliases(path, &["https://example.com/docs/index.html"]));
+        index.save().await.unwrap();
+
+        let reloaded = CacheIndex::load(dir.path());
+        assert_eq!(
+            reloaded.aliases(path),
+            [
+                "https://example.com/docs",
+                "https://example.com/docs/",
+                "https://example.com/docs/index.html"
+            ]
+        );
+        reloaded.forget_url(path);
+        assert!(reloaded.aliases(path).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_record_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = CacheIndex::load(dir.path());
+        let path = "docs.example.com/guide.md";
+        assert!(index.record_hash("aaa", path));
+        assert!(!index.record_hash("aaa", path));
+        assert!(index.record_aliases(path, &["https://mirror.example.net/guide.md"]));
+        index.save().await.unwrap();
+
+        let reloaded = CacheIndex::load(dir.path());
+        assert_eq!(reloaded.path_with_hash("aaa").as_deref(), Some(path));
+        assert_eq!(
+            reloaded.alias_paths("https://mirror.example.net/guide.md"),
+            [path]
+        );
+        // New content replaces the old hash
+        assert!(reloaded.record_hash("bbb", path));
+        assert_eq!(reloaded.path_with_hash("aaa"), None);
+
+        assert!(!reloaded.forget_alias("https://mirror.example.net/guide.md", path));
+        assert!(reloaded.forget_alias(
+            "https://mirror.example.net/guide.md",
+            "mirror.example.net/guide.md"
+        ));
+        assert!(
+            reloaded
+                .alias_paths("https://mirror.example.net/guide.md")
+                .is_empty()
+        );
+        reloaded.forget_url(path);
+        assert_eq!(reloaded.path_with_hash("bbb"), None);
+    }
+
+    #[test]
+    fn test_negative_statuses() {
+        assert!(is_negative_status(404));
+        assert!(is_negative_status(410));
+        assert!(!is_negative_status(429));
+        assert!(!is_negative_status(503));
+    }
+}
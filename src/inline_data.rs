@@ -0,0 +1,129 @@
+//! Large inline scripts and state blobs, cut from pages before conversion.
+//!
+//! Server-rendered apps embed their state in the page, such as Next.js's
+//! `<script id="__NEXT_DATA__" type="application/json">`, often megabytes of
+//! JSON. Readability leaves scripts out, but a `selector`, `readability =
+//! false` or the fallback to the whole `<body>` converts their text as if it
+//! were content. Scripts and styles of at least [`MIN_BYTES`] are cut from the
+//! HTML first, so the Markdown stays proportional to what the page shows;
+//! smaller ones are left to the conversion as before.
+//!
+//! Elements are found by scanning for their tags rather than by parsing, as
+//! an HTML parser would end them: at the first matching end tag.
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+/// Scripts and styles with less text than this are left in.
+pub const MIN_BYTES: usize = 10 * 1024;
+
+/// Elements whose text is never page content.
+const ELEMENTS: [&str; 2] = ["script", "style"];
+
+/// `html` without its large scripts and styles.
+pub fn strip(html: &str) -> Cow<'_, str> {
+    let blobs = blobs(html);
+    if blobs.is_empty() {
+        return Cow::Borrowed(html);
+    }
+    let mut stripped = String::with_capacity(html.len() - size_of(&blobs));
+    let mut kept = 0;
+    for blob in blobs {
+        stripped.push_str(&html[kept..blob.start]);
+        kept = blob.end;
+    }
+    stripped.push_str(&html[kept..]);
+    Cow::Owned(stripped)
+}
+
+/// Bytes of `html` that [`strip`] cuts.
+pub fn size(html: &str) -> usize {
+    size_of(&blobs(html))
+}
+
+fn size_of(blobs: &[Range<usize>]) -> usize {
+    blobs.iter().map(ExactSizeIterator::len).sum()
+}
+
+/// Byte ranges of the large scripts and styles in `html`, from their start
+/// tag through their end tag, in order.
+fn blobs(html: &str) -> Vec<Range<usize>> {
+    // Lowercasing ASCII keeps byte offsets, so they index `html` too
+    let lower = html.to_ascii_lowercase();
+    let mut blobs = Vec::new();
+    let mut at = 0;
+    while let Some((start, name)) = next_start_tag(&lower, at) {
+        let Some(open_end) = lower[start..].find('>').map(|i| start + i + 1) else {
+            break;
+        };
+        let close = format!("</{name}");
+        let (text_end, end) = match lower[open_end..].find(&close) {
+            Some(i) => {
+                let text_end = open_end + i;
+                let end = lower[text_end..]
+                    .find('>')
+                    .map_or(lower.len(), |i| text_end + i + 1);
+                (text_end, end)
+            }
+            // Unclosed, it runs to the end of the page
+            None => (lower.len(), lower.len()),
+        };
+        if text_end - open_end >= MIN_BYTES {
+            blobs.push(start..end);
+        }
+        at = end;
+    }
+    blobs
+}
+
+/// The first `<script` or `<style` start tag at or after `from`, and its
+/// element name.
+fn next_start_tag(lower: &str, from: usize) -> Option<(usize, &'static str)> {
+    ELEMENTS
+        .iter()
+        .filter_map(|name| {
+            let tag = format!("<{name}");
+            let mut at = from;
+            while let Some(i) = lower[at..].find(&tag) {
+                let start = at + i;
+                let after = lower.as_bytes().get(start + tag.len());
+                // `<scripts>` or `<style-guide>` are other elements
+                if after.is_none_or(|&c| c.is_ascii_whitespace() || c == b'>' || c == b'/') {
+                    return Some((start, *name));
+                }
+                at = start + tag.len();
+            }
+            None
+        })
+        .min_by_key(|(start, _)| *start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip() {
+        let state = format!("{{\"items\": [{}]}}", "\"<style>\",".repeat(MIN_BYTES / 10));
+        let html = format!(
+            "<html><head><style>body {{ margin: 0 }}</style></head><body>\
+             <h1>Title</h1><script>track();</script>\
+             <SCRIPT id=\"__NEXT_DATA__\" type=\"application/json\">{state}</Script >\
+             <p>Text</p><scripts>kept</scripts>\
+             <style>{}</style></body></html>",
+            ".a { color: red }".repeat(MIN_BYTES / 10)
+        );
+        assert_eq!(
+            strip(&html),
+            "<html><head><style>body { margin: 0 }</style></head><body>\
+             <h1>Title</h1><script>track();</script><p>Text</p><scripts>kept</scripts>\
+             </body></html>"
+        );
+        assert_eq!(size(&html), html.len() - strip(&html).len());
+
+        let unclosed = format!("<p>Text</p><script>{state}");
+        assert_eq!(strip(&unclosed), "<p>Text</p>");
+        assert!(matches!(strip("<p>Text</p>"), Cow::Borrowed(_)));
+        assert_eq!(size("<script>small()</script>"), 0);
+    }
+}
@@ -0,0 +1,290 @@
+//! Structured data pages embed for search engines, from
+//! `<script type="application/ld+json">` blocks.
+//!
+//! Articles and software pages describe themselves with schema.org types
+//! (`Article`, `TechArticle`, `SoftwareApplication`) that name the headline,
+//! publication date, author and version more reliably than the visible page.
+//! `FAQPage` data holds the questions and answers of FAQs whose answers are
+//! often collapsed behind scripts, so they can be added to the Markdown.
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt::{self, Write};
+
+/// Types whose fields are reported, `FAQPage` last so an article on the same
+/// page describes it.
+const TYPES: [&str; 4] = ["Article", "TechArticle", "SoftwareApplication", "FAQPage"];
+
+/// What a page's structured data says about it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Metadata {
+    /// schema.org type the fields come from, e.g. `TechArticle`
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headline: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_published: Option<String>,
+    /// Author names, comma-separated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// Software version, for `SoftwareApplication`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(headline) = &self.headline {
+            write!(f, " \"{headline}\"")?;
+        }
+        if let Some(version) = &self.version {
+            write!(f, ", version {version}")?;
+        }
+        if let Some(date) = &self.date_published {
+            write!(f, ", published {date}")?;
+        }
+        if let Some(author) = &self.author {
+            write!(f, ", by {author}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A question of an `FAQPage` and its answer, as HTML.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Question {
+    pub question: String,
+    pub answer: String,
+}
+
+/// The structured data of a page.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct StructuredData {
+    pub metadata: Option<Metadata>,
+    pub faq: Vec<Question>,
+}
+
+/// Reads the JSON-LD blocks of `html`. Blocks that aren't valid JSON are
+/// skipped.
+pub fn read(html: &str) -> StructuredData {
+    let document = dom_query::Document::from(html);
+    let mut nodes = Vec::new();
+    for script in document.select("script[type='application/ld+json']").iter() {
+        if let Ok(json) = serde_json::from_str::<Value>(&script.text()) {
+            collect_nodes(json, &mut nodes);
+        }
+    }
+
+    let mut data = StructuredData::default();
+    for kind in TYPES {
+        for node in nodes.iter().filter(|node| has_type(node, kind)) {
+            let metadata = data.metadata.get_or_insert_with(|| Metadata {
+                kind: kind.to_string(),
+                ..Metadata::default()
+            });
+            let field = |key: &str| text(node.get(key));
+            metadata.headline = metadata
+                .headline
+                .take()
+                .or_else(|| field("headline"))
+                .or_else(|| field("name"));
+            metadata.date_published = metadata
+                .date_published
+                .take()
+                .or_else(|| field("datePublished"));
+            metadata.author = metadata.author.take().or_else(|| names(node.get("author")));
+            metadata.version = metadata.version.take().or_else(|| field("softwareVersion"));
+            if kind == "FAQPage" {
+                data.faq.extend(questions(node));
+            }
+        }
+    }
+    data
+}
+
+/// The questions of `faq` whose answers the Markdown converted from the page
+/// doesn't show, as a section to append, or `None` when it shows them all.
+pub fn faq_markdown(faq: &[Question], markdown: &str) -> Option<String> {
+    let visible = normalize(markdown);
+    let mut section = String::new();
+    for q in faq {
+        let answer = html2md::parse_html(&q.answer);
+        if !visible.contains(&normalize(&answer)) {
+            write!(section, "\n### {}\n\n{}\n", q.question, answer.trim()).unwrap();
+        }
+    }
+    (!section.is_empty()).then(|| format!("## Frequently asked questions\n{section}"))
+}
+
+/// Flattens top-level arrays and `@graph` lists into their nodes.
+fn collect_nodes(value: Value, nodes: &mut Vec<Value>) {
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                collect_nodes(item, nodes);
+            }
+        }
+        Value::Object(mut object) => {
+            if let Some(graph) = object.remove("@graph") {
+                collect_nodes(graph, nodes);
+            }
+            nodes.push(Value::Object(object));
+        }
+        _ => {}
+    }
+}
+
+/// Whether `node`'s `@type` (one or a list, possibly as a schema.org URL or
+/// prefixed name) is `kind`.
+fn has_type(node: &Value, kind: &str) -> bool {
+    let matches = |value: &Value| {
+        value
+            .as_str()
+            .and_then(|name| name.rsplit(['/', ':']).next())
+            .is_some_and(|name| name == kind)
+    };
+    match node.get("@type") {
+        Some(Value::Array(types)) => types.iter().any(matches),
+        Some(value) => matches(value),
+        None => false,
+    }
+}
+
+/// A string or number, trimmed, if not empty.
+fn text(value: Option<&Value>) -> Option<String> {
+    let text = match value? {
+        Value::String(text) => text.trim().to_string(),
+        Value::Number(number) => number.to_string(),
+        _ => return None,
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+/// Names of people or organizations given as names, objects with a `name`,
+/// or a list of either.
+fn names(value: Option<&Value>) -> Option<String> {
+    let names: Vec<String> = match value? {
+        Value::Array(items) => items.iter().filter_map(|item| names(Some(item))).collect(),
+        Value::Object(object) => text(object.get("name")).into_iter().collect(),
+        other => text(Some(other)).into_iter().collect(),
+    };
+    (!names.is_empty()).then(|| names.join(", "))
+}
+
+/// The questions of an `FAQPage` node that have an answer.
+fn questions(node: &Value) -> Vec<Question> {
+    let entities = match node.get("mainEntity") {
+        Some(Value::Array(items)) => items.iter().collect(),
+        Some(item) => vec![item],
+        None => Vec::new(),
+    };
+    entities
+        .into_iter()
+        .filter(|entity| has_type(entity, "Question"))
+        .filter_map(|entity| {
+            let answer = match entity.get("acceptedAnswer")? {
+                Value::Array(answers) => answers.first()?,
+                answer => answer,
+            };
+            Some(Question {
+                question: text(entity.get("name"))?,
+                answer: text(answer.get("text"))?,
+            })
+        })
+        .collect()
+}
+
+/// Lowercase words of `text` separated by single spaces, ignoring Markdown
+/// emphasis and escapes.
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| word.replace(['*', '_', '`', '\\'], "").to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read() {
+        let html = r#"<html><head>
+            <script type="application/ld+json">
+            {"@context": "https://schema.org", "@graph": [
+                {"@type": "WebSite", "name": "Example Docs"},
+                {"@type": ["TechArticle"], "headline": " Getting started ",
+                 "datePublished": "2024-05-01",
+                 "author": [{"@type": "Person", "name": "Ada"}, "Grace"]}
+            ]}
+            </script>
+            <script type="application/ld+json">{"@type": "schema:FAQPage", "mainEntity": [
+                {"@type": "Question", "name": "Is it free?",
+                 "acceptedAnswer": {"@type": "Answer", "text": "Yes, <b>always</b>."}},
+                {"@type": "Question", "name": "Unanswered?"}
+            ]}</script>
+            <script type="application/ld+json">{ not json</script>
+            </head><body></body></html>"#;
+        let data = read(html);
+        assert_eq!(
+            data.metadata,
+            Some(Metadata {
+                kind: "TechArticle".to_string(),
+                headline: Some("Getting started".to_string()),
+                date_published: Some("2024-05-01".to_string()),
+                author: Some("Ada, Grace".to_string()),
+                version: None,
+            })
+        );
+        assert_eq!(
+            data.faq,
+            [Question {
+                question: "Is it free?".to_string(),
+                answer: "Yes, <b>always</b>.".to_string(),
+            }]
+        );
+        assert_eq!(
+            data.metadata.unwrap().to_string(),
+            "TechArticle \"Getting started\", published 2024-05-01, by Ada, Grace"
+        );
+
+        let app = read(
+            r#"<script type="application/ld+json">[{"@type": "https://schema.org/SoftwareApplication",
+                "name": "Tool", "softwareVersion": 2}]</script>"#,
+        );
+        let app = app.metadata.unwrap();
+        assert_eq!(app.headline.as_deref(), Some("Tool"));
+        assert_eq!(app.version.as_deref(), Some("2"));
+
+        assert_eq!(read("<p>No data</p>"), StructuredData::default());
+    }
+
+    #[test]
+    fn test_faq_markdown() {
+        let faq = [
+            Question {
+                question: "Is it free?".to_string(),
+                answer: "Yes, <b>always</b>.".to_string(),
+            },
+            Question {
+                question: "Does it work offline?".to_string(),
+                answer: "<p>No.</p>".to_string(),
+            },
+        ];
+        // Questions shown with their answers collapsed still need the answers
+        let section = faq_markdown(
+            &faq,
+            "# FAQ\n\n**Is it free?**\n\nYes, *always*.\n\nDoes it work offline?\n",
+        )
+        .unwrap();
+        assert_eq!(
+            section,
+            "## Frequently asked questions\n\n### Does it work offline?\n\nNo.\n"
+        );
+        assert!(faq_markdown(&faq, "Yes, always. No.").is_none());
+        assert!(faq_markdown(&[], "").is_none());
+    }
+}
@@ -0,0 +1,263 @@
+//! Guessing the language a page is written in.
+//!
+//! Sites don't always say, or say wrongly, and an agent that asked for German
+//! docs may still end up with the English page. The guess goes by the script
+//! of the letters, then for Latin script by how often each language's most
+//! common words occur. Code blocks and inline code are skipped, since their
+//! keywords are English whatever the prose is. Text too short or too mixed to
+//! tell gets no guess.
+
+use crate::toc;
+use std::collections::HashMap;
+
+/// Words sampled from the start of a document; more doesn't change the guess.
+const MAX_WORDS: usize = 2000;
+
+/// Documents with fewer words of prose get no guess.
+const MIN_WORDS: usize = 20;
+
+/// Common words of the Latin-script languages told apart, by ISO 639-1 code.
+const STOPWORDS: [(&str, &[&str]); 9] = [
+    (
+        "en",
+        &[
+            "the", "and", "of", "to", "is", "that", "it", "for", "with", "this", "are", "you",
+            "be", "not", "can",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "das", "und", "ist", "nicht", "mit", "sie", "ein", "eine", "für", "auf",
+            "den", "zu", "wird",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "les", "et", "est", "des", "une", "pour", "dans", "qui", "pas", "sur", "avec",
+            "vous", "au", "ce",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "los", "las", "y", "es", "una", "para", "por", "con", "del", "se", "como",
+            "pero", "más", "está",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "o", "os", "as", "e", "é", "um", "uma", "para", "com", "não", "do", "da", "dos", "em",
+            "são",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "lo", "gli", "e", "è", "di", "che", "un", "per", "con", "non", "della", "sono",
+            "del", "si",
+        ],
+    ),
+    (
+        "nl",
+        &[
+            "de", "het", "een", "en", "van", "is", "dat", "niet", "op", "voor", "met", "zijn",
+            "je", "wordt", "ook",
+        ],
+    ),
+    (
+        "pl",
+        &[
+            "i", "w", "z", "na", "się", "nie", "jest", "do", "że", "to", "jak", "dla", "od", "są",
+            "przez",
+        ],
+    ),
+    (
+        "sv",
+        &[
+            "och", "att", "det", "är", "som", "en", "för", "på", "med", "inte", "av", "till",
+            "den", "har", "kan",
+        ],
+    ),
+];
+
+/// Writing systems, told apart by Unicode block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Script {
+    Latin,
+    Cyrillic,
+    Greek,
+    Arabic,
+    Hebrew,
+    Devanagari,
+    Thai,
+    Hangul,
+    Kana,
+    Han,
+}
+
+fn script(c: char) -> Option<Script> {
+    let script = match c {
+        c if c.is_ascii_alphabetic() => Script::Latin,
+        '\u{00C0}'..='\u{024F}' => Script::Latin,
+        '\u{0370}'..='\u{03FF}' => Script::Greek,
+        '\u{0400}'..='\u{04FF}' => Script::Cyrillic,
+        '\u{0590}'..='\u{05FF}' => Script::Hebrew,
+        '\u{0600}'..='\u{06FF}' => Script::Arabic,
+        '\u{0900}'..='\u{097F}' => Script::Devanagari,
+        '\u{0E00}'..='\u{0E7F}' => Script::Thai,
+        '\u{1100}'..='\u{11FF}' | '\u{AC00}'..='\u{D7AF}' => Script::Hangul,
+        '\u{3040}'..='\u{30FF}' => Script::Kana,
+        '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}' => Script::Han,
+        _ => return None,
+    };
+    Some(script)
+}
+
+/// The prose of `markdown`: lines outside code blocks, with inline code removed.
+fn prose(markdown: &str) -> String {
+    let in_code = toc::fenced_lines(markdown);
+    let mut text = String::new();
+    for (line, _) in markdown.lines().zip(in_code).filter(|(_, code)| !code) {
+        for (i, part) in line.split('`').enumerate() {
+            if i % 2 == 0 {
+                text.push_str(part);
+                text.push(' ');
+            }
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// The ISO 639-1 code of the language `markdown` is most likely written in,
+/// or `None` if it can't tell.
+pub fn detect(markdown: &str) -> Option<&'static str> {
+    let text = prose(markdown);
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().any(char::is_alphabetic))
+        .take(MAX_WORDS)
+        .map(str::to_lowercase)
+        .collect();
+
+    let mut counts: HashMap<Script, usize> = HashMap::new();
+    for letter in words
+        .iter()
+        .flat_map(|word| word.chars())
+        .filter_map(script)
+    {
+        *counts.entry(letter).or_default() += 1;
+    }
+    let total: usize = counts.values().sum();
+    if total == 0 {
+        return None;
+    }
+    let count = |script: Script| counts.get(&script).copied().unwrap_or_default();
+    // Japanese mixes kana with Han; Chinese has no kana
+    let cjk = count(Script::Han) + count(Script::Kana);
+    if cjk * 2 > total {
+        return Some(if count(Script::Kana) * 10 > cjk {
+            "ja"
+        } else {
+            "zh"
+        });
+    }
+    let non_latin = [
+        (Script::Cyrillic, "ru"),
+        (Script::Greek, "el"),
+        (Script::Arabic, "ar"),
+        (Script::Hebrew, "he"),
+        (Script::Devanagari, "hi"),
+        (Script::Thai, "th"),
+        (Script::Hangul, "ko"),
+    ];
+    if let Some((script, language)) = non_latin.iter().find(|(s, _)| count(*s) * 2 > total) {
+        // Ukrainian shares Cyrillic with Russian but has letters of its own
+        let ukrainian = text
+            .chars()
+            .filter(|c| matches!(c, 'ї' | 'є' | 'і'))
+            .count();
+        return Some(if *script == Script::Cyrillic && ukrainian * 100 > total {
+            "uk"
+        } else {
+            language
+        });
+    }
+
+    if words.len() < MIN_WORDS || count(Script::Latin) * 2 <= total {
+        return None;
+    }
+    let mut scores: Vec<(usize, &'static str)> = STOPWORDS
+        .iter()
+        .map(|(language, stopwords)| {
+            let hits = words
+                .iter()
+                .filter(|word| stopwords.contains(&word.as_str()))
+                .count();
+            (hits, *language)
+        })
+        .collect();
+    scores.sort_by_key(|(hits, _)| std::cmp::Reverse(*hits));
+    let (best, language) = scores[0];
+    let second = scores[1].0;
+    // Enough of the text has to be common words, clearly of one language
+    (best * 20 >= words.len() && best * 2 > second * 3).then_some(language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_latin() {
+        let cases = [
+            (
+                "en",
+                "The runtime is started for you when the main function is marked with the attribute, and it can be configured with the builder if you need to change the number of threads.",
+            ),
+            (
+                "de",
+                "Die Laufzeit wird für Sie gestartet, wenn die Hauptfunktion mit dem Attribut markiert ist, und sie kann mit dem Builder konfiguriert werden, wenn Sie die Anzahl der Threads ändern müssen.",
+            ),
+            (
+                "fr",
+                "Le runtime est démarré pour vous lorsque la fonction principale est marquée avec l'attribut, et il peut être configuré avec le builder si vous devez changer le nombre de threads.",
+            ),
+            (
+                "es",
+                "El runtime se inicia por usted cuando la función principal está marcada con el atributo, y se puede configurar con el constructor si necesita cambiar el número de hilos.",
+            ),
+        ];
+        for (expected, text) in cases {
+            assert_eq!(detect(text), Some(expected), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_detect_scripts() {
+        assert_eq!(
+            detect("ランタイムは、メイン関数に属性が付いているときに自動的に開始されます。"),
+            Some("ja")
+        );
+        assert_eq!(
+            detect("当主函数标有该属性时，运行时会自动为您启动。"),
+            Some("zh")
+        );
+        assert_eq!(
+            detect(
+                "Среда выполнения запускается автоматически, когда главная функция помечена атрибутом."
+            ),
+            Some("ru")
+        );
+    }
+
+    #[test]
+    fn test_code_and_short_text() {
+        assert_eq!(detect("Too short to tell."), None);
+        let german_with_code = "Die Laufzeit wird für Sie gestartet, wenn die Hauptfunktion mit dem Attribut markiert ist, und sie kann mit dem Builder konfiguriert werden.\n\n```rust\n// the runtime is the thing that is started for you and it is the one\nfn main() {}\n```\n";
+        assert_eq!(detect(german_with_code), Some("de"));
+    }
+}
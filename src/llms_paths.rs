@@ -0,0 +1,123 @@
+//! llms.txt files published outside the standard locations.
+//!
+//! Besides `{url}/llms.txt` and `{url}/llms-full.txt`, some sites put the file
+//! somewhere else (`/docs/llms.txt`, `/static/llms-full.txt`). Such locations
+//! come from the `llms-paths` domain policy, or from links on the fetched page
+//! whose file name looks like `llms*.txt`. Either way a file is only taken if
+//! it lies under the requested URL, as the standard variations do, so that a
+//! site-wide llms.txt linked from every page's footer doesn't replace the page
+//! that was asked for.
+
+use url::Url;
+
+/// Linked llms.txt files fetched per page; more are ignored.
+const MAX_LINKED: usize = 3;
+
+/// Whether `candidate` lies under the directory `url` names: same origin, no
+/// query, and a path within `url`'s.
+fn is_under(url: &Url, candidate: &Url) -> bool {
+    if url.query().is_some() || candidate.origin() != url.origin() {
+        return false;
+    }
+    let base = url.path().trim_end_matches('/');
+    candidate
+        .path()
+        .strip_prefix(base)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Whether the last path segment of `url` looks like an llms.txt file.
+#[allow(clippy::case_sensitive_file_extension_comparisons)]
+fn is_llms_file(url: &Url) -> bool {
+    url.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .map(str::to_lowercase)
+        .is_some_and(|name| name.starts_with("llms") && name.ends_with(".txt"))
+}
+
+/// The configured `paths` (absolute paths on `url`'s host) that lie under
+/// `url`, as URLs.
+pub fn configured(url: &str, paths: &[String]) -> Vec<String> {
+    let Ok(base) = Url::parse(url) else {
+        return Vec::new();
+    };
+    paths
+        .iter()
+        .filter_map(|path| base.join(path).ok())
+        .filter(|candidate| is_under(&base, candidate))
+        .map(String::from)
+        .collect()
+}
+
+/// Links in `html`, fetched from `url`, to llms.txt files under `url`, in
+/// document order and without duplicates.
+pub fn linked(html: &str, url: &str) -> Vec<String> {
+    let Ok(base) = Url::parse(url) else {
+        return Vec::new();
+    };
+    let document = dom_query::Document::from(html);
+    let mut found: Vec<String> = Vec::new();
+    for link in document.select("a[href], link[href]").iter() {
+        let Some(href) = link.attr("href") else {
+            continue;
+        };
+        let Ok(mut candidate) = base.join(href.trim()) else {
+            continue;
+        };
+        candidate.set_fragment(None);
+        let candidate_str = candidate.to_string();
+        if is_llms_file(&candidate)
+            && is_under(&base, &candidate)
+            && !found.contains(&candidate_str)
+        {
+            found.push(candidate_str);
+            if found.len() == MAX_LINKED {
+                break;
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_configured() {
+        let paths = [
+            "/docs/llms.txt".to_string(),
+            "/static/llms-full.txt".to_string(),
+        ];
+        assert_eq!(
+            configured("https://example.com", &paths),
+            [
+                "https://example.com/docs/llms.txt",
+                "https://example.com/static/llms-full.txt"
+            ]
+        );
+        assert_eq!(
+            configured("https://example.com/docs/", &paths),
+            ["https://example.com/docs/llms.txt"]
+        );
+        assert!(configured("https://example.com/docs/guide", &paths).is_empty());
+        assert!(configured("https://example.com/?lang=en", &paths).is_empty());
+    }
+
+    #[test]
+    fn test_linked() {
+        let html = r#"<head><link rel="alternate" type="text/plain" href="/docs/llms.txt"></head>
+            <a href="/docs/llms.txt#top">again</a>
+            <a href="llms-small.txt">small</a>
+            <a href="/llms.txt">site-wide</a>
+            <a href="https://other.com/docs/llms-full.txt">elsewhere</a>
+            <a href="/docs/readme.txt">not llms</a>"#;
+        assert_eq!(
+            linked(html, "https://example.com/docs/"),
+            [
+                "https://example.com/docs/llms.txt",
+                "https://example.com/docs/llms-small.txt"
+            ]
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! `file://` URLs for local documentation.
+//!
+//! Only files under an allowed root can be read: the cache directory, the
+//! directories passed with `--allow-path`, and the roots the MCP client
+//! advertises. Paths are canonicalized before the check, so `..` segments and
+//! symlinks can't escape a root.
+
+use std::path::{Path, PathBuf};
+
+/// Cache directory for local files, alongside the host directories.
+pub const CACHE_DIR: &str = "_file";
+
+/// Returns the local path named by `url`, or `None` if it isn't a `file:` URL.
+pub fn file_path(url: &str) -> Option<Result<PathBuf, String>> {
+    let parsed = url::Url::parse(url).ok()?;
+    if parsed.scheme() != "file" {
+        return None;
+    }
+    Some(
+        parsed
+            .to_file_path()
+            .map_err(|()| format!("{url} is not a valid local file URL")),
+    )
+}
+
+/// Converts a client root URI (always a `file://` URI) to a directory.
+pub fn root_path(uri: &str) -> Option<PathBuf> {
+    file_path(uri)?.ok()
+}
+
+/// Resolves `path` and checks it lies under one of `roots`.
+pub fn check_allowed(path: &Path, roots: &[PathBuf]) -> Result<PathBuf, String> {
+    let resolved = path
+        .canonicalize()
+        .map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+    let allowed = roots
+        .iter()
+        .filter_map(|root| root.canonicalize().ok())
+        .any(|root| resolved.starts_with(root));
+    if allowed {
+        Ok(resolved)
+    } else {
+        Err(format!(
+            "{} is outside the allowed directories; pass --allow-path to permit it",
+            path.display()
+        ))
+    }
+}
+
+/// Whether a local file should be converted as HTML or kept as Markdown, going
+/// by its extension: `(is_html, is_markdown)`.
+pub fn kind(path: &Path) -> (bool, bool) {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("html" | "htm" | "xhtml") => (true, false),
+        Some("md" | "markdown" | "mdx") => (false, true),
+        _ => (false, false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_path() {
+        assert!(file_path("https://example.com/a.md").is_none());
+        assert_eq!(
+            file_path("file:///srv/docs/guide.md").unwrap().unwrap(),
+            PathBuf::from("/srv/docs/guide.md")
+        );
+        assert!(file_path("file://remote-host/share/a.md").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_check_allowed() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = dir.path().join("docs");
+        let private = dir.path().join("private");
+        std::fs::create_dir_all(&docs).unwrap();
+        std::fs::create_dir_all(&private).unwrap();
+        std::fs::write(docs.join("guide.md"), "# Guide").unwrap();
+        std::fs::write(private.join("secret.md"), "# Secret").unwrap();
+        let roots = [docs.clone()];
+
+        assert!(check_allowed(&docs.join("guide.md"), &roots).is_ok());
+        assert!(check_allowed(&private.join("secret.md"), &roots).is_err());
+        assert!(check_allowed(&docs.join("../private/secret.md"), &roots).is_err());
+        assert!(check_allowed(&docs.join("missing.md"), &roots).is_err());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(private.join("secret.md"), docs.join("link.md")).unwrap();
+            assert!(check_allowed(&docs.join("link.md"), &roots).is_err());
+        }
+    }
+
+    #[test]
+    fn test_kind() {
+        assert_eq!(kind(Path::new("a/index.HTML")), (true, false));
+        assert_eq!(kind(Path::new("a/README.md")), (false, true));
+        assert_eq!(kind(Path::new("a/notes.txt")), (false, false));
+    }
+}
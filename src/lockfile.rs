@@ -0,0 +1,148 @@
+//! Lockfiles of cached content, for teams that commit a docs snapshot.
+//!
+//! A lockfile is TOML with a table per URL holding the SHA-256 of its cached
+//! file, the same hash sidecars record, and the `ETag` it was served with:
+//!
+//! ```toml
+//! ["https://docs.example.com/llms.txt"]
+//! sha256 = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+//! etag = '"5f2b-1a"'
+//! ```
+//!
+//! The `lock` subcommand writes one from the cache. `verify --lockfile`
+//! checks the cache against it, so a vendored cache that was edited or only
+//! partly restored is caught before agents read it, and with `--lockfile` the
+//! server checks every page it fetches, so agents across a team read the same
+//! docs.
+
+use crate::sidecar;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Default lockfile name, in the current directory.
+pub const LOCKFILE: &str = "llms-fetch.lock";
+
+/// What `fetch` does with content that differs from the lockfile.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Drift {
+    /// Save it and add a warning
+    #[default]
+    Warn,
+    /// Don't save it, keeping the locked copy in the cache
+    Fail,
+}
+
+/// Locked content of a URL.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Locked {
+    /// Hex SHA-256 of the cached file
+    pub sha256: String,
+    /// `ETag` the content was served with, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+}
+
+/// URL → locked content.
+pub type Lockfile = BTreeMap<String, Locked>;
+
+/// Reads the lockfile at `path`.
+pub fn load(path: &Path) -> Result<Lockfile, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read lockfile {}: {e}", path.display()))?;
+    toml::from_str(&text).map_err(|e| format!("Invalid lockfile {}: {e}", path.display()))
+}
+
+/// Locks every file cached under `root` that has a sidecar, with its current
+/// content's hash.
+pub fn from_cache(root: &Path) -> std::io::Result<Lockfile> {
+    let mut lockfile = Lockfile::new();
+    for entry in sidecar::list(root)? {
+        let Some(meta) = entry.sidecar else {
+            continue;
+        };
+        let locked = Locked {
+            sha256: sidecar::sha256(&std::fs::read(&entry.path)?),
+            etag: meta.http.and_then(|http| http.etag),
+        };
+        lockfile.insert(meta.url, locked);
+    }
+    Ok(lockfile)
+}
+
+/// Writes `lockfile` to `path`.
+pub fn save(path: &Path, lockfile: &Lockfile) -> Result<(), String> {
+    let text = toml::to_string(lockfile).map_err(|e| format!("Failed to encode lockfile: {e}"))?;
+    std::fs::write(path, text)
+        .map_err(|e| format!("Failed to write lockfile {}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("llms-fetch.lock");
+        std::fs::write(
+            &path,
+            "[\"https://example.com/llms.txt\"]\nsha256 = \"abc\"\n",
+        )
+        .unwrap();
+        let lockfile = load(&path).unwrap();
+        assert_eq!(
+            lockfile.get("https://example.com/llms.txt"),
+            Some(&Locked {
+                sha256: "abc".to_string(),
+                etag: None,
+            })
+        );
+
+        std::fs::write(&path, "[\"https://example.com/\"]\nhash = \"abc\"\n").unwrap();
+        assert!(load(&path).is_err());
+        assert!(load(&dir.path().join("missing.lock")).is_err());
+    }
+
+    #[test]
+    fn test_from_cache_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let site = dir.path().join("example.com");
+        std::fs::create_dir_all(&site).unwrap();
+        std::fs::write(site.join("page.md"), "# Page").unwrap();
+        let meta = sidecar::Sidecar {
+            url: "https://example.com/page.md".to_string(),
+            fetched_at: 0,
+            http: Some(sidecar::HttpHeaders {
+                etag: Some("\"v1\"".to_string()),
+                ..sidecar::HttpHeaders::default()
+            }),
+            version: None,
+            sha256: None,
+            sections: Vec::new(),
+        };
+        std::fs::write(
+            sidecar::sidecar_path(&site.join("page.md")),
+            serde_json::to_string(&meta).unwrap(),
+        )
+        .unwrap();
+        std::fs::write(site.join("no-sidecar.md"), "# Old").unwrap();
+
+        let lockfile = from_cache(dir.path()).unwrap();
+        assert_eq!(
+            lockfile,
+            Lockfile::from([(
+                "https://example.com/page.md".to_string(),
+                Locked {
+                    sha256: sidecar::sha256(b"# Page"),
+                    etag: Some("\"v1\"".to_string()),
+                },
+            )])
+        );
+        let path = dir.path().join(LOCKFILE);
+        save(&path, &lockfile).unwrap();
+        assert_eq!(load(&path).unwrap(), lockfile);
+    }
+}
@@ -1,515 +1,7510 @@
 #![warn(clippy::pedantic)]
 
+mod admonitions;
+mod audit;
+mod cassette;
+mod changelog;
+mod cite;
+mod config;
+mod cookies;
+mod crawl;
+mod data_url;
+mod details;
+mod dns;
+#[cfg(test)]
+mod fixtures;
+mod frontier;
+#[cfg(feature = "ftp")]
+mod ftp;
+#[cfg(feature = "gemini")]
+mod gemini;
+mod github;
+#[cfg(feature = "gopher")]
+mod gopher;
+mod grep;
+mod heading_ids;
+mod hreflang;
+#[cfg(feature = "http3")]
+mod http3;
+mod idn;
+mod index;
+mod inline_data;
+mod jsonld;
+mod language;
+mod llms_paths;
+mod local;
+mod lockfile;
+mod manifest;
+mod memory;
+mod migrate;
+mod mirror;
+mod normalize;
+mod page_toc;
+mod paywall;
+mod pins;
+mod policy;
+mod print_view;
+mod quota;
+mod search;
+#[cfg(feature = "search-index")]
+mod search_index;
+mod sections;
+mod sidecar;
+mod site;
+mod soft404;
+mod stats;
+mod suggest;
+mod tabs;
 mod toc;
+mod update;
+mod verify;
+mod versions;
+#[cfg(feature = "xpath")]
+mod xpath;
 
 use clap::Parser;
+use config::{Cli, Command, Settings};
 use dom_smoothie::{Config, Readability, TextMode};
+use rmcp::RoleServer;
 use rmcp::handler::server::ServerHandler;
-use rmcp::handler::server::tool::ToolRouter;
+use rmcp::handler::server::tool::{ToolCallContext, ToolRouter};
 use rmcp::handler::server::wrapper::Parameters;
 use rmcp::model::{
-    CallToolResult, Content, Implementation, ProtocolVersion, ServerCapabilities, ServerInfo,
+    AnnotateAble, CallToolRequestParam, CallToolResult, Content, Implementation,
+    InitializeRequestParam, InitializeResult, ListResourcesResult, ListToolsResult,
+    PaginatedRequestParam, ProtocolVersion, RawResource, ReadResourceRequestParam,
+    ReadResourceResult, ResourceContents, ResourceUpdatedNotificationParam, ServerCapabilities,
+    ServerInfo, SubscribeRequestParam, UnsubscribeRequestParam,
 };
-use rmcp::{ErrorData as McpError, ServiceExt, tool, tool_handler, tool_router};
+use rmcp::service::RequestContext;
+use rmcp::{ErrorData as McpError, Peer, ServiceExt, tool, tool_router};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use std::hash::{DefaultHasher, Hash, Hasher};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::hash::{DefaultHasher, Hasher};
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
 use tokio::fs;
 
-#[derive(Parser)]
-#[command(author, version, about = "MCP server for fetching and caching web documentation", long_about = None)]
-struct Cli {
-    /// Cache directory path (default: .llms-fetch-mcp)
-    #[arg(value_name = "CACHE_DIR")]
-    cache_dir: Option<PathBuf>,
-
-    /// Maximum `ToC` size in bytes
-    #[arg(long, default_value_t = toc::DEFAULT_TOC_BUDGET)]
-    toc_budget: usize,
-
-    /// Minimum document size in bytes to generate `ToC`
-    #[arg(long, default_value_t = toc::DEFAULT_TOC_THRESHOLD)]
-    toc_threshold: usize,
-}
-
 #[derive(Clone)]
 struct FetchServer {
     cache_dir: Arc<PathBuf>,
-    toc_config: toc::TocConfig,
+    index: Arc<index::CacheIndex>,
+    /// Survives config reloads, like the index
+    cookies: Arc<cookies::CookieJar>,
+    /// Swapped wholesale when the config file changes; readers take a snapshot
+    /// with [`FetchServer::live`] instead of holding the lock across awaits
+    live: Arc<RwLock<Arc<Live>>>,
+    throttle: Arc<policy::Throttle>,
+    /// Survives config reloads; cleared only by `reset_quota`
+    usage: Arc<quota::Usage>,
+    /// Survives config reloads and `reset_quota`
+    stats: Arc<stats::Stats>,
+    audit_log: Arc<audit::AuditLog>,
+    /// Bounds concurrent HTML conversions
+    conversions: Arc<tokio::sync::Semaphore>,
+    /// Cache root and URL of stale entries being refreshed in the background
+    refreshing: Arc<Mutex<HashSet<(PathBuf, String)>>>,
+    /// Survives config reloads, which only replace the configured pins
+    pins: Arc<pins::Pins>,
+    /// Watched URLs by namespace, with the client to notify when a refresh
+    /// changes them (none after it unsubscribed)
+    watches: Arc<Mutex<BTreeMap<pins::Key, Option<Peer<RoleServer>>>>>,
+    #[cfg(feature = "search-index")]
+    search_indexes: Arc<search_index::Indexes>,
+    /// Where cached files are kept under `--ephemeral`, instead of on disk
+    memory: Option<Arc<memory::MemoryCache>>,
+    /// Revision agreed on during `initialize`
+    protocol_version: Arc<OnceLock<ProtocolVersion>>,
+    /// Result of the `--update-check` at startup, once it's done
+    update: Arc<OnceLock<update::Status>>,
     #[allow(dead_code)]
     tool_router: ToolRouter<Self>,
 }
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
-struct FetchInput {
-    url: String,
+/// Settings that can be reloaded while the server runs.
+#[allow(clippy::struct_excessive_bools)]
+struct Live {
+    read_only: bool,
+    namespace: Option<String>,
+    toc_config: toc::TocConfig,
+    /// Whether converted pages' bold pseudo-headings become headings, unless
+    /// a domain's policy says otherwise
+    bold_headings: bool,
+    /// Normalization of converted pages, unless a domain's policy says
+    /// otherwise
+    normalize: normalize::Normalize,
+    preview_threshold: usize,
+    preview_lines: usize,
+    github_token: Option<Arc<str>>,
+    http: Http,
+    /// How long a fetch waits for its variations
+    fetch_timeout: Duration,
+    /// Cached pages younger than this are served without a request
+    max_age: Option<Duration>,
+    /// How long past `max_age` cached pages are still served, while they are
+    /// refreshed in the background
+    stale_while_revalidate: Option<Duration>,
+    negative_cache_ttl: Option<Duration>,
+    quota: quota::Limits,
+    audit: bool,
+    /// Paths in tool results are relative, see [`FetchServer::output_paths`]
+    relative_paths: bool,
+    /// Server path prefix → the prefix shown in tool results
+    path_maps: Vec<(PathBuf, PathBuf)>,
+    /// Directories `file://` URLs may read from, besides the cache directory
+    /// and the client's roots
+    allow_paths: Vec<PathBuf>,
+    /// Language tag whose `hreflang` version of a page is fetched instead
+    language: Option<String>,
+    /// Lowercase header names `fetch` callers may set
+    allow_headers: Vec<String>,
+    policies: policy::Policies,
+    /// Lockfile fetched content is checked against, and its path
+    lockfile: Option<(PathBuf, lockfile::Lockfile)>,
+    lockfile_drift: lockfile::Drift,
+    dedup_scope: index::DedupScope,
+    /// The settings as the `info` tool reports them
+    config: EffectiveConfig,
 }
 
-#[derive(Debug)]
-struct FileInfo {
-    path: String,
-    source_url: String,
-    content_type: String,
-    lines: usize,
-    words: usize,
-    characters: usize,
-    table_of_contents: Option<String>,
-    content: Option<String>, // Full content for small files (below ToC threshold)
-}
+impl Live {
+    fn new(
+        settings: &Settings,
+        cookies: &Arc<cookies::CookieJar>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let resolver = dns::resolver(&settings.dns_servers, settings.ip_version)?;
+        let mut http = Http::new(
+            &settings.resolve,
+            settings.host_headers.clone(),
+            settings.language.as_deref(),
+            settings.timeouts,
+            settings.redirects.clone(),
+            &resolver,
+            Arc::clone(cookies),
+        )?;
+        if let Some(cassette) = &settings.cassette {
+            cassette.prepare()?;
+            http.cassette = Some(Arc::new(cassette.clone()));
+        }
+        Ok(Self {
+            read_only: settings.read_only,
+            namespace: settings.namespace.clone(),
+            toc_config: settings.toc.clone(),
+            bold_headings: settings.bold_headings,
+            normalize: settings.normalize,
+            preview_threshold: settings.preview_threshold,
+            preview_lines: settings.preview_lines,
+            github_token: settings
+                .github_token
+                .as_deref()
+                .filter(|t| !t.trim().is_empty())
+                .map(Into::into),
+            http,
+            fetch_timeout: settings.timeouts.fetch,
+            max_age: settings.max_age.map(Duration::from_secs),
+            stale_while_revalidate: settings.stale_while_revalidate.map(Duration::from_secs),
+            negative_cache_ttl: settings
+                .negative_cache_ttl
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs),
+            quota: settings.quota,
+            audit: settings.audit,
+            relative_paths: settings.relative_paths,
+            path_maps: settings.path_maps.clone(),
+            allow_paths: settings.allow_paths.clone(),
+            language: settings.language.clone(),
+            allow_headers: settings.allow_headers.clone(),
+            policies: settings.policies.clone(),
+            lockfile: match &settings.lockfile {
+                Some(path) => Some((path.clone(), lockfile::load(path)?)),
+                None => None,
+            },
+            lockfile_drift: settings.lockfile_drift,
+            dedup_scope: settings.dedup_scope,
+            config: EffectiveConfig::new(settings),
+        })
+    }
 
-#[derive(Debug)]
-struct FetchResult {
-    url: String,
-    content: String,
-    is_html: bool,
-    is_markdown: bool,
+    /// Validates the headers a `fetch` caller passed against `allow_headers`.
+    fn call_headers(
+        &self,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<reqwest::header::HeaderMap, McpError> {
+        let mut map = reqwest::header::HeaderMap::new();
+        for (name, value) in headers {
+            if !self.allow_headers.contains(&name.to_ascii_lowercase()) {
+                return Err(McpError::invalid_params(
+                    format!(
+                        "Header {name} is not allowed; the server must permit it with --allow-header {name}"
+                    ),
+                    None,
+                ));
+            }
+            let header_name =
+                reqwest::header::HeaderName::from_bytes(name.as_bytes()).map_err(|e| {
+                    McpError::invalid_params(format!("Invalid header name {name}: {e}"), None)
+                })?;
+            let header_value = reqwest::header::HeaderValue::from_str(value).map_err(|e| {
+                McpError::invalid_params(format!("Invalid value for header {name}: {e}"), None)
+            })?;
+            map.insert(header_name, header_value);
+        }
+        Ok(map)
+    }
+
+    /// Builds the `FileInfo` for cached content: stats, `ToC`, and inline content
+    /// for small files.
+    fn file_info(
+        &self,
+        file_path: &Path,
+        source_url: &str,
+        content_type: &str,
+        content: &str,
+        stats: Stats,
+    ) -> FileInfo {
+        let Stats {
+            lines,
+            words,
+            characters,
+            ..
+        } = stats;
+
+        let toc_config = &self.toc_config;
+        let table_of_contents = toc::generate_toc(content, characters, toc_config);
+        let detected = language::detect(content).map(str::to_string);
+
+        // For small files (below ToC threshold), include full content inline;
+        // oversized files get their first lines so clients can orient
+        // themselves without reading megabytes into context
+        let truncated_preview = content.len() >= self.preview_threshold;
+        let content = if truncated_preview {
+            Some(head_lines(content, self.preview_lines).to_string())
+        } else if characters < toc_config.full_content_threshold {
+            Some(content.to_string())
+        } else {
+            None
+        };
+
+        FileInfo {
+            path: file_path.to_string_lossy().to_string(),
+            source_url: source_url.to_string(),
+            content_type: content_type.to_string(),
+            lines,
+            words,
+            characters,
+            table_of_contents,
+            content,
+            truncated_preview,
+            http: None,
+            version: versions::version_of(source_url),
+            languages: Vec::new(),
+            language: detected,
+            metadata: None,
+            inline_data_bytes: None,
+            sections: None,
+            aliases: Vec::new(),
+            duplicate_of: None,
+            storage: Storage::Disk,
+        }
+    }
 }
 
-#[derive(Debug)]
-enum FetchAttempt {
-    Success(FetchResult),
-    HttpError { url: String, status: u16 },
-    NetworkError { url: String },
+const LATEST_PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion::V_2025_06_18;
+
+/// MCP revisions the server implements, oldest first.
+const SUPPORTED_PROTOCOL_VERSIONS: [ProtocolVersion; 3] = [
+    ProtocolVersion::V_2024_11_05,
+    ProtocolVersion::V_2025_03_26,
+    LATEST_PROTOCOL_VERSION,
+];
+
+/// Answers with the client's revision when supported, otherwise with the newest
+/// one we support and leaves it to the client to disconnect if it can't use it.
+fn negotiate_protocol_version(requested: &ProtocolVersion) -> ProtocolVersion {
+    SUPPORTED_PROTOCOL_VERSIONS
+        .iter()
+        .find(|&version| version == requested)
+        .cloned()
+        .unwrap_or(LATEST_PROTOCOL_VERSION)
 }
 
-async fn fetch_url(client: &reqwest::Client, url: &str) -> FetchAttempt {
-    match client
-        .get(url)
-        .header(
-            "Accept",
-            "text/markdown, text/x-markdown, text/plain, text/html;q=0.5, */*;q=0.1",
-        )
-        .header(
-            "User-Agent",
-            "llms-fetch-mcp/0.1.7 (+https://github.com/crazytieguy/llms-fetch-mcp)",
-        )
-        .send()
-        .await
-    {
-        Ok(response) => {
-            let status = response.status().as_u16();
-            if response.status().is_success() {
-                let content_type = response
-                    .headers()
-                    .get("content-type")
-                    .and_then(|v| v.to_str().ok())
-                    .unwrap_or("");
+const USER_AGENT: &str = "llms-fetch-mcp/0.1.7 (+https://github.com/crazytieguy/llms-fetch-mcp)";
 
-                let is_html = content_type.contains("text/html");
-                let is_markdown = content_type.contains("text/markdown")
-                    || content_type.contains("text/x-markdown");
+/// The `url` of a `fetch` call: one URL, or several.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum UrlList {
+    /// One URL, or several on separate lines
+    One(String),
+    Many(Vec<String>),
+}
 
-                match response.text().await {
-                    Ok(content) => FetchAttempt::Success(FetchResult {
-                        url: url.to_string(),
-                        content,
-                        is_html,
-                        is_markdown,
-                    }),
-                    Err(_) => FetchAttempt::NetworkError {
-                        url: url.to_string(),
-                    },
-                }
+impl UrlList {
+    /// The URLs in the order given, without blanks and repeats. A `data:` URL
+    /// is never split, as its content may span lines.
+    fn urls(self) -> Vec<String> {
+        let listed = match self {
+            Self::One(text) if text.trim_start().starts_with("data:") => vec![text],
+            Self::One(text) => text.lines().map(str::to_string).collect(),
+            Self::Many(urls) => urls,
+        };
+        let mut urls: Vec<String> = Vec::new();
+        for url in listed {
+            let url = if url.trim_start().starts_with("data:") {
+                url
             } else {
-                FetchAttempt::HttpError {
-                    url: url.to_string(),
-                    status,
-                }
+                idn::unicode_url(url.trim()).into_owned()
+            };
+            if !url.trim().is_empty() && !urls.contains(&url) {
+                urls.push(url);
             }
         }
-        Err(_) => FetchAttempt::NetworkError {
-            url: url.to_string(),
-        },
+        urls
     }
 }
 
-fn get_url_variations(url: &str) -> Vec<String> {
-    let mut variations = vec![url.to_string()];
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct FetchInput {
+    /// URL to fetch. Several, as an array or one per line, are fetched
+    /// concurrently and reported in the order given
+    url: UrlList,
+    /// Cache namespace keeping this project's files apart from others sharing
+    /// the server (default: the server's configured namespace, if any)
+    #[serde(default)]
+    namespace: Option<String>,
+    /// Extra request headers for this fetch only (e.g. `Authorization`,
+    /// `X-API-Version`), sent only to the requested URL's host. Each name must
+    /// be permitted by the server's `--allow-header` list.
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    /// How to interpret the body of the requested URL, for servers that
+    /// mislabel it: `html` converts it to Markdown, `markdown` and `text`
+    /// save it as is. `auto` (the default) goes by its Content-Type, or a
+    /// local file's extension.
+    #[serde(default)]
+    content_type_hint: ContentTypeHint,
+}
 
-    let url_lower = url.to_lowercase();
-    #[allow(clippy::case_sensitive_file_extension_comparisons)]
-    if url_lower.ends_with(".md") || url_lower.ends_with(".txt") {
-        return variations;
-    }
+/// How a `fetch` caller asked the requested page's body to be interpreted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum ContentTypeHint {
+    #[default]
+    Auto,
+    Html,
+    Markdown,
+    Text,
+}
 
-    // Don't try variations for URLs with query parameters
-    if url.contains('?') {
-        return variations;
+impl ContentTypeHint {
+    /// `(is_html, is_markdown)` of a body detected as `detected`.
+    fn apply(self, detected: (bool, bool)) -> (bool, bool) {
+        match self {
+            Self::Auto => detected,
+            Self::Html => (true, false),
+            Self::Markdown => (false, true),
+            Self::Text => (false, false),
+        }
     }
+}
 
-    let base = url.trim_end_matches('/');
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct ChangelogInput {
+    /// Changelog page (e.g. a raw CHANGELOG.md) or `https://github.com/{owner}/{repo}/releases`
+    url: String,
+    /// Only entries for versions strictly newer than this are returned (e.g. "1.4.2")
+    since_version: String,
+    /// Cache namespace, as for `fetch`
+    #[serde(default)]
+    namespace: Option<String>,
+}
 
-    // Check if URL has a file extension (to avoid file/directory conflicts)
-    let has_file_extension = if let Ok(parsed) = url::Url::parse(url) {
-        let path = parsed.path();
-        path.rsplit_once('/')
-            .is_some_and(|(_, last)| last.contains('.') && !last.ends_with('.'))
-    } else {
-        false
-    };
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct PinInput {
+    /// Page to keep fresh in the cache
+    url: String,
+    /// Refresh interval in seconds (at least 60); 0 unpins the URL
+    interval_secs: u64,
+    /// Cache namespace, as for `fetch`
+    #[serde(default)]
+    namespace: Option<String>,
+}
 
-    variations.push(format!("{base}.md"));
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct WatchInput {
+    /// Page to watch for changes
+    url: String,
+    /// How often to check it, in seconds (at least 60, default 3600)
+    #[serde(default)]
+    interval_secs: Option<u64>,
+    /// Cache namespace, as for `fetch`
+    #[serde(default)]
+    namespace: Option<String>,
+}
 
-    // Only add .html.md and directory-based variations if URL doesn't have a file extension
-    // This prevents file/directory conflicts (e.g., npm.html file vs npm.html/ directory)
-    // and avoids nonsensical double extensions (e.g., page.html.html.md)
-    if !has_file_extension {
-        variations.push(format!("{base}.html.md"));
-        variations.push(format!("{base}/index.md"));
-        variations.push(format!("{base}/llms.txt"));
-        variations.push(format!("{base}/llms-full.txt"));
-    }
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct GenerateLlmsTxtInput {
+    /// Page to start crawling from; the crawl stays under its directory
+    url: String,
+    /// Most pages to fetch (default 50, at most 500)
+    #[serde(default)]
+    max_pages: Option<usize>,
+    /// Cache namespace, as for `fetch`
+    #[serde(default)]
+    namespace: Option<String>,
+}
 
-    variations
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct FetchSiteInput {
+    /// Page to start crawling from; the crawl stays under its directory
+    url: String,
+    /// Most pages to fetch (default 50, at most 500)
+    #[serde(default)]
+    max_pages: Option<usize>,
+    /// Most link hops from the start page to follow (default 3, at most 10)
+    #[serde(default)]
+    max_depth: Option<usize>,
+    /// Cache namespace, as for `fetch`
+    #[serde(default)]
+    namespace: Option<String>,
 }
 
-fn url_to_path(base_dir: &Path, url: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
-    let parsed = url::Url::parse(url)?;
-    let domain = parsed.host_str().ok_or("No host in URL")?;
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct SearchInput {
+    /// Words to find, all of which must occur; put phrases in double quotes
+    query: String,
+    /// Only search pages from this domain and its subdomains
+    #[serde(default)]
+    domain: Option<String>,
+    /// Most results to return (default 10, at most 50)
+    #[serde(default)]
+    limit: Option<usize>,
+    /// Cache namespace, as for `fetch`
+    #[serde(default)]
+    namespace: Option<String>,
+}
 
-    let mut path = base_dir.join(domain);
+/// A cached file matching a search, as returned by the `search` tool.
+#[derive(Debug, Serialize, JsonSchema)]
+struct SearchHit {
+    path: String,
+    /// Absent for files cached before metadata sidecars were written
+    source_url: Option<String>,
+    /// Relevance; higher is better, comparable only within one search
+    score: f32,
+    /// 1-based line matching the most query terms
+    line: usize,
+    /// That line, shortened around the match
+    snippet: String,
+    /// Absent when the line comes before the first heading
+    section: Option<SearchSection>,
+}
 
-    let url_path = parsed.path().trim_start_matches('/');
+/// The section of a cached file containing a search hit.
+#[derive(Debug, Serialize, JsonSchema)]
+struct SearchSection {
+    /// Enclosing headings, outermost first
+    breadcrumb: Vec<String>,
+    /// 1-based line of the section's heading
+    start_line: usize,
+    /// 1-based last line of the section, including its subsections
+    end_line: usize,
+}
 
-    // Security: Sanitize path components to prevent directory traversal
-    if !url_path.is_empty() {
-        for component in url_path.split('/') {
-            if component == ".." || component == "." {
-                return Err("Invalid path component in URL".into());
+/// Structured result of the `search` tool.
+#[derive(Debug, Serialize, JsonSchema)]
+struct SearchOutput {
+    /// `index` when ranked by the search index, `scan` when the files were read
+    engine: &'static str,
+    hits: Vec<SearchHit>,
+}
+
+impl SearchOutput {
+    fn into_result(self, structured: bool) -> CallToolResult {
+        use std::fmt::Write;
+
+        let mut text = String::new();
+        if self.hits.is_empty() {
+            text.push_str("No matches in the cache.");
+        }
+        for hit in &self.hits {
+            writeln!(text, "{}:{}", hit.path, hit.line).unwrap();
+            if let Some(url) = &hit.source_url {
+                writeln!(text, "  Source: {url}").unwrap();
             }
-            if !component.is_empty() {
-                path.push(component);
+            if let Some(section) = &hit.section {
+                writeln!(
+                    text,
+                    "  Section: {} (lines {}-{})",
+                    section.breadcrumb.join(" > "),
+                    section.start_line,
+                    section.end_line
+                )
+                .unwrap();
             }
+            writeln!(text, "  {}", hit.snippet).unwrap();
+        }
+        let mut result = CallToolResult::success(vec![Content::text(text.trim_end().to_string())]);
+        if structured {
+            result.structured_content = serde_json::to_value(&self).ok();
         }
+        result
     }
+}
 
-    // Determine if we need to add an index file
-    let needs_index = if url_path.is_empty() {
-        true
-    } else {
-        let last_segment = url_path.split('/').next_back().unwrap_or("");
-        Path::new(last_segment).extension().is_none()
-    };
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct GrepInput {
+    /// Regular expression (Rust syntax) matched against each line; prefix with
+    /// `(?i)` to ignore case
+    pattern: String,
+    /// Only search files whose path relative to the cache directory matches
+    /// this glob, e.g. `docs.rs/tokio/**`
+    #[serde(default)]
+    path_glob: Option<String>,
+    /// Lines of context before each match (default 0, at most 20)
+    #[serde(default)]
+    before: Option<usize>,
+    /// Lines of context after each match (default 0, at most 20)
+    #[serde(default)]
+    after: Option<usize>,
+    /// Stop after this many matching lines (default 50, at most 500)
+    #[serde(default)]
+    max_matches: Option<usize>,
+    /// Cache namespace, as for `fetch`
+    #[serde(default)]
+    namespace: Option<String>,
+}
 
-    if needs_index {
-        path.push("index");
-    }
+/// A matching line, as returned by the `grep` tool.
+#[derive(Debug, Serialize, JsonSchema)]
+struct GrepMatch {
+    path: String,
+    /// Absent for files cached before metadata sidecars were written
+    source_url: Option<String>,
+    /// 1-based line number
+    line: usize,
+    text: String,
+    /// Lines just before the match, ending at `line - 1`
+    before: Vec<String>,
+    /// Lines just after the match, starting at `line + 1`
+    after: Vec<String>,
+}
 
-    if let Some(query) = parsed.query() {
-        // Security: Sanitize query parameters for filesystem safety
-        let safe_query = query.replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], "_");
-        let current_ext = path.extension().and_then(|s| s.to_str()).unwrap_or("");
-        let new_ext = if current_ext.is_empty() {
-            format!("?{safe_query}")
-        } else {
-            format!("{current_ext}?{safe_query}")
-        };
-        path.set_extension(new_ext);
-    }
+/// Structured result of the `grep` tool.
+#[derive(Debug, Serialize, JsonSchema)]
+struct GrepOutput {
+    matches: Vec<GrepMatch>,
+    /// Whether the search stopped at `max_matches`
+    truncated: bool,
+}
 
-    // Security: Verify final path is within base directory
-    if !path.starts_with(base_dir) {
-        return Err("Path traversal detected".into());
+impl GrepOutput {
+    fn into_result(self, structured: bool) -> CallToolResult {
+        use std::fmt::Write;
+
+        let mut text = String::new();
+        if self.matches.is_empty() {
+            text.push_str("No matches in the cache.");
+        }
+        let with_context = self
+            .matches
+            .iter()
+            .any(|m| !m.before.is_empty() || !m.after.is_empty());
+        for (i, m) in self.matches.iter().enumerate() {
+            // Separate groups like grep does when showing context
+            if with_context && i > 0 {
+                text.push_str("--\n");
+            }
+            let first = m.line - m.before.len();
+            for (offset, line) in m.before.iter().enumerate() {
+                writeln!(text, "{}-{}-{line}", m.path, first + offset).unwrap();
+            }
+            writeln!(text, "{}:{}:{}", m.path, m.line, m.text).unwrap();
+            for (offset, line) in m.after.iter().enumerate() {
+                writeln!(text, "{}-{}-{line}", m.path, m.line + 1 + offset).unwrap();
+            }
+        }
+        if self.truncated {
+            writeln!(
+                text,
+                "\nStopped after {} matches; narrow the pattern or path_glob, or raise max_matches.",
+                self.matches.len()
+            )
+            .unwrap();
+        }
+        let mut result = CallToolResult::success(vec![Content::text(text.trim_end().to_string())]);
+        if structured {
+            result.structured_content = serde_json::to_value(&self).ok();
+        }
+        result
     }
+}
 
-    Ok(path)
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct CiteInput {
+    /// Path of a cached file, as returned by `fetch`, `search` or `grep`, or
+    /// relative to the cache directory
+    path: String,
+    /// 1-based first line of the cited passage
+    #[serde(default)]
+    start_line: Option<usize>,
+    /// 1-based last line of the cited passage
+    #[serde(default)]
+    end_line: Option<usize>,
+    /// 0-based character offset of the passage's start, instead of
+    /// `start_line` (as shown in a `ToC` with offsets)
+    #[serde(default)]
+    start_offset: Option<usize>,
+    /// 0-based character offset of the passage's end, instead of `end_line`
+    #[serde(default)]
+    end_offset: Option<usize>,
+    /// Cache namespace, as for `fetch`
+    #[serde(default)]
+    namespace: Option<String>,
 }
 
-async fn ensure_gitignore(base_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    let gitignore_path = base_dir.join(".gitignore");
+/// Structured result of the `cite` tool.
+#[derive(Debug, Serialize, JsonSchema)]
+struct CiteOutput {
+    /// URL the file was fetched from
+    source_url: String,
+    /// `source_url` pointing at the heading's anchor, if there's a heading
+    link: String,
+    /// Nearest heading at or above `start_line`
+    heading: Option<String>,
+    /// Fragment of the heading on the source page
+    anchor: Option<String>,
+    /// Markdown link to embed in an answer
+    markdown: String,
+    path: String,
+    start_line: usize,
+    end_line: usize,
+}
 
-    if !gitignore_path.exists() {
-        fs::create_dir_all(base_dir).await?;
-        fs::write(&gitignore_path, "*\n").await?;
+impl CiteOutput {
+    fn into_result(self, structured: bool) -> CallToolResult {
+        let text = format!(
+            "{}\n\nLines {}-{} of {}",
+            self.markdown, self.start_line, self.end_line, self.path
+        );
+        let mut result = CallToolResult::success(vec![Content::text(text)]);
+        if structured {
+            result.structured_content = serde_json::to_value(&self).ok();
+        }
+        result
     }
+}
 
-    Ok(())
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct ReadLinesInput {
+    /// Path of a cached file, as returned by `fetch`, `search` or `grep`, or
+    /// relative to the cache directory
+    path: String,
+    /// 1-based first line to read, such as a line number from the `ToC`
+    start_line: usize,
+    /// 1-based last line to read (default: `start_line` plus 199, at most
+    /// 500 lines in all)
+    #[serde(default)]
+    end_line: Option<usize>,
+    /// Cache namespace, as for `fetch`
+    #[serde(default)]
+    namespace: Option<String>,
 }
 
-/// Converts HTML to Markdown with fallback extraction:
-/// 1. Try Readability to extract `<main>`/`<article>` content
-/// 2. Fall back to `<body>` content if available
-/// 3. Fall back to full HTML as last resort
-fn html_to_markdown(html: &str, document_url: &str) -> Result<String, Box<dyn std::error::Error>> {
-    if html.trim().is_empty() {
-        return Err("HTML content is empty".into());
-    }
+/// Lines `read_lines` returns by default.
+const READ_LINES_DEFAULT: usize = 200;
 
-    let cfg = Config {
-        text_mode: TextMode::Raw,
-        ..Default::default()
-    };
+/// Most lines one `read_lines` call returns.
+const READ_LINES_MAX: usize = 500;
 
-    let html_to_convert = Readability::new(html, Some(document_url), Some(cfg))
-        .ok()
-        .and_then(|mut r| r.parse().ok())
-        .and_then(|article| {
-            let cleaned = article.content;
-            (!cleaned.trim().is_empty()).then(|| cleaned.to_string())
-        })
-        .or_else(|| extract_body(html))
-        .unwrap_or_else(|| html.to_string());
+/// Structured result of the `read_lines` tool.
+#[derive(Debug, Serialize, JsonSchema)]
+struct ReadLinesOutput {
+    path: String,
+    start_line: usize,
+    /// Last line returned, which is less than asked for past the end of the
+    /// file or the limit
+    end_line: usize,
+    /// Lines in the whole file
+    total_lines: usize,
+    /// The lines, without line numbers
+    content: String,
+}
 
-    let markdown = html2md::parse_html(&html_to_convert);
+impl ReadLinesOutput {
+    fn into_result(self, structured: bool) -> CallToolResult {
+        use std::fmt::Write;
 
-    if markdown.trim().is_empty() {
-        return Err("Extracted content is empty (page may have no readable content)".into());
+        let mut text = format!(
+            "Lines {}-{} of {} in {}\n\n",
+            self.start_line, self.end_line, self.total_lines, self.path
+        );
+        for (offset, line) in self.content.lines().enumerate() {
+            writeln!(text, "{}\t{line}", self.start_line + offset).unwrap();
+        }
+        if self.end_line < self.total_lines {
+            writeln!(
+                text,
+                "\nMore follows; continue with start_line {}.",
+                self.end_line + 1
+            )
+            .unwrap();
+        }
+        let mut result = CallToolResult::success(vec![Content::text(text.trim_end().to_string())]);
+        if structured {
+            result.structured_content = serde_json::to_value(&self).ok();
+        }
+        result
     }
+}
 
-    Ok(markdown)
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct FetchDependenciesInput {
+    /// `Cargo.toml`, `package.json` or `pyproject.toml` to read, absolute or
+    /// relative to the client's first root (default: each of them found at
+    /// the top of the client's roots)
+    #[serde(default)]
+    manifest: Option<String>,
+    /// Also fetch the docs of development dependencies
+    #[serde(default)]
+    include_dev: bool,
+    /// Cache namespace, as for `fetch`
+    #[serde(default)]
+    namespace: Option<String>,
 }
 
-fn extract_body(html: &str) -> Option<String> {
-    let lower = html.to_lowercase();
-    let start = lower.find("<body")?;
-    let body_start = lower[start..].find('>')? + start + 1;
-    let body_end = lower.rfind("</body>")?;
+/// What `fetch_dependencies` did for one dependency.
+#[derive(Debug, Serialize, JsonSchema)]
+struct DependencyDocs {
+    name: String,
+    /// `cargo`, `npm` or `python`
+    ecosystem: &'static str,
+    /// The version required by the manifest, when it names one
+    version: Option<String>,
+    /// The documentation fetched; absent when none was found
+    docs_url: Option<String>,
+    /// Why the docs couldn't be fetched; absent when they were cached
+    error: Option<String>,
+}
 
-    if body_end >= body_start {
-        Some(html[body_start..body_end].to_string())
-    } else {
-        None
+/// Structured result of the `fetch_dependencies` tool.
+#[derive(Debug, Serialize, JsonSchema)]
+struct FetchDependenciesOutput {
+    /// Manifests read
+    manifests: Vec<String>,
+    dependencies: Vec<DependencyDocs>,
+    warnings: Vec<String>,
+}
+
+impl FetchDependenciesOutput {
+    fn into_result(self, structured: bool) -> CallToolResult {
+        use std::fmt::Write;
+
+        let cached = self
+            .dependencies
+            .iter()
+            .filter(|d| d.error.is_none())
+            .count();
+        let mut text = format!(
+            "Cached the docs of {cached} of {} dependencies from {}\n\n",
+            self.dependencies.len(),
+            self.manifests.join(", ")
+        );
+        for dependency in &self.dependencies {
+            let version = dependency
+                .version
+                .as_deref()
+                .map(|v| format!(" {v}"))
+                .unwrap_or_default();
+            write!(
+                text,
+                "- {}{version} ({})",
+                dependency.name, dependency.ecosystem
+            )
+            .unwrap();
+            if let Some(url) = &dependency.docs_url {
+                write!(text, ": {url}").unwrap();
+            }
+            match &dependency.error {
+                Some(error) => writeln!(text, " (failed: {error})").unwrap(),
+                None => writeln!(text).unwrap(),
+            }
+        }
+        for warning in &self.warnings {
+            writeln!(text, "\nWarning: {warning}").unwrap();
+        }
+        let mut result = CallToolResult::success(vec![Content::text(text.trim_end().to_string())]);
+        if structured {
+            result.structured_content = serde_json::to_value(&self).ok();
+        }
+        result
     }
 }
 
-fn count_stats(content: &str) -> (usize, usize, usize) {
-    let lines = content.lines().count();
-    let words = content.split_whitespace().count();
-    let characters = content.chars().count();
-    (lines, words, characters)
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct VersionsInput {
+    /// A page of versioned docs, e.g. `https://docs.python.org/3.12/library/os.html`
+    url: String,
 }
 
-/// Format file infos as human-readable text for LLM consumption.
-fn format_output(files: &[FileInfo]) -> String {
-    use std::fmt::Write;
+/// Another version of a docs page, as returned by the `versions` tool.
+#[derive(Debug, Serialize, JsonSchema)]
+struct DocVersion {
+    version: String,
+    /// The same page in this version; it may not exist there when found in
+    /// the sitemap
+    url: String,
+    /// `sitemap` when the site's sitemap lists the version, `probe` when a
+    /// request for the page succeeded
+    found_in: &'static str,
+}
 
-    if files.is_empty() {
-        return String::from("No files fetched.");
-    }
+/// Structured result of the `versions` tool.
+#[derive(Debug, Serialize, JsonSchema)]
+struct VersionsOutput {
+    /// The version in the requested URL
+    current: String,
+    /// Other versions, channels (`stable`, `latest`) first, then newest first
+    versions: Vec<DocVersion>,
+    warnings: Vec<String>,
+}
 
-    let mut output = String::new();
+impl VersionsOutput {
+    fn into_result(self, structured: bool) -> CallToolResult {
+        use std::fmt::Write;
 
-    for (i, f) in files.iter().enumerate() {
-        if i > 0 {
-            writeln!(output).unwrap();
+        let mut text = format!("Current version: {}\n", self.current);
+        if self.versions.is_empty() {
+            text.push_str("No other versions found.\n");
         }
-        writeln!(output, "## {}", f.source_url).unwrap();
-        writeln!(output, "Saved to: {}", f.path).unwrap();
-        writeln!(
-            output,
-            "Type: {} ({} lines, {} words, {} chars)",
-            f.content_type, f.lines, f.words, f.characters
-        )
-        .unwrap();
-
+        for version in &self.versions {
+            writeln!(
+                text,
+                "- {}: {} (from {})",
+                version.version, version.url, version.found_in
+            )
+            .unwrap();
+        }
+        for warning in &self.warnings {
+            writeln!(text, "\nWarning: {warning}").unwrap();
+        }
+        let mut result = CallToolResult::success(vec![Content::text(text.trim_end().to_string())]);
+        if structured {
+            result.structured_content = serde_json::to_value(&self).ok();
+        }
+        result
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct ListCacheInput {
+    /// Only list files whose source URL starts with this
+    #[serde(default)]
+    url_prefix: Option<String>,
+    /// Cache namespace, as for `fetch`
+    #[serde(default)]
+    namespace: Option<String>,
+}
+
+/// A file in the cache, as listed by the `list_cache` tool.
+#[derive(Debug, Serialize, JsonSchema)]
+struct CachedFile {
+    path: String,
+    /// Absent for files cached before metadata sidecars were written
+    source_url: Option<String>,
+    bytes: u64,
+    /// Unix timestamp (seconds) of the fetch
+    fetched_at: Option<u64>,
+    /// Selected headers of the response the file was saved from
+    http: Option<sidecar::HttpHeaders>,
+    /// Docs version named in the source URL
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    /// Other URLs cached in the same file: other forms of the source URL,
+    /// such as `/docs/` or `/docs/index.html` for `/docs`, AMP pages of it,
+    /// and URLs with the same content under `--dedup-scope`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<String>,
+}
+
+/// Structured result of the `list_cache` tool.
+#[derive(Debug, Serialize, JsonSchema)]
+struct ListCacheOutput {
+    files: Vec<CachedFile>,
+}
+
+impl ListCacheOutput {
+    fn into_result(self, structured: bool) -> CallToolResult {
+        use std::fmt::Write;
+
+        let mut text = String::new();
+        if self.files.is_empty() {
+            text.push_str("No cached files.");
+        }
+        for file in &self.files {
+            writeln!(text, "{} ({} bytes)", file.path, file.bytes).unwrap();
+            if let Some(url) = &file.source_url {
+                writeln!(text, "  Source: {url}").unwrap();
+            }
+            if !file.aliases.is_empty() {
+                writeln!(text, "  Aliases: {}", file.aliases.join(", ")).unwrap();
+            }
+            if let Some(version) = &file.version {
+                writeln!(text, "  Version: {version}").unwrap();
+            }
+            if let Some(http) = &file.http {
+                let headers = [
+                    ("Content-Type", &http.content_type),
+                    ("ETag", &http.etag),
+                    ("Last-Modified", &http.last_modified),
+                    ("Content-Language", &http.content_language),
+                    ("Link", &http.link),
+                ];
+                for (name, value) in headers {
+                    if let Some(value) = value {
+                        writeln!(text, "  {name}: {value}").unwrap();
+                    }
+                }
+            }
+        }
+        let mut result = CallToolResult::success(vec![Content::text(text.trim_end().to_string())]);
+        if structured {
+            result.structured_content = serde_json::to_value(&self).ok();
+        }
+        result
+    }
+}
+
+/// Structured result of the `info` tool.
+#[derive(Debug, Serialize, JsonSchema)]
+struct InfoOutput {
+    version: &'static str,
+    /// MCP revision agreed on with the client
+    protocol_version: Option<String>,
+    /// Optional features compiled in
+    features: Vec<&'static str>,
+    /// URL schemes `fetch` accepts
+    schemes: Vec<&'static str>,
+    /// Requests made this session, counted against `max_requests`
+    requests: u64,
+    /// Bytes downloaded this session, counted against `max_bytes`
+    bytes: u64,
+    /// Latest release, when `--update-check` is on and the check is done
+    update: Option<update::Status>,
+    config: EffectiveConfig,
+}
+
+/// Settings as the `info` tool reports them. Secrets are only reported as set
+/// or not.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+struct EffectiveConfig {
+    cache_dir: String,
+    /// `disk`, `memory` (`--ephemeral`) or `read_only`
+    cache_mode: &'static str,
+    namespace: Option<String>,
+    toc_budget: usize,
+    toc_threshold: usize,
+    preview_threshold: usize,
+    preview_lines: usize,
+    connect_timeout_secs: u64,
+    read_timeout_secs: u64,
+    variation_timeout_secs: u64,
+    fetch_timeout_secs: u64,
+    max_redirects: usize,
+    max_age_secs: Option<u64>,
+    stale_while_revalidate_secs: Option<u64>,
+    negative_cache_ttl_secs: Option<u64>,
+    max_requests: Option<u64>,
+    max_bytes: Option<u64>,
+    audit: bool,
+    relative_paths: bool,
+    /// `FROM=TO` path prefixes
+    path_maps: Vec<String>,
+    /// Cassette HTTP traffic is recorded to, under `--record`
+    record: Option<String>,
+    /// Cassette HTTP responses are replayed from, under `--replay`
+    replay: Option<String>,
+    update_check: bool,
+    allow_paths: Vec<String>,
+    allow_headers: Vec<String>,
+    language: Option<String>,
+    lockfile: Option<String>,
+    /// Pinned URL → refresh interval in seconds
+    pins: BTreeMap<String, u64>,
+    /// Domains with a policy in the config file
+    policy_domains: Vec<String>,
+    github_token: bool,
+    /// Domains configured cookies are sent to
+    cookie_domains: Vec<String>,
+    /// Cookies are kept across restarts
+    persistent_cookies: bool,
+}
+
+impl EffectiveConfig {
+    fn new(settings: &Settings) -> Self {
+        let display = |path: &PathBuf| path.to_string_lossy().into_owned();
+        let mut cookie_domains: Vec<String> = settings.cookies.keys().cloned().collect();
+        cookie_domains.sort();
+        Self {
+            cache_dir: display(&settings.cache_dir),
+            cache_mode: if settings.ephemeral {
+                "memory"
+            } else if settings.read_only {
+                "read_only"
+            } else {
+                "disk"
+            },
+            namespace: settings.namespace.clone(),
+            toc_budget: settings.toc.toc_budget,
+            toc_threshold: settings.toc.full_content_threshold,
+            preview_threshold: settings.preview_threshold,
+            preview_lines: settings.preview_lines,
+            connect_timeout_secs: settings.timeouts.connect.as_secs(),
+            read_timeout_secs: settings.timeouts.read.as_secs(),
+            variation_timeout_secs: settings.timeouts.variation.as_secs(),
+            fetch_timeout_secs: settings.timeouts.fetch.as_secs(),
+            max_redirects: settings.redirects.max,
+            max_age_secs: settings.max_age,
+            stale_while_revalidate_secs: settings.stale_while_revalidate,
+            negative_cache_ttl_secs: settings.negative_cache_ttl,
+            max_requests: settings.quota.max_requests,
+            max_bytes: settings.quota.max_bytes,
+            audit: settings.audit,
+            relative_paths: settings.relative_paths,
+            path_maps: settings
+                .path_maps
+                .iter()
+                .map(|(from, to)| format!("{}={}", from.display(), to.display()))
+                .collect(),
+            record: settings
+                .cassette
+                .as_ref()
+                .filter(|cassette| cassette.mode == cassette::Mode::Record)
+                .map(|cassette| display(&cassette.dir)),
+            replay: settings
+                .cassette
+                .as_ref()
+                .filter(|cassette| cassette.mode == cassette::Mode::Replay)
+                .map(|cassette| display(&cassette.dir)),
+            update_check: settings.update_check,
+            allow_paths: settings.allow_paths.iter().map(display).collect(),
+            allow_headers: settings.allow_headers.clone(),
+            language: settings.language.clone(),
+            lockfile: settings.lockfile.as_ref().map(display),
+            pins: settings.pins.clone(),
+            policy_domains: settings.policies.domains().map(str::to_string).collect(),
+            github_token: settings
+                .github_token
+                .as_deref()
+                .is_some_and(|token| !token.trim().is_empty()),
+            cookie_domains,
+            persistent_cookies: settings.cookie_key.is_some() && !settings.ephemeral,
+        }
+    }
+}
+
+impl InfoOutput {
+    fn into_result(self, structured: bool) -> CallToolResult {
+        use std::fmt::Write;
+
+        let mut text = format!("llms-fetch-mcp {}", self.version);
+        if let Some(version) = &self.protocol_version {
+            write!(text, " (MCP {version})").unwrap();
+        }
+        let features = if self.features.is_empty() {
+            "none".to_string()
+        } else {
+            self.features.join(", ")
+        };
+        write!(
+            text,
+            "\nFeatures: {features}\nURL schemes: {}\nThis session: {} requests, {} bytes\n\nConfiguration:",
+            self.schemes.join(", "),
+            self.requests,
+            self.bytes
+        )
+        .unwrap();
+        if let Some(update) = &self.update {
+            write!(text, "\nUpdate check: {update}").unwrap();
+        }
+        if let Ok(serde_json::Value::Object(config)) = serde_json::to_value(&self.config) {
+            for (name, value) in config {
+                let value = match value {
+                    serde_json::Value::Null => continue,
+                    serde_json::Value::Array(items) if items.is_empty() => continue,
+                    serde_json::Value::Object(map) if map.is_empty() => continue,
+                    serde_json::Value::String(value) => value,
+                    serde_json::Value::Array(items) => items
+                        .iter()
+                        .map(|item| {
+                            item.as_str()
+                                .map_or_else(|| item.to_string(), str::to_string)
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    serde_json::Value::Object(map) => map
+                        .iter()
+                        .map(|(key, value)| format!("{key}={value}"))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    value => value.to_string(),
+                };
+                write!(text, "\n  {name}: {value}").unwrap();
+            }
+        }
+        let mut result = CallToolResult::success(vec![Content::text(text)]);
+        if structured {
+            result.structured_content = serde_json::to_value(&self).ok();
+        }
+        result
+    }
+}
+
+/// Structured result of the `session_stats` tool.
+#[derive(Debug, Serialize, JsonSchema)]
+struct SessionStatsOutput {
+    /// Seconds since the server started
+    uptime_secs: u64,
+    /// Tool name → calls, this one included
+    calls: BTreeMap<String, u64>,
+    /// Host → URLs of it passed to `fetch`
+    domains: BTreeMap<String, u64>,
+    /// Fetches served from the cache without a request
+    cache_hits: u64,
+    /// Fetches that went to the network
+    cache_misses: u64,
+    /// Share of fetches served from the cache, absent before the first fetch
+    cache_hit_rate: Option<f64>,
+}
+
+impl SessionStatsOutput {
+    fn new(uptime_secs: u64, counts: stats::Counts) -> Self {
+        Self {
+            uptime_secs,
+            cache_hit_rate: counts.cache_hit_rate(),
+            calls: counts.calls,
+            domains: counts.domains,
+            cache_hits: counts.cache_hits,
+            cache_misses: counts.cache_misses,
+        }
+    }
+
+    fn into_result(self, structured: bool) -> CallToolResult {
+        use std::fmt::Write;
+
+        // Most used first
+        let ranked = |counts: &BTreeMap<String, u64>| {
+            let mut ranked: Vec<(&String, &u64)> = counts.iter().collect();
+            ranked.sort_by(|a, b| b.1.cmp(a.1));
+            ranked
+                .into_iter()
+                .map(|(name, count)| format!("{name} {count}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut text = format!(
+            "Session statistics, kept in memory only (up {}s)\nTool calls: {}",
+            self.uptime_secs,
+            ranked(&self.calls)
+        );
+        let fetches = self.cache_hits + self.cache_misses;
+        write!(text, "\nFetches: {fetches}").unwrap();
+        if let Some(rate) = self.cache_hit_rate {
+            write!(
+                text,
+                ", {} from the cache ({:.0}%)\nDomains: {}",
+                self.cache_hits,
+                rate * 100.0,
+                ranked(&self.domains)
+            )
+            .unwrap();
+        }
+        let mut result = CallToolResult::success(vec![Content::text(text)]);
+        if structured {
+            result.structured_content = serde_json::to_value(&self).ok();
+        }
+        result
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+struct DebugConvertInput {
+    /// URL of an HTML page
+    url: String,
+}
+
+/// The start of a text, as returned by the `debug_convert` tool.
+#[derive(Debug, Serialize, JsonSchema)]
+struct Excerpt {
+    /// Characters in the whole text
+    characters: usize,
+    text: String,
+    /// `text` is only the start
+    truncated: bool,
+}
+
+impl Excerpt {
+    fn new(text: &str) -> Self {
+        let end = text
+            .char_indices()
+            .nth(DEBUG_EXCERPT_CHARS)
+            .map_or(text.len(), |(i, _)| i);
+        Self {
+            characters: text.chars().count(),
+            text: text[..end].to_string(),
+            truncated: end < text.len(),
+        }
+    }
+}
+
+/// An extraction step's result, as returned by the `debug_convert` tool.
+#[derive(Debug, Serialize, JsonSchema)]
+struct ExtractionStep {
+    step: Extraction,
+    /// Why the step extracted nothing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skipped: Option<String>,
+    /// Size of the HTML the step extracted
+    html_bytes: usize,
+    /// The extracted HTML converted to Markdown, before any cleanup
+    markdown: Option<Excerpt>,
+}
+
+/// Structured result of the `debug_convert` tool.
+#[derive(Debug, Serialize, JsonSchema)]
+struct DebugConvertOutput {
+    url: String,
+    content_type: Option<String>,
+    /// Size of the page as downloaded
+    html_bytes: usize,
+    /// The step whose output was converted, absent when conversion failed
+    chosen: Option<Extraction>,
+    /// Every step in the order they're tried, including those after the chosen one
+    steps: Vec<ExtractionStep>,
+    /// The Markdown `fetch` would save, absent when conversion failed
+    markdown: Option<Excerpt>,
+    warnings: Vec<String>,
+}
+
+impl DebugConvertOutput {
+    fn into_result(self, structured: bool) -> CallToolResult {
+        use std::fmt::Write;
+
+        let mut text = format!(
+            "Conversion of {} ({} bytes of HTML): ",
+            self.url, self.html_bytes
+        );
+        match self.chosen {
+            Some(chosen) => writeln!(text, "used {}\n", chosen.name()).unwrap(),
+            None => text.push_str("failed\n\n"),
+        }
+        for step in &self.steps {
+            let name = step.step.name();
+            match (&step.skipped, &step.markdown) {
+                (Some(reason), _) => writeln!(text, "- {name}: nothing, {reason}").unwrap(),
+                (None, Some(markdown)) => writeln!(
+                    text,
+                    "- {name}: {} bytes of HTML, {} characters of Markdown",
+                    step.html_bytes, markdown.characters
+                )
+                .unwrap(),
+                (None, None) => {}
+            }
+        }
+        for warning in &self.warnings {
+            writeln!(text, "\nWarning: {warning}").unwrap();
+        }
+        let excerpts = self
+            .steps
+            .iter()
+            .filter_map(|step| Some((step.step.name(), step.markdown.as_ref()?)))
+            .chain(
+                self.markdown
+                    .as_ref()
+                    .map(|markdown| ("final Markdown", markdown)),
+            );
+        for (name, excerpt) in excerpts {
+            write!(text, "\n--- {name}").unwrap();
+            if excerpt.truncated {
+                write!(
+                    text,
+                    " (first {DEBUG_EXCERPT_CHARS} of {} characters)",
+                    excerpt.characters
+                )
+                .unwrap();
+            }
+            writeln!(text, " ---\n{}", excerpt.text.trim_end()).unwrap();
+        }
+        let mut result = CallToolResult::success(vec![Content::text(text.trim_end().to_string())]);
+        if structured {
+            result.structured_content = serde_json::to_value(&self).ok();
+        }
+        result
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct FileInfo {
+    path: String,
+    source_url: String,
+    content_type: String,
+    lines: usize,
+    words: usize,
+    characters: usize,
+    table_of_contents: Option<String>,
+    /// Full content for small files (below the `ToC` threshold), or the first lines
+    /// of oversized files when `truncated_preview` is set
+    content: Option<String>,
+    /// `content` is only the start of the document; read the saved file for the rest
+    truncated_preview: bool,
+    /// Selected headers of the response the file was saved from
+    http: Option<sidecar::HttpHeaders>,
+    /// Docs version named in the URL (`/v2/`, `/en/stable/`); the `versions`
+    /// tool finds the others
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    /// Language versions of the page listed with `hreflang` links
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    languages: Vec<hreflang::Alternate>,
+    /// ISO 639-1 code of the language the content appears to be written in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    /// What the page's JSON-LD structured data says about it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<jsonld::Metadata>,
+    /// Bytes of large inline scripts and state blobs (`__NEXT_DATA__`) left
+    /// out of the conversion
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inline_data_bytes: Option<usize>,
+    /// How the top-level sections of a large file changed since it was last
+    /// cached
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sections: Option<sections::Changes>,
+    /// Other URLs cached as this file: the AMP page it's the canonical
+    /// version of, or the URL as requested before a policy dropped query
+    /// parameters
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<String>,
+    /// URL an already cached file with the same content was saved from; `path`
+    /// is that file, shared under `--dedup-scope`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duplicate_of: Option<String>,
+    /// Where the file is kept, when not on disk at `path`; `content` then
+    /// holds the whole document
+    #[serde(skip_serializing_if = "Storage::on_disk")]
+    storage: Storage,
+}
+
+/// Where a fetched file is kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum Storage {
+    /// In the cache directory
+    #[default]
+    Disk,
+    /// In memory, under `--ephemeral`; `cite` and `grep` still find it at `path`
+    Memory,
+    /// Nowhere: the cache couldn't be written
+    Unsaved,
+}
+
+impl Storage {
+    #[allow(clippy::trivially_copy_pass_by_ref)] // for `skip_serializing_if`
+    fn on_disk(&self) -> bool {
+        *self == Self::Disk
+    }
+}
+
+impl FileInfo {
+    /// Marks the file as kept in `storage`, off disk, with its whole `content`.
+    fn off_disk(&mut self, storage: Storage, content: &str) {
+        self.storage = storage;
+        self.content = Some(content.to_string());
+        self.truncated_preview = false;
+    }
+
+    /// Link to the file as a resource of the server, unless it wasn't saved.
+    fn resource_link(&self, cache_dir: &Path) -> Option<Content> {
+        if self.storage == Storage::Unsaved {
+            return None;
+        }
+        let mut resource = RawResource::new(
+            cache_uri(cache_dir, Path::new(&self.path))?,
+            &self.source_url,
+        );
+        resource.mime_type = Some("text/markdown".to_string());
+        Some(Content::resource_link(resource))
+    }
+}
+
+/// What happened to one URL variation during a fetch.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+enum Outcome {
+    /// Fetched and saved to the cache
+    Success,
+    /// The server answered with a non-success status
+    HttpError { status: u16 },
+    /// The request failed before a response was received
+    NetworkError,
+    /// Still running when the fetch deadline passed, so abandoned
+    TimedOut,
+    /// Still downloading when a text variation had already arrived; saved to
+    /// the cache in the background when done
+    InBackground,
+    /// Served from the cache without a request, under `--max-age` or
+    /// `--stale-while-revalidate`
+    Cached,
+    /// Unchanged since it was cached (HTTP 304), so the cached copy was kept
+    NotModified,
+    /// Not requested, or fetched but not saved
+    Skipped { reason: String },
+    /// Same content as an earlier variation, so not saved again
+    Deduplicated { duplicate_of: String },
+    /// Behind a paywall or login: not saved, so a teaser can't pass for the page
+    AccessRestricted { reason: String },
+    /// Redirected in a loop, too many times, or to another origin under
+    /// `--confirm-cross-origin-redirects`
+    RedirectNotFollowed {
+        reason: String,
+        /// The redirect's target, when it can be fetched directly
+        #[serde(skip_serializing_if = "Option::is_none")]
+        location: Option<String>,
+    },
+    /// An error or maintenance page served with a success status: not saved
+    SoftNotFound {
+        reason: String,
+        /// Canonical and language-version URLs the page links to
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        alternates: Vec<String>,
+    },
+    /// Different from the locked content under `--lockfile-drift fail`: not
+    /// saved, so the cached copy stays as locked
+    Drifted {
+        /// Hash of the fetched content
+        sha256: String,
+        /// Hash in the lockfile
+        locked_sha256: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+struct Variation {
+    url: String,
+    #[serde(flatten)]
+    outcome: Outcome,
+    /// Request duration; absent when the variation wasn't requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elapsed_ms: Option<u64>,
+}
+
+impl Variation {
+    fn describe(&self) -> String {
+        match &self.outcome {
+            Outcome::Success => format!("{}: success", self.url),
+            Outcome::HttpError { status } => format!("{}: HTTP {status}", self.url),
+            Outcome::NetworkError => format!("{}: network error", self.url),
+            Outcome::TimedOut => format!("{}: abandoned at the fetch deadline", self.url),
+            Outcome::InBackground => format!("{}: still downloading", self.url),
+            Outcome::Cached => format!("{}: served from the cache", self.url),
+            Outcome::NotModified => format!("{}: unchanged since it was cached", self.url),
+            Outcome::Skipped { reason } => format!("{}: skipped, {reason}", self.url),
+            Outcome::Deduplicated { duplicate_of } => {
+                format!("{}: same content as {duplicate_of}", self.url)
+            }
+            Outcome::AccessRestricted { reason } => {
+                format!("{}: access restricted, {reason}", self.url)
+            }
+            Outcome::RedirectNotFollowed { reason, .. } => {
+                format!("{}: redirect not followed, {reason}", self.url)
+            }
+            Outcome::SoftNotFound { reason, .. } => {
+                format!("{}: error page served as a success, {reason}", self.url)
+            }
+            Outcome::Drifted { .. } => format!("{}: differs from the lockfile", self.url),
+        }
+    }
+}
+
+/// Bumped whenever a `FetchOutput` field is renamed, removed, or changes
+/// meaning; additions keep the version.
+///
+/// 2: `content` may hold a truncated preview (see `truncated_preview`).
+const FETCH_OUTPUT_SCHEMA_VERSION: u32 = 2;
+
+// Doc comments here become descriptions in the advertised output schema. The
+// text content is rendered from the same data by `format_output`.
+/// Structured result of the `fetch` tool.
+#[derive(Debug, Serialize, JsonSchema)]
+struct FetchOutput {
+    /// Incremented when a field is renamed, removed, or changes meaning
+    schema_version: u32,
+    files: Vec<FileInfo>,
+    /// Every URL tried, in the order they were generated
+    variations: Vec<Variation>,
+    warnings: Vec<String>,
+    /// When several URLs were requested, what happened to each, in the order
+    /// given; the files, variations and warnings above are theirs combined
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    urls: Vec<UrlOutcome>,
+}
+
+/// What a `fetch` of several URLs did for one of them.
+#[derive(Debug, Serialize, JsonSchema)]
+struct UrlOutcome {
+    url: String,
+    /// `cache` when served without a request, `local` for `file:` and `data:`
+    /// URLs, and `network` otherwise
+    source: &'static str,
+    /// Why the fetch failed; absent when it succeeded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl FetchOutput {
+    /// Renders the text content, attaching the structured form and a resource
+    /// link to each file in `cache_dir` only for clients that negotiated a
+    /// revision supporting them. File paths are shown as `paths` says.
+    fn into_result(
+        mut self,
+        structured: bool,
+        cache_dir: &Path,
+        paths: &OutputPaths,
+    ) -> CallToolResult {
+        let links: Vec<Content> = if structured {
+            self.files
+                .iter()
+                .filter_map(|file| file.resource_link(cache_dir))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        for file in &mut self.files {
+            file.path = paths.show(Path::new(&file.path));
+        }
+        let mut content = vec![Content::text(format_output(&self.files, &self.warnings))];
+        content.extend(links);
+        let mut result = CallToolResult::success(content);
+        if structured {
+            result.structured_content = serde_json::to_value(&self).ok();
+        }
+        result
+    }
+}
+
+/// One line of the audit log.
+#[derive(Debug, Serialize)]
+struct AuditRecord {
+    /// Unix timestamp (seconds) when the call started
+    timestamp: u64,
+    tool: &'static str,
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    namespace: Option<String>,
+    variations: Vec<Variation>,
+    /// Response bytes downloaded during the call
+    bytes: u64,
+    /// `success` or `error`
+    result: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl AuditRecord {
+    fn new(tool: &'static str, url: &str, namespace: Option<&str>) -> Self {
+        Self {
+            timestamp: index::unix_now(),
+            tool,
+            url: url.to_string(),
+            namespace: namespace.map(str::to_string),
+            variations: Vec::new(),
+            bytes: 0,
+            result: "success",
+            error: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FetchResult {
+    url: String,
+    content: String,
+    is_html: bool,
+    is_markdown: bool,
+    headers: sidecar::HttpHeaders,
+    /// Other URLs of the same document, such as the AMP page it's the
+    /// canonical version of
+    aliases: Vec<String>,
+}
+
+#[derive(Debug)]
+enum FetchAttempt {
+    Success(FetchResult),
+    HttpError {
+        url: String,
+        status: u16,
+        /// A 401 or 403 answered with an HTML page, typically a login form
+        login_page: bool,
+    },
+    NetworkError {
+        url: String,
+    },
+    /// A redirect [`Http::send`] stopped at
+    RedirectNotFollowed {
+        url: String,
+        reason: String,
+        /// Where it pointed, for redirects the caller may follow itself
+        location: Option<String>,
+    },
+}
+
+/// Pages a crawl fetches at once.
+const CRAWL_CONCURRENCY: usize = 4;
+
+/// Dependencies `fetch_dependencies` fetches the docs of at once.
+const DEPENDENCY_CONCURRENCY: usize = 4;
+
+/// URLs one `fetch` call may request.
+const MAX_FETCH_URLS: usize = 20;
+
+/// URLs of a `fetch` of several fetched at once.
+const FETCH_CONCURRENCY: usize = 4;
+
+/// A crawl in progress: where its pages are saved and what it has found.
+struct CrawlState<'a> {
+    root: &'a Path,
+    scope: crawl::Scope,
+    frontier: frontier::Frontier,
+}
+
+/// A page fetched by a crawl.
+struct CrawlFetch {
+    /// Position in the crawl's frontier
+    position: usize,
+    /// Where the page is cached
+    file: PathBuf,
+    /// Whether the request asked for the page only if it changed
+    revalidating: bool,
+    attempt: FetchAttempt,
+}
+
+/// Check interval for `watch` calls that don't give one.
+const DEFAULT_WATCH_INTERVAL: u64 = 3600;
+
+/// How long a fetch keeps waiting for other variations once a text one has
+/// arrived. Large ones such as `llms-full.txt` finish in the background.
+const EARLY_RETURN_GRACE: Duration = Duration::from_secs(2);
+
+/// How long past the fetch deadline variations finishing in the background
+/// may take before they are abandoned.
+const BACKGROUND_GRACE: Duration = Duration::from_mins(1);
+
+/// Variation requests, each returning its position in the variation report,
+/// the attempt, and how long it took.
+type FetchTasks = tokio::task::JoinSet<(usize, FetchAttempt, Duration)>;
+
+/// Variation requests a fetch stopped waiting for, left to finish in the
+/// background.
+struct Stragglers {
+    tasks: FetchTasks,
+    /// The fetch deadline
+    deadline: tokio::time::Instant,
+}
+
+/// A response from [`Http::send`] and how it was reached.
+struct Sent {
+    response: reqwest::Response,
+    /// URLs that redirected, starting with the one requested; empty when
+    /// there was no redirect
+    redirects: Vec<String>,
+    /// Why the redirect `response` answers with wasn't followed, if it is one
+    stopped: Option<RedirectStop>,
+}
+
+/// Why [`Http::send`] stopped at a redirect.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RedirectStop {
+    /// `--max-redirects` were followed already
+    Limit(usize),
+    /// Back to a URL visited before
+    Loop(String),
+    /// To another origin, under `--confirm-cross-origin-redirects`
+    CrossOrigin(String),
+    /// Off the requested site, under `--same-site`
+    OtherSite(String),
+}
+
+impl RedirectStop {
+    /// The reason and, for redirects worth following by hand, the target.
+    fn describe(&self) -> (String, Option<String>) {
+        match self {
+            Self::Limit(max) => (format!("more than {max} redirects"), None),
+            Self::Loop(url) => (format!("redirect loop back to {url}"), None),
+            Self::CrossOrigin(url) => (
+                format!("redirects to another origin, {url}"),
+                Some(url.clone()),
+            ),
+            Self::OtherSite(url) => (
+                format!("redirects to another site, {url}"),
+                Some(url.clone()),
+            ),
+        }
+    }
+}
+
+/// Shared HTTP client plus per-host request overrides.
+#[derive(Clone)]
+struct Http {
+    /// For API requests, following redirects itself
+    client: reqwest::Client,
+    /// For page requests, whose redirects [`Http::send`] follows so cookies
+    /// set along the way reach the next hop
+    pages: reqwest::Client,
+    cookies: Arc<cookies::CookieJar>,
+    /// Lowercase domain → `Host` header value to send instead of the URL's host
+    host_headers: Arc<HashMap<String, String>>,
+    /// `Accept-Language` value for the preferred language, if one is set
+    accept_language: Option<reqwest::header::HeaderValue>,
+    redirects: config::Redirects,
+    /// Headers a caller passed for one fetch, with the only host they go to
+    call_headers: Option<(String, reqwest::header::HeaderMap)>,
+    /// How a caller asked the body of the one URL they fetched to be
+    /// interpreted; derived variations keep their own Content-Type
+    content_type_hint: Option<(String, ContentTypeHint)>,
+    /// Where requests are recorded to or replayed from, under `--record` or
+    /// `--replay`
+    cassette: Option<Arc<cassette::Cassette>>,
+    #[cfg(feature = "http3")]
+    http3: Arc<http3::Http3>,
+}
+
+impl Http {
+    fn new(
+        resolve: &[(String, SocketAddr)],
+        host_headers: HashMap<String, String>,
+        language: Option<&str>,
+        timeouts: config::Timeouts,
+        redirects: config::Redirects,
+        resolver: &Arc<dns::CachingResolver>,
+        cookies: Arc<cookies::CookieJar>,
+    ) -> Result<Self, reqwest::Error> {
+        let builder = || {
+            // `--resolve` overrides are consulted before the resolver
+            let mut builder = reqwest::Client::builder()
+                .connect_timeout(timeouts.connect)
+                .read_timeout(timeouts.read)
+                .timeout(timeouts.variation)
+                .user_agent(USER_AGENT)
+                // Connections stay pooled between fetches; keepalive probes
+                // stop NATs and proxies from silently dropping idle ones
+                .pool_idle_timeout(Duration::from_secs(90))
+                .tcp_keepalive(Duration::from_secs(30))
+                .dns_resolver(Arc::clone(resolver));
+            for (host, address) in resolve {
+                builder = builder.resolve(host, *address);
+            }
+            builder
+        };
+        Ok(Self {
+            client: builder()
+                .redirect(reqwest::redirect::Policy::limited(redirects.max))
+                .build()?,
+            pages: builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()?,
+            cookies,
+            host_headers: Arc::new(host_headers),
+            // Servers that negotiate by header then pick the right version
+            // without a second request
+            accept_language: language.and_then(|lang| format!("{lang}, *;q=0.5").parse().ok()),
+            redirects,
+            call_headers: None,
+            content_type_hint: None,
+            cassette: None,
+            #[cfg(feature = "http3")]
+            http3: Arc::new(http3::Http3::new(
+                builder()
+                    .redirect(reqwest::redirect::Policy::none())
+                    .http3_prior_knowledge()
+                    .build()?,
+            )),
+        })
+    }
+
+    /// A client that also sends `headers` on requests to the host of `url`.
+    /// Other hosts (AMP canonicals, translations elsewhere) never see them.
+    fn with_call_headers(&self, url: &str, headers: reqwest::header::HeaderMap) -> Self {
+        let host = url::Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_lowercase));
+        Self {
+            call_headers: host
+                .filter(|_| !headers.is_empty())
+                .map(|host| (host, headers)),
+            ..self.clone()
+        }
+    }
+
+    /// A client that interprets the body of `url` as `hint` says.
+    fn with_content_type_hint(&self, url: &str, hint: ContentTypeHint) -> Self {
+        Self {
+            content_type_hint: (hint != ContentTypeHint::Auto).then(|| (url.to_string(), hint)),
+            ..self.clone()
+        }
+    }
+
+    /// Requests `url`, following redirects and updating the cookie jar from
+    /// every response on the way. Stops at loops, past `--max-redirects`, and
+    /// at cross-origin or off-site redirects when those need confirming.
+    async fn send(
+        &self,
+        url: &str,
+        accept: &str,
+    ) -> Result<Sent, Box<dyn std::error::Error + Send + Sync>> {
+        let requested = url;
+        let mut url = url.to_string();
+        let mut redirects: Vec<String> = Vec::new();
+        loop {
+            let response = self.send_once(&url, accept).await?;
+            self.cookies.store(response.url(), response.headers());
+            let next = response
+                .status()
+                .is_redirection()
+                .then(|| response.headers().get(reqwest::header::LOCATION))
+                .flatten()
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| response.url().join(location).ok())
+                .filter(|next| matches!(next.scheme(), "http" | "https"));
+            let Some(next) = next else {
+                return Ok(Sent {
+                    response,
+                    redirects,
+                    stopped: None,
+                });
+            };
+            let stopped = if redirects.len() >= self.redirects.max {
+                Some(RedirectStop::Limit(self.redirects.max))
+            } else if next.as_str() == url || redirects.iter().any(|r| r == next.as_str()) {
+                Some(RedirectStop::Loop(next.to_string()))
+            } else if self.off_site(requested, next.as_str()) {
+                Some(RedirectStop::OtherSite(next.to_string()))
+            } else if self.redirects.confirm_cross_origin
+                && next.origin() != response.url().origin()
+            {
+                Some(RedirectStop::CrossOrigin(next.to_string()))
+            } else {
+                None
+            };
+            redirects.push(url);
+            if stopped.is_some() {
+                return Ok(Sent {
+                    response,
+                    redirects,
+                    stopped,
+                });
+            }
+            url = next.into();
+        }
+    }
+
+    /// Whether `--same-site` keeps `to` from being followed automatically
+    /// from `from`.
+    fn off_site(&self, from: &str, to: &str) -> bool {
+        let Some(same_site) = &self.redirects.same_site else {
+            return false;
+        };
+        match (url::Url::parse(from), url::Url::parse(to)) {
+            (Ok(from), Ok(to)) => !same_site.permits(&from, &to),
+            _ => true,
+        }
+    }
+
+    /// Sends a single request, over HTTP/3 if the host has advertised it and
+    /// over TCP otherwise or when that fails, or serves it from the cassette.
+    async fn send_once(
+        &self,
+        url: &str,
+        accept: &str,
+    ) -> Result<reqwest::Response, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(cassette) = &self.cassette {
+            let request = self.get(url).header(reqwest::header::ACCEPT, accept);
+            return cassette.send(&self.pages, request.build()?).await;
+        }
+        #[cfg(feature = "http3")]
+        if let Ok(parsed) = url::Url::parse(url)
+            && self.http3.should_try(&parsed)
+        {
+            let request = self
+                .request(&self.http3.client, url)
+                .header(reqwest::header::ACCEPT, accept)
+                .send();
+            let error = match tokio::time::timeout(http3::HANDSHAKE_TIMEOUT, request).await {
+                Ok(Ok(response)) => {
+                    self.http3.note_response(&parsed, response.headers());
+                    return Ok(response);
+                }
+                Ok(Err(e)) => e.to_string(),
+                Err(_) => "timed out".to_string(),
+            };
+            // Only reported once: the host stays on TCP from here on
+            eprintln!("warning: HTTP/3 to {url} failed ({error}), falling back to TCP");
+            self.http3.mark_failed(&parsed);
+        }
+        let response = self
+            .get(url)
+            .header(reqwest::header::ACCEPT, accept)
+            .send()
+            .await?;
+        #[cfg(feature = "http3")]
+        self.http3.note_response(response.url(), response.headers());
+        Ok(response)
+    }
+
+    fn get(&self, url: &str) -> reqwest::RequestBuilder {
+        self.request(&self.pages, url)
+    }
+
+    /// The GitHub API, recorded or replayed along with the other requests.
+    fn github<'a>(&'a self, token: Option<&'a str>) -> github::Api<'a> {
+        github::Api::new(&self.client, token).with_cassette(self.cassette.as_deref())
+    }
+
+    /// A GET for `url` on `client` with the per-host and per-call headers.
+    fn request(&self, client: &reqwest::Client, url: &str) -> reqwest::RequestBuilder {
+        let mut request = client.get(url);
+        if let Some(language) = &self.accept_language {
+            request = request.header(reqwest::header::ACCEPT_LANGUAGE, language.clone());
+        }
+        let parsed = url::Url::parse(url).ok();
+        if let Some(cookie) = parsed.as_ref().and_then(|u| self.cookies.header(u)) {
+            request = request.header(reqwest::header::COOKIE, cookie);
+        }
+        let host = parsed.and_then(|u| u.host_str().map(str::to_lowercase));
+        if let Some((call_host, headers)) = &self.call_headers
+            && host.as_ref() == Some(call_host)
+        {
+            request = request.headers(headers.clone());
+        }
+        let host_header = host.and_then(|host| self.host_headers.get(&host));
+        match host_header {
+            Some(value) => request.header("Host", value),
+            None => request,
+        }
+    }
+}
+
+/// `If-None-Match` and `If-Modified-Since` headers from the validators the
+/// cached file at `file` was served with, if it's still there.
+async fn conditional_headers(file: &Path) -> reqwest::header::HeaderMap {
+    let mut headers = reqwest::header::HeaderMap::new();
+    if !fs::try_exists(file).await.unwrap_or(false) {
+        return headers;
+    }
+    let Some(http) = sidecar::read(file).await.and_then(|meta| meta.http) else {
+        return headers;
+    };
+    if let Some(etag) = http.etag.and_then(|v| v.parse().ok()) {
+        headers.insert(reqwest::header::IF_NONE_MATCH, etag);
+    }
+    if let Some(modified) = http.last_modified.and_then(|v| v.parse().ok()) {
+        headers.insert(reqwest::header::IF_MODIFIED_SINCE, modified);
+    }
+    headers
+}
+
+async fn fetch_url(http: &Http, url: &str) -> FetchAttempt {
+    match http
+        .send(
+            url,
+            "text/markdown, text/x-markdown, text/plain, text/html;q=0.5, */*;q=0.1",
+        )
+        .await
+    {
+        Ok(Sent {
+            stopped: Some(stop),
+            ..
+        }) => {
+            let (reason, location) = stop.describe();
+            FetchAttempt::RedirectNotFollowed {
+                url: url.to_string(),
+                reason,
+                location,
+            }
+        }
+        Ok(Sent {
+            response,
+            redirects,
+            stopped: None,
+        }) => {
+            let status = response.status().as_u16();
+            if response.status().is_success() {
+                let content_type = response
+                    .headers()
+                    .get("content-type")
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or("");
+
+                let detected = (
+                    content_type.contains("text/html"),
+                    content_type.contains("text/markdown")
+                        || content_type.contains("text/x-markdown"),
+                );
+                let (is_html, is_markdown) = match &http.content_type_hint {
+                    Some((hinted, hint)) if hinted == url => hint.apply(detected),
+                    _ => detected,
+                };
+                let headers = sidecar::HttpHeaders {
+                    redirects,
+                    ..sidecar::HttpHeaders::from_response(response.headers())
+                };
+
+                match response.text().await {
+                    Ok(content) => FetchAttempt::Success(FetchResult {
+                        url: url.to_string(),
+                        content,
+                        is_html,
+                        is_markdown,
+                        headers,
+                        aliases: Vec::new(),
+                    }),
+                    Err(_) => FetchAttempt::NetworkError {
+                        url: url.to_string(),
+                    },
+                }
+            } else {
+                let is_html = response
+                    .headers()
+                    .get("content-type")
+                    .and_then(|v| v.to_str().ok())
+                    .is_some_and(|v| v.contains("text/html"));
+                FetchAttempt::HttpError {
+                    url: url.to_string(),
+                    status,
+                    login_page: matches!(status, 401 | 403) && is_html,
+                }
+            }
+        }
+        Err(_) => FetchAttempt::NetworkError {
+            url: url.to_string(),
+        },
+    }
+}
+
+fn get_url_variations(url: &str) -> Vec<String> {
+    let mut variations = vec![url.to_string()];
+
+    let url_lower = url.to_lowercase();
+    #[allow(clippy::case_sensitive_file_extension_comparisons)]
+    if url_lower.ends_with(".md") || url_lower.ends_with(".txt") {
+        return variations;
+    }
+
+    // Don't try variations for URLs with query parameters
+    if url.contains('?') {
+        return variations;
+    }
+
+    let base = url.trim_end_matches('/');
+
+    // Check if URL has a file extension (to avoid file/directory conflicts)
+    let has_file_extension = if let Ok(parsed) = url::Url::parse(url) {
+        let path = parsed.path();
+        path.rsplit_once('/')
+            .is_some_and(|(_, last)| last.contains('.') && !last.ends_with('.'))
+    } else {
+        false
+    };
+
+    variations.push(format!("{base}.md"));
+
+    // Only add .html.md and directory-based variations if URL doesn't have a file extension
+    // This prevents file/directory conflicts (e.g., npm.html file vs npm.html/ directory)
+    // and avoids nonsensical double extensions (e.g., page.html.html.md)
+    if !has_file_extension {
+        variations.push(format!("{base}.html.md"));
+        variations.push(format!("{base}/index.md"));
+        variations.push(format!("{base}/llms.txt"));
+        variations.push(format!("{base}/llms-full.txt"));
+    }
+
+    variations
+}
+
+/// The variations of `url` to try under `policy`: the standard ones it keeps,
+/// then its extra llms.txt locations.
+fn policy_variations(policy: &policy::DomainPolicy, url: &str) -> Vec<String> {
+    let mut variations = policy.apply_variations(url, get_url_variations(url));
+    let extra = policy.llms_paths.as_deref().unwrap_or_default();
+    for path in llms_paths::configured(url, extra) {
+        if !variations.contains(&path) {
+            variations.push(path);
+        }
+    }
+    variations
+}
+
+/// GitHub releases listing that backs a changelog request for `url`, if any.
+fn releases_page(url: &str) -> Option<github::GithubPage> {
+    match github::GithubPage::parse(url)? {
+        github::GithubPage::Releases { owner, repo, .. }
+        | github::GithubPage::Release { owner, repo, .. } => Some(github::GithubPage::Releases {
+            owner,
+            repo,
+            page: 1,
+        }),
+        _ => None,
+    }
+}
+
+/// Reads a previously cached file for `url` from `store` without touching the
+/// network.
+async fn read_cached(
+    store: &memory::Store,
+    file_path: &Path,
+    url: &str,
+) -> Result<String, McpError> {
+    store.read_async(file_path).await.map_err(|e| {
+        let reason = if e.kind() == std::io::ErrorKind::NotFound {
+            "is not in the cache".to_string()
+        } else {
+            format!("could not be read from the cache ({e})")
+        };
+        McpError::resource_not_found(
+            format!("{url} {reason}, and the server is in read-only mode"),
+            None,
+        )
+    })
+}
+
+/// Directories the client exposes as roots, if it supports them. A client
+/// that fails to answer gets no extra roots rather than a failed fetch.
+async fn client_roots(peer: &Peer<RoleServer>) -> Vec<PathBuf> {
+    let supported = peer
+        .peer_info()
+        .is_some_and(|info| info.capabilities.roots.is_some());
+    if !supported {
+        return Vec::new();
+    }
+    match peer.list_roots().await {
+        Ok(result) => result
+            .roots
+            .iter()
+            .filter_map(|root| local::root_path(&root.uri))
+            .collect(),
+        Err(e) => {
+            eprintln!("warning: failed to list client roots: {e}");
+            Vec::new()
+        }
+    }
+}
+
+/// Directory holding a namespace's cache. Hostnames can't contain `@`, so
+/// namespaces never collide with the un-namespaced host directories.
+fn namespace_dir(cache_dir: &Path, namespace: &str) -> PathBuf {
+    cache_dir.join(format!("@{namespace}"))
+}
+
+/// Start of the resource URI of a cached file, followed by its path in the
+/// cache directory, so clients that can't see the server's filesystem can read
+/// it with `resources/read`.
+const CACHE_URI: &str = "llms-fetch://cache/";
+
+/// Characters escaped in the path of a cache resource URI, besides controls.
+const CACHE_URI_ESCAPES: &percent_encoding::AsciiSet = &percent_encoding::CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b'\\')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+/// How tool results show the paths of cached files.
+#[derive(Debug, Default)]
+struct OutputPaths {
+    /// Directory paths are relative to, under `--relative-paths`
+    base: Option<PathBuf>,
+    /// `--path-map` prefixes, for paths that aren't made relative
+    maps: Vec<(PathBuf, PathBuf)>,
+}
+
+impl OutputPaths {
+    /// `path` as shown in tool results.
+    fn show(&self, path: &Path) -> String {
+        let relative = self
+            .base
+            .as_deref()
+            .and_then(|base| path.strip_prefix(base).ok());
+        let shown = match relative {
+            Some(relative) => relative.to_path_buf(),
+            None => remap(path, self.maps.iter().map(|(from, to)| (from, to))),
+        };
+        shown.to_string_lossy().into_owned()
+    }
+
+    /// The path on the server of a `path` shown by [`OutputPaths::show`], for
+    /// tools that take one back.
+    fn resolve(&self, path: &str) -> PathBuf {
+        remap(
+            Path::new(path),
+            self.maps.iter().map(|(from, to)| (to, from)),
+        )
+    }
+}
+
+/// `path` with the longest of the `maps` prefixes it starts with replaced.
+fn remap<'a>(path: &Path, maps: impl Iterator<Item = (&'a PathBuf, &'a PathBuf)>) -> PathBuf {
+    maps.filter(|(from, _)| path.starts_with(from))
+        .max_by_key(|(from, _)| from.components().count())
+        .and_then(|(from, to)| Some(to.join(path.strip_prefix(from).ok()?)))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Resource URI of the file at `path` in `cache_dir`, if it's in it.
+fn cache_uri(cache_dir: &Path, path: &Path) -> Option<String> {
+    let segments = path
+        .strip_prefix(cache_dir)
+        .ok()?
+        .components()
+        .map(|component| match component {
+            std::path::Component::Normal(name) => Some(
+                percent_encoding::utf8_percent_encode(name.to_str()?, CACHE_URI_ESCAPES)
+                    .to_string(),
+            ),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    (!segments.is_empty()).then(|| format!("{CACHE_URI}{}", segments.join("/")))
+}
+
+/// Path in `cache_dir` of the file a [`cache_uri`] names. URIs that would
+/// leave the cache directory name nothing.
+fn cache_uri_path(cache_dir: &Path, uri: &str) -> Option<PathBuf> {
+    let mut path = cache_dir.to_path_buf();
+    for segment in uri.strip_prefix(CACHE_URI)?.split('/') {
+        let name = percent_encoding::percent_decode_str(segment)
+            .decode_utf8()
+            .ok()?;
+        if matches!(&*name, "" | "." | "..") || name.contains(['/', '\\']) {
+            return None;
+        }
+        path.push(&*name);
+    }
+    Some(path)
+}
+
+/// Longest file or directory name in the cache, in bytes; filesystems allow
+/// 255.
+const MAX_NAME_BYTES: usize = 200;
+
+/// Cache file or directory name for a URL path segment: percent escapes are
+/// decoded so `%20` and Unicode read naturally, except for characters that
+/// aren't safe in file names (`/`, `\`, `%`, controls and those Windows
+/// reserves), which stay escaped. Segments that aren't UTF-8 once decoded, or
+/// that would decode to `.` or `..`, are kept as written.
+fn segment_name(segment: &str) -> String {
+    use std::fmt::Write;
+
+    let Ok(decoded) = percent_encoding::percent_decode_str(segment).decode_utf8() else {
+        return cap_name(segment.to_string());
+    };
+    if decoded == "." || decoded == ".." {
+        return cap_name(segment.to_string());
+    }
+    let mut name = String::with_capacity(decoded.len());
+    for c in decoded.chars() {
+        if c.is_control()
+            || matches!(
+                c,
+                '/' | '\\' | '%' | ':' | '*' | '?' | '"' | '<' | '>' | '|'
+            )
+        {
+            for byte in c.to_string().bytes() {
+                write!(name, "%{byte:02X}").unwrap();
+            }
+        } else {
+            name.push(c);
+        }
+    }
+    cap_name(name)
+}
+
+/// Longest extension, with any query after it, that [`cap_name`] keeps.
+const MAX_SUFFIX_BYTES: usize = 64;
+
+/// `name`, or when it's longer than [`MAX_NAME_BYTES`], its start followed by
+/// a hash of the whole name, so long names stay distinct. The extension stays
+/// at the end, with the query after it if that fits in [`MAX_SUFFIX_BYTES`],
+/// so the file keeps its type.
+fn cap_name(name: String) -> String {
+    if name.len() <= MAX_NAME_BYTES {
+        return name;
+    }
+    let stem_end = name.find('?').unwrap_or(name.len());
+    let suffix = name[..stem_end].rfind('.').map_or("", |dot| {
+        if name.len() - dot <= MAX_SUFFIX_BYTES {
+            &name[dot..]
+        } else if stem_end - dot <= MAX_SUFFIX_BYTES && stem_end - dot > 1 {
+            &name[dot..stem_end]
+        } else {
+            ""
+        }
+    });
+    let mut end = MAX_NAME_BYTES - 17 - suffix.len();
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!(
+        "{}~{}{suffix}",
+        &name[..end],
+        &sidecar::sha256(name.as_bytes())[..16]
+    )
+}
+
+/// Whether `name` looks shortened by [`cap_name`].
+fn is_shortened(name: &std::ffi::OsStr) -> bool {
+    name.to_str()
+        .and_then(|name| name.rsplit_once('~'))
+        .and_then(|(_, rest)| rest.get(..16).zip(rest.get(16..)))
+        .is_some_and(|(hash, suffix)| {
+            hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+                && (suffix.is_empty() || suffix.starts_with('.'))
+        })
+}
+
+/// Names a server gives a directory's own page.
+const INDEX_NAMES: [&str; 2] = ["index.html", "index.htm"];
+
+/// The form a directory's URL is cached under when `url` is another one:
+/// `https://site/docs/` and `https://site/docs/index.html` fold into
+/// `https://site/docs`, so the three share a cache entry. `None` when `url`
+/// is already folded, isn't http(s), or has a query.
+fn folded_url(url: &str) -> Option<String> {
+    let mut parsed = url::Url::parse(url)
+        .ok()
+        .filter(|u| matches!(u.scheme(), "http" | "https") && u.query().is_none())?;
+    let path = parsed.path();
+    let dir = INDEX_NAMES
+        .iter()
+        .find_map(|name| path.strip_suffix(name).filter(|dir| dir.ends_with('/')))
+        .unwrap_or(path);
+    let folded = match dir.trim_end_matches('/') {
+        "" => "/",
+        dir => dir,
+    };
+    if folded == path {
+        return None;
+    }
+    let folded = folded.to_string();
+    parsed.set_path(&folded);
+    Some(parsed.to_string())
+}
+
+fn url_to_path(base_dir: &Path, url: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let folded = folded_url(url);
+    let parsed = url::Url::parse(folded.as_deref().unwrap_or(url))?;
+    if parsed.scheme() == "data" {
+        return Ok(base_dir
+            .join(data_url::CACHE_DIR)
+            .join(data_url::cache_file_name(url)));
+    }
+    let domain = match parsed.host_str() {
+        Some(host) => host,
+        None if parsed.scheme() == "file" => local::CACHE_DIR,
+        None => return Err("No host in URL".into()),
+    };
+    // Security: `http://../` parses with ".." as its host
+    if domain == "." || domain == ".." {
+        return Err("Invalid host in URL".into());
+    }
+
+    let mut path = base_dir.join(domain);
+
+    let url_path = parsed.path().trim_start_matches('/');
+
+    // Security: Sanitize path components to prevent directory traversal
+    let mut last_name = String::new();
+    if !url_path.is_empty() {
+        for component in url_path.split('/') {
+            if component == ".." || component == "." {
+                return Err("Invalid path component in URL".into());
+            }
+            last_name = segment_name(component);
+            if !last_name.is_empty() {
+                path.push(&last_name);
+            }
+        }
+    }
+
+    // Determine if we need to add an index file
+    if Path::new(&last_name).extension().is_none() {
+        path.push("index");
+    }
+
+    if let Some(query) = parsed.query() {
+        // Security: Sanitize query parameters for filesystem safety
+        let safe_query = query.replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], "_");
+        let current_ext = path.extension().and_then(|s| s.to_str()).unwrap_or("");
+        let new_ext = if current_ext.is_empty() {
+            format!("?{safe_query}")
+        } else {
+            format!("{current_ext}?{safe_query}")
+        };
+        path.set_extension(new_ext);
+    }
+    if let Some(name) = path.file_name().and_then(|name| name.to_str())
+        && name.len() > MAX_NAME_BYTES
+    {
+        path.set_file_name(cap_name(name.to_string()));
+    }
+
+    // Security: Verify final path is within base directory, which
+    // `starts_with` alone doesn't since it compares `..` like any component
+    let within = path.strip_prefix(base_dir).is_ok_and(|rest| {
+        rest.components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)))
+    });
+    if !within {
+        return Err("Path traversal detected".into());
+    }
+
+    Ok(path)
+}
+
+/// Records `url` in the cache `index`, with the form it's filed under, if it's
+/// another form of a directory's URL. Returns whether the index changed.
+fn record_aliases(index: &index::CacheIndex, cache_dir: &Path, path: &Path, url: &str) -> bool {
+    let Some(folded) = folded_url(url) else {
+        return false;
+    };
+    path.strip_prefix(cache_dir)
+        .is_ok_and(|relative| index.record_aliases(&relative.to_string_lossy(), &[url, &folded]))
+}
+
+/// Records `url` in the cache `index` if `path`, under `cache_dir`, doesn't
+/// spell it out. Returns whether the index changed.
+fn record_url(index: &index::CacheIndex, cache_dir: &Path, path: &Path, url: &str) -> bool {
+    path.strip_prefix(cache_dir).is_ok_and(|relative| {
+        relative
+            .iter()
+            .any(|name| is_shortened(name) || name == sidecar::DISPLACED_NAME)
+            && index.record_url(&relative.to_string_lossy(), url)
+    })
+}
+
+/// Moves a cached file that sits where `file_path`, under `root`, needs a
+/// directory into that directory as [`sidecar::DISPLACED_NAME`], with its
+/// sidecar, so both URLs stay cached. Returns the file's new path.
+async fn make_room(root: &Path, file_path: &Path) -> std::io::Result<Option<PathBuf>> {
+    let Some(relative) = file_path
+        .parent()
+        .and_then(|parent| parent.strip_prefix(root).ok())
+    else {
+        return Ok(None);
+    };
+    let mut dir = root.to_path_buf();
+    for component in relative.components() {
+        dir.push(component);
+        match fs::symlink_metadata(&dir).await {
+            Ok(meta) if meta.is_file() => {
+                let temp_path = sidecar::temp_path(&sidecar::sidecar_path(&dir));
+                fs::rename(&dir, &temp_path).await?;
+                fs::create_dir(&dir).await?;
+                let moved = dir.join(sidecar::DISPLACED_NAME);
+                fs::rename(&temp_path, &moved).await?;
+                let old_sidecar = sidecar::sidecar_path(&dir);
+                if fs::try_exists(&old_sidecar).await? {
+                    fs::rename(&old_sidecar, sidecar::sidecar_path(&moved)).await?;
+                }
+                return Ok(Some(moved));
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(None)
+}
+
+/// Whether `path` is in the cache rooted at `root`, a namespace's directory
+/// or `cache_dir` itself; namespaces are separate caches within the latter.
+fn in_cache_root(cache_dir: &Path, root: &Path, path: &Path) -> bool {
+    path.strip_prefix(root).is_ok_and(|relative| {
+        root != cache_dir
+            || !relative
+                .components()
+                .next()
+                .is_some_and(|first| first.as_os_str().to_string_lossy().starts_with('@'))
+    })
+}
+
+/// A file written to the cache.
+#[derive(Debug)]
+struct Saved {
+    path: PathBuf,
+    /// How the sections of a large file changed, when it was cached before
+    sections: Option<sections::Changes>,
+    /// URL the file was saved from, when it holds another URL's content
+    duplicate_of: Option<String>,
+}
+
+/// A write to the cache that failed.
+#[derive(Debug)]
+struct CacheWriteError {
+    action: &'static str,
+    source: std::io::Error,
+}
+
+impl CacheWriteError {
+    /// Whether nothing can be written to the cache, as on a read-only mount
+    /// or a full disk, rather than this one write failing.
+    fn unwritable(&self) -> bool {
+        use std::io::ErrorKind;
+
+        matches!(
+            self.source.kind(),
+            ErrorKind::ReadOnlyFilesystem
+                | ErrorKind::StorageFull
+                | ErrorKind::QuotaExceeded
+                | ErrorKind::PermissionDenied
+        )
+    }
+}
+
+impl std::fmt::Display for CacheWriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to {}: {}", self.action, self.source)
+    }
+}
+
+impl From<CacheWriteError> for McpError {
+    fn from(e: CacheWriteError) -> Self {
+        McpError::internal_error(e.to_string(), None)
+    }
+}
+
+async fn ensure_gitignore(base_dir: &Path) -> std::io::Result<()> {
+    let gitignore_path = base_dir.join(".gitignore");
+
+    if !gitignore_path.exists() {
+        fs::create_dir_all(base_dir).await?;
+        fs::write(&gitignore_path, "*\n").await?;
+    }
+
+    Ok(())
+}
+
+/// Which part of the page was converted by [`html_to_markdown`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum Extraction {
+    /// Elements matching the domain policy's selector
+    Selector,
+    /// Main content found by Readability
+    Readability,
+    /// Readability failed; the whole `<body>` was converted
+    Body,
+    /// Neither worked; the raw document was converted
+    FullHtml,
+}
+
+impl Extraction {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Selector => "selector",
+            Self::Readability => "readability",
+            Self::Body => "body",
+            Self::FullHtml => "full_html",
+        }
+    }
+
+    /// Fallbacks keep navigation, footers, and scripts, so callers surface them
+    /// rather than silently saving a noisy file.
+    fn fallback_warning(self, url: &str) -> Option<String> {
+        match self {
+            Self::Selector | Self::Readability => None,
+            Self::Body => Some(format!(
+                "Readability could not extract the main content of {url}; converted the whole <body> instead (may include navigation and boilerplate)"
+            )),
+            Self::FullHtml => Some(format!(
+                "Readability could not extract the main content of {url} and no <body> was found; converted the raw HTML instead (output may be noisy)"
+            )),
+        }
+    }
+}
+
+/// Converts HTML to Markdown with fallback extraction:
+/// 1. Use the elements matching the policy's selector, if any
+/// 2. Try Readability to extract `<main>`/`<article>` content (unless disabled)
+/// 3. Fall back to `<body>` content if available
+/// 4. Fall back to full HTML as last resort
+///
+/// Returns the Markdown along with the extraction step that produced it.
+fn html_to_markdown(
+    html: &str,
+    document_url: &str,
+    policy: &policy::DomainPolicy,
+) -> Result<(String, Extraction), Box<dyn std::error::Error>> {
+    if html.trim().is_empty() {
+        return Err("HTML content is empty".into());
+    }
+    let html = &*prepare_html(html);
+
+    let (html_to_convert, extraction) = policy
+        .selector
+        .as_deref()
+        .and_then(|selector| select_html(html, selector))
+        .map(|selected| (selected, Extraction::Selector))
+        .or_else(|| {
+            policy
+                .use_readability()
+                .then(|| readability_html(html, document_url))
+                .flatten()
+                .map(|article| (article, Extraction::Readability))
+        })
+        .or_else(|| extract_body(html).map(|body| (body, Extraction::Body)))
+        .unwrap_or_else(|| (html.to_string(), Extraction::FullHtml));
+
+    let markdown = admonitions::tidy(&html2md::parse_html(&html_to_convert)).into_owned();
+
+    if markdown.trim().is_empty() {
+        return Err("Extracted content is empty (page may have no readable content)".into());
+    }
+
+    Ok((markdown, extraction))
+}
+
+/// `html` as the extraction steps see it: large inline data cut, and tabs,
+/// admonitions and `<details>` rewritten so they survive the conversion.
+fn prepare_html(html: &str) -> Cow<'_, str> {
+    let mut prepared = inline_data::strip(html);
+    for pass in [tabs::prepare, admonitions::prepare, details::prepare] {
+        let changed = match pass(&prepared) {
+            Cow::Owned(changed) => Some(changed),
+            Cow::Borrowed(_) => None,
+        };
+        if let Some(changed) = changed {
+            prepared = Cow::Owned(changed);
+        }
+    }
+    prepared
+}
+
+/// The main content Readability finds in `html`, if any.
+fn readability_html(html: &str, document_url: &str) -> Option<String> {
+    let cfg = Config {
+        text_mode: TextMode::Raw,
+        ..Default::default()
+    };
+    Readability::new(html, Some(document_url), Some(cfg))
+        .ok()
+        .and_then(|mut r| r.parse().ok())
+        .map(|article| article.content.to_string())
+        .filter(|content| !content.trim().is_empty())
+}
+
+/// Converts fetched HTML, recording a warning (also logged to stderr) when
+/// extraction had to fall back.
+fn convert_html(
+    html: &str,
+    url: &str,
+    policy: &policy::DomainPolicy,
+    warnings: &mut Vec<String>,
+) -> Result<String, McpError> {
+    convert_html_step(html, url, policy, warnings).map(|(markdown, _)| markdown)
+}
+
+/// [`convert_html`], also returning the extraction step it used.
+fn convert_html_step(
+    html: &str,
+    url: &str,
+    policy: &policy::DomainPolicy,
+    warnings: &mut Vec<String>,
+) -> Result<(String, Extraction), McpError> {
+    let (markdown, extraction) = html_to_markdown(html, url, policy).map_err(|e| {
+        McpError::internal_error(format!("Failed to convert HTML to markdown: {e}"), None)
+    })?;
+    let mut new_warnings = Vec::new();
+    if let Some(selector) = &policy.selector
+        && extraction != Extraction::Selector
+    {
+        new_warnings.push(format!(
+            "Selector `{selector}` matched nothing on {url}; used the default extraction"
+        ));
+    }
+    // The page's own table of contents restores headings the conversion
+    // flattened, and shows when extraction lost sections
+    let entries = page_toc::extract(html);
+    let markdown = if entries.is_empty() {
+        markdown
+    } else {
+        let applied = page_toc::apply(&markdown, &entries);
+        // A selector picks part of the page on purpose
+        if extraction != Extraction::Selector {
+            new_warnings.extend(page_toc::missing_warning(url, &applied.missing));
+        }
+        applied.markdown
+    };
+    // Citations link to the page's own ids rather than guessed slugs
+    let markdown = heading_ids::apply(&markdown, &heading_ids::extract(html));
+    // FAQ answers are often collapsed behind scripts, out of the converted page
+    let faq = jsonld::read(html).faq;
+    let markdown = match jsonld::faq_markdown(&faq, &markdown) {
+        Some(section) => format!("{}\n\n{section}", markdown.trim_end()),
+        None => markdown,
+    };
+    // Converting the whole body is the intent when Readability is disabled
+    if policy.use_readability()
+        && let Some(warning) = extraction.fallback_warning(url)
+    {
+        new_warnings.push(warning);
+    }
+    for warning in new_warnings {
+        eprintln!("warning: {warning}");
+        warnings.push(warning);
+    }
+    Ok((markdown, extraction))
+}
+
+/// Outer HTML of every element matching `selector`, a CSS selector list or
+/// an `xpath:` expression, in document order, or `None` if nothing
+/// (non-empty) matches.
+fn select_html(html: &str, selector: &str) -> Option<String> {
+    let document = dom_query::Document::from(html);
+    let nodes = match selector.strip_prefix(policy::XPATH_PREFIX) {
+        #[cfg(feature = "xpath")]
+        Some(expression) => xpath::select(&document, expression)?,
+        #[cfg(not(feature = "xpath"))]
+        Some(_) => return None,
+        None => document.try_select(selector)?.nodes().to_vec(),
+    };
+    // Matches come in document order; one inside another is already part of it
+    let matched: HashSet<dom_query::NodeId> = nodes.iter().map(|node| node.id).collect();
+    let selected: Vec<String> = nodes
+        .iter()
+        .filter(|node| {
+            !node
+                .ancestors_it(None)
+                .any(|ancestor| matched.contains(&ancestor.id))
+        })
+        .map(|node| node.html().to_string())
+        .collect();
+    let joined = selected.join("\n");
+    (!joined.trim().is_empty()).then_some(joined)
+}
+
+/// The canonical URL of an AMP page (`<html amp>` or `<html ⚡>` with a
+/// `<link rel="canonical">`), if it differs from `page_url`.
+fn amp_canonical(html: &str, page_url: &str) -> Option<String> {
+    let document = dom_query::Document::from(html);
+    let root = document.select("html");
+    if !(root.has_attr("amp") || root.has_attr("⚡")) {
+        return None;
+    }
+    let href = document.select("link[rel~='canonical']").attr("href")?;
+    let canonical = url::Url::parse(page_url).ok()?.join(href.trim()).ok()?;
+    matches!(canonical.scheme(), "http" | "https")
+        .then(|| canonical.to_string())
+        .filter(|canonical| canonical != page_url)
+}
+
+/// The URLs other than `page_url` that a page names as its canonical or
+/// language versions.
+fn page_alternates(html: &str, page_url: &str) -> Vec<String> {
+    let document = dom_query::Document::from(html);
+    let canonical = document
+        .select("link[rel~='canonical']")
+        .attr("href")
+        .and_then(|href| url::Url::parse(page_url).ok()?.join(href.trim()).ok())
+        .filter(|canonical| matches!(canonical.scheme(), "http" | "https"))
+        .map(String::from);
+    let languages = hreflang::Languages::discover(html, None, None, page_url);
+    let mut alternates: Vec<String> = Vec::new();
+    for url in canonical
+        .into_iter()
+        .chain(languages.alternates.into_iter().map(|a| a.url))
+    {
+        if url != page_url && !alternates.contains(&url) {
+            alternates.push(url);
+        }
+    }
+    alternates
+}
+
+fn extract_body(html: &str) -> Option<String> {
+    let lower = html.to_lowercase();
+    let start = lower.find("<body")?;
+    let body_start = lower[start..].find('>')? + start + 1;
+    let body_end = lower.rfind("</body>")?;
+
+    if body_end >= body_start {
+        Some(html[body_start..body_end].to_string())
+    } else {
+        None
+    }
+}
+
+/// Characters of each Markdown excerpt `debug_convert` returns.
+const DEBUG_EXCERPT_CHARS: usize = 1500;
+
+/// What each extraction step of [`html_to_markdown`] gets from `result`, the
+/// step it chose, and the Markdown `fetch` would save, for `debug_convert`.
+/// Unlike a conversion, every step runs on the [`prepare_html`] output so
+/// their outputs can be compared.
+fn debug_conversion(
+    result: &FetchResult,
+    policy: &policy::DomainPolicy,
+    bold_headings: bool,
+    normalize: normalize::Normalize,
+) -> DebugConvertOutput {
+    let (html, url) = (result.content.as_str(), result.url.as_str());
+    let prepared = &*prepare_html(html);
+    let step = |step, extracted: Result<String, String>| match extracted {
+        Ok(extracted) => ExtractionStep {
+            step,
+            skipped: None,
+            html_bytes: extracted.len(),
+            markdown: Some(Excerpt::new(&admonitions::tidy(&html2md::parse_html(
+                &extracted,
+            )))),
+        },
+        Err(reason) => ExtractionStep {
+            step,
+            skipped: Some(reason),
+            html_bytes: 0,
+            markdown: None,
+        },
+    };
+    let selector = match policy.selector.as_deref() {
+        None => Err("no selector is configured for this domain".to_string()),
+        Some(selector) => {
+            select_html(prepared, selector).ok_or_else(|| format!("`{selector}` matched nothing"))
+        }
+    };
+    let readability = if policy.use_readability() {
+        readability_html(prepared, url).ok_or_else(|| "found no main content".to_string())
+    } else {
+        Err("disabled by the domain policy".to_string())
+    };
+    let body = extract_body(prepared).ok_or_else(|| "no <body> found".to_string());
+    let steps = vec![
+        step(Extraction::Selector, selector),
+        step(Extraction::Readability, readability),
+        step(Extraction::Body, body),
+        step(Extraction::FullHtml, Ok(prepared.to_string())),
+    ];
+
+    let mut warnings = Vec::new();
+    let (chosen, markdown) = match convert_html_step(html, url, policy, &mut warnings) {
+        Ok((markdown, extraction)) => {
+            let markdown = normalize::apply(&markdown, normalize).into_owned();
+            let markdown = bold_headings
+                .then(|| toc::promote_bold_headings(&markdown))
+                .flatten()
+                .unwrap_or(markdown);
+            (Some(extraction), Some(Excerpt::new(&markdown)))
+        }
+        Err(e) => {
+            warnings.push(e.message.to_string());
+            (None, None)
+        }
+    };
+    DebugConvertOutput {
+        url: result.url.clone(),
+        content_type: result.headers.content_type.clone(),
+        html_bytes: html.len(),
+        chosen,
+        steps,
+        markdown,
+        warnings,
+    }
+}
+
+/// Counts of a document plus a hash of its bytes for deduplication.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Stats {
+    lines: usize,
+    words: usize,
+    characters: usize,
+    hash: u64,
+}
+
+/// Computes [`Stats`] in a single pass, matching `lines()`, `split_whitespace()`
+/// and `chars()` counts. Hashing line by line keeps each chunk in cache while
+/// it is counted.
+fn count_stats(content: &str) -> Stats {
+    let mut hasher = DefaultHasher::new();
+    let mut stats = Stats::default();
+    for line in content.split_inclusive('\n') {
+        hasher.write(line.as_bytes());
+        stats.lines += 1;
+        let mut in_word = false;
+        for c in line.chars() {
+            stats.characters += 1;
+            let is_space = c.is_whitespace();
+            if !is_space && !in_word {
+                stats.words += 1;
+            }
+            in_word = !is_space;
+        }
+    }
+    stats.hash = hasher.finish();
+    stats
+}
+
+/// A successful variation converted to Markdown, ready to be saved.
+struct Converted {
+    content: String,
+    hash: u64,
+    info: FileInfo,
+    warnings: Vec<String>,
+}
+
+/// Converts `result` to Markdown if needed and builds its `FileInfo`.
+fn convert_result(
+    result: FetchResult,
+    policy: &policy::DomainPolicy,
+    live: &Live,
+    root: &Path,
+) -> Result<Converted, McpError> {
+    let url_lower = result.url.to_lowercase();
+    let content_type = if url_lower.contains("/llms-full.txt") {
+        "llms-full"
+    } else if url_lower.contains("/llms.txt") {
+        "llms"
+    } else if result.is_markdown {
+        "markdown"
+    } else if result.is_html {
+        "html-converted"
+    } else {
+        "text"
+    };
+
+    let languages = hreflang::Languages::discover(
+        if result.is_html { &result.content } else { "" },
+        None,
+        result.headers.link.as_deref(),
+        &result.url,
+    );
+    let mut warnings = Vec::new();
+    let converted_html = result.is_html && !result.is_markdown;
+    let metadata = converted_html
+        .then(|| jsonld::read(&result.content).metadata)
+        .flatten();
+    let inline_data_bytes = converted_html
+        .then(|| inline_data::size(&result.content))
+        .filter(|&bytes| bytes > 0);
+    let mut content = if converted_html {
+        let markdown = convert_html(&result.content, &result.url, policy, &mut warnings)?;
+        let level = policy.normalize.unwrap_or(live.normalize);
+        normalize::apply(&markdown, level).into_owned()
+    } else {
+        result.content
+    };
+    if matches!(content_type, "html-converted" | "markdown")
+        && policy.bold_headings.unwrap_or(live.bold_headings)
+        && let Some(promoted) = toc::promote_bold_headings(&content)
+    {
+        content = promoted;
+    }
+
+    let stats = count_stats(&content);
+    let file_path = url_to_path(root, &result.url)
+        .map_err(|e| McpError::internal_error(format!("Failed to create cache path: {e}"), None))?;
+    let mut info = live.file_info(&file_path, &result.url, content_type, &content, stats);
+    // Local files have no response headers to report
+    info.http = (result.headers != sidecar::HttpHeaders::default()).then_some(result.headers);
+    info.languages = languages.alternates;
+    info.metadata = metadata;
+    info.inline_data_bytes = inline_data_bytes;
+    info.aliases = result.aliases;
+    if let (Some(preferred), Some(detected)) = (&live.language, &info.language)
+        && !hreflang::same_primary(preferred, detected)
+    {
+        let warning = format!(
+            "{} appears to be in {detected}, not the preferred language {preferred}",
+            result.url
+        );
+        eprintln!("warning: {warning}");
+        warnings.push(warning);
+    }
+
+    Ok(Converted {
+        content,
+        hash: stats.hash,
+        info,
+        warnings,
+    })
+}
+
+/// The first `count` lines of `content`, without the final line break.
+fn head_lines(content: &str, count: usize) -> &str {
+    let end = content
+        .match_indices('\n')
+        .nth(count.saturating_sub(1))
+        .map_or(content.len(), |(i, _)| i);
+    if count == 0 { "" } else { &content[..end] }
+}
+
+/// Format file infos as human-readable text for LLM consumption.
+fn format_output(files: &[FileInfo], warnings: &[String]) -> String {
+    use std::fmt::Write;
+
+    let mut output = String::new();
+
+    if files.is_empty() {
+        output.push_str("No files fetched.\n");
+    }
+
+    for (i, f) in files.iter().enumerate() {
+        if i > 0 {
+            writeln!(output).unwrap();
+        }
+        writeln!(output, "## {}", f.source_url).unwrap();
+        match f.storage {
+            Storage::Disk => writeln!(output, "Saved to: {}", f.path).unwrap(),
+            Storage::Memory => writeln!(output, "Kept in memory: {}", f.path).unwrap(),
+            Storage::Unsaved => writeln!(output, "Not saved: the cache isn't writable").unwrap(),
+        }
+        let language = f
+            .language
+            .as_deref()
+            .map(|language| format!(", language: {language}"))
+            .unwrap_or_default();
+        writeln!(
+            output,
+            "Type: {} ({} lines, {} words, {} chars{language})",
+            f.content_type, f.lines, f.words, f.characters
+        )
+        .unwrap();
+        if let Some(version) = &f.version {
+            writeln!(
+                output,
+                "Version: {version} (the `versions` tool lists the others)"
+            )
+            .unwrap();
+        }
+        if !f.languages.is_empty() {
+            let languages: Vec<String> = f
+                .languages
+                .iter()
+                .map(|a| format!("{} ({})", a.language, a.url))
+                .collect();
+            writeln!(output, "Languages: {}", languages.join(", ")).unwrap();
+        }
+        if let Some(metadata) = &f.metadata {
+            writeln!(output, "Structured data: {metadata}").unwrap();
+        }
+        if let Some(bytes) = f.inline_data_bytes {
+            writeln!(
+                output,
+                "Inline data: {bytes} bytes of scripts and state left out of the conversion"
+            )
+            .unwrap();
+        }
+        if let Some(sections) = &f.sections {
+            writeln!(output, "Sections: {sections}").unwrap();
+        }
+        if !f.aliases.is_empty() {
+            writeln!(output, "Aliases: {}", f.aliases.join(", ")).unwrap();
+        }
+        if let Some(original) = &f.duplicate_of {
+            writeln!(output, "Same content as {original}; saved once").unwrap();
+        }
+
         if let Some(toc) = &f.table_of_contents {
             writeln!(output).unwrap();
             writeln!(output, "### Table of Contents").unwrap();
             writeln!(output, "{toc}").unwrap();
-        } else if let Some(content) = &f.content {
+        }
+        if f.truncated_preview
+            && let Some(content) = &f.content
+        {
+            writeln!(output).unwrap();
+            writeln!(
+                output,
+                "### Preview (first {} of {} lines; read the saved file in parts for the rest)",
+                content.lines().count(),
+                f.lines
+            )
+            .unwrap();
+            writeln!(output, "{content}").unwrap();
+        } else if (f.table_of_contents.is_none() || !f.storage.on_disk())
+            && let Some(content) = &f.content
+        {
             writeln!(output).unwrap();
             writeln!(output, "### Content").unwrap();
             writeln!(output, "{content}").unwrap();
         }
     }
 
-    output.trim_end().to_string()
-}
+    if !warnings.is_empty() {
+        writeln!(output).unwrap();
+        writeln!(output, "### Warnings").unwrap();
+        for warning in warnings {
+            writeln!(output, "- {warning}").unwrap();
+        }
+    }
+
+    output.trim_end().to_string()
+}
+
+/// A way to recover from a failed fetch, listed in the error's data.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum NextStep {
+    /// Fetch another URL that likely has the content
+    Fetch { url: String, reason: String },
+    /// Retry after changing a `fetch` parameter or server setting
+    Setting { name: String, hint: String },
+}
+
+impl NextStep {
+    fn fetch(url: impl Into<String>, reason: &str) -> Self {
+        Self::Fetch {
+            url: url.into(),
+            reason: reason.to_string(),
+        }
+    }
+
+    fn setting(name: &str, hint: impl Into<String>) -> Self {
+        Self::Setting {
+            name: name.to_string(),
+            hint: hint.into(),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::Fetch { url, reason } => format!("fetch {url} ({reason})"),
+            Self::Setting { hint, .. } => hint.clone(),
+        }
+    }
+}
+
+/// Ways to recover from the failed fetch of `url` that follow from what
+/// happened to its `variations`: URLs the error page pointed to, credentials
+/// or pacing for the site, and the Internet Archive's copy. Sitemap matches
+/// need requests and are added by the caller.
+fn next_steps(url: &str, variations: &[Variation], allow_headers: &[String]) -> Vec<NextStep> {
+    let mut steps = Vec::new();
+    let page = url::Url::parse(url)
+        .ok()
+        .filter(|page| matches!(page.scheme(), "http" | "https"));
+    let host = page
+        .as_ref()
+        .and_then(|page| page.host_str())
+        .unwrap_or_default()
+        .to_string();
+    let original = variations.iter().find(|v| v.url == url).map(|v| &v.outcome);
+
+    if let Some(Outcome::SoftNotFound { reason, alternates }) = original {
+        for alternate in alternates {
+            steps.push(NextStep::fetch(
+                alternate,
+                "the error page names it as the canonical or another language version",
+            ));
+        }
+        if reason == "the page is empty" {
+            steps.push(NextStep::setting(
+                "selector",
+                format!(
+                    "nothing was extracted from the page; a [domains.\"{host}\"] policy with a \
+                     `selector` for the content or `readability = false` may find it, unless \
+                     the page is rendered by JavaScript"
+                ),
+            ));
+        }
+    }
+    if variations
+        .iter()
+        .any(|v| matches!(v.outcome, Outcome::Drifted { .. }))
+    {
+        steps.push(NextStep::setting(
+            "lockfile-drift",
+            "the content changed since it was locked and the locked copy is still cached; to \
+             accept the new version, fetch it with `warn` and run `llms-fetch-mcp lock`",
+        ));
+    }
+    let unauthorized = matches!(
+        original,
+        Some(Outcome::HttpError { status: 401 | 403 } | Outcome::AccessRestricted { .. })
+    );
+    if unauthorized {
+        steps.extend(credential_steps(&host, allow_headers));
+    }
+    if variations
+        .iter()
+        .any(|v| matches!(v.outcome, Outcome::HttpError { status: 429 }))
+    {
+        steps.push(NextStep::setting(
+            "requests-per-second",
+            format!(
+                "the site is rate limiting requests; retry later, and set requests-per-second \
+                 in a [domains.\"{host}\"] policy"
+            ),
+        ));
+    }
+    match original {
+        Some(Outcome::RedirectNotFollowed {
+            location: Some(location),
+            ..
+        }) => steps.push(NextStep::fetch(
+            location,
+            "the page redirects there; fetch it to follow the redirect",
+        )),
+        Some(Outcome::RedirectNotFollowed { reason, .. }) if reason.starts_with("more than") => {
+            steps.push(NextStep::setting(
+                "max-redirects",
+                "the page redirects more times than allowed; raise --max-redirects",
+            ));
+        }
+        _ => {}
+    }
+    if matches!(original, Some(Outcome::TimedOut)) {
+        steps.push(NextStep::setting(
+            "fetch-timeout",
+            "the page didn't arrive in time; raise --fetch-timeout",
+        ));
+    }
+
+    // Archived copies help with pages that are gone, blocked or down, but
+    // only public sites get archived
+    let archivable = page
+        .as_ref()
+        .is_some_and(|page| matches!(page.host(), Some(url::Host::Domain(d)) if d != "localhost"));
+    let worth_archive = matches!(
+        original,
+        Some(
+            Outcome::HttpError { .. }
+                | Outcome::NetworkError
+                | Outcome::TimedOut
+                | Outcome::SoftNotFound { .. }
+        )
+    );
+    if archivable && worth_archive {
+        steps.push(NextStep::fetch(
+            format!("https://web.archive.org/web/{url}"),
+            "the Internet Archive's latest snapshot, if it has one",
+        ));
+    }
+    steps
+}
+
+/// Ways to pass credentials for `host`, whose pages need a login.
+fn credential_steps(host: &str, allow_headers: &[String]) -> [NextStep; 2] {
+    let hint = if allow_headers.iter().any(|name| name == "authorization") {
+        "pass credentials for the site in the `headers` parameter, e.g. an Authorization header"
+    } else {
+        "pass credentials for the site in the `headers` parameter, after permitting the header \
+         with --allow-header Authorization"
+    };
+    [
+        NextStep::setting("headers", hint),
+        NextStep::setting(
+            "cookie",
+            format!(
+                "start the server with --cookie {host}=COOKIES holding a browser session for the site"
+            ),
+        ),
+    ]
+}
+
+/// Whether `url` itself doesn't exist: HTTP 404 or 410, or an error page
+/// served as a success.
+fn page_missing(url: &str, variations: &[Variation]) -> bool {
+    variations.iter().any(|v| {
+        v.url == url
+            && match &v.outcome {
+                Outcome::HttpError { status } => index::is_negative_status(*status),
+                Outcome::SoftNotFound { .. } => true,
+                _ => false,
+            }
+    })
+}
+
+/// `message` followed by the next steps, if any.
+fn with_next_steps(mut message: String, steps: &[NextStep]) -> String {
+    if !steps.is_empty() {
+        let described: Vec<String> = steps.iter().map(NextStep::describe).collect();
+        message.push_str(". Next steps: ");
+        message.push_str(&described.join("; "));
+    }
+    message
+}
+
+/// The `access_restricted` error for a fetch whose only content was behind a
+/// paywall or login, or `None` if no variation was restricted.
+fn access_restricted(url: &str, variations: &[Variation], steps: &[NextStep]) -> Option<McpError> {
+    let restricted: Vec<String> = variations
+        .iter()
+        .filter(|v| matches!(v.outcome, Outcome::AccessRestricted { .. }))
+        .map(Variation::describe)
+        .collect();
+    if restricted.is_empty() {
+        return None;
+    }
+    let message = format!(
+        "Access restricted: {url} requires a subscription or login ({}). \
+         Nothing was cached; look for a public source instead",
+        restricted.join("; ")
+    );
+    Some(McpError::invalid_request(
+        with_next_steps(message, steps),
+        Some(serde_json::json!({
+            "error": "access_restricted",
+            "url": url,
+            "next_steps": steps,
+        })),
+    ))
+}
+
+/// The error for a fetch that saved nothing, listing what happened to each
+/// variation and what to try next.
+fn fetch_failed(url: &str, variations: &[Variation], steps: &[NextStep]) -> McpError {
+    let failures: Vec<String> = variations.iter().map(Variation::describe).collect();
+    let message = format!(
+        "Failed to fetch content from {url} ({})",
+        failures.join("; ")
+    );
+    let missing = page_missing(url, variations);
+    McpError::resource_not_found(
+        with_next_steps(message, steps),
+        Some(serde_json::json!({
+            "error": if missing { "not_found" } else { "fetch_failed" },
+            "url": url,
+            "next_steps": steps,
+        })),
+    )
+}
+
+fn quota_error(exceeded: quota::Exceeded) -> McpError {
+    McpError::invalid_request(exceeded.to_string(), None)
+}
+
+/// The start page of a crawl tools are asked for, which must be http(s).
+fn crawl_start(url: &str) -> Result<url::Url, McpError> {
+    url::Url::parse(url)
+        .ok()
+        .filter(|u| matches!(u.scheme(), "http" | "https"))
+        .ok_or_else(|| {
+            McpError::invalid_params(
+                format!("Only http(s) sites can be crawled, not \"{url}\""),
+                None,
+            )
+        })
+}
+
+/// Fails a crawl that couldn't read its start page, with the first warning
+/// as the reason.
+fn check_crawled(
+    start: &url::Url,
+    crawled: &[(crawl::Page, PathBuf)],
+    warnings: &[String],
+) -> Result<(), McpError> {
+    if crawled
+        .first()
+        .is_none_or(|(page, _)| page.url != start.as_str())
+    {
+        return Err(McpError::resource_not_found(
+            format!(
+                "Failed to crawl {start}: {}",
+                warnings.first().map_or("not a text page", String::as_str)
+            ),
+            None,
+        ));
+    }
+    Ok(())
+}
+
+#[tool_router]
+impl FetchServer {
+    fn new(settings: &Settings) -> Result<Self, Box<dyn std::error::Error>> {
+        let cache_path = &settings.cache_dir;
+        // Ensure cache_dir is absolute for security (prevents relative path bypass)
+        let absolute_cache = if settings.ephemeral {
+            PathBuf::from(memory::ROOT)
+        } else {
+            cache_path.canonicalize().unwrap_or_else(|_| {
+                // If path doesn't exist, make it absolute relative to current dir
+                std::env::current_dir()
+                    .unwrap_or_else(|_| PathBuf::from("/tmp"))
+                    .join(cache_path)
+            })
+        };
+
+        // Persisted cookies would be written to the cache directory
+        let cookie_key = settings
+            .cookie_key
+            .as_deref()
+            .filter(|_| !settings.ephemeral);
+        let cookies = Arc::new(cookies::CookieJar::load(&absolute_cache, cookie_key)?);
+        cookies.seed(&settings.cookies);
+        let pins = Arc::new(pins::Pins::default());
+        pins.configure(&settings.pins, settings.namespace.as_deref());
+
+        Ok(Self {
+            index: Arc::new(index::CacheIndex::load(&absolute_cache)),
+            live: Arc::new(RwLock::new(Arc::new(Live::new(settings, &cookies)?))),
+            cookies,
+            audit_log: Arc::new(audit::AuditLog::new(&absolute_cache)),
+            conversions: Arc::new(tokio::sync::Semaphore::new(
+                std::thread::available_parallelism().map_or(4, std::num::NonZero::get),
+            )),
+            refreshing: Arc::default(),
+            pins,
+            watches: Arc::default(),
+            #[cfg(feature = "search-index")]
+            search_indexes: Arc::default(),
+            memory: settings.ephemeral.then(Arc::default),
+            cache_dir: Arc::new(absolute_cache),
+            throttle: Arc::new(policy::Throttle::default()),
+            usage: Arc::new(quota::Usage::default()),
+            stats: Arc::new(stats::Stats::default()),
+            protocol_version: Arc::new(OnceLock::new()),
+            update: Arc::new(OnceLock::new()),
+            tool_router: Self::tool_router(),
+        })
+    }
+
+    fn live(&self) -> Arc<Live> {
+        Arc::clone(&self.live.read().unwrap())
+    }
+
+    /// Where cached files are read from.
+    fn store(&self) -> memory::Store {
+        match &self.memory {
+            Some(memory) => memory::Store::Memory(Arc::clone(memory)),
+            None => memory::Store::Disk,
+        }
+    }
+
+    /// Applies reloaded settings to subsequent tool calls. The cache directory
+    /// and the cookie key are fixed for the server's lifetime.
+    fn reload(&self, settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
+        let live = Live::new(settings, &self.cookies)?;
+        self.cookies.seed(&settings.cookies);
+        self.pins
+            .configure(&settings.pins, settings.namespace.as_deref());
+        *self.live.write().unwrap() = Arc::new(live);
+        Ok(())
+    }
+
+    /// Structured tool output was added in the 2025-06-18 revision; older
+    /// clients only get the text content.
+    fn structured_output(&self) -> bool {
+        self.protocol_version
+            .get()
+            .is_some_and(|version| *version >= ProtocolVersion::V_2025_06_18)
+    }
+
+    /// Resolves the directory cached files are written to: the cache directory
+    /// itself, or the namespace's directory within it.
+    fn cache_root(&self, namespace: Option<&str>) -> Result<PathBuf, McpError> {
+        match namespace {
+            None => Ok(self.cache_dir.to_path_buf()),
+            Some(name) => config::parse_namespace(name)
+                .map(|name| namespace_dir(&self.cache_dir, &name))
+                .map_err(|e| McpError::invalid_params(e, None)),
+        }
+    }
+
+    /// Records `url` in the cache index if `path` doesn't spell it out.
+    /// Returns whether the index changed.
+    fn record_url(&self, path: &Path, url: &str) -> bool {
+        record_url(&self.index, &self.cache_dir, path, url)
+    }
+
+    /// Writes content to its cache path for `url` under `root` along with its
+    /// metadata sidecar, returning the path.
+    ///
+    /// Content identical to what a large file already holds isn't written
+    /// again, and how its sections changed is returned with the path. Under
+    /// `--dedup-scope domain` or `global`, content another file already holds
+    /// isn't written either: `url` is recorded as an alias of that file, and
+    /// its path is returned.
+    async fn save_to_cache(
+        &self,
+        root: &Path,
+        url: &str,
+        content: &str,
+        http: Option<&sidecar::HttpHeaders>,
+    ) -> Result<Saved, CacheWriteError> {
+        let failed = |action| move |source| CacheWriteError { action, source };
+        let file_path = url_to_path(root, url)
+            .map_err(|e| failed("create cache path")(std::io::Error::other(e.to_string())))?;
+        let file_path = self.store().locate_async(file_path).await;
+        let metadata = sidecar::Sidecar {
+            url: idn::unicode_url(url).into_owned(),
+            fetched_at: index::unix_now(),
+            http: http.cloned(),
+            version: versions::version_of(url),
+            sha256: Some(sidecar::sha256(content.as_bytes())),
+            sections: sections::hash(content),
+        };
+        if let Some(memory) = &self.memory {
+            memory.insert(file_path.clone(), content, metadata);
+            return Ok(Saved {
+                path: file_path,
+                sections: None,
+                duplicate_of: None,
+            });
+        }
+
+        ensure_gitignore(&self.cache_dir)
+            .await
+            .map_err(failed("create .gitignore"))?;
+
+        let dedup_scope = self.live().dedup_scope;
+        if let Some(saved) = self
+            .save_as_duplicate(root, &file_path, &metadata, dedup_scope)
+            .await
+        {
+            return Ok(saved);
+        }
+
+        let previous = if metadata.sections.is_empty() {
+            None
+        } else {
+            sidecar::read(&file_path).await
+        };
+        let changes = previous
+            .as_ref()
+            .filter(|previous| !previous.sections.is_empty())
+            .map(|previous| sections::compare(&previous.sections, &metadata.sections));
+        let unchanged = previous.is_some_and(|previous| previous.sha256 == metadata.sha256)
+            && fs::read(&file_path)
+                .await
+                .is_ok_and(|cached| cached == content.as_bytes());
+
+        let mut moved = None;
+        if !unchanged {
+            moved = make_room(root, &file_path)
+                .await
+                .map_err(failed("move a cached file aside"))?;
+            if let Some(parent) = file_path.parent() {
+                fs::create_dir_all(parent)
+                    .await
+                    .map_err(failed("create directory"))?;
+            }
+
+            // Atomic write: temp file + rename to prevent corruption from concurrent writes
+            let temp_path = sidecar::temp_path(&file_path);
+            fs::write(&temp_path, content)
+                .await
+                .map_err(failed("write temp file"))?;
+            fs::rename(&temp_path, &file_path)
+                .await
+                .map_err(failed("finalize file"))?;
+        }
+
+        sidecar::write(&file_path, &metadata)
+            .await
+            .map_err(failed("write metadata sidecar"))?;
+        // Paths that can't be read back as the URL keep it in the index
+        let mut recorded = false;
+        if let Some(moved) = moved
+            && let Some(meta) = sidecar::read(&moved).await
+        {
+            recorded |= self.record_url(&moved, &meta.url);
+        }
+        recorded |= self.record_url(&file_path, &metadata.url);
+        recorded |= record_aliases(&self.index, &self.cache_dir, &file_path, &metadata.url);
+        if let Ok(relative) = file_path.strip_prefix(self.cache_dir.as_path()) {
+            let relative = relative.to_string_lossy();
+            recorded |= self.index.forget_alias(&metadata.url, &relative);
+            if dedup_scope != index::DedupScope::Call
+                && let Some(sha256) = &metadata.sha256
+            {
+                recorded |= self.index.record_hash(sha256, &relative);
+            }
+        }
+        if recorded && let Err(e) = self.index.save().await {
+            eprintln!("warning: Failed to update cache index: {e}");
+        }
+        #[cfg(feature = "search-index")]
+        if !unchanged {
+            self.index_in_background(root, &file_path, url, content);
+        }
+
+        Ok(Saved {
+            path: file_path,
+            sections: changes,
+            duplicate_of: None,
+        })
+    }
+
+    /// Records the URL of `metadata` as an alias of the cached file under
+    /// `root` that already holds its content, if `scope` lets it share one.
+    async fn save_as_duplicate(
+        &self,
+        root: &Path,
+        file_path: &Path,
+        metadata: &sidecar::Sidecar,
+        scope: index::DedupScope,
+    ) -> Option<Saved> {
+        let (path, original) = self
+            .cached_duplicate(
+                root,
+                file_path,
+                &metadata.url,
+                metadata.sha256.as_deref()?,
+                scope,
+            )
+            .await?;
+        self.record_folded(&path, std::slice::from_ref(&metadata.url))
+            .await;
+        Some(Saved {
+            path,
+            sections: None,
+            duplicate_of: Some(original),
+        })
+    }
+
+    /// The cached file under `root` that already holds content with hash
+    /// `sha256`, and the URL it was saved from, if `scope` lets `url` share it
+    /// rather than be saved to `file_path`. URLs already cached at their own
+    /// path keep their file.
+    async fn cached_duplicate(
+        &self,
+        root: &Path,
+        file_path: &Path,
+        url: &str,
+        sha256: &str,
+        scope: index::DedupScope,
+    ) -> Option<(PathBuf, String)> {
+        if scope == index::DedupScope::Call || fs::try_exists(file_path).await.unwrap_or(true) {
+            return None;
+        }
+        let path = self.cache_dir.join(self.index.path_with_hash(sha256)?);
+        if !in_cache_root(&self.cache_dir, root, &path) {
+            return None;
+        }
+        let meta = sidecar::read(&path).await?;
+        let host = |url: &str| url::Url::parse(url).ok()?.host_str().map(str::to_string);
+        let matches = meta.sha256.as_deref() == Some(sha256)
+            && (scope == index::DedupScope::Global || host(&meta.url) == host(url))
+            && fs::try_exists(&path).await.unwrap_or(false);
+        matches.then_some((path, meta.url))
+    }
+
+    /// Records `urls` as aliases of the cached file at `path`, so looking up
+    /// any of them finds it.
+    async fn record_folded(&self, path: &Path, urls: &[String]) {
+        if urls.is_empty() || self.memory.is_some() {
+            return;
+        }
+        let urls: Vec<String> = urls
+            .iter()
+            .map(|url| idn::unicode_url(url).into_owned())
+            .collect();
+        let urls: Vec<&str> = urls.iter().map(String::as_str).collect();
+        let recorded = path
+            .strip_prefix(self.cache_dir.as_path())
+            .is_ok_and(|relative| {
+                self.index
+                    .record_aliases(&relative.to_string_lossy(), &urls)
+            });
+        if recorded && let Err(e) = self.index.save().await {
+            eprintln!("warning: Failed to update cache index: {e}");
+        }
+    }
+
+    /// Saves a converted file like [`Self::save_to_cache`], but when the cache
+    /// can't be written at all, puts the whole document in `info` with a
+    /// warning instead of failing. Files kept in memory also get the whole
+    /// document in `info`.
+    async fn save_or_inline(
+        &self,
+        root: &Path,
+        info: &mut FileInfo,
+        content: &str,
+        warnings: &mut Vec<String>,
+    ) -> Result<(), McpError> {
+        match self
+            .save_to_cache(root, &info.source_url, content, info.http.as_ref())
+            .await
+        {
+            Ok(saved) => {
+                info.sections = saved.sections;
+                if saved.duplicate_of.is_some() {
+                    info.path = saved.path.to_string_lossy().into_owned();
+                    info.duplicate_of = saved.duplicate_of;
+                }
+                self.record_folded(&saved.path, &info.aliases).await;
+                if self.memory.is_some() {
+                    info.off_disk(Storage::Memory, content);
+                }
+                Ok(())
+            }
+            Err(e) if e.unwritable() => {
+                warnings.push(format!(
+                    "{e}; {} is returned in full instead of cached",
+                    info.source_url
+                ));
+                info.off_disk(Storage::Unsaved, content);
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Adds a file just written to the cache to the search index of `root`.
+    #[cfg(feature = "search-index")]
+    fn index_in_background(&self, root: &Path, file_path: &Path, url: &str, content: &str) {
+        let indexes = Arc::clone(&self.search_indexes);
+        let (root, file_path) = (root.to_path_buf(), file_path.to_path_buf());
+        let (url, content) = (url.to_string(), content.to_string());
+        tokio::task::spawn_blocking(move || {
+            if let Some(index) = indexes.get(&root, true)
+                && let Err(e) = index.update(&file_path, &url, &content)
+            {
+                eprintln!("warning: failed to index {}: {e}", file_path.display());
+            }
+        });
+    }
+
+    /// Serves GitHub pages from the API, caching each release individually
+    /// alongside a listing.
+    ///
+    /// Returns `Ok(None)` when the API rate limit is exhausted so the caller can
+    /// fall back to HTML conversion.
+    async fn fetch_github(
+        &self,
+        page: &github::GithubPage,
+        root: &Path,
+        warnings: &mut Vec<String>,
+        record: &mut AuditRecord,
+    ) -> Result<Option<FileInfo>, McpError> {
+        let live = self.live();
+        let api = live.http.github(live.github_token.as_deref());
+        let fetched = github::fetch(&api, page, github::DEFAULT_PER_PAGE).await;
+        self.record_api_usage(&api, record);
+        let fetched = match fetched {
+            Ok(fetched) => fetched,
+            Err(e) if e.is::<github::RateLimited>() => {
+                warnings.push(format!("{e}; fell back to HTML conversion"));
+                return Ok(None);
+            }
+            Err(e) => {
+                return Err(McpError::resource_not_found(
+                    format!("Failed to fetch {} from the GitHub API: {e}", page.url()),
+                    None,
+                ));
+            }
+        };
+        warnings.extend(api.rate_limit().and_then(|r| r.warning()));
+
+        for release in &fetched.releases {
+            self.save_to_cache(root, &release.url, &release.markdown, None)
+                .await?;
+        }
+
+        let file_path = self
+            .save_to_cache(root, &fetched.main.url, &fetched.main.markdown, None)
+            .await?
+            .path;
+        Ok(Some(live.file_info(
+            &file_path,
+            &fetched.main.url,
+            "github-api",
+            &fetched.main.markdown,
+            count_stats(&fetched.main.markdown),
+        )))
+    }
+
+    fn record_api_usage(&self, api: &github::Api<'_>, record: &mut AuditRecord) {
+        let (requests, bytes) = api.usage();
+        self.usage.add_requests(requests);
+        self.usage.add_bytes(bytes);
+        record.bytes += bytes;
+    }
+
+    /// Appends the call to the audit log unless `--no-audit` is set. Failures
+    /// are reported on stderr rather than failing the call.
+    async fn audit<T>(&self, mut record: AuditRecord, result: &Result<T, McpError>) {
+        let live = self.live();
+        // The audit log lives in the cache directory, which read-only and
+        // in-memory modes leave untouched
+        if !live.audit || live.read_only || self.memory.is_some() {
+            return;
+        }
+        if let Err(e) = result {
+            record.result = "error";
+            record.error = Some(e.message.to_string());
+        }
+        let appended = match ensure_gitignore(&self.cache_dir)
+            .await
+            .map_err(|e| e.to_string())
+        {
+            Ok(()) => self
+                .audit_log
+                .append(&record)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = appended {
+            eprintln!("warning: failed to write audit log: {e}");
+        }
+    }
+
+    /// Saves cookies set during the call if the jar is kept across restarts.
+    /// Like audit failures, errors only go to stderr.
+    async fn save_cookies(&self) {
+        if self.live().read_only {
+            return;
+        }
+        if let Err(e) = self.cookies.save().await {
+            eprintln!("warning: failed to save cookies: {e}");
+        }
+    }
+
+    /// Requests every variation of `url` in parallel, skipping ones the cache
+    /// index knows are missing.
+    ///
+    /// Returns the successful responses, each with its index into the variation
+    /// report, alongside the report itself in variation order. Once a text
+    /// variation has arrived, the rest get [`EARLY_RETURN_GRACE`]; any still
+    /// downloading after that are returned to finish in the background.
+    async fn fetch_variations(
+        &self,
+        url: &str,
+        http: &Http,
+        warnings: &mut Vec<String>,
+        record: &mut AuditRecord,
+    ) -> (
+        Vec<(usize, FetchResult)>,
+        Vec<Variation>,
+        Option<Stragglers>,
+    ) {
+        let live = self.live();
+        let policy = live.policies.for_url(url);
+        let negative_cache_ttl = policy
+            .negative_cache_ttl
+            .map_or(live.negative_cache_ttl, |secs| {
+                (secs > 0).then(|| Duration::from_secs(secs))
+            });
+        let now = index::unix_now();
+        let deadline = tokio::time::Instant::now() + live.fetch_timeout;
+        let (mut variations, mut tasks, mut pending) =
+            self.start_variations(url, http, negative_cache_ttl, now);
+
+        let mut results = Vec::new();
+        let mut wait_until = deadline;
+        let timed_out = loop {
+            let (position, attempt, elapsed) =
+                match tokio::time::timeout_at(wait_until, tasks.join_next_with_id()).await {
+                    Ok(Some(Ok((id, finished)))) => {
+                        pending.remove(&id);
+                        finished
+                    }
+                    // Keeps its placeholder network error, with a warning why
+                    Ok(Some(Err(e))) => {
+                        if let Some(position) = pending.remove(&e.id()) {
+                            warnings.push(format!(
+                                "Request for {} failed: {e}",
+                                variations[position].url
+                            ));
+                        }
+                        continue;
+                    }
+                    Ok(None) => break false,
+                    Err(_) => break true,
+                };
+            let report = &mut variations[position];
+            report.elapsed_ms = Some(u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX));
+            match attempt {
+                FetchAttempt::Success(result) => {
+                    self.usage.add_bytes(result.content.len() as u64);
+                    record.bytes += result.content.len() as u64;
+                    self.index.clear_missing(&result.url);
+                    report.outcome = Outcome::Success;
+                    if !result.is_html {
+                        wait_until = deadline.min(tokio::time::Instant::now() + EARLY_RETURN_GRACE);
+                    }
+                    results.push((position, result));
+                }
+                FetchAttempt::HttpError {
+                    url: variation,
+                    status,
+                    login_page,
+                } => {
+                    if negative_cache_ttl.is_some() && index::is_negative_status(status) {
+                        self.index.record_missing(&variation, status, now);
+                    }
+                    // Derived variations are often blocked wholesale, login
+                    // or not; only the page itself being blocked matters
+                    report.outcome = if login_page && variation == url {
+                        Outcome::AccessRestricted {
+                            reason: format!("HTTP {status} with a login page"),
+                        }
+                    } else {
+                        Outcome::HttpError { status }
+                    };
+                }
+                FetchAttempt::NetworkError { .. } => {}
+                FetchAttempt::RedirectNotFollowed {
+                    reason, location, ..
+                } => {
+                    report.outcome = Outcome::RedirectNotFollowed { reason, location };
+                }
+            }
+        };
+        // Completion order isn't variation order
+        results.sort_by_key(|(position, _)| *position);
+
+        let early = timed_out && wait_until < deadline;
+        for &position in pending.values() {
+            variations[position].outcome = if early {
+                Outcome::InBackground
+            } else {
+                Outcome::TimedOut
+            };
+        }
+        if early {
+            warnings.push(format!(
+                "{} variation(s) still downloading; they will be saved to the cache when done",
+                pending.len()
+            ));
+        } else if timed_out {
+            warnings.push(format!(
+                "Stopped waiting for {} variation(s) at the {}s fetch deadline",
+                pending.len(),
+                live.fetch_timeout.as_secs()
+            ));
+        }
+        if let Some(ttl) = negative_cache_ttl {
+            self.save_missing(&live, ttl, now, warnings).await;
+        }
+
+        record.variations.clone_from(&variations);
+        // Dropping the set at the deadline aborts what is left
+        let stragglers = early.then_some(Stragglers { tasks, deadline });
+        (results, variations, stragglers)
+    }
+
+    /// Starts requesting the variations of `url`, returning the variation
+    /// report (with placeholder outcomes for those requested), the requests,
+    /// and the position of each request by task.
+    fn start_variations(
+        &self,
+        url: &str,
+        http: &Http,
+        negative_cache_ttl: Option<Duration>,
+        now: u64,
+    ) -> (Vec<Variation>, FetchTasks, HashMap<tokio::task::Id, usize>) {
+        let live = self.live();
+        let policy = live.policies.for_url(url);
+        let mut variations = Vec::new();
+        let mut tasks = FetchTasks::new();
+        let mut pending = HashMap::new();
+        for variation in policy_variations(&policy, url) {
+            // The requested URL itself is always tried; only derived variations
+            // are skipped when recently missing
+            let skip_reason = if variation != url
+                && let Some(ttl) = negative_cache_ttl
+                && let Some(missing) = self.index.missing(&variation, ttl, now)
+            {
+                Some(format!("recently returned HTTP {}", missing.status))
+            } else {
+                self.usage
+                    .reserve_request(live.quota)
+                    .err()
+                    .map(|exceeded| exceeded.to_string())
+            };
+            if let Some(reason) = skip_reason {
+                variations.push(Variation {
+                    url: variation,
+                    outcome: Outcome::Skipped { reason },
+                    elapsed_ms: None,
+                });
+                continue;
+            }
+            let position = variations.len();
+            let http = http.clone();
+            let throttle = Arc::clone(&self.throttle);
+            let rate = policy.requests_per_second;
+            let task_url = variation.clone();
+            let task = tasks.spawn(async move {
+                if let Some(rate) = rate {
+                    throttle.wait(&task_url, rate).await;
+                }
+                let started = Instant::now();
+                let attempt = fetch_url(&http, &task_url).await;
+                (position, attempt, started.elapsed())
+            });
+            pending.insert(task.id(), position);
+            variations.push(Variation {
+                url: variation,
+                outcome: Outcome::NetworkError,
+                elapsed_ms: None,
+            });
+        }
+        (variations, tasks, pending)
+    }
+
+    /// Waits, up to [`BACKGROUND_GRACE`] past the fetch deadline, for the
+    /// variations `fetch_variations` stopped waiting for and saves them to the
+    /// cache. The fetch already returned a text variation, so HTML ones are
+    /// dropped as they would have been then, and so is content the fetch
+    /// saved, whose hashes arrive through `saved`.
+    async fn save_in_background(
+        &self,
+        url: &str,
+        root: &Path,
+        stragglers: Stragglers,
+        mut variations: Vec<Variation>,
+        saved: tokio::sync::oneshot::Receiver<HashMap<u64, String>>,
+    ) {
+        let Stragglers {
+            mut tasks,
+            deadline,
+        } = stragglers;
+        let mut results = Vec::new();
+        let mut warnings = Vec::new();
+        let joined = tokio::time::timeout_at(deadline + BACKGROUND_GRACE, async {
+            while let Some(finished) = tasks.join_next().await {
+                if let Ok((position, FetchAttempt::Success(result), _)) = finished
+                    && !result.is_html
+                {
+                    self.usage.add_bytes(result.content.len() as u64);
+                    self.index.clear_missing(&result.url);
+                    results.push((position, result));
+                }
+            }
+        })
+        .await;
+        if joined.is_err() {
+            // Dropping the set aborts what is left
+            warnings.push(format!(
+                "Stopped waiting for {} variation(s) of {url} in the background",
+                tasks.len()
+            ));
+        }
+        results.sort_by_key(|(position, _)| *position);
+        // A fetch that failed before saving anything sends nothing
+        let mut seen_hashes = saved.await.unwrap_or_default();
+        if let Err(e) = self
+            .convert_and_save(
+                url,
+                root,
+                results,
+                &mut variations,
+                &mut seen_hashes,
+                &mut warnings,
+            )
+            .await
+        {
+            warnings.push(e.message.to_string());
+        }
+        for warning in warnings {
+            eprintln!("warning: {warning}");
+        }
+    }
+
+    /// Prunes expired misses from the cache index and saves it, after a fetch
+    /// under a negative cache TTL of `ttl` recorded new ones.
+    async fn save_missing(&self, live: &Live, ttl: Duration, now: u64, warnings: &mut Vec<String>) {
+        // Other domains may keep misses longer than this one
+        let longest = live
+            .policies
+            .longest_negative_cache_ttl()
+            .map(Duration::from_secs)
+            .max(live.negative_cache_ttl)
+            .map_or(ttl, |longest| longest.max(ttl));
+        self.index.prune_missing(longest, now);
+        if self.memory.is_some() {
+            return;
+        }
+        let saved = match ensure_gitignore(&self.cache_dir)
+            .await
+            .map_err(|e| e.to_string())
+        {
+            Ok(()) => self.index.save().await.map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = saved {
+            warnings.push(format!("Failed to update cache index: {e}"));
+        }
+    }
+
+    /// When only HTML was fetched, fetches the llms.txt files the pages link
+    /// to under the requested URL, adding them to `results` and `variations`
+    /// like the other variations.
+    async fn fetch_linked_llms(
+        &self,
+        results: &mut Vec<(usize, FetchResult)>,
+        variations: &mut Vec<Variation>,
+        http: &Http,
+        record: &mut AuditRecord,
+    ) {
+        if results.iter().any(|(_, result)| !result.is_html) {
+            return;
+        }
+        let linked: Vec<String> = results
+            .iter()
+            .flat_map(|(_, result)| llms_paths::linked(&result.content, &result.url))
+            .filter(|link| !variations.iter().any(|v| &v.url == link))
+            .collect();
+        let live = self.live();
+        for link in linked {
+            let position = variations.len();
+            if let Err(exceeded) = self.usage.reserve_request(live.quota) {
+                variations.push(Variation {
+                    url: link,
+                    outcome: Outcome::Skipped {
+                        reason: exceeded.to_string(),
+                    },
+                    elapsed_ms: None,
+                });
+                continue;
+            }
+            if let Some(rate) = live.policies.for_url(&link).requests_per_second {
+                self.throttle.wait(&link, rate).await;
+            }
+            let started = Instant::now();
+            let attempt = fetch_url(http, &link).await;
+            let elapsed_ms = Some(u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX));
+            let outcome = match attempt {
+                FetchAttempt::Success(result) => {
+                    self.usage.add_bytes(result.content.len() as u64);
+                    record.bytes += result.content.len() as u64;
+                    results.push((position, result));
+                    Outcome::Success
+                }
+                FetchAttempt::HttpError { status, .. } => Outcome::HttpError { status },
+                FetchAttempt::NetworkError { .. } => Outcome::NetworkError,
+                FetchAttempt::RedirectNotFollowed {
+                    reason, location, ..
+                } => Outcome::RedirectNotFollowed { reason, location },
+            };
+            variations.push(Variation {
+                url: link,
+                outcome,
+                elapsed_ms,
+            });
+        }
+        record.variations.clone_from(variations);
+    }
+
+    /// Replaces HTML pages among `results` with a better version of the same
+    /// page: the canonical version of AMP pages, which converts better and
+    /// shares a cache entry with direct fetches of the canonical URL, then
+    /// the version in the preferred language, then a cleaner print view. If a
+    /// replacement can't be fetched the page is kept.
+    async fn resolve_alternates(
+        &self,
+        results: &mut [(usize, FetchResult)],
+        http: &Http,
+        warnings: &mut Vec<String>,
+        record: &mut AuditRecord,
+    ) {
+        let live = self.live();
+        for (_, result) in results.iter_mut() {
+            if !result.is_html {
+                continue;
+            }
+            if let Some(canonical) = amp_canonical(&result.content, &result.url) {
+                let mut folded = result.aliases.clone();
+                folded.push(result.url.clone());
+                let warning = self
+                    .replace_result(
+                        result,
+                        http,
+                        &canonical,
+                        "is an AMP page",
+                        "its canonical version",
+                        record,
+                    )
+                    .await;
+                // The AMP page is cached as the canonical page it stands for
+                if result.url == canonical {
+                    result.aliases = folded;
+                }
+                warnings.push(warning);
+            }
+            if let Some(language) = &live.language
+                && let Some(alternate) = hreflang::Languages::discover(
+                    &result.content,
+                    result.headers.content_language.as_deref(),
+                    result.headers.link.as_deref(),
+                    &result.url,
+                )
+                .preferred(language, &result.url)
+            {
+                let warning = self
+                    .replace_result(
+                        result,
+                        http,
+                        &alternate.url,
+                        &format!("is not in the preferred language {language}"),
+                        &format!("its {} version", alternate.language),
+                        record,
+                    )
+                    .await;
+                warnings.push(warning);
+            }
+            if let Some(warning) = self.prefer_print_view(result, http, record).await {
+                warnings.push(warning);
+            }
+        }
+    }
+
+    /// Puts the print view of the HTML page `result` in its place when the
+    /// domain's `print-view` policy finds one that converts to substantially
+    /// cleaner Markdown, returning a warning that says so. The first
+    /// candidate that can be fetched decides. The page keeps its URL, so it's
+    /// cached where fetches of it look.
+    async fn prefer_print_view(
+        &self,
+        result: &mut FetchResult,
+        http: &Http,
+        record: &mut AuditRecord,
+    ) -> Option<String> {
+        if !result.is_html || result.is_markdown {
+            return None;
+        }
+        let live = self.live();
+        let policy = live.policies.for_url(&result.url);
+        let setting = policy.print_view.clone()?;
+        for candidate in print_view::candidates(&setting, &result.content, &result.url) {
+            self.usage.reserve_request(live.quota).ok()?;
+            if let Some(rate) = policy.requests_per_second {
+                self.throttle.wait(&candidate, rate).await;
+            }
+            let FetchAttempt::Success(print) = fetch_url(http, &candidate).await else {
+                continue;
+            };
+            self.usage.add_bytes(print.content.len() as u64);
+            record.bytes += print.content.len() as u64;
+
+            let permit = Arc::clone(&self.conversions)
+                .acquire_owned()
+                .await
+                .expect("conversion semaphore is never closed");
+            let (html, page_url, policy) =
+                (result.content.clone(), result.url.clone(), policy.clone());
+            let (print, cleaner) = tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                let markdown = |html: &str, url: &str| {
+                    html_to_markdown(html, url, &policy)
+                        .ok()
+                        .map(|(markdown, _)| markdown)
+                };
+                let page = markdown(&html, &page_url);
+                let converted = if print.is_html && !print.is_markdown {
+                    markdown(&print.content, &print.url)
+                } else {
+                    Some(print.content.clone())
+                };
+                let cleaner = converted
+                    .is_some_and(|converted| print_view::is_cleaner(page.as_deref(), &converted));
+                (print, cleaner)
+            })
+            .await
+            .ok()?;
+            if !cleaner {
+                return None;
+            }
+            let url = std::mem::take(&mut result.url);
+            let warning = format!(
+                "{url} converts to cleaner Markdown from its print view {candidate}; saved that instead"
+            );
+            *result = FetchResult { url, ..print };
+            return Some(warning);
+        }
+        None
+    }
+
+    /// Fetches `target` and puts it in place of `result`, returning a warning
+    /// that explains the swap (or why it didn't happen) as "{url} {reason};
+    /// saved {description} {target} instead".
+    async fn replace_result(
+        &self,
+        result: &mut FetchResult,
+        http: &Http,
+        target: &str,
+        reason: &str,
+        description: &str,
+        record: &mut AuditRecord,
+    ) -> String {
+        if http.off_site(&result.url, target) {
+            return format!(
+                "{} {reason}, but {description} {target} was not fetched: it is on another site",
+                result.url
+            );
+        }
+        let live = self.live();
+        if let Err(exceeded) = self.usage.reserve_request(live.quota) {
+            return format!(
+                "{} {reason}, but {description} {target} was not fetched: {exceeded}",
+                result.url
+            );
+        }
+        match fetch_url(http, target).await {
+            FetchAttempt::Success(resolved) => {
+                self.usage.add_bytes(resolved.content.len() as u64);
+                record.bytes += resolved.content.len() as u64;
+                let warning = format!(
+                    "{} {reason}; saved {description} {target} instead",
+                    result.url
+                );
+                *result = resolved;
+                warning
+            }
+            FetchAttempt::HttpError { status, .. } => format!(
+                "{} {reason}, but {description} {target} returned HTTP {status}",
+                result.url
+            ),
+            FetchAttempt::NetworkError { .. } => format!(
+                "{} {reason}, but {description} {target} could not be fetched",
+                result.url
+            ),
+            FetchAttempt::RedirectNotFollowed {
+                reason: stop_reason,
+                ..
+            } => format!(
+                "{} {reason}, but {description} {target} was not fetched: {stop_reason}",
+                result.url
+            ),
+        }
+    }
+
+    #[tool(
+        description = "Use to access documentation and guides from the web. Start with documentation root URLs (e.g., https://docs.example.com) - the tool automatically discovers llms.txt files and tries multiple formats (.md, /index.md, /llms.txt, /llms-full.txt), so you don't need to explicitly request /llms.txt. Local files under allowed directories can be fetched with file:// URLs. Inline content can be passed as a data: URL (text/markdown, text/html, or text/plain, optionally base64). Content is converted to markdown and cached locally. Returns file path with table of contents for navigation. Several URLs (an array, or one per line) are fetched at once and reported in the order given. For GitHub files, use raw.githubusercontent.com URLs for best results; GitHub releases/tags pages and issue, pull request, and discussion threads (with all comments) are fetched through the GitHub API (use ?page=N for older releases).",
+        output_schema = rmcp::handler::server::tool::cached_schema_for_type::<FetchOutput>(),
+        // Writes only to the local cache, so not read-only but never destructive;
+        // repeating a call converges on the same cached files
+        annotations(
+            title = "Fetch Documentation",
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = true
+        )
+    )]
+    async fn fetch(
+        &self,
+        params: Parameters<FetchInput>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let FetchInput {
+            url,
+            namespace,
+            headers,
+            content_type_hint,
+        } = params.0;
+        let namespace = namespace.or_else(|| self.live().namespace.clone());
+        let mut urls = url.urls();
+        let result = match urls.len() {
+            0 => Err(McpError::invalid_params("No URL given", None)),
+            1 => {
+                let url = urls.remove(0);
+                let (result, _) = self
+                    .fetch_one(
+                        &url,
+                        namespace.as_deref(),
+                        &headers,
+                        content_type_hint,
+                        &peer,
+                    )
+                    .await;
+                result
+            }
+            count if count > MAX_FETCH_URLS => Err(McpError::invalid_params(
+                format!("{count} URLs given; fetch at most {MAX_FETCH_URLS} per call"),
+                None,
+            )),
+            _ => Ok(self
+                .fetch_list(urls, namespace, headers, content_type_hint, peer)
+                .await),
+        };
+        self.save_cookies().await;
+        result
+    }
+
+    /// Fetches one URL of a `fetch` call and records it in the audit log,
+    /// returning the result and where its content came from (see
+    /// [`UrlOutcome::source`]).
+    async fn fetch_one(
+        &self,
+        url: &str,
+        namespace: Option<&str>,
+        headers: &BTreeMap<String, String>,
+        hint: ContentTypeHint,
+        peer: &Peer<RoleServer>,
+    ) -> (Result<CallToolResult, McpError>, &'static str) {
+        let mut record = AuditRecord::new("fetch", url, namespace);
+        let result = self
+            .fetch_documentation(url, namespace, headers, hint, peer, &mut record)
+            .await;
+        let source = if url.starts_with("data:") || local::file_path(url).is_some() {
+            "local"
+        } else if self.live().read_only
+            || (!record.variations.is_empty()
+                && record
+                    .variations
+                    .iter()
+                    .all(|v| matches!(v.outcome, Outcome::Cached)))
+        {
+            "cache"
+        } else {
+            "network"
+        };
+        if source != "local" {
+            self.stats.record_fetch(url, source == "cache");
+        }
+        self.audit(record, &result).await;
+        (result, source)
+    }
+
+    /// Fetches `urls` at most [`FETCH_CONCURRENCY`] at a time, returning each
+    /// one's position, result and source in the order given. A task that
+    /// panicked is reported as a failure of its URL.
+    async fn fetch_concurrently(
+        &self,
+        urls: Vec<String>,
+        namespace: Option<String>,
+        headers: BTreeMap<String, String>,
+        hint: ContentTypeHint,
+        peer: Peer<RoleServer>,
+    ) -> Vec<(
+        usize,
+        String,
+        Result<CallToolResult, McpError>,
+        &'static str,
+    )> {
+        let mut results = Vec::new();
+        let mut queue = urls.into_iter().enumerate();
+        let mut tasks = tokio::task::JoinSet::new();
+        // Position and URL of each running task, for reporting one that panicked
+        let mut running = HashMap::new();
+        loop {
+            while tasks.len() < FETCH_CONCURRENCY
+                && let Some((position, url)) = queue.next()
+            {
+                let server = self.clone();
+                let (namespace, headers, peer) = (namespace.clone(), headers.clone(), peer.clone());
+                let task_url = url.clone();
+                let task = tasks.spawn(async move {
+                    server
+                        .fetch_one(&task_url, namespace.as_deref(), &headers, hint, &peer)
+                        .await
+                });
+                running.insert(task.id(), (position, url));
+            }
+            let Some(joined) = tasks.join_next_with_id().await else {
+                break;
+            };
+            let (id, (result, source)) = match joined {
+                Ok((id, fetched)) => (id, fetched),
+                Err(e) => {
+                    let error = McpError::internal_error(format!("Fetch task failed: {e}"), None);
+                    (e.id(), (Err(error), "network"))
+                }
+            };
+            if let Some((position, url)) = running.remove(&id) {
+                results.push((position, url, result, source));
+            }
+        }
+        results.sort_by_key(|(position, ..)| *position);
+        results
+    }
+
+    /// `fetch` of several URLs: fetches them concurrently and combines the
+    /// results in the order given. URLs that fail are reported rather than
+    /// failing the call, which is an error only when every URL failed.
+    async fn fetch_list(
+        &self,
+        urls: Vec<String>,
+        namespace: Option<String>,
+        headers: BTreeMap<String, String>,
+        hint: ContentTypeHint,
+        peer: Peer<RoleServer>,
+    ) -> CallToolResult {
+        use std::fmt::Write;
+
+        let results = self
+            .fetch_concurrently(urls, namespace, headers, hint, peer)
+            .await;
+
+        let mut text = String::new();
+        let mut combined = serde_json::json!({
+            "files": [],
+            "variations": [],
+            "warnings": [],
+        });
+        let mut outcomes = Vec::new();
+        let mut links = Vec::new();
+        for (position, url, result, source) in results {
+            let (status, body, error) = match result {
+                Ok(result) => {
+                    if let Some(structured) = &result.structured_content {
+                        for field in ["files", "variations", "warnings"] {
+                            if let (Some(all), Some(items)) =
+                                (combined[field].as_array_mut(), structured[field].as_array())
+                            {
+                                all.extend(items.iter().cloned());
+                            }
+                        }
+                    }
+                    let body: Vec<String> = result
+                        .content
+                        .iter()
+                        .filter_map(|content| content.as_text().map(|t| t.text.clone()))
+                        .collect();
+                    links.extend(
+                        result
+                            .content
+                            .into_iter()
+                            .filter(|content| content.as_resource_link().is_some()),
+                    );
+                    (source, body.join("\n"), None)
+                }
+                Err(e) => ("failed", e.message.to_string(), Some(e.message.to_string())),
+            };
+            write!(text, "# {}. {url} ({status})\n\n{body}\n\n", position + 1).unwrap();
+            outcomes.push(UrlOutcome { url, source, error });
+        }
+        let failed = outcomes.iter().filter(|o| o.error.is_some()).count();
+        let summary = format!(
+            "Fetched {} of {} URLs\n\n",
+            outcomes.len() - failed,
+            outcomes.len()
+        );
+        let mut content = vec![Content::text(summary + text.trim_end())];
+        content.extend(links);
+        let mut result = if failed == outcomes.len() {
+            CallToolResult::error(content)
+        } else {
+            CallToolResult::success(content)
+        };
+        if self.structured_output() {
+            let output = FetchOutput {
+                schema_version: FETCH_OUTPUT_SCHEMA_VERSION,
+                files: Vec::new(),
+                variations: Vec::new(),
+                warnings: Vec::new(),
+                urls: outcomes,
+            };
+            if let Ok(mut value) = serde_json::to_value(&output) {
+                for field in ["files", "variations", "warnings"] {
+                    value[field] = combined[field].take();
+                }
+                result.structured_content = Some(value);
+            }
+        }
+        result
+    }
+
+    /// Body of the `fetch` tool; `record` collects what was downloaded for the
+    /// audit log.
+    async fn fetch_documentation(
+        &self,
+        url: &str,
+        namespace: Option<&str>,
+        headers: &BTreeMap<String, String>,
+        hint: ContentTypeHint,
+        peer: &Peer<RoleServer>,
+        record: &mut AuditRecord,
+    ) -> Result<CallToolResult, McpError> {
+        let output = self
+            .fetch_output(url, namespace, headers, hint, peer, record)
+            .await?;
+        let paths = self.output_paths(peer).await;
+        Ok(output.into_result(self.structured_output(), &self.cache_dir, &paths))
+    }
+
+    /// How tool results show paths. Under `--relative-paths` they're relative
+    /// to the client root holding the cache directory, so they resolve in the
+    /// client's workspace, or else to the cache directory; `--path-map`
+    /// rewrites the rest.
+    async fn output_paths(&self, peer: &Peer<RoleServer>) -> OutputPaths {
+        let live = self.live();
+        let base = if live.relative_paths {
+            let root = client_roots(peer)
+                .await
+                .into_iter()
+                .find(|root| self.cache_dir.starts_with(root));
+            Some(root.unwrap_or_else(|| self.cache_dir.to_path_buf()))
+        } else {
+            None
+        };
+        OutputPaths {
+            base,
+            maps: live.path_maps.clone(),
+        }
+    }
+
+    /// `file`, if it's a cached document in the cache rooted at `root`: not
+    /// one of the cache's own files, nor in another namespace. On disk it's
+    /// returned canonicalized, so links can't escape `root`.
+    fn cached_document(&self, root: &Path, file: &Path) -> Option<PathBuf> {
+        if self.memory.is_some() {
+            // Only cached paths are in memory, so there's nothing to escape to
+            return sidecar::is_document(root, file).then(|| file.to_path_buf());
+        }
+        let root = root.canonicalize().ok()?;
+        file.canonicalize()
+            .ok()
+            .filter(|file| sidecar::is_document(&root, file))
+    }
+
+    /// The cached file a tool was given as `path`, as shown in results or
+    /// relative to the cache directory, with how results show paths and the
+    /// file's content. Only documents cached under `namespace` (the server's
+    /// configured one by default) can be read.
+    async fn read_cached(
+        &self,
+        path: &str,
+        namespace: Option<String>,
+        peer: &Peer<RoleServer>,
+    ) -> Result<(PathBuf, OutputPaths, String), McpError> {
+        let not_cached =
+            || McpError::invalid_params(format!("{path} is not a file in the cache"), None);
+        let root = self.cache_root(
+            namespace
+                .or_else(|| self.live().namespace.clone())
+                .as_deref(),
+        )?;
+        let mut paths = self.output_paths(peer).await;
+        let resolved = paths.resolve(path);
+        let file = if self.memory.is_some() {
+            let file = if resolved.starts_with(memory::ROOT) {
+                resolved
+            } else {
+                self.cache_dir.join(resolved)
+            };
+            self.cached_document(&root, &file)
+        } else {
+            // Relative paths may also be from `--relative-paths` output
+            paths.base = paths.base.and_then(|base| base.canonicalize().ok());
+            [&self.cache_dir.to_path_buf(), &root]
+                .into_iter()
+                .chain(&paths.base)
+                .find_map(|base| self.cached_document(&root, &base.join(&resolved)))
+        }
+        .ok_or_else(not_cached)?;
+        let content = self
+            .store()
+            .read_async(&file)
+            .await
+            .map_err(|_| not_cached())?;
+        Ok((file, paths, content))
+    }
+
+    /// Fetches `url`, or serves it from the cache, for [`Self::fetch_documentation`].
+    async fn fetch_output(
+        &self,
+        url: &str,
+        namespace: Option<&str>,
+        headers: &BTreeMap<String, String>,
+        hint: ContentTypeHint,
+        peer: &Peer<RoleServer>,
+        record: &mut AuditRecord,
+    ) -> Result<FetchOutput, McpError> {
+        let root = self.cache_root(namespace)?;
+        let headers = self.live().call_headers(headers)?;
+        if let Some(data) = data_url::DataUrl::parse(url) {
+            let data = data.map_err(|e| McpError::invalid_params(e, None))?;
+            return self.cache_data_url(data, &root, record).await;
+        }
+        let requested = url;
+        let canonical = self.live().policies.for_url(url).canonical_url(url);
+        let url = canonical.as_str();
+        if self.live().read_only {
+            return self.serve_cached(url, &root).await;
+        }
+        if let Some(path) = local::file_path(url) {
+            let path = path.map_err(|e| McpError::invalid_params(e, None))?;
+            return self
+                .fetch_local_file(&path, &root, hint, peer, record)
+                .await;
+        }
+        if let Ok(parsed) = url::Url::parse(url)
+            && matches!(parsed.scheme(), "ftp" | "gemini" | "gopher")
+        {
+            return self.fetch_other_protocol(&parsed, &root, record).await;
+        }
+        let http = self
+            .live()
+            .http
+            .with_call_headers(url, headers)
+            .with_content_type_hint(url, hint);
+        if let Some(cached) = self.serve_fresh(url, namespace, &root, &http).await {
+            record.variations.push(Variation {
+                url: url.to_string(),
+                outcome: Outcome::Cached,
+                elapsed_ms: None,
+            });
+            return Ok(cached);
+        }
+        let mut output = self.fetch_remote(url, root, &http, record).await?;
+        // URLs that differ only in insignificant query parameters share a file
+        if requested != url
+            && let Some(file) = output.files.iter_mut().find(|file| file.source_url == url)
+        {
+            file.aliases.push(requested.to_string());
+            self.record_folded(Path::new(&file.path), &file.aliases)
+                .await;
+        }
+        Ok(output)
+    }
+
+    /// Serves `url` from the cache if its copy is within `--max-age`, or
+    /// within `--stale-while-revalidate` after that, in which case it is also
+    /// refreshed in the background for the next call. `None` means the
+    /// network should be used.
+    async fn serve_fresh(
+        &self,
+        url: &str,
+        namespace: Option<&str>,
+        root: &Path,
+        http: &Http,
+    ) -> Option<FetchOutput> {
+        let live = self.live();
+        let fresh_for = live.max_age.unwrap_or_default().as_secs();
+        let usable_for = fresh_for + live.stale_while_revalidate.unwrap_or_default().as_secs();
+        if usable_for == 0 {
+            return None;
+        }
+        let (files, mut variations, fetched_at) =
+            self.cached_variations(url, root, "not cached").await;
+        let age = index::unix_now().saturating_sub(fetched_at?);
+        if age >= usable_for {
+            return None;
+        }
+        for variation in &mut variations {
+            if matches!(variation.outcome, Outcome::Success) {
+                variation.outcome = Outcome::Cached;
+            }
+        }
+        let mut warnings = Vec::new();
+        if age >= fresh_for {
+            self.refresh_in_background(url, namespace, root, http);
+            warnings.push(format!(
+                "Served a cached copy fetched {age}s ago; it is being refreshed in the background"
+            ));
+        }
+        Some(FetchOutput {
+            schema_version: FETCH_OUTPUT_SCHEMA_VERSION,
+            files,
+            variations,
+            warnings,
+            urls: Vec::new(),
+        })
+    }
+
+    /// Hash of the cached files of `url` under `root`, or `None` if it isn't
+    /// cached, to tell whether a refresh changed them.
+    async fn cached_digest(&self, url: &str, root: &Path) -> Option<u64> {
+        let (files, _, _) = self.cached_variations(url, root, "not cached").await;
+        if files.is_empty() {
+            return None;
+        }
+        let store = self.store();
+        let mut hasher = DefaultHasher::new();
+        for file in files {
+            hasher.write(file.path.as_bytes());
+            let content = store.read_async(Path::new(&file.path)).await;
+            hasher.write(content.unwrap_or_default().as_bytes());
+        }
+        Some(hasher.finish())
+    }
+
+    /// Asks GitHub for the latest release, with the configured token.
+    async fn update_status(&self) -> Result<update::Status, String> {
+        let live = self.live();
+        update::check(&live.http.github(live.github_token.as_deref())).await
+    }
+
+    /// Looks for a newer release, logging the result and keeping it for `info`.
+    async fn check_for_update(self) {
+        match self.update_status().await {
+            Ok(status) => {
+                if status.available {
+                    eprintln!("{status}");
+                }
+                let _ = self.update.set(status);
+            }
+            Err(e) => eprintln!("warning: {e}"),
+        }
+    }
+
+    /// Refreshes pinned URLs as they come due, for as long as the server runs.
+    async fn refresh_pins(self) {
+        let mut ticks = tokio::time::interval(pins::TICK);
+        loop {
+            ticks.tick().await;
+            let live = self.live();
+            if live.read_only {
+                continue;
+            }
+            for (url, namespace) in self.pins.take_due(Instant::now()) {
+                match self.cache_root(namespace.as_deref()) {
+                    Ok(root) => {
+                        self.refresh_in_background(&url, namespace.as_deref(), &root, &live.http);
+                    }
+                    Err(e) => eprintln!("warning: can't refresh pinned {url}: {}", e.message),
+                }
+            }
+        }
+    }
+
+    /// Refetches `url` in the background, unless a refresh of it is already
+    /// running.
+    fn refresh_in_background(&self, url: &str, namespace: Option<&str>, root: &Path, http: &Http) {
+        let key = (root.to_path_buf(), url.to_string());
+        if !self.refreshing.lock().unwrap().insert(key.clone()) {
+            return;
+        }
+        let server = self.clone();
+        let http = http.clone();
+        let mut record = AuditRecord::new("refresh", url, namespace);
+        let watcher = self
+            .watches
+            .lock()
+            .unwrap()
+            .get(&(namespace.map(str::to_string), url.to_string()))
+            .cloned()
+            .flatten();
+        tokio::spawn(async move {
+            let (root, url) = &key;
+            let before = match watcher {
+                Some(_) => server.cached_digest(url, root).await,
+                None => None,
+            };
+            let result = server
+                .fetch_remote(url, root.clone(), &http, &mut record)
+                .await;
+            if let Err(e) = &result {
+                eprintln!("warning: background refresh of {url} failed: {}", e.message);
+            }
+            // The first fetch of a watched URL fills the cache; only later
+            // changes are updates
+            if let (Some(peer), Some(before)) = (watcher, before)
+                && result.is_ok()
+                && server.cached_digest(url, root).await != Some(before)
+            {
+                let updated = ResourceUpdatedNotificationParam { uri: url.clone() };
+                if let Err(e) = peer.notify_resource_updated(updated).await {
+                    eprintln!("warning: failed to notify the client about {url}: {e}");
+                }
+            }
+            server.audit(record, &result).await;
+            server.save_cookies().await;
+            server.refreshing.lock().unwrap().remove(&key);
+        });
+    }
+
+    /// `fetch` for `ftp://`, `gemini://` and `gopher://` URLs, each supported
+    /// behind a feature of the same name: the document itself, without
+    /// variations.
+    async fn fetch_other_protocol(
+        &self,
+        url: &url::Url,
+        root: &Path,
+        record: &mut AuditRecord,
+    ) -> Result<FetchOutput, McpError> {
+        let scheme = url.scheme();
+        if !(cfg!(feature = "ftp") && scheme == "ftp"
+            || cfg!(feature = "gemini") && scheme == "gemini"
+            || cfg!(feature = "gopher") && scheme == "gopher")
+        {
+            return Err(McpError::invalid_params(
+                format!(
+                    "{scheme}:// URLs are not supported by this build (enable the `{scheme}` feature)"
+                ),
+                None,
+            ));
+        }
+        let live = self.live();
+        self.usage
+            .reserve_request(live.quota)
+            .map_err(quota_error)?;
+        if let Some(rate) = live.policies.for_url(url.as_str()).requests_per_second {
+            self.throttle.wait(url.as_str(), rate).await;
+        }
+
+        // Media type of the returned content, then the content
+        let retrieved: Result<(String, String), String> = match scheme {
+            #[cfg(feature = "ftp")]
+            "ftp" => ftp::retrieve(url).await.map(|bytes| {
+                let media_type = match local::kind(Path::new(url.path())) {
+                    (true, _) => "text/html",
+                    (_, true) => "text/markdown",
+                    _ => "text/plain",
+                };
+                (
+                    media_type.to_string(),
+                    String::from_utf8_lossy(&bytes).into_owned(),
+                )
+            }),
+            #[cfg(feature = "gemini")]
+            "gemini" => gemini::retrieve(url).await,
+            #[cfg(feature = "gopher")]
+            "gopher" => gopher::retrieve(url).await,
+            _ => Err("not supported by this build".to_string()),
+        };
+        let (media_type, content) = retrieved.map_err(|e| {
+            McpError::resource_not_found(format!("Failed to fetch {url}: {e}"), None)
+        })?;
+        self.usage.add_bytes(content.len() as u64);
+        record.bytes += content.len() as u64;
+        let result = FetchResult {
+            url: url.to_string(),
+            is_html: media_type.starts_with("text/html"),
+            is_markdown: media_type.starts_with("text/markdown"),
+            headers: sidecar::HttpHeaders {
+                content_type: Some(media_type),
+                ..sidecar::HttpHeaders::default()
+            },
+            content,
+            aliases: Vec::new(),
+        };
+        self.cache_single(result, root, record).await
+    }
+
+    /// `fetch` for a `file://` URL under one of the allowed roots.
+    async fn fetch_local_file(
+        &self,
+        path: &Path,
+        root: &Path,
+        hint: ContentTypeHint,
+        peer: &Peer<RoleServer>,
+        record: &mut AuditRecord,
+    ) -> Result<FetchOutput, McpError> {
+        let mut roots = vec![self.cache_dir.to_path_buf()];
+        roots.extend(self.live().allow_paths.iter().cloned());
+        roots.extend(client_roots(peer).await);
+        let path =
+            local::check_allowed(path, &roots).map_err(|e| McpError::invalid_request(e, None))?;
+        let content = fs::read_to_string(&path).await.map_err(|e| {
+            McpError::resource_not_found(format!("Cannot read {}: {e}", path.display()), None)
+        })?;
+        let url = url::Url::from_file_path(&path)
+            .map_err(|()| {
+                McpError::internal_error(format!("Cannot build a URL for {}", path.display()), None)
+            })?
+            .to_string();
+        let (is_html, is_markdown) = hint.apply(local::kind(&path));
+        let result = FetchResult {
+            url,
+            content,
+            is_html,
+            is_markdown,
+            headers: sidecar::HttpHeaders::default(),
+            aliases: Vec::new(),
+        };
+        self.cache_single(result, root, record).await
+    }
+
+    /// `fetch` over the network: GitHub pages through the API, anything else
+    /// by trying every variation.
+    async fn fetch_remote(
+        &self,
+        url: &str,
+        root: PathBuf,
+        http: &Http,
+        record: &mut AuditRecord,
+    ) -> Result<FetchOutput, McpError> {
+        self.usage.check(self.live().quota).map_err(quota_error)?;
+        let mut warnings = Vec::new();
+
+        // Discussions need an authenticated GraphQL call; without a token the
+        // HTML page is still better than nothing
+        if let Some(page) = github::GithubPage::parse(url)
+            && (self.live().github_token.is_some()
+                || !matches!(page, github::GithubPage::Discussion { .. }))
+            && let Some(info) = self
+                .fetch_github(&page, &root, &mut warnings, record)
+                .await?
+        {
+            let variations = vec![Variation {
+                url: info.source_url.clone(),
+                outcome: Outcome::Success,
+                elapsed_ms: None,
+            }];
+            record.variations.clone_from(&variations);
+            return Ok(FetchOutput {
+                schema_version: FETCH_OUTPUT_SCHEMA_VERSION,
+                variations,
+                files: vec![info],
+                warnings,
+                urls: Vec::new(),
+            });
+        }
+
+        let (mut results, mut variations, stragglers) = self
+            .fetch_variations(url, http, &mut warnings, record)
+            .await;
+        // The stragglers skip content the fetch saves, once it has
+        let saved = stragglers.map(|stragglers| {
+            let (sender, receiver) = tokio::sync::oneshot::channel();
+            let server = self.clone();
+            let url = url.to_string();
+            let root = root.clone();
+            let variations = variations.clone();
+            tokio::spawn(async move {
+                server
+                    .save_in_background(&url, &root, stragglers, variations, receiver)
+                    .await;
+            });
+            sender
+        });
+
+        if results.is_empty() {
+            return Err(self.fetch_error(url, &variations, record).await);
+        }
+        self.fetch_linked_llms(&mut results, &mut variations, http, record)
+            .await;
+        self.resolve_alternates(&mut results, http, &mut warnings, record)
+            .await;
+
+        let mut seen_hashes = HashMap::new();
+        let file_infos = self
+            .convert_and_save(
+                url,
+                &root,
+                results,
+                &mut variations,
+                &mut seen_hashes,
+                &mut warnings,
+            )
+            .await?;
+        if let Some(saved) = saved {
+            // The background task may be gone already
+            let _ = saved.send(seen_hashes);
+        }
+
+        record.variations.clone_from(&variations);
+        if file_infos.is_empty() {
+            return Err(self.fetch_error(url, &variations, record).await);
+        }
+        for variation in &variations {
+            if let Outcome::AccessRestricted { reason } = &variation.outcome {
+                warnings.push(format!(
+                    "{} was not saved: access restricted ({reason})",
+                    variation.url
+                ));
+            }
+        }
+        Ok(FetchOutput {
+            schema_version: FETCH_OUTPUT_SCHEMA_VERSION,
+            files: file_infos,
+            variations,
+            warnings,
+            urls: Vec::new(),
+        })
+    }
+
+    /// Converts the successful variations of `url`, marking restricted,
+    /// discarded and duplicate ones in `variations`, and saves the rest.
+    /// `seen_hashes` maps the hash of each content saved to its source URL,
+    /// so content already in it counts as a duplicate.
+    async fn convert_and_save(
+        &self,
+        url: &str,
+        root: &Path,
+        results: Vec<(usize, FetchResult)>,
+        variations: &mut [Variation],
+        seen_hashes: &mut HashMap<u64, String>,
+        warnings: &mut Vec<String>,
+    ) -> Result<Vec<FileInfo>, McpError> {
+        let has_non_html = results.iter().any(|(_, r)| !r.is_html);
+        let live = self.live();
+        let policy = live.policies.for_url(url);
+
+        // Conversion, stats and ToC generation are CPU-bound, so they run on the
+        // blocking pool with at most `conversions` at a time across all calls.
+        // Results are collected in variation order so deduplication keeps the
+        // same file as before.
+        let mut conversions = Vec::new();
+        for (variation, result) in results {
+            if has_non_html && result.is_html {
+                variations[variation].outcome = Outcome::Skipped {
+                    reason: "HTML discarded in favor of a text variation".to_string(),
+                };
+                continue;
+            }
+            let permit = Arc::clone(&self.conversions)
+                .acquire_owned()
+                .await
+                .expect("conversion semaphore is never closed");
+            let live = Arc::clone(&live);
+            let policy = policy.clone();
+            let root = root.to_path_buf();
+            conversions.push((
+                variation,
+                tokio::task::spawn_blocking(move || {
+                    let _permit = permit;
+                    let html =
+                        (result.is_html && !result.is_markdown).then(|| result.content.clone());
+                    let page_url = result.url.clone();
+                    let converted = convert_result(result, &policy, &live, &root)?;
+                    let rejected = html
+                        .as_deref()
+                        .and_then(|html| paywall::detect(html, &converted.content))
+                        .map(|reason| Outcome::AccessRestricted { reason })
+                        .or_else(|| {
+                            let reason = soft404::detect(html.as_deref(), &converted.content)?;
+                            let alternates = html
+                                .as_deref()
+                                .map(|html| page_alternates(html, &page_url))
+                                .unwrap_or_default();
+                            Some(Outcome::SoftNotFound { reason, alternates })
+                        });
+                    Ok::<_, McpError>((converted, rejected))
+                }),
+            ));
+        }
+
+        let mut file_infos: Vec<FileInfo> = Vec::new();
+        for (variation, task) in conversions {
+            let (mut converted, rejected) = task.await.map_err(|e| {
+                McpError::internal_error(format!("Conversion task failed: {e}"), None)
+            })??;
+            if let Some(outcome) = rejected {
+                variations[variation].outcome = outcome;
+                continue;
+            }
+            warnings.extend(converted.warnings);
+
+            if let Some(original) = seen_hashes.get(&converted.hash) {
+                variations[variation].outcome = Outcome::Deduplicated {
+                    duplicate_of: original.clone(),
+                };
+                continue;
+            }
+            if let Some((path, locked)) = &live.lockfile
+                && let Some(locked) = locked.get(&converted.info.source_url)
+            {
+                let sha256 = sidecar::sha256(converted.content.as_bytes());
+                if sha256 != locked.sha256 {
+                    if live.lockfile_drift == lockfile::Drift::Fail {
+                        variations[variation].outcome = Outcome::Drifted {
+                            sha256,
+                            locked_sha256: locked.sha256.clone(),
+                        };
+                        continue;
+                    }
+                    warnings.push(format!(
+                        "{} differs from the version locked in {}",
+                        converted.info.source_url,
+                        path.display()
+                    ));
+                }
+            }
+            seen_hashes.insert(converted.hash, converted.info.source_url.clone());
+
+            self.save_or_inline(root, &mut converted.info, &converted.content, warnings)
+                .await?;
+            file_infos.push(converted.info);
+        }
+
+        Ok(file_infos)
+    }
+
+    /// `fetch` for a `data:` URL: converts and caches the inline content
+    /// without any network access.
+    async fn cache_data_url(
+        &self,
+        data: data_url::DataUrl,
+        root: &Path,
+        record: &mut AuditRecord,
+    ) -> Result<FetchOutput, McpError> {
+        let live = self.live();
+        if live.read_only {
+            return Err(McpError::invalid_request(
+                "data: URLs can't be cached while the server is in read-only mode",
+                None,
+            ));
+        }
+        let label = data.label();
+        record.url.clone_from(&label);
+        let result = FetchResult {
+            url: label.clone(),
+            is_html: data.is_html(),
+            is_markdown: data.is_markdown(),
+            headers: sidecar::HttpHeaders {
+                content_type: Some(data.media_type),
+                ..sidecar::HttpHeaders::default()
+            },
+            content: data.content,
+            aliases: Vec::new(),
+        };
+        self.cache_single(result, root, record).await
+    }
+
+    /// Converts and caches a single document that didn't go through variation
+    /// discovery, reporting it as the only variation.
+    async fn cache_single(
+        &self,
+        result: FetchResult,
+        root: &Path,
+        record: &mut AuditRecord,
+    ) -> Result<FetchOutput, McpError> {
+        let live = self.live();
+        let url = result.url.clone();
+        let policy = live.policies.for_url(&url);
+        let permit = Arc::clone(&self.conversions)
+            .acquire_owned()
+            .await
+            .expect("conversion semaphore is never closed");
+        let root = root.to_path_buf();
+        let converted = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let converted = convert_result(result, &policy, &live, &root);
+            converted.map(|converted| (converted, root))
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("Conversion task failed: {e}"), None))?;
+        let (mut converted, root) = converted?;
+
+        self.save_or_inline(
+            &root,
+            &mut converted.info,
+            &converted.content,
+            &mut converted.warnings,
+        )
+        .await?;
+        let variations = vec![Variation {
+            url,
+            outcome: Outcome::Success,
+            elapsed_ms: None,
+        }];
+        record.variations.clone_from(&variations);
+        Ok(FetchOutput {
+            schema_version: FETCH_OUTPUT_SCHEMA_VERSION,
+            files: vec![converted.info],
+            variations,
+            warnings: converted.warnings,
+            urls: Vec::new(),
+        })
+    }
+
+    /// `fetch` in read-only mode: returns whichever variations of `url` are
+    /// already cached under `root`.
+    async fn serve_cached(&self, url: &str, root: &Path) -> Result<FetchOutput, McpError> {
+        let (files, variations, _) = self
+            .cached_variations(url, root, "not cached (read-only mode)")
+            .await;
+        if files.is_empty() {
+            return Err(McpError::resource_not_found(
+                format!("{url} is not in the cache, and the server is in read-only mode"),
+                None,
+            ));
+        }
+        Ok(FetchOutput {
+            schema_version: FETCH_OUTPUT_SCHEMA_VERSION,
+            files,
+            variations,
+            warnings: Vec::new(),
+            urls: Vec::new(),
+        })
+    }
+
+    /// Reads the cached variations of `url` under `root` as `fetch` returned
+    /// them, marking uncached ones skipped for `missing_reason`.
+    ///
+    /// Also returns when the oldest of the files was fetched, if there are
+    /// any and all of them have sidecars.
+    async fn cached_variations(
+        &self,
+        url: &str,
+        root: &Path,
+        missing_reason: &str,
+    ) -> (Vec<FileInfo>, Vec<Variation>, Option<u64>) {
+        let live = self.live();
+        let policy = live.policies.for_url(url);
+        let mut files: Vec<FileInfo> = Vec::new();
+        let mut variations = Vec::new();
+        let mut seen_hashes: HashMap<u64, usize> = HashMap::new();
+        let mut oldest = None;
+        let store = self.store();
+        for variation in policy_variations(&policy, url) {
+            let path = url_to_path(root, &variation).ok();
+            let mut cached = match path {
+                Some(path) => {
+                    let path = store.locate_async(path).await;
+                    store.read_async(&path).await.ok().map(|c| (path, c))
+                }
+                None => None,
+            };
+            // Saved once into a file with the same content, under `--dedup-scope`
+            if cached.is_none()
+                && let Some(path) = self
+                    .index
+                    .alias_paths(&idn::unicode_url(&variation))
+                    .into_iter()
+                    .map(|relative| self.cache_dir.join(relative))
+                    .find(|path| in_cache_root(&self.cache_dir, root, path))
+            {
+                cached = store.read_async(&path).await.ok().map(|c| (path, c));
+            }
+            let outcome = match cached {
+                None => Outcome::Skipped {
+                    reason: missing_reason.to_string(),
+                },
+                Some((path, content)) => {
+                    let stats = count_stats(&content);
+                    if let Some(&original) = seen_hashes.get(&stats.hash) {
+                        Outcome::Deduplicated {
+                            duplicate_of: files[original].source_url.clone(),
+                        }
+                    } else {
+                        let lower = variation.to_lowercase();
+                        let content_type = if lower.contains("/llms-full.txt") {
+                            "llms-full"
+                        } else if lower.contains("/llms.txt") {
+                            "llms"
+                        } else {
+                            "markdown"
+                        };
+                        seen_hashes.insert(stats.hash, files.len());
+                        let mut info =
+                            live.file_info(&path, &variation, content_type, &content, stats);
+                        if self.memory.is_some() {
+                            info.off_disk(Storage::Memory, &content);
+                        }
+                        let meta = store.sidecar(&path).await;
+                        oldest = match (&meta, files.is_empty()) {
+                            (Some(meta), true) => Some(meta.fetched_at),
+                            (Some(meta), false) => oldest.map(|at| at.min(meta.fetched_at)),
+                            (None, _) => None,
+                        };
+                        info.http = meta.and_then(|meta| meta.http);
+                        files.push(info);
+                        Outcome::Success
+                    }
+                }
+            };
+            variations.push(Variation {
+                url: variation,
+                outcome,
+                elapsed_ms: None,
+            });
+        }
+        (files, variations, oldest)
+    }
+
+    /// Fetches the changelog behind `url`: GitHub releases through the API,
+    /// anything else as a page.
+    ///
+    /// Returns the source URL and the Markdown.
+    async fn fetch_changelog(
+        &self,
+        live: &Live,
+        url: &str,
+        warnings: &mut Vec<String>,
+        record: &mut AuditRecord,
+    ) -> Result<(String, String), McpError> {
+        if let Some(page) = releases_page(url) {
+            let api = live.http.github(live.github_token.as_deref());
+            let fetched = github::fetch(&api, &page, 100).await;
+            self.record_api_usage(&api, record);
+            match fetched {
+                Ok(fetched) => {
+                    record.variations.push(Variation {
+                        url: page.url(),
+                        outcome: Outcome::Success,
+                        elapsed_ms: None,
+                    });
+                    warnings.extend(api.rate_limit().and_then(|r| r.warning()));
+                    return Ok((fetched.main.url, fetched.main.markdown));
+                }
+                Err(e) if e.is::<github::RateLimited>() => {
+                    warnings.push(format!("{e}; fell back to HTML conversion"));
+                }
+                Err(e) => {
+                    return Err(McpError::resource_not_found(
+                        format!("Failed to fetch {} from the GitHub API: {e}", page.url()),
+                        None,
+                    ));
+                }
+            }
+        }
+
+        self.fetch_changelog_page(live, url, warnings, record).await
+    }
+
+    /// Fetches a changelog page directly, converting HTML to Markdown.
+    ///
+    /// Returns the source URL and the Markdown.
+    async fn fetch_changelog_page(
+        &self,
+        live: &Live,
+        url: &str,
+        warnings: &mut Vec<String>,
+        record: &mut AuditRecord,
+    ) -> Result<(String, String), McpError> {
+        let policy = live.policies.for_url(url);
+        self.usage
+            .reserve_request(live.quota)
+            .map_err(quota_error)?;
+        if let Some(rate) = policy.requests_per_second {
+            self.throttle.wait(url, rate).await;
+        }
+        let attempt = fetch_url(&live.http, url).await;
+        record.variations.push(Variation {
+            url: url.to_string(),
+            outcome: match &attempt {
+                FetchAttempt::Success(_) => Outcome::Success,
+                FetchAttempt::HttpError { status, .. } => Outcome::HttpError { status: *status },
+                FetchAttempt::NetworkError { .. } => Outcome::NetworkError,
+                FetchAttempt::RedirectNotFollowed {
+                    reason, location, ..
+                } => Outcome::RedirectNotFollowed {
+                    reason: reason.clone(),
+                    location: location.clone(),
+                },
+            },
+            elapsed_ms: None,
+        });
+        let result = match attempt {
+            FetchAttempt::Success(result) => {
+                self.usage.add_bytes(result.content.len() as u64);
+                record.bytes += result.content.len() as u64;
+                result
+            }
+            FetchAttempt::HttpError { url, status, .. } => {
+                return Err(McpError::resource_not_found(
+                    format!("Failed to fetch changelog from {url}: HTTP {status}"),
+                    None,
+                ));
+            }
+            FetchAttempt::NetworkError { url } => {
+                return Err(McpError::resource_not_found(
+                    format!("Failed to fetch changelog from {url}: network error"),
+                    None,
+                ));
+            }
+            FetchAttempt::RedirectNotFollowed { url, reason, .. } => {
+                return Err(McpError::resource_not_found(
+                    format!("Failed to fetch changelog from {url}: {reason}"),
+                    None,
+                ));
+            }
+        };
+        let markdown = if result.is_html && !result.is_markdown {
+            convert_html(&result.content, &result.url, &policy, warnings)?
+        } else {
+            result.content
+        };
+        Ok((result.url, markdown))
+    }
+
+    #[tool(
+        description = "Use when evaluating an upgrade: fetches a changelog (a CHANGELOG.md/release-notes page, or GitHub releases via https://github.com/{owner}/{repo}/releases) and returns only the entries for versions newer than since_version. The full changelog is cached locally.",
+        annotations(
+            title = "Changelog Since Version",
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = true
+        )
+    )]
+    async fn changelog(
+        &self,
+        params: Parameters<ChangelogInput>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let namespace = params
+            .0
+            .namespace
+            .clone()
+            .or_else(|| self.live().namespace.clone());
+        let mut record = AuditRecord::new("changelog", &params.0.url, namespace.as_deref());
+        let paths = self.output_paths(&peer).await;
+        let result = self.changelog_since(params.0, &paths, &mut record).await;
+        self.audit(record, &result).await;
+        self.save_cookies().await;
+        result
+    }
+
+    /// Body of the `changelog` tool; `record` collects what was downloaded for
+    /// the audit log, and the saved path is shown as `paths` says.
+    async fn changelog_since(
+        &self,
+        input: ChangelogInput,
+        paths: &OutputPaths,
+        record: &mut AuditRecord,
+    ) -> Result<CallToolResult, McpError> {
+        use std::fmt::Write;
+
+        let live = self.live();
+        self.usage.check(live.quota).map_err(quota_error)?;
+        let ChangelogInput {
+            url,
+            since_version,
+            namespace,
+        } = input;
+        let root = self.cache_root(namespace.or_else(|| live.namespace.clone()).as_deref())?;
+        let since = changelog::Version::find_in(&since_version).ok_or_else(|| {
+            McpError::invalid_params(
+                format!("Could not parse since_version \"{since_version}\" (expected e.g. 1.4 or v2.0.1)"),
+                None,
+            )
+        })?;
+
+        let mut warnings = Vec::new();
+        let (source_url, markdown, file_path) = if live.read_only {
+            let cache_url = releases_page(&url).map_or(url, |page| page.url());
+            let file_path = url_to_path(&root, &cache_url).map_err(|e| {
+                McpError::internal_error(format!("Failed to create cache path: {e}"), None)
+            })?;
+            let file_path = self.store().locate_async(file_path).await;
+            let markdown = read_cached(&self.store(), &file_path, &cache_url).await?;
+            (cache_url, markdown, file_path)
+        } else {
+            let (source_url, markdown) = self
+                .fetch_changelog(&live, &url, &mut warnings, record)
+                .await?;
+            let file_path = self
+                .save_to_cache(&root, &source_url, &markdown, None)
+                .await?
+                .path;
+            (source_url, markdown, file_path)
+        };
+        let all_entries = changelog::parse_entries(&markdown);
+        let newer: Vec<_> = all_entries.iter().filter(|e| e.version > since).collect();
+
+        let mut output = String::new();
+        writeln!(output, "## {source_url}").unwrap();
+        writeln!(output, "Saved to: {}", paths.show(&file_path)).unwrap();
+        if all_entries.is_empty() {
+            writeln!(
+                output,
+                "No version headings found; read the cached file instead."
+            )
+            .unwrap();
+        } else if newer.is_empty() {
+            let latest = all_entries.iter().map(|e| &e.version).max().unwrap();
+            writeln!(output, "No entries newer than {since} (latest: {latest}).").unwrap();
+        } else {
+            writeln!(
+                output,
+                "Entries newer than {since}: {} of {}",
+                newer.len(),
+                all_entries.len()
+            )
+            .unwrap();
+            for entry in newer {
+                writeln!(output).unwrap();
+                writeln!(output, "{}", entry.text).unwrap();
+            }
+        }
+        for warning in &warnings {
+            writeln!(output, "\nWarning: {warning}").unwrap();
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(
+            output.trim_end().to_string(),
+        )]))
+    }
+
+    /// Crawls the site from `start` within [`crawl::Scope`] and `limits`,
+    /// fetching a few pages at a time and saving each to the cache under
+    /// `root`.
+    ///
+    /// Resumes the last crawl from `start` if it didn't finish, and asks for
+    /// cached pages only if they changed. Returns the text pages read, in the
+    /// order they were found, starting with `start`, each with its cached
+    /// file. Pages that fail are skipped with a warning, and the crawl stops
+    /// early if the quota runs out.
+    async fn crawl(
+        &self,
+        live: &Live,
+        root: &Path,
+        start: &url::Url,
+        limits: crawl::Limits,
+        warnings: &mut Vec<String>,
+        record: &mut AuditRecord,
+    ) -> Result<Vec<(crawl::Page, PathBuf)>, McpError> {
+        let cache_path = |base: &Path, url: &str| {
+            url_to_path(base, url).map_err(|e| {
+                McpError::internal_error(format!("Failed to create cache path: {e}"), None)
+            })
+        };
+        let state = CrawlState {
+            root,
+            scope: crawl::Scope::new(start),
+            frontier: frontier::Frontier::load(frontier::path_for(&cache_path(
+                &root.join(frontier::CACHE_DIR),
+                start.as_str(),
+            )?)),
+        };
+        let (pending, done) = state.frontier.begin(start.as_str());
+        if !done.is_empty() {
+            warnings.push(format!(
+                "Resumed an unfinished crawl of {start}; {} pages were already done",
+                done.len()
+            ));
+        }
+        let mut crawled = Vec::new();
+        for (position, page) in done {
+            let file = self
+                .store()
+                .locate_async(cache_path(root, &page.url)?)
+                .await;
+            if fs::try_exists(&file).await.unwrap_or(false) {
+                crawled.push((position, page, file));
+            }
+        }
+
+        let mut queue = VecDeque::from(pending);
+        let mut tasks = tokio::task::JoinSet::new();
+        // Position and URL of each task's page, for tasks that panic
+        let mut running = HashMap::new();
+        let mut stopped = false;
+        loop {
+            while tasks.len() < CRAWL_CONCURRENCY
+                && !stopped
+                && let Some((position, url, _)) = queue.pop_front()
+            {
+                if let Err(e) = self.usage.reserve_request(live.quota) {
+                    warnings.push(format!("Stopped crawling early: {e}; run again to resume"));
+                    stopped = true;
+                    break;
+                }
+                let file = self.store().locate_async(cache_path(root, &url)?).await;
+                let id = self
+                    .spawn_crawl_fetch(live, &state, &mut tasks, position, &url, file)
+                    .await;
+                running.insert(id, (position, url));
+            }
+            let Some(joined) = tasks.join_next_with_id().await else {
+                break;
+            };
+            let fetched = match joined {
+                Ok((id, fetched)) => {
+                    running.remove(&id);
+                    fetched
+                }
+                // Done as a failed page, like one whose request failed
+                Err(e) => {
+                    if let Some((position, url)) = running.remove(&e.id()) {
+                        warnings.push(format!("Failed to crawl {url}: {e}"));
+                        queue.extend(state.frontier.complete(position, None, limits));
+                    }
+                    continue;
+                }
+            };
+            let (position, file) = (fetched.position, fetched.file.clone());
+            let page = self
+                .crawled_page(live, &state, fetched, warnings, record)
+                .await;
+            queue.extend(state.frontier.complete(position, page.as_ref(), limits));
+            if let Some(page) = page {
+                crawled.push((position, page, file));
+            }
+            if self.memory.is_none()
+                && let Err(e) = state.frontier.save().await
+            {
+                eprintln!("warning: failed to save the crawl frontier: {e}");
+            }
+        }
+        if !stopped {
+            state.frontier.finish();
+            if self.memory.is_none()
+                && let Err(e) = state.frontier.save().await
+            {
+                eprintln!("warning: failed to save the crawl frontier: {e}");
+            }
+        }
+        crawled.sort_by_key(|(position, _, _)| *position);
+        Ok(crawled
+            .into_iter()
+            .map(|(_, page, file)| (page, file))
+            .collect())
+    }
+
+    /// Starts fetching the crawl page `url` at `position`, cached at `file`,
+    /// asking only for changes if it was crawled before. Returns the task's ID.
+    async fn spawn_crawl_fetch(
+        &self,
+        live: &Live,
+        state: &CrawlState<'_>,
+        tasks: &mut tokio::task::JoinSet<CrawlFetch>,
+        position: usize,
+        url: &str,
+        file: PathBuf,
+    ) -> tokio::task::Id {
+        let conditional = if state.frontier.known(url).is_some() {
+            conditional_headers(&file).await
+        } else {
+            reqwest::header::HeaderMap::new()
+        };
+        let revalidating = !conditional.is_empty();
+        let http = live.http.with_call_headers(url, conditional);
+        let throttle = Arc::clone(&self.throttle);
+        let rate = live.policies.for_url(url).requests_per_second;
+        let url = url.to_string();
+        tasks
+            .spawn(async move {
+                if let Some(rate) = rate {
+                    throttle.wait(&url, rate).await;
+                }
+                CrawlFetch {
+                    position,
+                    file,
+                    revalidating,
+                    attempt: fetch_url(&http, &url).await,
+                }
+            })
+            .id()
+    }
+
+    /// Handles a fetched crawl page: saves it to the cache, or keeps the
+    /// cached copy if it hasn't changed.
+    ///
+    /// Returns what the page says about itself, or `None` if it failed or
+    /// isn't text.
+    async fn crawled_page(
+        &self,
+        live: &Live,
+        state: &CrawlState<'_>,
+        fetched: CrawlFetch,
+        warnings: &mut Vec<String>,
+        record: &mut AuditRecord,
+    ) -> Option<crawl::Page> {
+        let mut variation = |url: &str, outcome| {
+            record.variations.push(Variation {
+                url: url.to_string(),
+                outcome,
+                elapsed_ms: None,
+            });
+        };
+        let result = match fetched.attempt {
+            FetchAttempt::Success(result) => result,
+            FetchAttempt::HttpError { url, status, .. }
+                if status == 304 && fetched.revalidating =>
+            {
+                variation(&url, Outcome::NotModified);
+                return state.frontier.known(&url);
+            }
+            FetchAttempt::HttpError { url, status, .. } => {
+                variation(&url, Outcome::HttpError { status });
+                warnings.push(format!("Skipped {url}: HTTP {status}"));
+                return None;
+            }
+            FetchAttempt::NetworkError { url } => {
+                variation(&url, Outcome::NetworkError);
+                warnings.push(format!("Skipped {url}: network error"));
+                return None;
+            }
+            FetchAttempt::RedirectNotFollowed {
+                url,
+                reason,
+                location,
+            } => {
+                warnings.push(format!("Skipped {url}: {reason}"));
+                variation(&url, Outcome::RedirectNotFollowed { reason, location });
+                return None;
+            }
+        };
+        variation(&result.url, Outcome::Success);
+        self.usage.add_bytes(result.content.len() as u64);
+        record.bytes += result.content.len() as u64;
+        let is_text = result
+            .headers
+            .content_type
+            .as_deref()
+            .is_none_or(|t| t.starts_with("text/"));
+        let url = url::Url::parse(&result.url).ok().filter(|_| is_text)?;
+
+        let is_html = result.is_html && !result.is_markdown;
+        let (page, markdown) = if is_html {
+            let page = crawl::Page::from_html(&result.content, &url, &state.scope);
+            let policy = live.policies.for_url(&result.url);
+            let permit = Arc::clone(&self.conversions)
+                .acquire_owned()
+                .await
+                .expect("conversion semaphore is never closed");
+            let (content, page_url) = (result.content.clone(), result.url.clone());
+            let converted = tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                let mut warnings = Vec::new();
+                let markdown = convert_html(&content, &page_url, &policy, &mut warnings);
+                (markdown, warnings)
+            })
+            .await;
+            match converted {
+                Ok((Ok(markdown), new_warnings)) => {
+                    warnings.extend(new_warnings);
+                    (page, markdown)
+                }
+                Ok((Err(e), _)) => {
+                    warnings.push(format!("Skipped {url}: {}", e.message));
+                    return None;
+                }
+                Err(e) => {
+                    warnings.push(format!("Skipped {url}: conversion failed ({e})"));
+                    return None;
+                }
+            }
+        } else {
+            let page = crawl::Page::from_markdown(&result.content, &url, &state.scope);
+            (page, result.content)
+        };
+        if let Err(e) = self
+            .save_to_cache(state.root, &result.url, &markdown, Some(&result.headers))
+            .await
+        {
+            warnings.push(format!("Skipped {url}: {e}"));
+            return None;
+        }
+        Some(page)
+    }
+
+    /// Body of the `mirror` subcommand: crawls from `url` and writes the
+    /// converted pages, with links rewritten, and an index page under
+    /// [`mirror::CACHE_DIR`]. Replaces an earlier mirror of the same scope.
+    ///
+    /// Returns a summary for the terminal.
+    async fn mirror(&self, url: &str, limits: crawl::Limits) -> Result<String, McpError> {
+        use std::fmt::Write;
+
+        let live = self.live();
+        if live.read_only {
+            return Err(McpError::invalid_request(
+                "Sites can't be mirrored while the server is in read-only mode",
+                None,
+            ));
+        }
+        let start = url::Url::parse(url)
+            .ok()
+            .filter(|u| matches!(u.scheme(), "http" | "https"))
+            .ok_or_else(|| {
+                McpError::invalid_params(
+                    format!("Only http(s) sites can be mirrored, not \"{url}\""),
+                    None,
+                )
+            })?;
+        let root = self.cache_root(live.namespace.as_deref())?;
+        let dir = mirror::mirror_dir(&start)
+            .map(|dir| root.join(dir))
+            .ok_or_else(|| McpError::invalid_params(format!("Can't mirror {url}"), None))?;
+
+        let mut record = AuditRecord::new("mirror", url, live.namespace.as_deref());
+        let mut warnings = Vec::new();
+        let result = match self
+            .crawl(&live, &root, &start, limits, &mut warnings, &mut record)
+            .await
+        {
+            Ok(crawled) => {
+                self.write_mirror(&start, &dir, crawled, &mut warnings)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        self.audit(
+            record,
+            &result.clone().map(|_| CallToolResult::success(Vec::new())),
+        )
+        .await;
+        self.save_cookies().await;
+        let pages = result?;
+
+        let mut summary = format!(
+            "Mirrored {pages} pages of {url} to {}",
+            dir.join(mirror::INDEX_FILE).display()
+        );
+        for warning in &warnings {
+            write!(summary, "\nwarning: {warning}").unwrap();
+        }
+        Ok(summary)
+    }
+
+    /// Writes the `crawled` pages (each with its cached file) and the index
+    /// page to `dir`. Returns how many pages were written.
+    async fn write_mirror(
+        &self,
+        start: &url::Url,
+        dir: &Path,
+        crawled: Vec<(crawl::Page, PathBuf)>,
+        warnings: &mut Vec<String>,
+    ) -> Result<usize, McpError> {
+        let scope = crawl::Scope::new(start);
+        let mut files = HashMap::new();
+        let mut pages = Vec::new();
+        for (page, cached) in crawled {
+            let Ok(url) = url::Url::parse(&page.url) else {
+                continue;
+            };
+            let Some(file) = mirror::page_path(&scope, &url) else {
+                warnings.push(format!("Skipped {}: no file name for it", page.url));
+                continue;
+            };
+            files.insert(page.url.clone(), file.clone());
+            pages.push((page, cached, file));
+        }
+        if pages.is_empty() {
+            return Err(McpError::resource_not_found(
+                format!(
+                    "Failed to mirror {start}: {}",
+                    warnings.first().map_or("not a text page", String::as_str)
+                ),
+                None,
+            ));
+        }
+
+        let write_error = |e: std::io::Error| {
+            McpError::internal_error(format!("Failed to write mirror: {e}"), None)
+        };
+        ensure_gitignore(&self.cache_dir).await.map_err(|e| {
+            McpError::internal_error(format!("Failed to create .gitignore: {e}"), None)
+        })?;
+        if fs::try_exists(dir).await.unwrap_or(false) {
+            fs::remove_dir_all(dir).await.map_err(write_error)?;
+        }
+        let mut index = Vec::new();
+        for (page, cached, file) in pages {
+            let markdown = match fs::read_to_string(&cached).await {
+                Ok(markdown) => markdown,
+                Err(e) => {
+                    warnings.push(format!(
+                        "Skipped {}: cached copy unreadable ({e})",
+                        page.url
+                    ));
+                    continue;
+                }
+            };
+            let page_url = url::Url::parse(&page.url).expect("parsed above");
+            let markdown = mirror::rewrite_links(&markdown, &page_url, &file, &files);
+            let path = dir.join(&file);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).await.map_err(write_error)?;
+            }
+            fs::write(&path, markdown).await.map_err(write_error)?;
+            index.push((page.title.unwrap_or(page.url), file));
+        }
+        let index_page = mirror::index_page(start.as_str(), &index);
+        fs::write(dir.join(mirror::INDEX_FILE), index_page)
+            .await
+            .map_err(write_error)?;
+        Ok(index.len())
+    }
+
+    #[tool(
+        description = "Crawls a documentation site from url (staying under its directory, up to max_pages pages) and writes a synthesized llms.txt index of it to the cache: the site's title and description, then links to each page with its description, grouped into sections by directory. Use it to get an overview of sites that don't publish an llms.txt, or to draft one for a site you maintain.",
+        annotations(
+            title = "Generate llms.txt",
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = true
+        )
+    )]
+    async fn generate_llms_txt(
+        &self,
+        params: Parameters<GenerateLlmsTxtInput>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let namespace = params
+            .0
+            .namespace
+            .clone()
+            .or_else(|| self.live().namespace.clone());
+        let mut record = AuditRecord::new("generate_llms_txt", &params.0.url, namespace.as_deref());
+        let paths = self.output_paths(&peer).await;
+        let result = self
+            .generate_llms_txt_from(params.0, &paths, &mut record)
+            .await;
+        self.audit(record, &result).await;
+        self.save_cookies().await;
+        result
+    }
+
+    #[tool(
+        description = "Crawls a documentation site from url and caches every page it reaches, following links on the same host under the url's directory up to max_depth hops (default 3) and max_pages pages (default 50). Returns the title, URL and cached file of each page, so a whole docs site can be cached in one call instead of one fetch per page. Read the files, or use search and grep on them, afterwards.",
+        annotations(
+            title = "Fetch Site",
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = true
+        )
+    )]
+    async fn fetch_site(
+        &self,
+        params: Parameters<FetchSiteInput>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let namespace = params
+            .0
+            .namespace
+            .clone()
+            .or_else(|| self.live().namespace.clone());
+        let mut record = AuditRecord::new("fetch_site", &params.0.url, namespace.as_deref());
+        let paths = self.output_paths(&peer).await;
+        let result = self.fetch_site_from(params.0, &paths, &mut record).await;
+        self.audit(record, &result).await;
+        self.save_cookies().await;
+        result
+    }
+
+    /// The manifests `fetch_dependencies` reads: `manifest` if given (relative
+    /// to the client's first root), otherwise every one at the top of the
+    /// client's roots.
+    async fn find_manifests(
+        &self,
+        manifest: Option<&str>,
+        peer: &Peer<RoleServer>,
+    ) -> Result<Vec<PathBuf>, McpError> {
+        let client_roots = client_roots(peer).await;
+        let Some(manifest) = manifest else {
+            let mut found = Vec::new();
+            for root in &client_roots {
+                for name in manifest::FILE_NAMES {
+                    if fs::try_exists(root.join(name)).await.unwrap_or(false) {
+                        found.push(root.join(name));
+                    }
+                }
+            }
+            if found.is_empty() {
+                return Err(McpError::invalid_params(
+                    "No Cargo.toml, package.json or pyproject.toml at the top of the client's roots; pass manifest",
+                    None,
+                ));
+            }
+            return Ok(found);
+        };
+        let path = match (Path::new(manifest), client_roots.first()) {
+            (path, _) if path.is_absolute() => path.to_path_buf(),
+            (path, Some(root)) => root.join(path),
+            (_, None) => {
+                return Err(McpError::invalid_params(
+                    "The client has no roots, so manifest must be an absolute path",
+                    None,
+                ));
+            }
+        };
+        let mut roots = client_roots;
+        roots.extend(self.live().allow_paths.iter().cloned());
+        let path =
+            local::check_allowed(&path, &roots).map_err(|e| McpError::invalid_request(e, None))?;
+        Ok(vec![path])
+    }
+
+    /// Finds the docs of `dependency` and fetches them like the `fetch` tool,
+    /// with an audit record of its own.
+    async fn fetch_dependency(
+        &self,
+        dependency: manifest::Dependency,
+        namespace: Option<&str>,
+        peer: &Peer<RoleServer>,
+    ) -> DependencyDocs {
+        let lookup_url = dependency.lookup_url();
+        let mut record = AuditRecord::new("fetch_dependencies", &lookup_url, namespace);
+        let docs_url = match dependency.ecosystem {
+            manifest::Ecosystem::Cargo => Ok(lookup_url.clone()),
+            _ => match self
+                .request_uncached(&self.live(), &lookup_url, &mut record)
+                .await
+            {
+                Ok(Some(metadata)) => manifest::docs_url(&dependency, &metadata.content)
+                    .ok_or_else(|| format!("{lookup_url} names no documentation")),
+                Ok(None) => Err(format!("{lookup_url} couldn't be fetched")),
+                Err(e) => Err(e.to_string()),
+            },
+        };
+        let result = match &docs_url {
+            Ok(url) => {
+                record.url.clone_from(url);
+                self.fetch_documentation(
+                    url,
+                    namespace,
+                    &BTreeMap::new(),
+                    ContentTypeHint::Auto,
+                    peer,
+                    &mut record,
+                )
+                .await
+            }
+            Err(e) => Err(McpError::resource_not_found(e.clone(), None)),
+        };
+        let error = result.as_ref().err().map(|e| e.message.to_string());
+        self.audit(record, &result).await;
+        DependencyDocs {
+            name: dependency.name,
+            ecosystem: dependency.ecosystem.name(),
+            version: dependency.version,
+            docs_url: docs_url.ok(),
+            error,
+        }
+    }
+
+    /// Body of the `versions` tool; `record` collects what was downloaded
+    /// for the audit log.
+    async fn find_versions(
+        &self,
+        url: &str,
+        record: &mut AuditRecord,
+    ) -> Result<CallToolResult, McpError> {
+        let live = self.live();
+        if live.read_only {
+            return Err(McpError::invalid_request(
+                "Other versions can't be looked up while the server is in read-only mode",
+                None,
+            ));
+        }
+        self.usage.check(live.quota).map_err(quota_error)?;
+        let page = url::Url::parse(url)
+            .ok()
+            .filter(|u| matches!(u.scheme(), "http" | "https"))
+            .ok_or_else(|| {
+                McpError::invalid_params(format!("Not an http(s) URL: \"{url}\""), None)
+            })?;
+        let located = versions::detect(&page).ok_or_else(|| {
+            McpError::invalid_params(
+                format!(
+                    "No version in \"{url}\"; expected a path segment like /v2/, /en/stable/ or /3.12/"
+                ),
+                None,
+            )
+        })?;
+
+        let mut warnings = Vec::new();
+        let locs = self.sitemap_locs(&live, &page, &mut warnings, record).await;
+        let mut found: BTreeMap<String, &'static str> =
+            versions::sitemap_versions(&page, &located, &locs)
+                .into_iter()
+                .map(|version| (version, "sitemap"))
+                .collect();
+        found.remove(&located.version);
+        if found.is_empty() {
+            let guesses = versions::guesses(&located.version)
+                .into_iter()
+                .map(|version| {
+                    let sibling = versions::with_version(&page, &located, &version);
+                    (version, sibling.to_string())
+                })
+                .collect();
+            for version in self.probe(&live, guesses, &mut warnings, record).await {
+                found.insert(version, "probe");
+            }
+        }
+
+        let mut found: Vec<DocVersion> = found
+            .into_iter()
+            .map(|(version, found_in)| DocVersion {
+                url: versions::with_version(&page, &located, &version).to_string(),
+                version,
+                found_in,
+            })
+            .collect();
+        found.sort_by(|a, b| versions::compare(&a.version, &b.version));
+        Ok(VersionsOutput {
+            current: located.version,
+            versions: found,
+            warnings,
+        }
+        .into_result(self.structured_output()))
+    }
+
+    /// Body of the `debug_convert` tool; `record` collects what was downloaded
+    /// for the audit log.
+    async fn debug_convert_url(
+        &self,
+        url: &str,
+        record: &mut AuditRecord,
+    ) -> Result<CallToolResult, McpError> {
+        let live = self.live();
+        if live.read_only {
+            return Err(McpError::invalid_request(
+                "Pages can't be downloaded while the server is in read-only mode",
+                None,
+            ));
+        }
+        self.usage.check(live.quota).map_err(quota_error)?;
+        if !url::Url::parse(url).is_ok_and(|u| matches!(u.scheme(), "http" | "https")) {
+            return Err(McpError::invalid_params(
+                format!("Not an http(s) URL: \"{url}\""),
+                None,
+            ));
+        }
+        let Some(result) = self
+            .request_uncached(&live, url, record)
+            .await
+            .map_err(quota_error)?
+        else {
+            let reason = match record.variations.last().map(|v| &v.outcome) {
+                Some(Outcome::HttpError { status }) => format!("HTTP {status}"),
+                Some(Outcome::RedirectNotFollowed { reason, .. }) => reason.clone(),
+                _ => "network error".to_string(),
+            };
+            return Err(McpError::invalid_params(
+                format!("Failed to fetch {url}: {reason}"),
+                None,
+            ));
+        };
+        if !result.is_html || result.is_markdown {
+            return Err(McpError::invalid_params(
+                format!(
+                    "{} was served as {}, not HTML; fetch saves it without conversion",
+                    result.url,
+                    result.headers.content_type.as_deref().unwrap_or("text")
+                ),
+                None,
+            ));
+        }
+
+        let policy = live.policies.for_url(&result.url);
+        let bold_headings = policy.bold_headings.unwrap_or(live.bold_headings);
+        let normalize = policy.normalize.unwrap_or(live.normalize);
+        let permit = Arc::clone(&self.conversions)
+            .acquire_owned()
+            .await
+            .expect("conversion semaphore is never closed");
+        let output = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            debug_conversion(&result, &policy, bold_headings, normalize)
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("Conversion task failed: {e}"), None))?;
+        Ok(output.into_result(self.structured_output()))
+    }
+
+    /// Requests `url` for a lookup that doesn't cache it, counting it against
+    /// the quota and recording it for the audit log.
+    async fn request_uncached(
+        &self,
+        live: &Live,
+        url: &str,
+        record: &mut AuditRecord,
+    ) -> Result<Option<FetchResult>, quota::Exceeded> {
+        self.usage.reserve_request(live.quota)?;
+        if let Some(rate) = live.policies.for_url(url).requests_per_second {
+            self.throttle.wait(url, rate).await;
+        }
+        let (outcome, result) = match fetch_url(&live.http, url).await {
+            FetchAttempt::Success(result) => {
+                self.usage.add_bytes(result.content.len() as u64);
+                record.bytes += result.content.len() as u64;
+                let outcome = Outcome::Skipped {
+                    reason: "only checked, not cached".to_string(),
+                };
+                (outcome, Some(result))
+            }
+            FetchAttempt::HttpError { status, .. } => (Outcome::HttpError { status }, None),
+            FetchAttempt::NetworkError { .. } => (Outcome::NetworkError, None),
+            FetchAttempt::RedirectNotFollowed {
+                reason, location, ..
+            } => (Outcome::RedirectNotFollowed { reason, location }, None),
+        };
+        record.variations.push(Variation {
+            url: url.to_string(),
+            outcome,
+            elapsed_ms: None,
+        });
+        Ok(result)
+    }
+
+    /// The error for a fetch of `url` that saved nothing, with the next steps
+    /// to try. When the page itself doesn't exist, the site's sitemap is read
+    /// for pages to suggest instead.
+    async fn fetch_error(
+        &self,
+        url: &str,
+        variations: &[Variation],
+        record: &mut AuditRecord,
+    ) -> McpError {
+        let live = self.live();
+        let mut steps = next_steps(url, variations, &live.allow_headers);
+        let page = url::Url::parse(url)
+            .ok()
+            .filter(|page| matches!(page.scheme(), "http" | "https"));
+        if let Some(page) = page.filter(|_| page_missing(url, variations)) {
+            // Reading the sitemap is a courtesy; its problems aren't reported
+            let locs = self
+                .sitemap_locs(&live, &page, &mut Vec::new(), record)
+                .await;
+            let similar = suggest::closest(&page, &locs).into_iter().map(|loc| {
+                NextStep::fetch(loc, "a page with a similar path in the site's sitemap")
+            });
+            steps.splice(0..0, similar);
+        }
+        access_restricted(url, variations, &steps)
+            .unwrap_or_else(|| fetch_failed(url, variations, &steps))
+    }
+
+    /// The `<loc>` URLs of the sitemap of `page`'s site, following a sitemap
+    /// index to its first few sitemaps on the site.
+    async fn sitemap_locs(
+        &self,
+        live: &Live,
+        page: &url::Url,
+        warnings: &mut Vec<String>,
+        record: &mut AuditRecord,
+    ) -> Vec<String> {
+        let Ok(sitemap) = page.join("/sitemap.xml") else {
+            return Vec::new();
+        };
+        let mut queue = vec![sitemap.to_string()];
+        let mut locs = Vec::new();
+        let mut children = 0;
+        while let Some(sitemap) = queue.pop() {
+            let xml = match self.request_uncached(live, &sitemap, record).await {
+                Ok(Some(result)) => result.content,
+                Ok(None) => continue,
+                Err(e) => {
+                    warnings.push(format!("Stopped reading the sitemap: {e}"));
+                    break;
+                }
+            };
+            let mut found = versions::sitemap_locs(&xml);
+            if xml.contains("<sitemapindex") {
+                // Under `--same-site`, an index can't send requests elsewhere
+                let listed = found.len();
+                found.retain(|loc| !live.http.off_site(&sitemap, loc));
+                if found.len() < listed {
+                    warnings.push(format!(
+                        "Skipped {} sitemap(s) off the site listed in {sitemap}",
+                        listed - found.len()
+                    ));
+                }
+                let remaining = versions::MAX_CHILD_SITEMAPS.saturating_sub(children);
+                children += found.len().min(remaining);
+                queue.extend(found.into_iter().take(remaining).rev());
+            } else {
+                locs.extend(found);
+            }
+        }
+        locs
+    }
+
+    /// The versions among `guesses` (version, URL of the page in it) whose page
+    /// exists.
+    async fn probe(
+        &self,
+        live: &Live,
+        guesses: Vec<(String, String)>,
+        warnings: &mut Vec<String>,
+        record: &mut AuditRecord,
+    ) -> Vec<String> {
+        let mut existing = Vec::new();
+        for (version, url) in guesses {
+            match self.request_uncached(live, &url, record).await {
+                Ok(Some(_)) => existing.push(version),
+                Ok(None) => {}
+                Err(e) => {
+                    warnings.push(format!("Stopped checking versions: {e}"));
+                    break;
+                }
+            }
+        }
+        existing
+    }
+
+    /// Body of the `generate_llms_txt` tool; `record` collects what was
+    /// downloaded for the audit log, and the saved path is shown as `paths`
+    /// says.
+    async fn generate_llms_txt_from(
+        &self,
+        input: GenerateLlmsTxtInput,
+        paths: &OutputPaths,
+        record: &mut AuditRecord,
+    ) -> Result<CallToolResult, McpError> {
+        use std::fmt::Write;
+
+        let live = self.live();
+        if live.read_only {
+            return Err(McpError::invalid_request(
+                "Sites can't be crawled while the server is in read-only mode",
+                None,
+            ));
+        }
+        self.usage.check(live.quota).map_err(quota_error)?;
+        let GenerateLlmsTxtInput {
+            url,
+            max_pages,
+            namespace,
+        } = input;
+        let root = self.cache_root(namespace.or_else(|| live.namespace.clone()).as_deref())?;
+        let start = crawl_start(&url)?;
+        let limits = crawl::Limits {
+            max_pages: max_pages
+                .unwrap_or(crawl::DEFAULT_MAX_PAGES)
+                .clamp(1, crawl::MAX_PAGES),
+            max_depth: crawl::DEFAULT_MAX_DEPTH,
+        };
+
+        let mut warnings = Vec::new();
+        let crawled = self
+            .crawl(&live, &root, &start, limits, &mut warnings, record)
+            .await?;
+        check_crawled(&start, &crawled, &warnings)?;
+        let pages: Vec<crawl::Page> = crawled.into_iter().map(|(page, _)| page).collect();
+        let scope = crawl::Scope::new(&start);
+        let llms_txt = crawl::llms_txt(&pages, &scope);
+        let llms_txt_url = start.join("llms.txt").map_err(|e| {
+            McpError::internal_error(format!("Failed to build the llms.txt URL: {e}"), None)
+        })?;
+        let file_path = self
+            .save_to_cache(&root, llms_txt_url.as_str(), &llms_txt, None)
+            .await?
+            .path;
+
+        let mut output = String::new();
+        writeln!(output, "## {llms_txt_url} (generated)").unwrap();
+        writeln!(output, "Saved to: {}", paths.show(&file_path)).unwrap();
+        writeln!(output, "Pages crawled: {}\n", pages.len()).unwrap();
+        output.push_str(&llms_txt);
+        for warning in &warnings {
+            writeln!(output, "\nWarning: {warning}").unwrap();
+        }
+        Ok(CallToolResult::success(vec![Content::text(
+            output.trim_end().to_string(),
+        )]))
+    }
+
+    /// Body of the `fetch_site` tool; `record` collects what was downloaded
+    /// for the audit log, and cached paths are shown as `paths` says.
+    async fn fetch_site_from(
+        &self,
+        input: FetchSiteInput,
+        paths: &OutputPaths,
+        record: &mut AuditRecord,
+    ) -> Result<CallToolResult, McpError> {
+        use std::fmt::Write;
+
+        let live = self.live();
+        if live.read_only {
+            return Err(McpError::invalid_request(
+                "Sites can't be crawled while the server is in read-only mode",
+                None,
+            ));
+        }
+        self.usage.check(live.quota).map_err(quota_error)?;
+        let FetchSiteInput {
+            url,
+            max_pages,
+            max_depth,
+            namespace,
+        } = input;
+        let root = self.cache_root(namespace.or_else(|| live.namespace.clone()).as_deref())?;
+        let start = crawl_start(&url)?;
+        let limits = crawl::Limits {
+            max_pages: max_pages
+                .unwrap_or(crawl::DEFAULT_MAX_PAGES)
+                .clamp(1, crawl::MAX_PAGES),
+            max_depth: max_depth
+                .unwrap_or(crawl::DEFAULT_MAX_DEPTH)
+                .min(crawl::MAX_DEPTH),
+        };
+
+        let mut warnings = Vec::new();
+        let crawled = self
+            .crawl(&live, &root, &start, limits, &mut warnings, record)
+            .await?;
+        check_crawled(&start, &crawled, &warnings)?;
+
+        let mut output = String::new();
+        writeln!(output, "## {start} (crawled)").unwrap();
+        writeln!(output, "Pages cached: {}\n", crawled.len()).unwrap();
+        for (page, file) in &crawled {
+            match &page.title {
+                Some(title) => writeln!(output, "- {title} ({})", page.url).unwrap(),
+                None => writeln!(output, "- {}", page.url).unwrap(),
+            }
+            writeln!(output, "  Saved to: {}", paths.show(file)).unwrap();
+        }
+        for warning in &warnings {
+            writeln!(output, "\nWarning: {warning}").unwrap();
+        }
+        Ok(CallToolResult::success(vec![Content::text(
+            output.trim_end().to_string(),
+        )]))
+    }
+
+    #[tool(
+        description = "Searches the local documentation cache for pages containing all the query's words (use double quotes for phrases), optionally only pages from one domain. Returns the best matches with the file path, source URL, the best-matching line, and the heading breadcrumb and line range of the section containing it, so just that section can be read. Makes no network requests.",
+        output_schema = rmcp::handler::server::tool::cached_schema_for_type::<SearchOutput>(),
+        annotations(
+            title = "Search Cache",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn search(
+        &self,
+        params: Parameters<SearchInput>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let SearchInput {
+            query,
+            domain,
+            limit,
+            namespace,
+        } = params.0;
+        let live = self.live();
+        let root = self.cache_root(namespace.or_else(|| live.namespace.clone()).as_deref())?;
+        let parsed = search::Query::parse(&query)
+            .ok_or_else(|| McpError::invalid_params("query has no words to search for", None))?;
+        let limit = limit
+            .unwrap_or(search::DEFAULT_LIMIT)
+            .clamp(1, search::MAX_LIMIT);
+        #[cfg(feature = "search-index")]
+        let (indexes, writable) = (Arc::clone(&self.search_indexes), !live.read_only);
+        let store = self.store();
+        let (engine, hits) = tokio::task::spawn_blocking(move || {
+            // The index is kept on disk, so files in memory are scanned
+            #[cfg(feature = "search-index")]
+            if matches!(store, memory::Store::Disk)
+                && let Some(index) = indexes.get(&root, writable)
+            {
+                match index.search(&query, &parsed, domain.as_deref(), limit) {
+                    Ok(hits) => return Ok(("index", hits)),
+                    Err(e) => eprintln!("warning: search index failed ({e}); scanning the files"),
+                }
+            }
+            search::scan(&store, &root, &parsed, domain.as_deref(), limit)
+                .map(|hits| ("scan", hits))
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("Search task failed: {e}"), None))?
+        .map_err(|e| McpError::internal_error(format!("Failed to search the cache: {e}"), None))?;
+
+        let paths = self.output_paths(&peer).await;
+        let hits = hits
+            .into_iter()
+            .map(|hit| SearchHit {
+                path: paths.show(&hit.path),
+                source_url: hit.source_url,
+                score: hit.score,
+                line: hit.line,
+                snippet: hit.snippet,
+                section: hit.section.map(|section| SearchSection {
+                    breadcrumb: section.breadcrumb,
+                    start_line: section.start_line,
+                    end_line: section.end_line,
+                }),
+            })
+            .collect();
+        Ok(SearchOutput { engine, hits }.into_result(self.structured_output()))
+    }
+
+    #[tool(
+        description = "Searches the lines of the cached files for a regular expression, like grep, for exact lookups such as error codes or function names. Optionally limits the files with a glob on their path in the cache and shows context lines around each match. Makes no network requests.",
+        output_schema = rmcp::handler::server::tool::cached_schema_for_type::<GrepOutput>(),
+        annotations(
+            title = "Grep Cache",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn grep(
+        &self,
+        params: Parameters<GrepInput>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let GrepInput {
+            pattern,
+            path_glob,
+            before,
+            after,
+            max_matches,
+            namespace,
+        } = params.0;
+        let root = self.cache_root(
+            namespace
+                .or_else(|| self.live().namespace.clone())
+                .as_deref(),
+        )?;
+        let pattern = regex::RegexBuilder::new(&pattern)
+            .size_limit(1 << 20)
+            .build()
+            .map_err(|e| McpError::invalid_params(format!("Invalid pattern: {e}"), None))?;
+        let paths = path_glob
+            .as_deref()
+            .map(grep::path_matcher)
+            .transpose()
+            .map_err(|e| McpError::invalid_params(format!("Invalid path_glob: {e}"), None))?;
+        let context = (
+            before.unwrap_or(0).min(grep::MAX_CONTEXT),
+            after.unwrap_or(0).min(grep::MAX_CONTEXT),
+        );
+        let max_matches = max_matches
+            .unwrap_or(grep::DEFAULT_MAX_MATCHES)
+            .clamp(1, grep::MAX_MATCHES);
+        let store = self.store();
+        let found = tokio::task::spawn_blocking(move || {
+            grep::grep(
+                &store,
+                &root,
+                &pattern,
+                paths.as_ref(),
+                context,
+                max_matches,
+            )
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("Grep task failed: {e}"), None))?
+        .map_err(|e| McpError::internal_error(format!("Failed to search the cache: {e}"), None))?;
+
+        let paths = self.output_paths(&peer).await;
+        let matches = found
+            .matches
+            .into_iter()
+            .map(|m| GrepMatch {
+                path: paths.show(&m.path),
+                source_url: m.source_url,
+                line: m.line,
+                text: m.text,
+                before: m.before,
+                after: m.after,
+            })
+            .collect();
+        Ok(GrepOutput {
+            matches,
+            truncated: found.truncated,
+        }
+        .into_result(self.structured_output()))
+    }
+
+    #[tool(
+        description = "Turns a passage of a cached file into a citation: the URL the file was fetched from, pointing at the anchor of the nearest heading above the passage, and a ready-to-embed Markdown link. Use it to cite where an answer came from. The passage is given as lines, or as character offsets like those a ToC shows with --toc-offsets. Makes no network requests.",
+        output_schema = rmcp::handler::server::tool::cached_schema_for_type::<CiteOutput>(),
+        annotations(
+            title = "Cite Cached Lines",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn cite(
+        &self,
+        params: Parameters<CiteInput>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let CiteInput {
+            path,
+            start_line,
+            end_line,
+            start_offset,
+            end_offset,
+            namespace,
+        } = params.0;
+        let (file, paths, content) = self.read_cached(&path, namespace, &peer).await?;
+        let store = self.store();
+        let Some(source_url) = store.sidecar(&file).await.map(|meta| meta.url) else {
+            return Err(McpError::invalid_params(
+                format!("{path} has no recorded source URL; fetch it again to cite it"),
+                None,
+            ));
+        };
+        let line_count = content.lines().count();
+        let line = |line: Option<usize>, offset: Option<usize>, name: &str| {
+            line.or_else(|| offset.and_then(|offset| cite::line_at(&content, offset)))
+                .ok_or_else(|| {
+                    McpError::invalid_params(
+                        format!(
+                            "Give {name}_line or a {name}_offset within the file's {} characters",
+                            content.chars().count()
+                        ),
+                        None,
+                    )
+                })
+        };
+        let start_line = line(start_line, start_offset, "start")?;
+        let end_line = line(end_line, end_offset, "end")?;
+        if start_line == 0 || start_line > end_line || start_line > line_count {
+            return Err(McpError::invalid_params(
+                format!("The lines must be within 1-{line_count}, with start_line <= end_line"),
+                None,
+            ));
+        }
+
+        let anchor = cite::anchor_before(&content, start_line);
+        let link = cite::link(&source_url, anchor.as_ref());
+        let title = anchor
+            .as_ref()
+            .map_or(source_url.as_str(), |anchor| anchor.heading.as_str());
+        let markdown = format!("[{}]({link})", title.replace(['[', ']'], ""));
+        Ok(CiteOutput {
+            source_url,
+            link,
+            heading: anchor.as_ref().map(|anchor| anchor.heading.clone()),
+            anchor: anchor.map(|anchor| anchor.id),
+            markdown,
+            path: paths.show(&file),
+            start_line,
+            end_line: end_line.min(line_count),
+        }
+        .into_result(self.structured_output()))
+    }
+
+    #[tool(
+        description = "Reads a range of lines from a cached file, with line numbers. Use it with the line numbers of a fetch result's ToC, or of search and grep matches, when the cached files can't be read directly, such as from a remote server. Returns up to 500 lines per call. Makes no network requests.",
+        output_schema = rmcp::handler::server::tool::cached_schema_for_type::<ReadLinesOutput>(),
+        annotations(
+            title = "Read Cached Lines",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn read_lines(
+        &self,
+        params: Parameters<ReadLinesInput>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let ReadLinesInput {
+            path,
+            start_line,
+            end_line,
+            namespace,
+        } = params.0;
+        let (file, paths, content) = self.read_cached(&path, namespace, &peer).await?;
+        let total_lines = content.lines().count();
+        let end_line = end_line.unwrap_or(start_line.saturating_add(READ_LINES_DEFAULT - 1));
+        if start_line == 0 || start_line > end_line || start_line > total_lines {
+            return Err(McpError::invalid_params(
+                format!("The lines must be within 1-{total_lines}, with start_line <= end_line"),
+                None,
+            ));
+        }
+        let end_line = end_line
+            .min(total_lines)
+            .min(start_line + READ_LINES_MAX - 1);
+        let lines: Vec<&str> = content
+            .lines()
+            .skip(start_line - 1)
+            .take(end_line + 1 - start_line)
+            .collect();
+        Ok(ReadLinesOutput {
+            path: paths.show(&file),
+            start_line,
+            end_line,
+            total_lines,
+            content: lines.join("\n"),
+        }
+        .into_result(self.structured_output()))
+    }
 
-#[tool_router]
-impl FetchServer {
-    fn new(cache_dir: Option<PathBuf>, toc_budget: usize, toc_threshold: usize) -> Self {
-        let cache_path = cache_dir.unwrap_or_else(|| PathBuf::from(".llms-fetch-mcp"));
-        // Ensure cache_dir is absolute for security (prevents relative path bypass)
-        let absolute_cache = cache_path.canonicalize().unwrap_or_else(|_| {
-            // If path doesn't exist, make it absolute relative to current dir
-            std::env::current_dir()
-                .unwrap_or_else(|_| PathBuf::from("/tmp"))
-                .join(&cache_path)
-        });
+    #[tool(
+        description = "Reads a project manifest (Cargo.toml, package.json or pyproject.toml) from the client's workspace and fetches the documentation of every dependency into the cache: docs.rs for crates, and the documentation or home page the npm or PyPI registry lists for packages. One call prepares the docs for a whole project; afterwards use search, grep or the cached files. Returns each dependency's docs URL, or why it failed.",
+        output_schema = rmcp::handler::server::tool::cached_schema_for_type::<FetchDependenciesOutput>(),
+        annotations(
+            title = "Fetch Dependency Docs",
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = true
+        )
+    )]
+    async fn fetch_dependencies(
+        &self,
+        params: Parameters<FetchDependenciesInput>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let FetchDependenciesInput {
+            manifest,
+            include_dev,
+            namespace,
+        } = params.0;
+        let live = self.live();
+        if live.read_only {
+            return Err(McpError::invalid_request(
+                "Dependencies can't be fetched while the server is in read-only mode",
+                None,
+            ));
+        }
+        self.usage.check(live.quota).map_err(quota_error)?;
+        let namespace = namespace.or_else(|| live.namespace.clone());
+        self.cache_root(namespace.as_deref())?;
+
+        let mut warnings = Vec::new();
+        let manifests = self.find_manifests(manifest.as_deref(), &peer).await?;
+        let mut dependencies = Vec::new();
+        for path in &manifests {
+            let content = fs::read_to_string(path).await.map_err(|e| {
+                McpError::resource_not_found(format!("Cannot read {}: {e}", path.display()), None)
+            })?;
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            let found = manifest::dependencies(&file_name, &content, include_dev)
+                .map_err(|e| McpError::invalid_params(e, None))?;
+            dependencies.extend(found);
+        }
+        if dependencies.len() > manifest::MAX_DEPENDENCIES {
+            warnings.push(format!(
+                "Only the first {} of {} dependencies were fetched",
+                manifest::MAX_DEPENDENCIES,
+                dependencies.len()
+            ));
+            dependencies.truncate(manifest::MAX_DEPENDENCIES);
+        }
 
-        Self {
-            cache_dir: Arc::new(absolute_cache),
-            toc_config: toc::TocConfig {
-                toc_budget,
-                full_content_threshold: toc_threshold,
-            },
-            tool_router: Self::tool_router(),
+        let mut results: Vec<(usize, DependencyDocs)> = Vec::new();
+        let mut queue = dependencies.into_iter().enumerate();
+        let mut tasks = tokio::task::JoinSet::new();
+        loop {
+            while tasks.len() < DEPENDENCY_CONCURRENCY
+                && let Some((position, dependency)) = queue.next()
+            {
+                let server = self.clone();
+                let (namespace, peer) = (namespace.clone(), peer.clone());
+                tasks.spawn(async move {
+                    let docs = server
+                        .fetch_dependency(dependency, namespace.as_deref(), &peer)
+                        .await;
+                    (position, docs)
+                });
+            }
+            let Some(joined) = tasks.join_next().await else {
+                break;
+            };
+            if let Ok(result) = joined {
+                results.push(result);
+            }
         }
+        results.sort_by_key(|(position, _)| *position);
+        self.save_cookies().await;
+        Ok(FetchDependenciesOutput {
+            manifests: manifests
+                .iter()
+                .map(|path| path.to_string_lossy().to_string())
+                .collect(),
+            dependencies: results.into_iter().map(|(_, docs)| docs).collect(),
+            warnings,
+        }
+        .into_result(self.structured_output()))
     }
 
     #[tool(
-        description = "Use to access documentation and guides from the web. Start with documentation root URLs (e.g., https://docs.example.com) - the tool automatically discovers llms.txt files and tries multiple formats (.md, /index.md, /llms.txt, /llms-full.txt), so you don't need to explicitly request /llms.txt. Content is converted to markdown and cached locally. Returns file path with table of contents for navigation. For GitHub files, use raw.githubusercontent.com URLs for best results."
+        description = "Finds the other versions of a versioned documentation page (a URL with a segment like /v2/, /en/stable/ or /3.12/) from the site's sitemap, or by requesting the page in neighbouring versions when the sitemap lists none. Returns each version with the URL of the same page in it, so the docs matching a project's dependency can be fetched.",
+        output_schema = rmcp::handler::server::tool::cached_schema_for_type::<VersionsOutput>(),
+        annotations(
+            title = "List Docs Versions",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = true
+        )
     )]
-    async fn fetch(&self, params: Parameters<FetchInput>) -> Result<CallToolResult, McpError> {
-        let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .build()
-            .map_err(|e| {
-                McpError::internal_error(format!("Failed to create HTTP client: {e}"), None)
-            })?;
+    async fn versions(
+        &self,
+        params: Parameters<VersionsInput>,
+    ) -> Result<CallToolResult, McpError> {
+        let url = params.0.url;
+        let mut record = AuditRecord::new("versions", &url, None);
+        let result = self.find_versions(&url, &mut record).await;
+        self.audit(record, &result).await;
+        self.save_cookies().await;
+        result
+    }
 
-        let variations = get_url_variations(&params.0.url);
+    #[tool(
+        description = "Lists files in the local documentation cache with their source URLs and the HTTP headers (content type, ETag, Last-Modified, language, links) they were served with. Makes no network requests.",
+        output_schema = rmcp::handler::server::tool::cached_schema_for_type::<ListCacheOutput>(),
+        annotations(
+            title = "List Cached Files",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn list_cache(
+        &self,
+        params: Parameters<ListCacheInput>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let ListCacheInput {
+            url_prefix,
+            namespace,
+        } = params.0;
+        let namespace = namespace.or_else(|| self.live().namespace.clone());
+        let root = self.cache_root(namespace.as_deref())?;
+        let store = self.store();
+        let entries = tokio::task::spawn_blocking(move || store.list(&root))
+            .await
+            .map_err(|e| McpError::internal_error(format!("Listing task failed: {e}"), None))?
+            .map_err(|e| McpError::internal_error(format!("Failed to list cache: {e}"), None))?;
+        let paths = self.output_paths(&peer).await;
+
+        let files = entries
+            .into_iter()
+            .map(|entry| {
+                let (source_url, fetched_at, http, version) = match entry.sidecar {
+                    Some(meta) => (
+                        Some(meta.url),
+                        Some(meta.fetched_at),
+                        meta.http,
+                        meta.version,
+                    ),
+                    None => (None, None, None, None),
+                };
+                let mut aliases = entry
+                    .path
+                    .strip_prefix(self.cache_dir.as_path())
+                    .map(|relative| self.index.aliases(&relative.to_string_lossy()))
+                    .unwrap_or_default();
+                aliases.retain(|alias| Some(alias) != source_url.as_ref());
+                CachedFile {
+                    path: paths.show(&entry.path),
+                    source_url,
+                    bytes: entry.bytes,
+                    fetched_at,
+                    http,
+                    version,
+                    aliases,
+                }
+            })
+            .filter(|file| {
+                url_prefix.as_deref().is_none_or(|prefix| {
+                    file.source_url
+                        .iter()
+                        .chain(&file.aliases)
+                        .any(|url| url.starts_with(prefix))
+                })
+            })
+            .collect();
+        Ok(ListCacheOutput { files }.into_result(self.structured_output()))
+    }
 
-        let mut fetch_tasks = Vec::new();
-        for url in &variations {
-            let client_clone = client.clone();
-            let url_clone = url.clone();
-            fetch_tasks.push(tokio::spawn(async move {
-                fetch_url(&client_clone, &url_clone).await
-            }));
+    #[tool(
+        description = "Keeps a frequently consulted page (release notes, a status page) warm in the cache by refetching it every interval_secs while the server runs. Set interval_secs to 0 to unpin. Lists the URLs pinned in the namespace.",
+        annotations(
+            title = "Pin URL",
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = true
+        )
+    )]
+    async fn pin(&self, params: Parameters<PinInput>) -> Result<CallToolResult, McpError> {
+        use std::fmt::Write;
+
+        let PinInput {
+            url,
+            interval_secs,
+            namespace,
+        } = params.0;
+        if self.live().read_only {
+            return Err(McpError::invalid_request(
+                "URLs can't be pinned while the server is in read-only mode",
+                None,
+            ));
+        }
+        if interval_secs > 0 {
+            pins::validate(&url, interval_secs).map_err(|e| McpError::invalid_params(e, None))?;
+        }
+        let namespace = namespace.or_else(|| self.live().namespace.clone());
+        self.cache_root(namespace.as_deref())?;
+        let was_pinned = self.pins.set(&url, interval_secs, namespace.as_deref());
+        if interval_secs == 0 {
+            self.watches
+                .lock()
+                .unwrap()
+                .remove(&(namespace.clone(), url.clone()));
         }
 
-        let mut results = Vec::new();
-        let mut errors = Vec::new();
-        for task in fetch_tasks {
-            match task.await {
-                Ok(attempt) => match attempt {
-                    FetchAttempt::Success(result) => results.push(result),
-                    FetchAttempt::HttpError { url, status } => {
-                        errors.push(format!("{url}: HTTP {status}"));
-                    }
-                    FetchAttempt::NetworkError { url } => {
-                        errors.push(format!("{url}: network error"));
-                    }
-                },
-                Err(e) => {
-                    errors.push(format!("task panicked: {e}"));
-                }
+        let mut text = match (interval_secs, was_pinned) {
+            (0, true) => format!("Unpinned {url}."),
+            (0, false) => format!("{url} was not pinned."),
+            (secs, _) => format!("Pinned {url}, refreshed every {secs}s."),
+        };
+        let pinned = self.pins.list(namespace.as_deref());
+        if !pinned.is_empty() {
+            text.push_str("\n\nPinned URLs:");
+        }
+        for pin in pinned {
+            write!(text, "\n- {} (every {}s", pin.url, pin.interval_secs).unwrap();
+            if let Some(namespace) = &pin.namespace {
+                write!(text, ", namespace {namespace}").unwrap();
+            }
+            if pin.configured {
+                text.push_str(", from the config file");
             }
+            text.push(')');
         }
+        Ok(CallToolResult::success(vec![Content::text(text)]))
+    }
 
-        if results.is_empty() {
-            let error_details = if errors.is_empty() {
-                format!("tried {} variations", variations.len())
-            } else {
-                errors.join("; ")
-            };
-            return Err(McpError::resource_not_found(
-                format!(
-                    "Failed to fetch content from {} ({})",
-                    params.0.url, error_details
-                ),
+    #[tool(
+        description = "Watches a page for changes: pins it (see pin) and exposes it as a resource. When a scheduled refresh finds the content changed, a resources/updated notification is sent for the URL, so the user can be alerted or a workflow re-run. Use pin with interval_secs 0 to stop watching.",
+        annotations(
+            title = "Watch URL",
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = true
+        )
+    )]
+    async fn watch(
+        &self,
+        params: Parameters<WatchInput>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let WatchInput {
+            url,
+            interval_secs,
+            namespace,
+        } = params.0;
+        let interval = interval_secs.unwrap_or(DEFAULT_WATCH_INTERVAL);
+        if self.live().read_only {
+            return Err(McpError::invalid_request(
+                "URLs can't be watched while the server is in read-only mode",
                 None,
             ));
         }
+        pins::validate(&url, interval).map_err(|e| McpError::invalid_params(e, None))?;
+        let namespace = namespace.or_else(|| self.live().namespace.clone());
+        self.cache_root(namespace.as_deref())?;
+        self.pins.set(&url, interval, namespace.as_deref());
+        self.watches
+            .lock()
+            .unwrap()
+            .insert((namespace, url.clone()), Some(peer));
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Watching {url}, checked every {interval}s. A resources/updated notification with this URL as the resource URI is sent when its content changes; read the resource for the current version."
+        ))]))
+    }
 
-        ensure_gitignore(&self.cache_dir).await.map_err(|e| {
-            McpError::internal_error(format!("Failed to create .gitignore: {e}"), None)
-        })?;
-
-        let mut file_infos = Vec::new();
-        let mut seen_hashes: HashSet<u64> = HashSet::new();
-
-        let has_non_html = results.iter().any(|r| !r.is_html);
-
-        for result in results {
-            let url_lower = result.url.to_lowercase();
-            let content_type = if url_lower.contains("/llms-full.txt") {
-                "llms-full"
-            } else if url_lower.contains("/llms.txt") {
-                "llms"
-            } else if result.is_markdown {
-                "markdown"
-            } else if result.is_html {
-                "html-converted"
-            } else {
-                "text"
-            };
+    #[tool(
+        description = "Resets this session's fetch quota after fetch or changelog failed with a quota error. Only call this when the user has approved continuing.",
+        annotations(
+            title = "Reset Fetch Quota",
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn reset_quota(&self) -> Result<CallToolResult, McpError> {
+        let limits = self.live().quota;
+        let (requests, bytes) = self.usage.reset();
+        let text = if limits.is_unlimited() {
+            format!(
+                "No quota is configured. Counters reset after {requests} requests and {bytes} bytes."
+            )
+        } else {
+            let limit =
+                |value: Option<u64>| value.map_or("unlimited".to_string(), |v| v.to_string());
+            format!(
+                "Quota reset. This session had used {requests} requests (limit: {}) and {bytes} bytes (limit: {}).",
+                limit(limits.max_requests),
+                limit(limits.max_bytes)
+            )
+        };
+        Ok(CallToolResult::success(vec![Content::text(text)]))
+    }
 
-            if has_non_html && result.is_html {
-                continue;
+    #[tool(
+        description = "Reports the server's version, the optional features compiled in, the URL schemes fetch accepts, this session's request and byte usage, and the settings in effect (cache directory, ToC budgets, timeouts, limits, policies). Secrets are only reported as set or not. Use it to check how the server is configured.",
+        output_schema = rmcp::handler::server::tool::cached_schema_for_type::<InfoOutput>(),
+        annotations(
+            title = "Server Info",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn info(&self) -> Result<CallToolResult, McpError> {
+        let (requests, bytes) = self.usage.get();
+        let mut config = self.live().config.clone();
+        // As resolved at startup, rather than as given
+        config.cache_dir = self.cache_dir.to_string_lossy().into_owned();
+        let mut features = Vec::new();
+        let mut schemes = vec!["http", "https", "file", "data"];
+        if cfg!(feature = "search-index") {
+            features.push("search-index");
+        }
+        if cfg!(feature = "http3") {
+            features.push("http3");
+        }
+        if cfg!(feature = "xpath") {
+            features.push("xpath");
+        }
+        for (feature, enabled) in [
+            ("ftp", cfg!(feature = "ftp")),
+            ("gemini", cfg!(feature = "gemini")),
+            ("gopher", cfg!(feature = "gopher")),
+        ] {
+            if enabled {
+                features.push(feature);
+                schemes.push(feature);
             }
+        }
+        Ok(InfoOutput {
+            version: env!("CARGO_PKG_VERSION"),
+            protocol_version: self.protocol_version.get().map(ToString::to_string),
+            features,
+            schemes,
+            requests,
+            bytes,
+            update: self.update.get().cloned(),
+            config,
+        }
+        .into_result(self.structured_output()))
+    }
 
-            let content_to_save = if result.is_html && !result.is_markdown {
-                html_to_markdown(&result.content, &result.url).map_err(|e| {
-                    McpError::internal_error(
-                        format!("Failed to convert HTML to markdown: {e}"),
-                        None,
-                    )
-                })?
-            } else {
-                result.content.clone()
-            };
+    #[tool(
+        description = "Reports this session's usage: calls per tool, the domains fetched, and how many fetches were served from the cache (the cache hit rate). Counts are kept in memory only and never sent anywhere. Use it to tune caching settings such as --max-age, or to describe usage in a bug report.",
+        output_schema = rmcp::handler::server::tool::cached_schema_for_type::<SessionStatsOutput>(),
+        annotations(
+            title = "Session Statistics",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = false,
+            open_world_hint = false
+        )
+    )]
+    async fn session_stats(&self) -> Result<CallToolResult, McpError> {
+        Ok(
+            SessionStatsOutput::new(self.stats.uptime_secs(), self.stats.get())
+                .into_result(self.structured_output()),
+        )
+    }
 
-            let mut hasher = DefaultHasher::new();
-            content_to_save.hash(&mut hasher);
-            if !seen_hashes.insert(hasher.finish()) {
-                continue;
-            }
+    #[tool(
+        description = "Downloads an HTML page without caching it and shows each stage of its conversion to Markdown: the size of the HTML, what the domain policy's selector, Readability and the <body> fallback each extract (as Markdown, truncated), which of them was used, and the final Markdown fetch would save. Use it to find out why a fetched page came out empty, noisy or missing sections, e.g. before writing a domain policy.",
+        output_schema = rmcp::handler::server::tool::cached_schema_for_type::<DebugConvertOutput>(),
+        annotations(
+            title = "Debug HTML Conversion",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = true
+        )
+    )]
+    async fn debug_convert(
+        &self,
+        params: Parameters<DebugConvertInput>,
+    ) -> Result<CallToolResult, McpError> {
+        let url = params.0.url;
+        let mut record = AuditRecord::new("debug_convert", &url, None);
+        let result = self.debug_convert_url(&url, &mut record).await;
+        self.audit(record, &result).await;
+        self.save_cookies().await;
+        result
+    }
+}
 
-            let file_path = url_to_path(&self.cache_dir, &result.url).map_err(|e| {
-                McpError::internal_error(format!("Failed to create cache path: {e}"), None)
-            })?;
+impl ServerHandler for FetchServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            protocol_version: LATEST_PROTOCOL_VERSION,
+            capabilities: ServerCapabilities::builder()
+                .enable_tools()
+                .enable_resources()
+                .enable_resources_subscribe()
+                .build(),
+            server_info: Implementation::from_build_env(),
+            instructions: Some(
+                "Web content fetcher with intelligent format detection for documentation. Automatically discovers llms.txt files - just provide the root URL. Cleans HTML and converts to Markdown. Generates table of contents for navigation. Deduplicates content automatically."
+                    .to_string(),
+            ),
+        }
+    }
 
-            if let Some(parent) = file_path.parent() {
-                fs::create_dir_all(parent).await.map_err(|e| {
-                    McpError::internal_error(format!("Failed to create directory: {e}"), None)
-                })?;
-            }
+    /// Dispatches to the tool router, counting the call for `session_stats`
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        if self.tool_router.has_route(&request.name) {
+            self.stats.record_call(&request.name);
+        }
+        self.tool_router
+            .call(ToolCallContext::new(self, request, context))
+            .await
+    }
 
-            // Atomic write: temp file + rename to prevent corruption from concurrent writes
-            let temp_path = file_path.with_extension("tmp");
-            fs::write(&temp_path, &content_to_save).await.map_err(|e| {
-                McpError::internal_error(format!("Failed to write temp file: {e}"), None)
-            })?;
-            fs::rename(&temp_path, &file_path).await.map_err(|e| {
-                McpError::internal_error(format!("Failed to finalize file: {e}"), None)
-            })?;
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult::with_all_items(self.tool_router.list_all()))
+    }
 
-            let (lines, words, characters) = count_stats(&content_to_save);
+    async fn initialize(
+        &self,
+        request: InitializeRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<InitializeResult, McpError> {
+        let protocol_version = negotiate_protocol_version(&request.protocol_version);
+        if context.peer.peer_info().is_none() {
+            context.peer.set_peer_info(request);
+        }
+        let _ = self.protocol_version.set(protocol_version.clone());
+        Ok(InitializeResult {
+            protocol_version,
+            ..self.get_info()
+        })
+    }
 
-            let table_of_contents =
-                toc::generate_toc(&content_to_save, characters, &self.toc_config);
+    /// URLs watched in the server's namespace are the resources
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        let namespace = self.live().namespace.clone();
+        let resources = self
+            .watches
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|(watched_namespace, _)| *watched_namespace == namespace)
+            .map(|(_, url)| {
+                let mut resource = RawResource::new(url, url);
+                resource.mime_type = Some("text/markdown".to_string());
+                resource.no_annotation()
+            })
+            .collect();
+        Ok(ListResourcesResult {
+            resources,
+            next_cursor: None,
+        })
+    }
 
-            // For small files (below ToC threshold), include full content inline
-            let content = if characters < self.toc_config.full_content_threshold {
-                Some(content_to_save)
-            } else {
-                None
+    /// The cached files of a watched URL, as `fetch` would return them, or a
+    /// cached file `fetch` linked to
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        let url = request.uri;
+        if url.starts_with(CACHE_URI) {
+            // Only the server's namespace, as the URI doesn't name one
+            let root = self.cache_root(self.live().namespace.as_deref())?;
+            let path = cache_uri_path(&self.cache_dir, &url)
+                .and_then(|path| self.cached_document(&root, &path));
+            let text = match path {
+                Some(path) => self.store().read_async(&path).await.ok(),
+                None => None,
             };
-
-            file_infos.push(FileInfo {
-                path: file_path.to_string_lossy().to_string(),
-                source_url: result.url.clone(),
-                content_type: content_type.to_string(),
-                lines,
-                words,
-                characters,
-                table_of_contents,
-                content,
+            let Some(text) = text else {
+                return Err(McpError::resource_not_found(
+                    format!("{url} is not in the cache"),
+                    None,
+                ));
+            };
+            return Ok(ReadResourceResult {
+                contents: vec![ResourceContents::TextResourceContents {
+                    uri: url,
+                    mime_type: Some("text/markdown".to_string()),
+                    text,
+                    meta: None,
+                }],
+            });
+        }
+        let namespace = self.live().namespace.clone();
+        let key = (namespace, url);
+        if !self.watches.lock().unwrap().contains_key(&key) {
+            return Err(McpError::resource_not_found(
+                format!("{} is not watched", key.1),
+                None,
+            ));
+        }
+        let (namespace, url) = key;
+        let root = self.cache_root(namespace.as_deref())?;
+        let (files, _, _) = self.cached_variations(&url, &root, "not cached").await;
+        let mut contents = Vec::new();
+        for file in files {
+            let text = read_cached(&self.store(), Path::new(&file.path), &url).await?;
+            contents.push(ResourceContents::TextResourceContents {
+                uri: file.source_url,
+                mime_type: Some("text/markdown".to_string()),
+                text,
+                meta: None,
             });
         }
+        if contents.is_empty() {
+            return Err(McpError::resource_not_found(
+                format!("{url} has not been fetched yet"),
+                None,
+            ));
+        }
+        Ok(ReadResourceResult { contents })
+    }
 
-        let text_output = format_output(&file_infos);
+    async fn subscribe(
+        &self,
+        request: SubscribeRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        let key = (self.live().namespace.clone(), request.uri);
+        match self.watches.lock().unwrap().get_mut(&key) {
+            Some(watcher) => {
+                *watcher = Some(context.peer);
+                Ok(())
+            }
+            None => Err(McpError::invalid_params(
+                format!("{} is not watched; call the watch tool first", key.1),
+                None,
+            )),
+        }
+    }
 
-        Ok(CallToolResult::success(vec![Content::text(text_output)]))
+    async fn unsubscribe(
+        &self,
+        request: UnsubscribeRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        let key = (self.live().namespace.clone(), request.uri);
+        if let Some(watcher) = self.watches.lock().unwrap().get_mut(&key) {
+            *watcher = None;
+        }
+        Ok(())
     }
 }
 
-#[tool_handler]
-impl ServerHandler for FetchServer {
-    fn get_info(&self) -> ServerInfo {
-        ServerInfo {
-            protocol_version: ProtocolVersion::V_2024_11_05,
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
-            server_info: Implementation::from_build_env(),
-            instructions: Some(
-                "Web content fetcher with intelligent format detection for documentation. Automatically discovers llms.txt files - just provide the root URL. Cleans HTML and converts to Markdown. Generates table of contents for navigation. Deduplicates content automatically."
-                    .to_string(),
-            ),
+/// Body of the `migrate` subcommand: moves the files cached under `root` to
+/// their current paths.
+async fn migrate_cache(
+    cache_dir: &Path,
+    root: &Path,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let index = index::CacheIndex::load(cache_dir);
+    let path_for = |url: &str| {
+        url_to_path(root, url)
+            .map(|path| memory::Store::Disk.locate(path))
+            .map_err(|e| e.to_string())
+    };
+    let report = migrate::migrate(cache_dir, root, &index, path_for, dry_run)
+        .map_err(|e| format!("Failed to migrate cache {}: {e}", root.display()))?;
+    if !dry_run {
+        for moved in &report.moved {
+            record_url(&index, cache_dir, &moved.to, &moved.url);
+            record_aliases(&index, cache_dir, &moved.to, &moved.url);
         }
+        index.save().await?;
     }
+    let note = if dry_run { "dry run, " } else { "" };
+    println!("{}: {note}{report}", root.display());
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
+    let settings = Settings::load(&cli)?;
+
+    let check_cache = || {
+        verify::check_and_repair(&settings.cache_dir).map_err(|e| {
+            format!(
+                "Failed to check cache {}: {e}",
+                settings.cache_dir.display()
+            )
+        })
+    };
+    let root = match &settings.namespace {
+        Some(namespace) => namespace_dir(&settings.cache_dir, namespace),
+        None => settings.cache_dir.clone(),
+    };
+    if cli.command == Some(Command::UpdateCheck) {
+        println!("{}", FetchServer::new(&settings)?.update_status().await?);
+        return Ok(());
+    }
+    if settings.ephemeral && cli.command.is_some() {
+        return Err("subcommands work on a cache directory, not --ephemeral".into());
+    }
+    if let Some(Command::Verify { lockfile }) = &cli.command {
+        println!("{}: {}", settings.cache_dir.display(), check_cache()?);
+        let lockfile = lockfile
+            .as_ref()
+            .or(settings.lockfile.as_ref())
+            .map(|path| lockfile::load(path))
+            .transpose()?;
+        let integrity = verify::check_integrity(&settings.cache_dir, &root, lockfile.as_ref())
+            .map_err(|e| format!("Failed to check cached files: {e}"))?;
+        println!("{}: {integrity}", settings.cache_dir.display());
+        if !integrity.is_clean() {
+            return Err("cached content doesn't match its recorded hashes".into());
+        }
+        return Ok(());
+    }
+    if let Some(Command::Lock { output }) = &cli.command {
+        let locked = lockfile::from_cache(&root)
+            .map_err(|e| format!("Failed to read cache {}: {e}", root.display()))?;
+        lockfile::save(output, &locked)?;
+        println!("{}: locked {} URLs", output.display(), locked.len());
+        return Ok(());
+    }
+    if let Some(Command::Migrate { dry_run }) = &cli.command {
+        return migrate_cache(&settings.cache_dir, &root, *dry_run).await;
+    }
+    #[cfg(feature = "search-index")]
+    if cli.command == Some(Command::Reindex) {
+        let server = FetchServer::new(&settings)?;
+        let root = server
+            .cache_root(settings.namespace.as_deref())
+            .map_err(|e| e.message)?;
+        let count = search_index::SearchIndex::open(&root, true)?.rebuild(&root)?;
+        println!("{}: indexed {count} files", root.display());
+        return Ok(());
+    }
+    if let Some(Command::Mirror {
+        url,
+        depth,
+        max_pages,
+    }) = &cli.command
+    {
+        let limits = crawl::Limits {
+            max_pages: (*max_pages).max(1),
+            max_depth: *depth,
+        };
+        let server = FetchServer::new(&settings)?;
+        let summary = server.mirror(url, limits).await.map_err(|e| e.message)?;
+        println!("{summary}");
+        return Ok(());
+    }
+    // Read-only mode leaves the cache exactly as provisioned
+    if !settings.read_only && !settings.ephemeral {
+        match check_cache() {
+            Ok(report) if !report.is_clean() => eprintln!("cache repaired: {report}"),
+            Ok(_) => {}
+            // Fetches still work on an unwritable cache, returning content in full
+            Err(e) => eprintln!("warning: {e}"),
+        }
+    }
+
+    let server = FetchServer::new(&settings)?;
+    tokio::spawn(server.clone().refresh_pins());
+    if settings.update_check {
+        tokio::spawn(server.clone().check_for_update());
+    }
 
-    let server = FetchServer::new(cli.cache_dir, cli.toc_budget, cli.toc_threshold);
+    if let Some(path) = cli.config.clone() {
+        let watched = server.clone();
+        tokio::spawn(config::watch(cli, path, move |reloaded| {
+            if reloaded.cache_dir != settings.cache_dir {
+                eprintln!("warning: cache-dir changes take effect after a restart");
+            }
+            if let Err(e) = watched.reload(&reloaded) {
+                eprintln!("warning: failed to apply reloaded config: {e}");
+            }
+        }));
+    }
 
     let running = server
         .serve((tokio::io::stdin(), tokio::io::stdout()))
@@ -586,31 +7581,216 @@ mod tests {
     }
 
     #[test]
-    fn test_url_variations_with_query_params() {
-        let url = "https://httpbin.org/get?test=value";
-        let variations = get_url_variations(url);
-
-        // Should not add variations for URLs with query parameters
-        assert_eq!(variations.len(), 1);
-        assert_eq!(variations[0], "https://httpbin.org/get?test=value");
+    fn test_url_variations_with_query_params() {
+        let url = "https://httpbin.org/get?test=value";
+        let variations = get_url_variations(url);
+
+        // Should not add variations for URLs with query parameters
+        assert_eq!(variations.len(), 1);
+        assert_eq!(variations[0], "https://httpbin.org/get?test=value");
+    }
+
+    #[test]
+    fn test_url_to_path_simple() {
+        let base = PathBuf::from("/cache");
+        let url = "https://example.com/docs/page";
+        let path = url_to_path(&base, url).unwrap();
+
+        assert_eq!(path, PathBuf::from("/cache/example.com/docs/page/index"));
+    }
+
+    #[test]
+    fn test_url_to_path_with_extension() {
+        let base = PathBuf::from("/cache");
+        let url = "https://example.com/docs/page.md";
+        let path = url_to_path(&base, url).unwrap();
+
+        assert_eq!(path, PathBuf::from("/cache/example.com/docs/page.md"));
+    }
+
+    #[test]
+    fn test_url_to_path_folds_index_urls() {
+        let base = Path::new("/cache");
+        for url in [
+            "https://example.com/docs",
+            "https://example.com/docs/",
+            "https://example.com/docs/index.html",
+            "https://example.com/docs/index.htm",
+        ] {
+            assert_eq!(
+                url_to_path(base, url).unwrap(),
+                PathBuf::from("/cache/example.com/docs/index"),
+                "{url}"
+            );
+        }
+        assert_eq!(
+            url_to_path(base, "https://example.com/index.html").unwrap(),
+            PathBuf::from("/cache/example.com/index")
+        );
+        assert_eq!(
+            url_to_path(base, "https://example.com/docs/index.html?v=2").unwrap(),
+            PathBuf::from("/cache/example.com/docs/index.html?v=2")
+        );
+    }
+
+    #[test]
+    fn test_folded_url() {
+        assert_eq!(
+            folded_url("https://example.com/docs/").as_deref(),
+            Some("https://example.com/docs")
+        );
+        assert_eq!(
+            folded_url("https://example.com/docs/index.html").as_deref(),
+            Some("https://example.com/docs")
+        );
+        assert_eq!(
+            folded_url("https://example.com/index.html").as_deref(),
+            Some("https://example.com/")
+        );
+        assert_eq!(folded_url("https://example.com/docs"), None);
+        assert_eq!(folded_url("https://example.com/"), None);
+        assert_eq!(folded_url("https://example.com/docs/myindex.html"), None);
+        assert_eq!(folded_url("https://example.com/docs/?page=2"), None);
+        assert_eq!(folded_url("file:///srv/docs/"), None);
+    }
+
+    #[test]
+    fn test_url_to_path_file_url() {
+        let base = Path::new("/cache");
+        assert_eq!(
+            url_to_path(base, "file:///srv/docs/guide.html").unwrap(),
+            PathBuf::from("/cache/_file/srv/docs/guide.html")
+        );
+        assert_eq!(
+            url_to_path(base, "file:///srv/docs/").unwrap(),
+            PathBuf::from("/cache/_file/srv/docs/index")
+        );
+    }
+
+    #[test]
+    fn test_url_to_path_data_url() {
+        let base = Path::new("/cache");
+        let label = "data:text/markdown;hash=0123456789abcdef,";
+        let path = url_to_path(base, label).unwrap();
+        assert_eq!(path.parent(), Some(Path::new("/cache/_data")));
+        assert_eq!(path, url_to_path(base, label).unwrap());
+        assert_ne!(
+            path,
+            url_to_path(base, "data:text/markdown;hash=fedcba9876543210,").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_releases_page_for_changelog() {
+        let listing = Some(github::GithubPage::Releases {
+            owner: "tokio-rs".to_string(),
+            repo: "tokio".to_string(),
+            page: 1,
+        });
+        assert_eq!(
+            releases_page("https://github.com/tokio-rs/tokio/releases?page=3"),
+            listing
+        );
+        assert_eq!(
+            releases_page("https://github.com/tokio-rs/tokio/releases/tag/tokio-1.40.0"),
+            listing
+        );
+        assert_eq!(releases_page("https://github.com/tokio-rs/tokio"), None);
+        assert_eq!(releases_page("https://docs.rs/tokio"), None);
+    }
+
+    #[test]
+    fn test_namespace_dir_is_separate_from_hosts() {
+        let base = PathBuf::from("/cache");
+        let root = namespace_dir(&base, "project-a");
+        let path = url_to_path(&root, "https://docs.rs/serde").unwrap();
+        assert_eq!(path, PathBuf::from("/cache/@project-a/docs.rs/serde/index"));
+        assert!(!path.starts_with(base.join("docs.rs")));
     }
 
     #[test]
-    fn test_url_to_path_simple() {
-        let base = PathBuf::from("/cache");
-        let url = "https://example.com/docs/page";
-        let path = url_to_path(&base, url).unwrap();
+    fn test_in_cache_root() {
+        let base = Path::new("/cache");
+        let namespace = namespace_dir(base, "project-a");
+        let file = Path::new("/cache/docs.rs/serde/index");
+        let namespaced = Path::new("/cache/@project-a/docs.rs/serde/index");
+        assert!(in_cache_root(base, base, file));
+        assert!(!in_cache_root(base, base, namespaced));
+        assert!(in_cache_root(base, &namespace, namespaced));
+        assert!(!in_cache_root(base, &namespace, file));
+    }
 
-        assert_eq!(path, PathBuf::from("/cache/example.com/docs/page/index"));
+    #[test]
+    fn test_output_paths() {
+        let path = Path::new("/work/.llms-fetch-mcp/docs.rs/serde/index");
+        let paths = OutputPaths::default();
+        assert_eq!(paths.show(path), path.to_string_lossy());
+
+        let relative = |base: &str| OutputPaths {
+            base: Some(PathBuf::from(base)),
+            ..OutputPaths::default()
+        };
+        assert_eq!(
+            relative("/work").show(path),
+            ".llms-fetch-mcp/docs.rs/serde/index"
+        );
+        assert_eq!(
+            relative("/work/.llms-fetch-mcp").show(path),
+            "docs.rs/serde/index"
+        );
+        assert_eq!(relative("/elsewhere").show(path), path.to_string_lossy());
+
+        let mapped = OutputPaths {
+            base: None,
+            maps: vec![
+                (PathBuf::from("/work"), PathBuf::from("/Users/me/project")),
+                (
+                    PathBuf::from("/work/.llms-fetch-mcp"),
+                    PathBuf::from("/Users/me/docs-cache"),
+                ),
+            ],
+        };
+        assert_eq!(
+            mapped.show(path),
+            "/Users/me/docs-cache/docs.rs/serde/index"
+        );
+        assert_eq!(
+            mapped.show(Path::new("/work/notes.md")),
+            "/Users/me/project/notes.md"
+        );
+        assert_eq!(mapped.show(Path::new("/workshop/a.md")), "/workshop/a.md");
+        assert_eq!(
+            mapped.resolve("/Users/me/docs-cache/docs.rs/serde/index"),
+            path
+        );
+        assert_eq!(
+            mapped.resolve("docs.rs/serde/index"),
+            Path::new("docs.rs/serde/index")
+        );
     }
 
     #[test]
-    fn test_url_to_path_with_extension() {
+    fn test_cache_uri() {
         let base = PathBuf::from("/cache");
-        let url = "https://example.com/docs/page.md";
-        let path = url_to_path(&base, url).unwrap();
-
-        assert_eq!(path, PathBuf::from("/cache/example.com/docs/page.md"));
+        let path = base.join("@team/example.com/my docs/100%25 [draft].md");
+        let uri = cache_uri(&base, &path).unwrap();
+        assert_eq!(
+            uri,
+            "llms-fetch://cache/@team/example.com/my%20docs/100%2525%20%5Bdraft%5D.md"
+        );
+        assert_eq!(cache_uri_path(&base, &uri), Some(path));
+        assert_eq!(cache_uri(&base, Path::new("/elsewhere/page.md")), None);
+        assert_eq!(cache_uri(&base, &base), None);
+
+        for uri in [
+            "llms-fetch://cache/../secret",
+            "llms-fetch://cache/example.com/%2E%2E/%2E%2E/secret",
+            "llms-fetch://cache/example.com/a%2Fb",
+            "llms-fetch://cache/example.com//page.md",
+            "https://example.com/page.md",
+        ] {
+            assert_eq!(cache_uri_path(&base, uri), None, "{uri}");
+        }
     }
 
     #[test]
@@ -622,20 +7802,133 @@ mod tests {
         assert_eq!(path, PathBuf::from("/cache/example.com/index"));
     }
 
+    #[test]
+    fn test_negotiate_protocol_version() {
+        for version in &SUPPORTED_PROTOCOL_VERSIONS {
+            assert_eq!(&negotiate_protocol_version(version), version);
+        }
+        let unknown: ProtocolVersion = serde_json::from_str("\"2099-01-01\"").unwrap();
+        assert_eq!(
+            negotiate_protocol_version(&unknown),
+            ProtocolVersion::V_2025_06_18
+        );
+    }
+
+    #[test]
+    fn test_fetch_output_matches_schema() {
+        let schema = rmcp::handler::server::tool::schema_for_type::<FetchOutput>();
+        let properties = schema["properties"].as_object().unwrap();
+        let output = serde_json::to_value(FetchOutput {
+            schema_version: FETCH_OUTPUT_SCHEMA_VERSION,
+            files: Vec::new(),
+            variations: Vec::new(),
+            warnings: Vec::new(),
+            urls: vec![UrlOutcome {
+                url: "https://example.com/".to_string(),
+                source: "network",
+                error: None,
+            }],
+        })
+        .unwrap();
+        let mut fields: Vec<&String> = output.as_object().unwrap().keys().collect();
+        let mut declared: Vec<&String> = properties.keys().collect();
+        fields.sort();
+        declared.sort();
+        assert_eq!(fields, declared);
+        assert_eq!(schema["type"], "object");
+    }
+
+    #[test]
+    fn test_variation_report_serialization() {
+        let variations = vec![
+            Variation {
+                url: "https://example.com/llms.txt".to_string(),
+                outcome: Outcome::HttpError { status: 404 },
+                elapsed_ms: Some(12),
+            },
+            Variation {
+                url: "https://example.com/index.md".to_string(),
+                outcome: Outcome::Deduplicated {
+                    duplicate_of: "https://example.com.md".to_string(),
+                },
+                elapsed_ms: Some(8),
+            },
+            Variation {
+                url: "https://example.com/llms-full.txt".to_string(),
+                outcome: Outcome::Skipped {
+                    reason: "recently returned HTTP 404".to_string(),
+                },
+                elapsed_ms: None,
+            },
+        ];
+        assert_eq!(
+            serde_json::to_value(&variations).unwrap(),
+            serde_json::json!([
+                {"url": "https://example.com/llms.txt", "outcome": "http_error", "status": 404, "elapsed_ms": 12},
+                {"url": "https://example.com/index.md", "outcome": "deduplicated", "duplicate_of": "https://example.com.md", "elapsed_ms": 8},
+                {"url": "https://example.com/llms-full.txt", "outcome": "skipped", "reason": "recently returned HTTP 404"},
+            ])
+        );
+        assert_eq!(
+            variations[2].describe(),
+            "https://example.com/llms-full.txt: skipped, recently returned HTTP 404"
+        );
+    }
+
     #[test]
     fn test_count_stats() {
         let content = "Line 1\nLine 2\nLine 3";
-        let (lines, words, chars) = count_stats(content);
+        let Stats {
+            lines,
+            words,
+            characters: chars,
+            ..
+        } = count_stats(content);
 
         assert_eq!(lines, 3);
         assert_eq!(words, 6);
         assert_eq!(chars, 20);
     }
 
+    #[test]
+    fn test_count_stats_matches_std_counts() {
+        for content in [
+            "a\n\nb  c\r\n",
+            "\n",
+            "trailing\n",
+            "  leading and\ttabs ",
+            "non\u{a0}breaking\u{3000}space ünïcödé",
+        ] {
+            let stats = count_stats(content);
+            assert_eq!(stats.lines, content.lines().count(), "{content:?}");
+            assert_eq!(
+                stats.words,
+                content.split_whitespace().count(),
+                "{content:?}"
+            );
+            assert_eq!(stats.characters, content.chars().count(), "{content:?}");
+        }
+        assert_eq!(count_stats("same").hash, count_stats("same").hash);
+        assert_ne!(count_stats("same").hash, count_stats("other").hash);
+    }
+
+    #[test]
+    fn test_head_lines() {
+        assert_eq!(head_lines("a\nb\nc\n", 2), "a\nb");
+        assert_eq!(head_lines("a\nb\nc", 5), "a\nb\nc");
+        assert_eq!(head_lines("a\nb", 0), "");
+        assert_eq!(head_lines("", 3), "");
+    }
+
     #[test]
     fn test_count_stats_empty() {
         let content = "";
-        let (lines, words, chars) = count_stats(content);
+        let Stats {
+            lines,
+            words,
+            characters: chars,
+            ..
+        } = count_stats(content);
 
         assert_eq!(lines, 0);
         assert_eq!(words, 0);
@@ -786,6 +8079,196 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_url_to_path_idn() {
+        let base = PathBuf::from("/cache");
+        for url in [
+            "https://bücher.example/docs",
+            "https://BÜCHER.example/docs",
+            "https://xn--bcher-kva.example/docs",
+        ] {
+            assert_eq!(
+                url_to_path(&base, url).unwrap(),
+                PathBuf::from("/cache/xn--bcher-kva.example/docs/index"),
+                "{url}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_cache_write_error_unwritable() {
+        use std::io::{Error, ErrorKind};
+
+        let error = |kind| CacheWriteError {
+            action: "write temp file",
+            source: Error::from(kind),
+        };
+        assert!(error(ErrorKind::ReadOnlyFilesystem).unwritable());
+        assert!(error(ErrorKind::StorageFull).unwritable());
+        assert!(error(ErrorKind::PermissionDenied).unwritable());
+        assert!(!error(ErrorKind::NotFound).unwritable());
+        assert!(
+            error(ErrorKind::StorageFull)
+                .to_string()
+                .starts_with("Failed to write temp file: ")
+        );
+    }
+
+    #[test]
+    fn test_url_to_path_percent_decoding() {
+        let base = PathBuf::from("/cache");
+        let path = |url: &str| url_to_path(&base, url).unwrap();
+        assert_eq!(
+            path("https://example.com/my%20docs/caf%C3%A9.md"),
+            PathBuf::from("/cache/example.com/my docs/café.md")
+        );
+        // Separators and escapes stay escaped, so names stay unambiguous
+        assert_eq!(
+            path("https://example.com/a%2Fb.md"),
+            PathBuf::from("/cache/example.com/a%2Fb.md")
+        );
+        assert_eq!(
+            path("https://example.com/100%25%3F.md"),
+            PathBuf::from("/cache/example.com/100%25%3F.md")
+        );
+        assert_eq!(
+            path("https://example.com/x%FF.md"),
+            PathBuf::from("/cache/example.com/x%FF.md")
+        );
+        assert_eq!(
+            path("https://example.com/guide%2Emd"),
+            PathBuf::from("/cache/example.com/guide.md")
+        );
+    }
+
+    #[test]
+    fn test_url_to_path_long_names() {
+        let base = PathBuf::from("/cache");
+        let long = "é".repeat(150);
+        let first = url_to_path(&base, &format!("https://example.com/{long}/a.md")).unwrap();
+        let second = url_to_path(&base, &format!("https://example.com/{long}x/a.md")).unwrap();
+        let name = first.iter().nth(3).unwrap();
+        assert!(name.len() <= MAX_NAME_BYTES);
+        assert!(is_shortened(name));
+        assert_ne!(first, second);
+
+        let query = "q=".to_string() + &"x".repeat(300);
+        let path = url_to_path(&base, &format!("https://example.com/a.md?{query}")).unwrap();
+        assert!(path.file_name().unwrap().len() <= MAX_NAME_BYTES);
+        assert!(is_shortened(path.file_name().unwrap()));
+        assert_eq!(path.extension().unwrap(), "md");
+        assert!(!is_shortened(std::ffi::OsStr::new("a~b.md")));
+
+        // A short query stays after the extension
+        let url = format!("https://example.com/{}.txt?v=1", "a".repeat(300));
+        let path = url_to_path(&base, &url).unwrap();
+        assert!(path.file_name().unwrap().len() <= MAX_NAME_BYTES);
+        assert!(is_shortened(path.file_name().unwrap()));
+        assert_eq!(path.extension().unwrap(), "txt?v=1");
+    }
+
+    #[tokio::test]
+    async fn test_make_room() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let page = url_to_path(root, "https://example.com/docs").unwrap();
+        std::fs::create_dir_all(page.parent().unwrap()).unwrap();
+        std::fs::write(&page, "# Docs").unwrap();
+        std::fs::write(sidecar::sidecar_path(&page), "{}").unwrap();
+
+        // `/docs/index` needs `docs/index`, where `/docs` is cached, as a directory
+        let nested = url_to_path(root, "https://example.com/docs/index").unwrap();
+        let moved = make_room(root, &nested).await.unwrap().unwrap();
+        assert_eq!(moved, page.join(sidecar::DISPLACED_NAME));
+        assert_eq!(std::fs::read_to_string(&moved).unwrap(), "# Docs");
+        assert!(sidecar::sidecar_path(&moved).exists());
+        assert!(!sidecar::sidecar_path(&page).exists());
+        // The URL's path stays the same; the store finds the file inside
+        assert_eq!(url_to_path(root, "https://example.com/docs").unwrap(), page);
+        assert_eq!(memory::Store::Disk.locate_async(page).await, moved);
+        assert_eq!(make_room(root, &nested).await.unwrap(), None);
+
+        // A file cached after its directory goes straight inside it
+        let file = url_to_path(root, "https://example.com/a.md").unwrap();
+        std::fs::create_dir_all(&file).unwrap();
+        assert_eq!(file, root.join("example.com/a.md"));
+        assert_eq!(
+            memory::Store::Disk.locate(file.clone()),
+            file.join(sidecar::DISPLACED_NAME)
+        );
+        // In memory, the file and the directory's files are kept side by side
+        let memory = memory::Store::Memory(Arc::default());
+        assert_eq!(memory.locate_async(file.clone()).await, file);
+    }
+
+    #[test]
+    fn test_url_to_path_rejects_dot_hosts() {
+        let base = PathBuf::from("/cache");
+        for url in ["http://../x", "http://%2e%2e/x", "file://../x", "http://./"] {
+            assert!(url_to_path(&base, url).is_err(), "{url}");
+        }
+        assert_eq!(
+            url_to_path(&base, "http://.../x.md").unwrap(),
+            PathBuf::from("/cache/.../x.md")
+        );
+    }
+
+    /// Hosts with unicode, dots and percent escapes; path segments with dots,
+    /// escapes and separators; queries up to 10 KB.
+    fn arbitrary_url() -> impl proptest::strategy::Strategy<Value = String> {
+        use proptest::prelude::*;
+
+        let host = prop_oneof![
+            "[a-z0-9.-]{1,20}",
+            "[a-zé文字ü.-]{1,12}",
+            "(%2[eE]|%2[fF]|%5[cC]|\\.){1,4}",
+            "[0-9]{1,3}\\.[0-9]{1,3}\\.[0-9]{1,3}\\.[0-9]{1,3}",
+        ];
+        let segment = prop_oneof![
+            "[a-zA-Z0-9_.~-]{0,12}",
+            "\\.{1,3}",
+            "(%2[eE]|%2[fF]|%5[cC]|%00|\\\\|;){1,4}",
+            "\\PC{0,8}",
+        ];
+        (
+            prop_oneof!["https", "http", "file", "ftp"],
+            host,
+            prop::collection::vec(segment, 0..8),
+            prop::option::of("\\PC{0,10000}"),
+        )
+            .prop_map(|(scheme, host, segments, query)| {
+                let mut url = format!("{scheme}://{host}/{}", segments.join("/"));
+                if let Some(query) = query {
+                    url.push('?');
+                    url.push_str(&query);
+                }
+                url
+            })
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn prop_url_to_path_stays_within_base(url in arbitrary_url()) {
+            let base = PathBuf::from("/cache");
+            if let Ok(path) = url_to_path(&base, &url) {
+                let rest = path.strip_prefix(&base).unwrap();
+                proptest::prop_assert!(
+                    rest.components()
+                        .all(|c| matches!(c, std::path::Component::Normal(_))),
+                    "{url} -> {path:?}"
+                );
+            }
+        }
+
+        #[test]
+        fn prop_url_to_path_accepts_any_input(url in "\\PC{0,200}") {
+            let base = PathBuf::from("/cache");
+            if let Ok(path) = url_to_path(&base, &url) {
+                proptest::prop_assert!(path.starts_with(&base));
+            }
+        }
+    }
+
     #[test]
     fn test_url_to_path_query_sanitization() {
         // Test that filesystem-unsafe characters in query params are sanitized
@@ -839,10 +8322,15 @@ mod tests {
             </html>
         ";
 
-        let result_with_main = html_to_markdown(html_with_main, "https://example.com");
+        let result_with_main = html_to_markdown(
+            html_with_main,
+            "https://example.com",
+            &policy::DomainPolicy::default(),
+        );
         assert!(result_with_main.is_ok());
-        let markdown_with_main = result_with_main.unwrap();
+        let (markdown_with_main, extraction) = result_with_main.unwrap();
         assert!(markdown_with_main.contains("Main Content"));
+        assert_eq!(extraction, Extraction::Readability);
 
         let html_without_main = r"
             <html>
@@ -858,9 +8346,13 @@ mod tests {
             </html>
         ";
 
-        let result_without_main = html_to_markdown(html_without_main, "https://example.com");
+        let result_without_main = html_to_markdown(
+            html_without_main,
+            "https://example.com",
+            &policy::DomainPolicy::default(),
+        );
         assert!(result_without_main.is_ok());
-        let markdown_without_main = result_without_main.unwrap();
+        let (markdown_without_main, _) = result_without_main.unwrap();
         assert!(markdown_without_main.contains("No Main Tag"));
         assert!(markdown_without_main.contains("Subsection"));
     }
@@ -868,10 +8360,19 @@ mod tests {
     #[test]
     fn test_html_to_markdown_edge_cases() {
         // Empty HTML
-        assert!(html_to_markdown("", "https://example.com").is_err());
+        assert!(
+            html_to_markdown("", "https://example.com", &policy::DomainPolicy::default()).is_err()
+        );
 
         // Whitespace-only HTML
-        assert!(html_to_markdown("   \n\t   ", "https://example.com").is_err());
+        assert!(
+            html_to_markdown(
+                "   \n\t   ",
+                "https://example.com",
+                &policy::DomainPolicy::default()
+            )
+            .is_err()
+        );
 
         // HTML with only scripts/styles (produces empty markdown)
         let script_only = r"
@@ -880,17 +8381,417 @@ mod tests {
                 <body><script>alert('hi');</script></body>
             </html>
         ";
-        let result = html_to_markdown(script_only, "https://example.com");
+        let result = html_to_markdown(
+            script_only,
+            "https://example.com",
+            &policy::DomainPolicy::default(),
+        );
         // This might succeed with minimal content or fail - either is acceptable
-        if let Ok(md) = result {
+        if let Ok((md, _)) = result {
             assert!(!md.trim().is_empty());
         }
 
         // Malformed HTML (unclosed tags) - html2md handles this gracefully
         let malformed = "<div><p>unclosed tags<h1>Header";
-        let result = html_to_markdown(malformed, "https://example.com");
+        let result = html_to_markdown(
+            malformed,
+            "https://example.com",
+            &policy::DomainPolicy::default(),
+        );
         assert!(result.is_ok());
-        assert!(result.unwrap().contains("Header"));
+        assert!(result.unwrap().0.contains("Header"));
+    }
+
+    #[test]
+    fn test_extraction_fallback_warning() {
+        let url = "https://example.com/page";
+        assert!(Extraction::Readability.fallback_warning(url).is_none());
+        let body = Extraction::Body.fallback_warning(url).unwrap();
+        assert!(body.contains(url) && body.contains("<body>"));
+        let full = Extraction::FullHtml.fallback_warning(url).unwrap();
+        assert!(full.contains("raw HTML"));
+    }
+
+    #[test]
+    fn test_html_to_markdown_with_policy() {
+        let html = r#"
+            <html><body>
+                <nav>Site Navigation</nav>
+                <div class="doc">First Section</div>
+                <main><h1>Main Content</h1><p>Readability would pick this.</p></main>
+                <div class="doc">Second Section</div>
+            </body></html>
+        "#;
+        let url = "https://example.com";
+
+        let selector = policy::DomainPolicy {
+            selector: Some(".doc".to_string()),
+            ..policy::DomainPolicy::default()
+        };
+        let (markdown, extraction) = html_to_markdown(html, url, &selector).unwrap();
+        assert_eq!(extraction, Extraction::Selector);
+        assert!(markdown.contains("First Section") && markdown.contains("Second Section"));
+        assert!(!markdown.contains("Site Navigation"));
+
+        let merged = select_html(
+            r#"<aside class="api-sidebar">Sidebar</aside>
+            <main>Main <div class="api-sidebar">Nested</div></main>"#,
+            "main, .api-sidebar",
+        )
+        .unwrap();
+        assert!(merged.find("Sidebar").unwrap() < merged.find("Main").unwrap());
+        assert_eq!(merged.matches("Nested").count(), 1);
+
+        let unmatched = policy::DomainPolicy {
+            selector: Some("#missing".to_string()),
+            ..policy::DomainPolicy::default()
+        };
+        let mut warnings = Vec::new();
+        convert_html(html, url, &unmatched, &mut warnings).unwrap();
+        assert!(warnings[0].contains("#missing"));
+
+        let no_readability = policy::DomainPolicy {
+            readability: Some(false),
+            ..policy::DomainPolicy::default()
+        };
+        let (markdown, extraction) = html_to_markdown(html, url, &no_readability).unwrap();
+        assert_eq!(extraction, Extraction::Body);
+        assert!(markdown.contains("Site Navigation"));
+    }
+
+    #[test]
+    fn test_debug_conversion() {
+        let article = "<p>Readability would pick this paragraph of the guide.</p>".repeat(80);
+        let result = FetchResult {
+            url: "https://example.com/guide".to_string(),
+            content: format!(
+                "<html><body><nav>Site Navigation</nav><main><h1>Guide</h1>{article}</main></body></html>"
+            ),
+            is_html: true,
+            is_markdown: false,
+            headers: sidecar::HttpHeaders {
+                content_type: Some("text/html".to_string()),
+                ..sidecar::HttpHeaders::default()
+            },
+            aliases: Vec::new(),
+        };
+        let output = debug_conversion(
+            &result,
+            &policy::DomainPolicy::default(),
+            false,
+            normalize::Normalize::default(),
+        );
+        assert_eq!(output.chosen, Some(Extraction::Readability));
+        assert_eq!(output.html_bytes, result.content.len());
+        let steps: Vec<_> = output.steps.iter().map(|step| step.step).collect();
+        assert_eq!(
+            steps,
+            [
+                Extraction::Selector,
+                Extraction::Readability,
+                Extraction::Body,
+                Extraction::FullHtml
+            ]
+        );
+        assert!(output.steps[0].skipped.is_some());
+        let body = output.steps[2].markdown.as_ref().unwrap();
+        assert!(body.text.contains("Site Navigation"));
+        let markdown = output.markdown.as_ref().unwrap();
+        assert!(markdown.truncated);
+        assert_eq!(markdown.text.chars().count(), DEBUG_EXCERPT_CHARS);
+        assert!(!markdown.text.contains("Site Navigation"));
+
+        let text = output.into_result(false).content[0]
+            .as_text()
+            .unwrap()
+            .text
+            .clone();
+        assert!(text.starts_with("Conversion of https://example.com/guide"));
+        assert!(text.contains("used readability"));
+        assert!(text.contains("- selector: nothing, no selector is configured"));
+        assert!(text.contains(&format!(
+            "--- final Markdown (first {DEBUG_EXCERPT_CHARS} of"
+        )));
+    }
+
+    #[test]
+    fn test_debug_conversion_strips_inline_data() {
+        let state = format!(r#"{{"props":"{}"}}"#, "hydration state ".repeat(1000));
+        let result = FetchResult {
+            url: "https://example.com/app".to_string(),
+            content: format!(
+                r#"<html><body><p>Rendered page.</p><script id="__NEXT_DATA__" type="application/json">{state}</script></body></html>"#
+            ),
+            is_html: true,
+            is_markdown: false,
+            headers: sidecar::HttpHeaders::default(),
+            aliases: Vec::new(),
+        };
+        let output = debug_conversion(
+            &result,
+            &policy::DomainPolicy::default(),
+            false,
+            normalize::Normalize::default(),
+        );
+        let body = &output.steps[2];
+        assert!(body.html_bytes < inline_data::MIN_BYTES);
+        let markdown = body.markdown.as_ref().unwrap();
+        assert!(markdown.text.contains("Rendered page."));
+        assert!(!markdown.text.contains("hydration state"));
+    }
+
+    #[test]
+    fn test_amp_canonical() {
+        let amp = r#"<!doctype html><html amp lang="en"><head>
+            <link rel="canonical" href="/blog/post"></head><body>AMP</body></html>"#;
+        assert_eq!(
+            amp_canonical(amp, "https://example.com/amp/blog/post"),
+            Some("https://example.com/blog/post".to_string())
+        );
+        let bolt =
+            r#"<html ⚡><head><link rel="canonical" href="https://example.com/a"></head></html>"#;
+        assert_eq!(
+            amp_canonical(bolt, "https://example.com/a?amp=1"),
+            Some("https://example.com/a".to_string())
+        );
+        // Self-canonical AMP pages and ordinary pages are left alone
+        assert_eq!(amp_canonical(bolt, "https://example.com/a"), None);
+        let regular = r#"<html><head><link rel="canonical" href="/b">
+            <link rel="amphtml" href="/amp/b"></head></html>"#;
+        assert_eq!(amp_canonical(regular, "https://example.com/b?ref=x"), None);
+    }
+
+    #[test]
+    fn test_call_headers() {
+        use clap::Parser;
+
+        let cli = config::Cli::parse_from(["llms-fetch-mcp", "--allow-header", "Authorization"]);
+        let settings = Settings::merge(&cli, config::FileConfig::default()).unwrap();
+        let jar = Arc::new(cookies::CookieJar::load(Path::new("/nonexistent"), None).unwrap());
+        let live = Live::new(&settings, &jar).unwrap();
+
+        let allowed = BTreeMap::from([("authorization".to_string(), "Bearer t".to_string())]);
+        let headers = live.call_headers(&allowed).unwrap();
+        let denied = BTreeMap::from([("Cookie".to_string(), "session=1".to_string())]);
+        assert!(live.call_headers(&denied).is_err());
+
+        let http = live
+            .http
+            .with_call_headers("https://docs.example.com/guide", headers);
+        let sent = |url| http.get(url).build().unwrap().headers().clone();
+        assert_eq!(
+            sent("https://docs.example.com/guide.md")["authorization"],
+            "Bearer t"
+        );
+        assert!(!sent("https://cdn.example.com/guide").contains_key("authorization"));
+    }
+
+    #[test]
+    fn test_info_output() {
+        use clap::Parser;
+
+        let cli = config::Cli::parse_from([
+            "llms-fetch-mcp",
+            "/var/cache/docs",
+            "--read-only",
+            "--max-requests",
+            "50",
+            "--cookie",
+            "example.com=session=secret",
+            "--github-token",
+            "ghp_secret",
+        ]);
+        let settings = Settings::merge(&cli, config::FileConfig::default()).unwrap();
+        let output = InfoOutput {
+            version: "1.0.0",
+            protocol_version: Some("2025-06-18".to_string()),
+            features: Vec::new(),
+            schemes: vec!["http", "https"],
+            requests: 3,
+            bytes: 2048,
+            update: Some(update::Status::new("1.0.0", "v1.1.0".to_string())),
+            config: EffectiveConfig::new(&settings),
+        };
+        let value = serde_json::to_value(&output).unwrap();
+        assert_eq!(value["config"]["cache_mode"], "read_only");
+        assert_eq!(value["config"]["max_requests"], 50);
+        assert_eq!(
+            value["config"]["cookie_domains"],
+            serde_json::json!(["example.com"])
+        );
+        assert_eq!(value["config"]["github_token"], true);
+        assert!(!value.to_string().contains("secret"));
+
+        let result = output.into_result(false);
+        let text = &result.content[0].as_text().unwrap().text;
+        assert!(text.starts_with("llms-fetch-mcp 1.0.0 (MCP 2025-06-18)\nFeatures: none\n"));
+        assert!(text.contains("This session: 3 requests, 2048 bytes"));
+        assert!(text.contains("\nUpdate check: llms-fetch-mcp v1.1.0 is available"));
+        assert!(text.contains("\n  cache_dir: /var/cache/docs\n"));
+        assert!(text.contains("\n  max_requests: 50\n"));
+        assert!(!text.contains("max_bytes"));
+        assert!(!text.contains("secret"));
+    }
+
+    #[test]
+    fn test_session_stats_output() {
+        let empty = SessionStatsOutput::new(5, stats::Counts::default()).into_result(true);
+        let text = &empty.content[0].as_text().unwrap().text;
+        assert!(text.ends_with("\nFetches: 0"));
+        let value = empty.structured_content.unwrap();
+        assert_eq!(value["cache_hit_rate"], serde_json::Value::Null);
+
+        let counts = stats::Counts {
+            calls: BTreeMap::from([("fetch".to_string(), 4), ("grep".to_string(), 1)]),
+            domains: BTreeMap::from([
+                ("a.example.com".to_string(), 1),
+                ("b.example.com".to_string(), 3),
+            ]),
+            cache_hits: 1,
+            cache_misses: 3,
+        };
+        let result = SessionStatsOutput::new(60, counts).into_result(false);
+        assert!(result.structured_content.is_none());
+        assert_eq!(
+            result.content[0].as_text().unwrap().text,
+            "Session statistics, kept in memory only (up 60s)\n\
+             Tool calls: fetch 4, grep 1\n\
+             Fetches: 4, 1 from the cache (25%)\n\
+             Domains: b.example.com 3, a.example.com 1"
+        );
+    }
+
+    #[test]
+    fn test_access_restricted_error() {
+        let variation = |url: &str, outcome| Variation {
+            url: url.to_string(),
+            outcome,
+            elapsed_ms: None,
+        };
+        let mut variations = vec![
+            variation(
+                "https://news.example.com/story",
+                Outcome::HttpError { status: 404 },
+            ),
+            variation("https://news.example.com/story.md", Outcome::NetworkError),
+        ];
+        assert!(access_restricted("https://news.example.com/story", &variations, &[]).is_none());
+
+        variations[0].outcome = Outcome::AccessRestricted {
+            reason: "HTTP 403 with a login page".to_string(),
+        };
+        let error = access_restricted("https://news.example.com/story", &variations, &[]).unwrap();
+        insta::assert_snapshot!(error.message, @"Access restricted: https://news.example.com/story requires a subscription or login (https://news.example.com/story: access restricted, HTTP 403 with a login page). Nothing was cached; look for a public source instead");
+        assert_eq!(error.data.unwrap()["error"], "access_restricted");
+    }
+
+    #[test]
+    fn test_url_list() {
+        let list: UrlList = serde_json::from_value(serde_json::json!(
+            "https://a.dev/\n\n  https://b.dev/ \nhttps://a.dev/"
+        ))
+        .unwrap();
+        assert_eq!(list.urls(), ["https://a.dev/", "https://b.dev/"]);
+        let list: UrlList =
+            serde_json::from_value(serde_json::json!(["https://a.dev/", " ", "https://c.dev/"]))
+                .unwrap();
+        assert_eq!(list.urls(), ["https://a.dev/", "https://c.dev/"]);
+        let data = "data:text/markdown,# Title\n\nBody";
+        let list: UrlList = serde_json::from_value(serde_json::json!(data)).unwrap();
+        assert_eq!(list.urls(), [data]);
+        // Both forms of an IDN are one URL, in Unicode
+        let list: UrlList = serde_json::from_value(serde_json::json!([
+            "https://xn--bcher-kva.example/docs",
+            "https://bücher.example/docs"
+        ]))
+        .unwrap();
+        assert_eq!(list.urls(), ["https://bücher.example/docs"]);
+    }
+
+    #[test]
+    fn test_next_steps() {
+        let url = "https://docs.example.com/guide";
+        let variations = |outcome| {
+            vec![Variation {
+                url: url.to_string(),
+                outcome,
+                elapsed_ms: None,
+            }]
+        };
+        let archive = NextStep::fetch(
+            "https://web.archive.org/web/https://docs.example.com/guide",
+            "the Internet Archive's latest snapshot, if it has one",
+        );
+
+        let html = r#"<html><head><link rel="canonical" href="/guide/"></head></html>"#;
+        let soft = variations(Outcome::SoftNotFound {
+            reason: "its title says \"404\"".to_string(),
+            alternates: page_alternates(html, url),
+        });
+        assert_eq!(
+            next_steps(url, &soft, &[]),
+            [
+                NextStep::fetch(
+                    "https://docs.example.com/guide/",
+                    "the error page names it as the canonical or another language version"
+                ),
+                archive.clone(),
+            ]
+        );
+
+        let forbidden = next_steps(url, &variations(Outcome::HttpError { status: 403 }), &[]);
+        let names: Vec<&str> = forbidden
+            .iter()
+            .map(|step| match step {
+                NextStep::Setting { name, .. } => name.as_str(),
+                NextStep::Fetch { .. } => "fetch",
+            })
+            .collect();
+        assert_eq!(names, ["headers", "cookie", "fetch"]);
+        assert!(forbidden[0].describe().contains("--allow-header"));
+
+        let redirected = next_steps(
+            url,
+            &variations(Outcome::RedirectNotFollowed {
+                reason: "redirects to another origin, https://new.example.org/guide".to_string(),
+                location: Some("https://new.example.org/guide".to_string()),
+            }),
+            &[],
+        );
+        assert_eq!(
+            redirected[0],
+            NextStep::fetch(
+                "https://new.example.org/guide",
+                "the page redirects there; fetch it to follow the redirect"
+            )
+        );
+
+        let drifted = next_steps(
+            url,
+            &variations(Outcome::Drifted {
+                sha256: "b2".to_string(),
+                locked_sha256: "a1".to_string(),
+            }),
+            &[],
+        );
+        assert!(
+            matches!(&drifted[..], [NextStep::Setting { name, .. }] if name == "lockfile-drift")
+        );
+
+        let local = next_steps(
+            "http://localhost:8080/guide",
+            &variations(Outcome::NetworkError),
+            &[],
+        );
+        assert!(local.is_empty());
+
+        let error = fetch_failed(url, &soft, &[archive]);
+        let data = error.data.unwrap();
+        assert_eq!(data["error"], "not_found");
+        assert_eq!(data["next_steps"][0]["kind"], "fetch");
+        assert!(error.message.ends_with(
+            "Next steps: fetch https://web.archive.org/web/https://docs.example.com/guide (the Internet Archive's latest snapshot, if it has one)"
+        ));
     }
 
     #[test]
@@ -925,6 +8826,7 @@ mod tests {
         use crate::toc::{self, TocConfig};
 
         /// Create FileInfo from a real test fixture file
+        #[allow(clippy::doc_markdown)]
         fn file_info_from_fixture(
             fixture_name: &str,
             source_url: &str,
@@ -934,7 +8836,12 @@ mod tests {
         ) -> FileInfo {
             let file_content =
                 std::fs::read_to_string(format!("test-fixtures/{fixture_name}")).unwrap();
-            let (lines, words, characters) = count_stats(&file_content);
+            let Stats {
+                lines,
+                words,
+                characters,
+                ..
+            } = count_stats(&file_content);
             let table_of_contents = toc::generate_toc(&file_content, characters, toc_config);
             let content = if characters < toc_config.full_content_threshold {
                 Some(file_content)
@@ -951,6 +8858,17 @@ mod tests {
                 characters,
                 table_of_contents,
                 content,
+                truncated_preview: false,
+                http: None,
+                version: None,
+                languages: Vec::new(),
+                language: None,
+                metadata: None,
+                inline_data_bytes: None,
+                sections: None,
+                aliases: Vec::new(),
+                duplicate_of: None,
+                storage: Storage::Disk,
             }
         }
 
@@ -964,7 +8882,7 @@ mod tests {
                 "html-converted",
                 &config,
             )];
-            insta::assert_snapshot!(format_output(&files));
+            insta::assert_snapshot!(format_output(&files, &[]));
         }
 
         #[test]
@@ -977,7 +8895,7 @@ mod tests {
                 "llms",
                 &config,
             )];
-            insta::assert_snapshot!(format_output(&files));
+            insta::assert_snapshot!(format_output(&files, &[]));
         }
 
         #[test]
@@ -985,6 +8903,7 @@ mod tests {
             let config = TocConfig {
                 toc_budget: 4000,
                 full_content_threshold: 2000,
+                ..TocConfig::default()
             };
             let files = vec![file_info_from_fixture(
                 "python-tutorial.txt",
@@ -993,7 +8912,7 @@ mod tests {
                 "html-converted",
                 &config,
             )];
-            insta::assert_snapshot!(format_output(&files));
+            insta::assert_snapshot!(format_output(&files, &[]));
         }
 
         #[test]
@@ -1016,7 +8935,7 @@ mod tests {
                     &config,
                 ),
             ];
-            insta::assert_snapshot!(format_output(&files));
+            insta::assert_snapshot!(format_output(&files, &[]));
         }
 
         #[test]
@@ -1030,7 +8949,7 @@ mod tests {
                 "html-converted",
                 &config,
             )];
-            insta::assert_snapshot!(format_output(&files));
+            insta::assert_snapshot!(format_output(&files, &[]));
         }
 
         #[test]
@@ -1040,6 +8959,7 @@ mod tests {
             let small_config = TocConfig {
                 toc_budget: 4000,
                 full_content_threshold: 100_000, // Won't generate ToC
+                ..TocConfig::default()
             };
             let files = vec![
                 file_info_from_fixture(
@@ -1057,13 +8977,92 @@ mod tests {
                     &small_config,
                 ),
             ];
-            insta::assert_snapshot!(format_output(&files));
+            insta::assert_snapshot!(format_output(&files, &[]));
+        }
+
+        #[test]
+        fn snapshot_truncated_preview() {
+            let config = TocConfig::default();
+            let mut info = file_info_from_fixture(
+                "react-learn.txt",
+                "https://react.dev/learn",
+                ".llms-fetch-mcp/react.dev/learn/index",
+                "html-converted",
+                &config,
+            );
+            let file_content = std::fs::read_to_string("test-fixtures/react-learn.txt").unwrap();
+            info.content = Some(head_lines(&file_content, 10).to_string());
+            info.truncated_preview = true;
+            insta::assert_snapshot!(format_output(&[info], &[]));
+        }
+
+        #[test]
+        fn test_unsaved_file() {
+            let config = TocConfig::default();
+            let mut info = file_info_from_fixture(
+                "react-learn.txt",
+                "https://react.dev/learn",
+                ".llms-fetch-mcp/react.dev/learn/index",
+                "html-converted",
+                &config,
+            );
+            let file_content = std::fs::read_to_string("test-fixtures/react-learn.txt").unwrap();
+            info.off_disk(Storage::Unsaved, &file_content);
+            let output = format_output(&[info], &[]);
+            assert!(output.contains("Not saved: the cache isn't writable"));
+            assert!(!output.contains("Saved to:"));
+            assert!(output.contains("### Table of Contents"));
+            assert!(output.contains(&format!("### Content\n{}", file_content.trim_end())));
+        }
+
+        #[test]
+        fn test_resource_links() {
+            let config = TocConfig::default();
+            let output = || {
+                let saved = file_info_from_fixture(
+                    "react-learn.txt",
+                    "https://react.dev/learn",
+                    "/cache/react.dev/learn/index",
+                    "html-converted",
+                    &config,
+                );
+                let mut unsaved = file_info_from_fixture(
+                    "react-learn.txt",
+                    "https://react.dev/reference",
+                    "/cache/react.dev/reference/index",
+                    "html-converted",
+                    &config,
+                );
+                unsaved.off_disk(Storage::Unsaved, "# Reference");
+                FetchOutput {
+                    schema_version: FETCH_OUTPUT_SCHEMA_VERSION,
+                    files: vec![saved, unsaved],
+                    variations: Vec::new(),
+                    warnings: Vec::new(),
+                    urls: Vec::new(),
+                }
+            };
+
+            let result = output().into_result(true, Path::new("/cache"), &OutputPaths::default());
+            let links: Vec<_> = result
+                .content
+                .iter()
+                .filter_map(|content| content.as_resource_link())
+                .collect();
+            assert_eq!(links.len(), 1);
+            assert_eq!(links[0].uri, "llms-fetch://cache/react.dev/learn/index");
+            assert_eq!(links[0].name, "https://react.dev/learn");
+            assert_eq!(links[0].mime_type.as_deref(), Some("text/markdown"));
+
+            // Clients that predate resource links only get the text
+            let result = output().into_result(false, Path::new("/cache"), &OutputPaths::default());
+            assert_eq!(result.content.len(), 1);
         }
 
         #[test]
         fn snapshot_empty_result() {
             let files: Vec<FileInfo> = vec![];
-            insta::assert_snapshot!(format_output(&files));
+            insta::assert_snapshot!(format_output(&files, &[]));
         }
 
         #[test]
@@ -1077,7 +9076,7 @@ mod tests {
                 "llms-full",
                 &config,
             )];
-            insta::assert_snapshot!(format_output(&files));
+            insta::assert_snapshot!(format_output(&files, &[]));
         }
     }
 }
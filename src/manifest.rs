@@ -0,0 +1,399 @@
+//! Project manifests, for fetching the documentation of every dependency.
+//!
+//! `Cargo.toml`, `package.json` and `pyproject.toml` are read for their
+//! dependencies. Rust crates are documented on docs.rs; for npm and Python
+//! packages the registry's metadata names the documentation or home page.
+
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Manifest file names looked for in the client's roots, in this order.
+pub const FILE_NAMES: [&str; 3] = ["Cargo.toml", "package.json", "pyproject.toml"];
+
+/// Upper bound on the dependencies fetched in one call.
+pub const MAX_DEPENDENCIES: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Ecosystem {
+    Cargo,
+    Npm,
+    Python,
+}
+
+impl Ecosystem {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Cargo => "cargo",
+            Self::Npm => "npm",
+            Self::Python => "python",
+        }
+    }
+}
+
+/// A dependency named in a manifest.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Dependency {
+    pub ecosystem: Ecosystem,
+    pub name: String,
+    /// The version requirement, when it's a plain version
+    pub version: Option<String>,
+}
+
+impl Dependency {
+    fn new(ecosystem: Ecosystem, name: &str, requirement: Option<&str>) -> Self {
+        Self {
+            ecosystem,
+            name: name.to_string(),
+            version: requirement.and_then(plain_version),
+        }
+    }
+
+    /// Where to find the documentation: the page itself for crates, the
+    /// registry's metadata for the others.
+    pub fn lookup_url(&self) -> String {
+        match self.ecosystem {
+            Ecosystem::Cargo => format!(
+                "https://docs.rs/{}/{}",
+                self.name,
+                self.version.as_deref().unwrap_or("latest")
+            ),
+            Ecosystem::Npm => format!("https://registry.npmjs.org/{}", self.name),
+            Ecosystem::Python => format!("https://pypi.org/pypi/{}/json", self.name),
+        }
+    }
+}
+
+/// `1.4`, `^1.4.2`, `~=2.0` or `==3.1` → the version; ranges and wildcards
+/// give `None`.
+fn plain_version(requirement: &str) -> Option<String> {
+    let version = requirement
+        .trim()
+        .trim_start_matches(['^', '~', '=', 'v'])
+        .trim();
+    let valid = !version.is_empty()
+        && version
+            .split('.')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()));
+    valid.then(|| version.to_string())
+}
+
+/// Reads the dependencies of the manifest `file_name` containing `content`,
+/// with the development ones if `include_dev` is set. Local (path, git or
+/// workspace link) dependencies are left out.
+pub fn dependencies(
+    file_name: &str,
+    content: &str,
+    include_dev: bool,
+) -> Result<Vec<Dependency>, String> {
+    let mut dependencies = match file_name {
+        "Cargo.toml" => cargo(content, include_dev).map_err(|e| e.to_string()),
+        "package.json" => npm(content, include_dev).map_err(|e| e.to_string()),
+        "pyproject.toml" => python(content, include_dev).map_err(|e| e.to_string()),
+        _ => return Err(format!("{file_name} is not a supported manifest")),
+    }
+    .map_err(|e| format!("Cannot parse {file_name}: {e}"))?;
+    dependencies.sort();
+    dependencies.dedup_by(|a, b| a.ecosystem == b.ecosystem && a.name == b.name);
+    Ok(dependencies)
+}
+
+fn cargo(content: &str, include_dev: bool) -> Result<Vec<Dependency>, toml::de::Error> {
+    let manifest: toml::Table = toml::from_str(content)?;
+    let mut tables = vec![manifest.get("dependencies")];
+    if include_dev {
+        tables.push(manifest.get("dev-dependencies"));
+    }
+    tables.push(
+        manifest
+            .get("workspace")
+            .and_then(|workspace| workspace.get("dependencies")),
+    );
+    let mut dependencies = Vec::new();
+    for (name, spec) in tables
+        .into_iter()
+        .flatten()
+        .filter_map(toml::Value::as_table)
+        .flatten()
+    {
+        let requirement = match spec {
+            toml::Value::String(version) => Some(version.as_str()),
+            toml::Value::Table(spec) => {
+                if spec.contains_key("path") || spec.contains_key("git") {
+                    continue;
+                }
+                spec.get("version").and_then(toml::Value::as_str)
+            }
+            _ => None,
+        };
+        let name = spec
+            .get("package")
+            .and_then(toml::Value::as_str)
+            .unwrap_or(name);
+        dependencies.push(Dependency::new(Ecosystem::Cargo, name, requirement));
+    }
+    Ok(dependencies)
+}
+
+fn npm(content: &str, include_dev: bool) -> Result<Vec<Dependency>, serde_json::Error> {
+    let manifest: Value = serde_json::from_str(content)?;
+    let mut sections = vec!["dependencies"];
+    if include_dev {
+        sections.push("devDependencies");
+    }
+    let mut dependencies = Vec::new();
+    for section in sections {
+        let Some(entries) = manifest.get(section).and_then(Value::as_object) else {
+            continue;
+        };
+        for (name, requirement) in entries {
+            let requirement = requirement.as_str().unwrap_or_default();
+            // Local packages and git checkouts have no registry entry
+            if ["file:", "link:", "workspace:", "git", "http"]
+                .iter()
+                .any(|prefix| requirement.starts_with(prefix))
+            {
+                continue;
+            }
+            dependencies.push(Dependency::new(Ecosystem::Npm, name, Some(requirement)));
+        }
+    }
+    Ok(dependencies)
+}
+
+fn python(content: &str, include_dev: bool) -> Result<Vec<Dependency>, toml::de::Error> {
+    let manifest: toml::Table = toml::from_str(content)?;
+    let mut requirements: Vec<&str> = Vec::new();
+    let project = manifest.get("project");
+    requirements.extend(strings(project.and_then(|p| p.get("dependencies"))));
+    if include_dev {
+        let groups = [
+            project.and_then(|p| p.get("optional-dependencies")),
+            manifest.get("dependency-groups"),
+        ];
+        for group in groups
+            .into_iter()
+            .flatten()
+            .filter_map(toml::Value::as_table)
+        {
+            for value in group.values() {
+                requirements.extend(strings(Some(value)));
+            }
+        }
+    }
+    let mut dependencies: Vec<Dependency> = requirements
+        .into_iter()
+        .filter_map(pep508)
+        .map(|(name, version)| Dependency::new(Ecosystem::Python, name, version))
+        .collect();
+
+    // Poetry keeps its own tables, keyed by name like Cargo
+    let poetry = manifest
+        .get("tool")
+        .and_then(|tool| tool.get("poetry"))
+        .and_then(toml::Value::as_table);
+    let mut tables = vec![poetry.and_then(|p| p.get("dependencies"))];
+    if include_dev && let Some(groups) = poetry.and_then(|p| p.get("group")) {
+        tables.extend(
+            groups
+                .as_table()
+                .into_iter()
+                .flat_map(|groups| groups.values())
+                .map(|group| group.get("dependencies")),
+        );
+    }
+    for (name, spec) in tables
+        .into_iter()
+        .flatten()
+        .filter_map(toml::Value::as_table)
+        .flatten()
+    {
+        if name == "python" || spec.get("path").is_some() || spec.get("git").is_some() {
+            continue;
+        }
+        let requirement = spec
+            .as_str()
+            .or_else(|| spec.get("version").and_then(toml::Value::as_str));
+        dependencies.push(Dependency::new(Ecosystem::Python, name, requirement));
+    }
+    Ok(dependencies)
+}
+
+/// The strings in a TOML array.
+fn strings(value: Option<&toml::Value>) -> Vec<&str> {
+    value
+        .and_then(toml::Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(toml::Value::as_str)
+        .collect()
+}
+
+/// Name and `==` version of a PEP 508 requirement like
+/// `requests[socks]==2.31.0; python_version > "3.8"`.
+fn pep508(requirement: &str) -> Option<(&str, Option<&str>)> {
+    let requirement = requirement.split(';').next()?.trim();
+    let end = requirement
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+        .unwrap_or(requirement.len());
+    let name = &requirement[..end];
+    if name.is_empty() || requirement[end..].trim_start().starts_with('@') {
+        return None;
+    }
+    let version = requirement[end..]
+        .split_once("==")
+        .map(|(_, version)| version.split(',').next().unwrap_or_default());
+    Some((name, version))
+}
+
+/// The documentation URL in the registry metadata `json` for `dependency`:
+/// npm's `homepage`, or the documentation link of a Python package, then its
+/// home page.
+pub fn docs_url(dependency: &Dependency, json: &str) -> Option<String> {
+    let metadata: Value = serde_json::from_str(json).ok()?;
+    let text = |value: Option<&Value>| {
+        value
+            .and_then(Value::as_str)
+            .filter(|url| url.starts_with("http"))
+            .map(str::to_string)
+    };
+    match dependency.ecosystem {
+        Ecosystem::Cargo => None,
+        Ecosystem::Npm => text(metadata.get("homepage")).or_else(|| {
+            // `git+https://github.com/owner/repo.git`
+            text(metadata.pointer("/repository/url")).or_else(|| {
+                metadata
+                    .pointer("/repository/url")
+                    .and_then(Value::as_str)
+                    .and_then(|url| url.strip_prefix("git+"))
+                    .map(|url| url.trim_end_matches(".git").to_string())
+            })
+        }),
+        Ecosystem::Python => {
+            let info = metadata.get("info")?;
+            let links: BTreeMap<String, String> = info
+                .get("project_urls")
+                .and_then(Value::as_object)
+                .into_iter()
+                .flatten()
+                .filter_map(|(label, url)| Some((label.to_lowercase(), text(Some(url))?)))
+                .collect();
+            ["documentation", "docs"]
+                .iter()
+                .find_map(|label| links.get(*label).cloned())
+                .or_else(|| text(info.get("docs_url")))
+                .or_else(|| {
+                    ["homepage", "home"]
+                        .iter()
+                        .find_map(|label| links.get(*label).cloned())
+                })
+                .or_else(|| text(info.get("home_page")))
+                .or_else(|| Some(format!("https://pypi.org/project/{}/", dependency.name)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(dependencies: &[Dependency]) -> Vec<(&str, Option<&str>)> {
+        dependencies
+            .iter()
+            .map(|d| (d.name.as_str(), d.version.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn test_cargo() {
+        let manifest = r#"
+            [dependencies]
+            tokio = { version = "1.47", features = ["full"] }
+            serde = "^1.0.228"
+            local = { path = "../local" }
+            renamed = { package = "reqwest", version = ">=0.12, <0.13" }
+
+            [dev-dependencies]
+            insta = "1.43"
+        "#;
+        assert_eq!(
+            names(&dependencies("Cargo.toml", manifest, false).unwrap()),
+            [
+                ("reqwest", None),
+                ("serde", Some("1.0.228")),
+                ("tokio", Some("1.47"))
+            ]
+        );
+        assert_eq!(dependencies("Cargo.toml", manifest, true).unwrap().len(), 4);
+        let tokio = Dependency::new(Ecosystem::Cargo, "tokio", Some("1.47"));
+        assert_eq!(tokio.lookup_url(), "https://docs.rs/tokio/1.47");
+    }
+
+    #[test]
+    fn test_npm() {
+        let manifest = r#"{
+            "dependencies": {"react": "^18.2.0", "lib": "file:../lib", "zod": "3.x"},
+            "devDependencies": {"vitest": "1.0.0"}
+        }"#;
+        assert_eq!(
+            names(&dependencies("package.json", manifest, false).unwrap()),
+            [("react", Some("18.2.0")), ("zod", None)]
+        );
+        assert!(dependencies("package.json", "{", false).is_err());
+    }
+
+    #[test]
+    fn test_python() {
+        let manifest = r#"
+            [project]
+            dependencies = ["requests[socks]==2.31.0; python_version > '3.8'", "numpy>=1.26", "pkg @ file:///x"]
+            [project.optional-dependencies]
+            test = ["pytest==8.0"]
+            [tool.poetry.dependencies]
+            python = "^3.11"
+            httpx = "^0.27.0"
+        "#;
+        assert_eq!(
+            names(&dependencies("pyproject.toml", manifest, false).unwrap()),
+            [
+                ("httpx", Some("0.27.0")),
+                ("numpy", None),
+                ("requests", Some("2.31.0"))
+            ]
+        );
+        assert_eq!(
+            dependencies("pyproject.toml", manifest, true)
+                .unwrap()
+                .len(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_docs_url() {
+        let npm = Dependency::new(Ecosystem::Npm, "zod", None);
+        assert_eq!(
+            docs_url(&npm, r#"{"homepage": "https://zod.dev"}"#).as_deref(),
+            Some("https://zod.dev")
+        );
+        assert_eq!(
+            docs_url(
+                &npm,
+                r#"{"repository": {"url": "git+https://github.com/colinhacks/zod.git"}}"#
+            )
+            .as_deref(),
+            Some("https://github.com/colinhacks/zod")
+        );
+        let python = Dependency::new(Ecosystem::Python, "httpx", None);
+        let json = r#"{"info": {"home_page": "", "project_urls": {
+            "Homepage": "https://github.com/encode/httpx",
+            "Documentation": "https://www.python-httpx.org/"}}}"#;
+        assert_eq!(
+            docs_url(&python, json).as_deref(),
+            Some("https://www.python-httpx.org/")
+        );
+        assert_eq!(
+            docs_url(&python, r#"{"info": {}}"#).as_deref(),
+            Some("https://pypi.org/project/httpx/")
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! In-memory cache for `--ephemeral`, on hosts where nothing should be
+//! written to disk.
+//!
+//! Files are kept, with their sidecars, under the paths they'd have in a cache
+//! directory named `:memory:`, so tools that take a cached file's path work the
+//! same way. Everything is gone when the server exits.
+
+use crate::sidecar::{self, Sidecar};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Cache directory of the in-memory cache, also accepted as `--cache-dir`.
+pub const ROOT: &str = ":memory:";
+
+/// Cached files by path.
+#[derive(Debug, Default)]
+pub struct MemoryCache {
+    files: Mutex<BTreeMap<PathBuf, (Arc<str>, Sidecar)>>,
+}
+
+impl MemoryCache {
+    pub fn insert(&self, path: PathBuf, content: &str, sidecar: Sidecar) {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(path, (content.into(), sidecar));
+    }
+
+    pub fn read(&self, path: &Path) -> Option<Arc<str>> {
+        let files = self.files.lock().unwrap();
+        files.get(path).map(|(content, _)| Arc::clone(content))
+    }
+
+    pub fn sidecar(&self, path: &Path) -> Option<Sidecar> {
+        let files = self.files.lock().unwrap();
+        files.get(path).map(|(_, sidecar)| sidecar.clone())
+    }
+
+    /// The files under `root`, sorted by path, skipping namespaces like
+    /// [`sidecar::list`].
+    pub fn list(&self, root: &Path) -> Vec<sidecar::Entry> {
+        let files = self.files.lock().unwrap();
+        files
+            .iter()
+            .filter(|(path, _)| {
+                path.strip_prefix(root).is_ok_and(|relative| {
+                    relative
+                        .iter()
+                        .next()
+                        .is_some_and(|first| !first.to_string_lossy().starts_with('@'))
+                })
+            })
+            .map(|(path, (content, sidecar))| sidecar::Entry {
+                path: path.clone(),
+                bytes: content.len() as u64,
+                sidecar: Some(sidecar.clone()),
+            })
+            .collect()
+    }
+}
+
+/// Where cached files are read from.
+#[derive(Debug, Clone)]
+pub enum Store {
+    Disk,
+    Memory(Arc<MemoryCache>),
+}
+
+impl Store {
+    /// The cached files under `root`, sorted by path.
+    pub fn list(&self, root: &Path) -> std::io::Result<Vec<sidecar::Entry>> {
+        match self {
+            Self::Disk => sidecar::list(root),
+            Self::Memory(memory) => Ok(memory.list(root)),
+        }
+    }
+
+    /// Content of the cached file at `path`.
+    pub fn read(&self, path: &Path) -> std::io::Result<String> {
+        match self {
+            Self::Disk => std::fs::read_to_string(path),
+            Self::Memory(memory) => memory
+                .read(path)
+                .map(|content| content.to_string())
+                .ok_or_else(|| std::io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    /// Where the file cached at `path` is. On disk, a directory that took its
+    /// place holds it as [`sidecar::DISPLACED_NAME`]; in memory, files and
+    /// directories don't collide.
+    pub fn locate(&self, path: PathBuf) -> PathBuf {
+        match self {
+            Self::Disk if path.is_dir() => path.join(sidecar::DISPLACED_NAME),
+            _ => path,
+        }
+    }
+
+    /// Async [`Store::locate`].
+    pub async fn locate_async(&self, path: PathBuf) -> PathBuf {
+        let displaced = match self {
+            Self::Disk => tokio::fs::metadata(&path)
+                .await
+                .is_ok_and(|meta| meta.is_dir()),
+            Self::Memory(_) => false,
+        };
+        if displaced {
+            path.join(sidecar::DISPLACED_NAME)
+        } else {
+            path
+        }
+    }
+
+    /// Async [`Store::read`].
+    pub async fn read_async(&self, path: &Path) -> std::io::Result<String> {
+        match self {
+            Self::Disk => tokio::fs::read_to_string(path).await,
+            Self::Memory(_) => self.read(path),
+        }
+    }
+
+    /// Sidecar of the cached file at `path`, if it has a readable one.
+    pub async fn sidecar(&self, path: &Path) -> Option<Sidecar> {
+        match self {
+            Self::Disk => sidecar::read(path).await,
+            Self::Memory(memory) => memory.sidecar(path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(url: &str) -> Sidecar {
+        Sidecar {
+            url: url.to_string(),
+            fetched_at: 0,
+            http: None,
+            version: None,
+            sha256: None,
+            sections: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        let memory = Arc::new(MemoryCache::default());
+        let root = Path::new(ROOT);
+        memory.insert(
+            root.join("example.com/b.md"),
+            "# B",
+            meta("https://example.com/b.md"),
+        );
+        memory.insert(
+            root.join("example.com/a.md"),
+            "# A",
+            meta("https://example.com/a.md"),
+        );
+        memory.insert(
+            root.join("@team/example.com/c.md"),
+            "# C",
+            meta("https://example.com/c.md"),
+        );
+        let store = Store::Memory(memory);
+
+        let paths = |root: &Path| -> Vec<PathBuf> {
+            store
+                .list(root)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.path)
+                .collect()
+        };
+        assert_eq!(
+            paths(root),
+            [root.join("example.com/a.md"), root.join("example.com/b.md")]
+        );
+        assert_eq!(
+            paths(&root.join("@team")),
+            [root.join("@team/example.com/c.md")]
+        );
+
+        let a = root.join("example.com/a.md");
+        assert_eq!(store.read_async(&a).await.unwrap(), "# A");
+        assert_eq!(
+            store.sidecar(&a).await.map(|meta| meta.url).as_deref(),
+            Some("https://example.com/a.md")
+        );
+        let missing = store.read(&root.join("example.com/missing.md"));
+        assert_eq!(missing.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+    }
+}
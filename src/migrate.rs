@@ -0,0 +1,265 @@
+//! Moving a cache written by an older version to the current path layout.
+//!
+//! The path a URL is cached at has changed over time (percent escapes decoded,
+//! long names shortened, internationalized hosts under their ASCII form, files
+//! moved aside for directories). Lookups only use the current layout, so files
+//! at old paths would be fetched again and cached twice. The `migrate`
+//! subcommand moves each cached file, with its sidecar, to the path its URL
+//! maps to now, taking the URL from the sidecar or the cache index.
+
+use crate::{index, sidecar};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A file moved to its current path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Move {
+    pub url: String,
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// What migrating a cache did, or would do in a dry run.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Files already at their current path
+    pub current: usize,
+    pub moved: Vec<Move>,
+    /// Older copies of URLs also cached at their current path, removed
+    pub duplicates: Vec<PathBuf>,
+    /// Files whose URL isn't known, left in place
+    pub unknown: Vec<PathBuf>,
+    /// Files that couldn't be moved, and why
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} file(s) already current, {} moved, {} duplicate(s) removed",
+            self.current,
+            self.moved.len(),
+            self.duplicates.len()
+        )?;
+        for path in &self.unknown {
+            write!(f, "\nleft in place, URL unknown: {}", path.display())?;
+        }
+        for (path, reason) in &self.failed {
+            write!(f, "\nleft in place: {}: {reason}", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Moves every file cached under `root`, part of the cache at `cache_dir`, to
+/// `path_for(url)`, removing the older copy when both paths hold the URL, or
+/// forms of it that share a path such as `/docs/` and `/docs/index.html`.
+/// Moves are dropped from `index`; recording the new paths is up to the
+/// caller. With `dry_run`, only reports what would be done.
+pub fn migrate(
+    cache_dir: &Path,
+    root: &Path,
+    index: &index::CacheIndex,
+    path_for: impl Fn(&str) -> Result<PathBuf, String>,
+    dry_run: bool,
+) -> std::io::Result<Report> {
+    let mut report = Report::default();
+    for entry in sidecar::list(root)? {
+        let relative = entry
+            .path
+            .strip_prefix(cache_dir)
+            .map(|relative| relative.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let fetched_at = entry.sidecar.as_ref().map(|meta| meta.fetched_at);
+        let Some(url) = entry
+            .sidecar
+            .map(|meta| meta.url)
+            .or_else(|| index.url_of(&relative))
+        else {
+            report.unknown.push(entry.path);
+            continue;
+        };
+        let to = match path_for(&url) {
+            Ok(to) if to == entry.path => {
+                report.current += 1;
+                continue;
+            }
+            Ok(to) => to,
+            Err(e) => {
+                report.failed.push((entry.path, e));
+                continue;
+            }
+        };
+        if to.exists() {
+            let existing = std::fs::read(sidecar::sidecar_path(&to))
+                .ok()
+                .and_then(|json| serde_json::from_slice::<sidecar::Sidecar>(&json).ok());
+            match existing {
+                Some(existing)
+                    if existing.url == url
+                        || path_for(&existing.url).is_ok_and(|path| path == to) =>
+                {
+                    // Keep whichever copy was fetched last
+                    let older = if fetched_at.is_some_and(|at| at > existing.fetched_at) {
+                        to.clone()
+                    } else {
+                        entry.path.clone()
+                    };
+                    if !dry_run {
+                        remove(&older)?;
+                        if older == entry.path {
+                            index.forget_url(&relative);
+                            remove_empty_parents(&entry.path, root);
+                        }
+                    }
+                    report.duplicates.push(older.clone());
+                    if older == entry.path {
+                        continue;
+                    }
+                }
+                _ => {
+                    let reason = format!("{} is taken by another file", to.display());
+                    report.failed.push((entry.path, reason));
+                    continue;
+                }
+            }
+        }
+        if !dry_run {
+            if let Err(e) = move_file(&entry.path, &to) {
+                report.failed.push((entry.path, e.to_string()));
+                continue;
+            }
+            index.forget_url(&relative);
+            remove_empty_parents(&entry.path, root);
+        }
+        report.moved.push(Move {
+            url,
+            from: entry.path,
+            to,
+        });
+    }
+    Ok(report)
+}
+
+/// Moves the file at `from` and its sidecar, if any, to `to`.
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::rename(from, to)?;
+    let sidecar = sidecar::sidecar_path(from);
+    if sidecar.exists() {
+        std::fs::rename(sidecar, sidecar::sidecar_path(to))?;
+    }
+    Ok(())
+}
+
+/// Removes the file at `path` and its sidecar, if any.
+fn remove(path: &Path) -> std::io::Result<()> {
+    std::fs::remove_file(path)?;
+    match std::fs::remove_file(sidecar::sidecar_path(path)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Removes the directories above `path` that are left empty, up to `root`.
+fn remove_empty_parents(path: &Path, root: &Path) {
+    for dir in path.ancestors().skip(1) {
+        if dir == root || !dir.starts_with(root) || std::fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_file(path: &Path, url: &str, fetched_at: u64, content: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+        let meta = sidecar::Sidecar {
+            url: url.to_string(),
+            fetched_at,
+            http: None,
+            version: None,
+            sha256: None,
+            sections: Vec::new(),
+        };
+        std::fs::write(
+            sidecar::sidecar_path(path),
+            serde_json::to_string(&meta).unwrap(),
+        )
+        .unwrap();
+    }
+
+    /// Maps URLs to `<host><path>` with `%20` decoded, standing in for the
+    /// server's layout.
+    fn layout(root: &Path) -> impl Fn(&str) -> Result<PathBuf, String> {
+        let root = root.to_path_buf();
+        move |url| {
+            let url = url::Url::parse(url).map_err(|e| e.to_string())?;
+            let path = format!("{}{}", url.host_str().unwrap(), url.path());
+            Ok(root.join(path.replace("%20", " ")))
+        }
+    }
+
+    #[test]
+    fn test_migrate() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let index = index::CacheIndex::load(root);
+        let old = root.join("example.com/my%20docs/a.md");
+        cache_file(&old, "https://example.com/my%20docs/a.md", 10, "# A");
+        cache_file(
+            &root.join("example.com/b.md"),
+            "https://example.com/b.md",
+            10,
+            "# B",
+        );
+        // Cached under both layouts; the old copy is newer
+        cache_file(
+            &root.join("example.com/my%20docs/c.md"),
+            "https://example.com/my%20docs/c.md",
+            20,
+            "# C new",
+        );
+        cache_file(
+            &root.join("example.com/my docs/c.md"),
+            "https://example.com/my%20docs/c.md",
+            10,
+            "# C old",
+        );
+        std::fs::write(root.join("example.com/stray.md"), "# ?").unwrap();
+
+        let dry_run = migrate(root, root, &index, layout(root), true).unwrap();
+        assert_eq!(dry_run.moved.len(), 2);
+        assert!(old.exists());
+
+        let report = migrate(root, root, &index, layout(root), false).unwrap();
+        assert_eq!(report.current, 2);
+        assert_eq!(
+            report.moved[0],
+            Move {
+                url: "https://example.com/my%20docs/a.md".to_string(),
+                from: old.clone(),
+                to: root.join("example.com/my docs/a.md"),
+            }
+        );
+        assert_eq!(report.duplicates, [root.join("example.com/my docs/c.md")]);
+        assert_eq!(report.unknown, [root.join("example.com/stray.md")]);
+        assert!(report.failed.is_empty());
+        assert_eq!(
+            std::fs::read_to_string(root.join("example.com/my docs/c.md")).unwrap(),
+            "# C new"
+        );
+        assert!(sidecar::sidecar_path(&root.join("example.com/my docs/a.md")).exists());
+        assert!(!root.join("example.com/my%20docs").exists());
+
+        let again = migrate(root, root, &index, layout(root), false).unwrap();
+        assert_eq!(again.current, 3);
+        assert!(again.moved.is_empty());
+    }
+}
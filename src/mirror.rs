@@ -0,0 +1,264 @@
+//! Offline Markdown mirrors of documentation sites, made by the `mirror`
+//! subcommand.
+//!
+//! A mirror holds the converted pages of a crawl under [`CACHE_DIR`], laid
+//! out like the site, with links between mirrored pages rewritten to relative
+//! file paths so it can be browsed without network access. Other links are
+//! made absolute so they still work online.
+
+use crate::crawl::Scope;
+use pulldown_cmark::{Event, Parser, Tag};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Component, Path, PathBuf};
+use url::Url;
+
+/// Directory of mirrors inside the cache root.
+pub const CACHE_DIR: &str = "_mirror";
+
+/// File name of the index page at the top of a mirror.
+pub const INDEX_FILE: &str = "_index.md";
+
+/// Directory of the mirror of the crawl scope of `start`, relative to the
+/// cache root: `https://example.com/docs/intro` → `_mirror/example.com/docs`.
+pub fn mirror_dir(start: &Url) -> Option<PathBuf> {
+    let mut dir = PathBuf::from(CACHE_DIR).join(start.host_str()?);
+    let path = start.path();
+    for component in path[..path.rfind('/').unwrap_or(0)].split('/') {
+        match component {
+            "" => {}
+            "." | ".." => return None,
+            component => dir.push(component),
+        }
+    }
+    Some(dir)
+}
+
+/// Where the page at `url` goes in the mirror, relative to its directory.
+/// HTML pages and extensionless paths become `.md` files, directories
+/// `index.md`; a query is kept in the file name.
+pub fn page_path(scope: &Scope, url: &Url) -> Option<PathBuf> {
+    let relative = scope.relative(url);
+    let mut path = PathBuf::new();
+    for component in relative.split('/') {
+        if matches!(component, "." | "..") {
+            return None;
+        }
+        if !component.is_empty() {
+            path.push(component);
+        }
+    }
+    let mut name = if relative.is_empty() || relative.ends_with('/') {
+        "index".to_string()
+    } else {
+        path.pop();
+        let last = relative.rsplit('/').next().unwrap_or_default();
+        match last.rsplit_once('.') {
+            Some((stem, "html" | "htm")) => stem.to_string(),
+            _ => last.to_string(),
+        }
+    };
+    if let Some(query) = url.query() {
+        let safe_query = query.replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], "_");
+        write!(name, "_{safe_query}").unwrap();
+    }
+    if Path::new(&name).extension().is_none() || url.query().is_some() {
+        name.push_str(".md");
+    }
+    path.push(name);
+    Some(path)
+}
+
+/// Relative link from the file at `from` to the file at `to`, both relative
+/// to the mirror's directory.
+fn relative_link(from: &Path, to: &Path) -> String {
+    let from_dir: Vec<Component> = from
+        .parent()
+        .map_or_else(Vec::new, |p| p.components().collect());
+    let to_parts: Vec<Component> = to.components().collect();
+    let common = from_dir
+        .iter()
+        .zip(&to_parts)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut parts: Vec<String> = vec!["..".to_string(); from_dir.len() - common];
+    parts.extend(
+        to_parts[common..]
+            .iter()
+            .map(|part| part.as_os_str().to_string_lossy().into_owned()),
+    );
+    parts.join("/")
+}
+
+/// Rewrites the inline links of `markdown`, the page at `page_url` stored at
+/// `page_file`: links to pages in `mirrored` (URL without fragment → file)
+/// become relative file links, and other relative links become absolute.
+pub fn rewrite_links(
+    markdown: &str,
+    page_url: &Url,
+    page_file: &Path,
+    mirrored: &HashMap<String, PathBuf>,
+) -> String {
+    let mut replacements = Vec::new();
+    for (event, range) in Parser::new(markdown).into_offset_iter() {
+        let Event::Start(Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. }) = event else {
+            continue;
+        };
+        let Ok(mut target) = page_url.join(dest_url.trim()) else {
+            continue;
+        };
+        let fragment = target.fragment().map(str::to_string);
+        target.set_fragment(None);
+        let rewritten = match mirrored.get(target.as_str()) {
+            Some(file) => {
+                let mut link = relative_link(page_file, file);
+                if let Some(fragment) = fragment {
+                    write!(link, "#{fragment}").unwrap();
+                }
+                link
+            }
+            None if Url::parse(&dest_url).is_err() && !dest_url.starts_with('#') => {
+                target.set_fragment(fragment.as_deref());
+                target.to_string()
+            }
+            None => continue,
+        };
+        // Inline links end with `(destination)` or `(destination "title")`;
+        // reference links keep their definition untouched
+        let source = &markdown[range.clone()];
+        if let Some(open) = source.rfind("](")
+            && let Some(offset) = source[open..].find(dest_url.as_ref())
+        {
+            let start = range.start + open + offset;
+            replacements.push((start..start + dest_url.len(), rewritten));
+        }
+    }
+
+    let mut output = String::with_capacity(markdown.len());
+    let mut last = 0;
+    for (range, rewritten) in replacements {
+        if range.start < last {
+            continue;
+        }
+        output.push_str(&markdown[last..range.start]);
+        output.push_str(&rewritten);
+        last = range.end;
+    }
+    output.push_str(&markdown[last..]);
+    output
+}
+
+/// The mirror's index page: one link per page (title, file), in crawl order.
+pub fn index_page(start_url: &str, pages: &[(String, PathBuf)]) -> String {
+    let mut output = String::new();
+    writeln!(output, "# Mirror of {start_url}\n").unwrap();
+    writeln!(
+        output,
+        "{} pages, converted to Markdown by llms-fetch-mcp.\n",
+        pages.len()
+    )
+    .unwrap();
+    for (title, file) in pages {
+        let link = relative_link(Path::new(INDEX_FILE), file);
+        writeln!(output, "- [{title}]({link})").unwrap();
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope() -> Scope {
+        Scope::new(&Url::parse("https://example.com/docs/intro").unwrap())
+    }
+
+    fn path(scope: &Scope, url: &str) -> Option<PathBuf> {
+        page_path(scope, &Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn test_mirror_dir() {
+        let start = Url::parse("https://example.com/docs/intro").unwrap();
+        assert_eq!(
+            mirror_dir(&start),
+            Some(PathBuf::from("_mirror/example.com/docs"))
+        );
+        let root = Url::parse("https://example.com/").unwrap();
+        assert_eq!(
+            mirror_dir(&root),
+            Some(PathBuf::from("_mirror/example.com"))
+        );
+    }
+
+    #[test]
+    fn test_page_path() {
+        let scope = scope();
+        let cases = [
+            ("https://example.com/docs/", "index.md"),
+            ("https://example.com/docs/intro", "intro.md"),
+            (
+                "https://example.com/docs/guide/setup.html",
+                "guide/setup.md",
+            ),
+            ("https://example.com/docs/guide/", "guide/index.md"),
+            ("https://example.com/docs/llms.txt", "llms.txt"),
+            ("https://example.com/docs/api?v=2", "api_v=2.md"),
+        ];
+        for (url, expected) in cases {
+            assert_eq!(path(&scope, url), Some(PathBuf::from(expected)), "{url}");
+        }
+    }
+
+    #[test]
+    fn test_relative_link() {
+        let link = |from: &str, to: &str| relative_link(Path::new(from), Path::new(to));
+        assert_eq!(link("intro.md", "guide/setup.md"), "guide/setup.md");
+        assert_eq!(link("guide/setup.md", "intro.md"), "../intro.md");
+        assert_eq!(link("guide/setup.md", "guide/faq.md"), "faq.md");
+        assert_eq!(link("a/b/c.md", "a/d/e.md"), "../d/e.md");
+    }
+
+    #[test]
+    fn test_rewrite_links() {
+        let page_url = Url::parse("https://example.com/docs/guide/setup").unwrap();
+        let mirrored = HashMap::from([
+            (
+                "https://example.com/docs/intro".to_string(),
+                PathBuf::from("intro.md"),
+            ),
+            (
+                "https://example.com/docs/guide/faq".to_string(),
+                PathBuf::from("guide/faq.md"),
+            ),
+        ]);
+        let markdown = "See [intro](/docs/intro), [FAQ](faq#top \"FAQ\"),\n\
+            [pricing](/pricing), [here](#install), [ext](https://other.example.com/x) and [ref][r].\n\n\
+            [r]: /docs/intro\n";
+        insta::assert_snapshot!(
+            rewrite_links(markdown, &page_url, Path::new("guide/setup.md"), &mirrored),
+            @r#"
+        See [intro](../intro.md), [FAQ](faq.md#top "FAQ"),
+        [pricing](https://example.com/pricing), [here](#install), [ext](https://other.example.com/x) and [ref][r].
+
+        [r]: /docs/intro
+        "#
+        );
+    }
+
+    #[test]
+    fn test_index_page() {
+        let pages = [
+            ("Intro".to_string(), PathBuf::from("intro.md")),
+            ("Setup".to_string(), PathBuf::from("guide/setup.md")),
+        ];
+        insta::assert_snapshot!(index_page("https://example.com/docs/intro", &pages), @r"
+        # Mirror of https://example.com/docs/intro
+
+        2 pages, converted to Markdown by llms-fetch-mcp.
+
+        - [Intro](intro.md)
+        - [Setup](guide/setup.md)
+        ");
+    }
+}
@@ -0,0 +1,125 @@
+//! Tidying the text of Markdown converted from HTML, for `--normalize`.
+//!
+//! Pages leave HTML entities the conversion doesn't decode (double-encoded
+//! ones such as `&amp;nbsp;` come out as `&nbsp;`), non-breaking and other
+//! unusual spaces, and invisible characters such as soft hyphens in the text.
+//! They cost tokens and keep searches for a phrase from matching it. Code is
+//! left as written: fenced blocks and inline code spans show entities on
+//! purpose.
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::borrow::Cow;
+
+/// How much `fetch` normalizes the text of converted pages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Normalize {
+    /// Leave the text as converted
+    Off,
+    /// Decode leftover entities, turn unusual spaces into plain ones and drop
+    /// invisible characters
+    #[default]
+    Text,
+    /// Also replace typographic quotes, dashes and ellipses with ASCII
+    Ascii,
+}
+
+/// `markdown` normalized to `level`.
+pub fn apply(markdown: &str, level: Normalize) -> Cow<'_, str> {
+    if level == Normalize::Off || !markdown.contains(|c: char| c == '&' || !c.is_ascii()) {
+        return Cow::Borrowed(markdown);
+    }
+    let mut normalized = String::with_capacity(markdown.len());
+    let mut fence: Option<&str> = None;
+    for line in markdown.split_inclusive('\n') {
+        let marker = line.trim_start();
+        let marker = ["```", "~~~"]
+            .into_iter()
+            .find(|marker_start| marker.starts_with(marker_start));
+        match (fence, marker) {
+            (None, Some(marker)) => fence = Some(marker),
+            (Some(open), Some(marker)) if open == marker => fence = None,
+            (None, None) => {
+                // Odd pieces between backticks are code spans
+                for (index, piece) in line.split('`').enumerate() {
+                    if index > 0 {
+                        normalized.push('`');
+                    }
+                    if index % 2 == 0 {
+                        normalize_text(piece, level, &mut normalized);
+                    } else {
+                        normalized.push_str(piece);
+                    }
+                }
+                continue;
+            }
+            _ => {}
+        }
+        normalized.push_str(line);
+    }
+    if normalized == markdown {
+        Cow::Borrowed(markdown)
+    } else {
+        Cow::Owned(normalized)
+    }
+}
+
+fn normalize_text(text: &str, level: Normalize, normalized: &mut String) {
+    // Angle brackets stay escaped as the conversion escapes them, not tags
+    let text = if text.contains('&') {
+        Cow::Owned(text.replace("&lt;", "\\<").replace("&gt;", "\\>"))
+    } else {
+        Cow::Borrowed(text)
+    };
+    for c in html_escape::decode_html_entities(&text).chars() {
+        match c {
+            '\u{a0}' | '\u{2000}'..='\u{200a}' | '\u{202f}' | '\u{205f}' | '\u{3000}' => {
+                normalized.push(' ');
+            }
+            '\u{ad}' | '\u{200b}' | '\u{2060}' | '\u{feff}' => {}
+            _ => match ascii(c).filter(|_| level == Normalize::Ascii) {
+                Some(replacement) => normalized.push_str(replacement),
+                None => normalized.push(c),
+            },
+        }
+    }
+}
+
+/// ASCII for typographic punctuation.
+fn ascii(c: char) -> Option<&'static str> {
+    Some(match c {
+        '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}' | '\u{2032}' => "'",
+        '\u{201c}' | '\u{201d}' | '\u{201e}' | '\u{201f}' | '\u{2033}' => "\"",
+        '\u{2010}' | '\u{2011}' | '\u{2012}' | '\u{2013}' | '\u{2212}' => "-",
+        '\u{2014}' | '\u{2015}' => "--",
+        '\u{2026}' => "...",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let markdown = "Use&nbsp;it&#160;now\u{a0}\u{2014} it\u{2019}s \u{201c}free\u{201d}\u{2026} \
+            hy\u{ad}phen\u{200b}\n\n`a &amp; b` and &lt;b&gt;\n\n```html\n<p>&nbsp;\u{2019}</p>\n```\n";
+        assert_eq!(
+            apply(markdown, Normalize::Text),
+            "Use it now \u{2014} it\u{2019}s \u{201c}free\u{201d}\u{2026} hyphen\n\n\
+             `a &amp; b` and \\<b\\>\n\n```html\n<p>&nbsp;\u{2019}</p>\n```\n"
+        );
+        assert_eq!(
+            apply(markdown, Normalize::Ascii),
+            "Use it now -- it's \"free\"... hyphen\n\n\
+             `a &amp; b` and \\<b\\>\n\n```html\n<p>&nbsp;\u{2019}</p>\n```\n"
+        );
+        assert_eq!(apply(markdown, Normalize::Off), markdown);
+        assert!(matches!(
+            apply("Plain text.\n", Normalize::Ascii),
+            Cow::Borrowed(_)
+        ));
+    }
+}
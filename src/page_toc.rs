@@ -0,0 +1,267 @@
+//! Tables of contents embedded in HTML pages.
+//!
+//! Docs pages often carry their own table of contents (`<nav class="toc">`,
+//! `.table-of-contents`), which Readability removes with the rest of the
+//! navigation. It's read from the original HTML and checked against the
+//! converted Markdown: entries the converter flattened into bold or plain
+//! lines become headings again, so the generated table of contents lists
+//! them, and entries missing from the Markdown altogether are reported, since
+//! they usually mean extraction dropped part of the page.
+
+use crate::cite;
+use crate::toc;
+use std::collections::HashMap;
+
+/// Elements taken for a page's table of contents.
+const SELECTOR: &str = "nav.toc, #toc, .toc, .table-of-contents, #table-of-contents, \
+    nav[aria-label='Table of contents'], nav[aria-label='On this page']";
+
+/// Elements with fewer in-page links aren't taken for a table of contents.
+const MIN_ENTRIES: usize = 2;
+
+/// Heading level of top-level entries when no entry matches a heading.
+const DEFAULT_TOP_LEVEL: usize = 2;
+
+/// Missing entries named in the warning; the rest are counted.
+const MAX_NAMED: usize = 5;
+
+/// A link in a page's table of contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Nesting depth, 0 for top-level entries
+    pub depth: usize,
+    pub text: String,
+}
+
+/// The entries of the first table of contents in `html`, or none if it has
+/// none.
+pub fn extract(html: &str) -> Vec<Entry> {
+    let document = dom_query::Document::from(html);
+    for root in document.select(SELECTOR).nodes() {
+        let entries: Vec<Entry> = root
+            .descendants_it()
+            .filter(|node| {
+                node.has_name("a")
+                    && node
+                        .attr("href")
+                        .is_some_and(|href| href.trim().starts_with('#'))
+            })
+            .filter_map(|link| {
+                let text = link.text().split_whitespace().collect::<Vec<_>>().join(" ");
+                let depth = link
+                    .ancestors_it(None)
+                    .take_while(|ancestor| ancestor.id != root.id)
+                    .filter(|ancestor| ancestor.has_name("ul") || ancestor.has_name("ol"))
+                    .count();
+                (!text.is_empty()).then(|| Entry {
+                    depth: depth.saturating_sub(1),
+                    text,
+                })
+            })
+            .collect();
+        if entries.len() >= MIN_ENTRIES {
+            return entries;
+        }
+    }
+    Vec::new()
+}
+
+/// Text for comparing entries with lines: formatting, case and a trailing
+/// colon don't count.
+fn key(text: &str) -> String {
+    cite::plain_text(text)
+        .trim_end_matches(':')
+        .trim()
+        .to_lowercase()
+}
+
+/// The text of a line the converter may have flattened a heading into: a
+/// paragraph of its own, bold or plain, that isn't a list item, quote, table
+/// row or link.
+fn flattened(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let text = ["**", "__"]
+        .iter()
+        .find_map(|mark| line.strip_prefix(mark)?.strip_suffix(mark))
+        .unwrap_or(line);
+    let structural = text.is_empty()
+        || text.starts_with(['#', '-', '*', '+', '>', '|', '[', '!', '<'])
+        || text.chars().next().is_some_and(|c| c.is_ascii_digit()) && text.contains(". ");
+    (!structural).then_some(text)
+}
+
+/// What [`apply`] did to a page's Markdown.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Applied {
+    pub markdown: String,
+    /// Entries turned back into headings
+    pub restored: usize,
+    /// Entries found nowhere in the Markdown
+    pub missing: Vec<String>,
+}
+
+/// Checks `markdown` against the page's table of contents `entries`,
+/// restoring flattened headings and listing the entries it lacks.
+pub fn apply(markdown: &str, entries: &[Entry]) -> Applied {
+    let headings = toc::extract_headings(markdown);
+    let heading_lines: HashMap<String, usize> = headings
+        .iter()
+        .rev()
+        .map(|heading| (key(&heading.text), heading.line_number - 1))
+        .collect();
+    // Level of top-level entries, going by the entries that are headings
+    let top_level = entries
+        .iter()
+        .find_map(|entry| {
+            let heading = headings.iter().find(|h| key(&h.text) == key(&entry.text))?;
+            usize::from(heading.level).checked_sub(entry.depth)
+        })
+        .filter(|level| *level > 0)
+        .unwrap_or(DEFAULT_TOP_LEVEL);
+
+    let mut lines: Vec<String> = markdown.lines().map(str::to_string).collect();
+    let in_code = toc::fenced_lines(markdown);
+    let blank = |lines: &[String], index: Option<usize>| {
+        index
+            .and_then(|index| lines.get(index))
+            .is_none_or(|line| line.trim().is_empty())
+    };
+
+    let searchable = key(markdown);
+    let mut applied = Applied::default();
+    let mut cursor = 0;
+    for entry in entries {
+        let wanted = key(&entry.text);
+        if let Some(&line) = heading_lines.get(&wanted) {
+            cursor = cursor.max(line + 1);
+            continue;
+        }
+        let found = (cursor..lines.len()).find(|&index| {
+            !in_code[index]
+                && blank(&lines, index.checked_sub(1))
+                && blank(&lines, Some(index + 1))
+                && flattened(&lines[index]).is_some_and(|text| key(text) == wanted)
+        });
+        match found {
+            Some(index) => {
+                let level = (top_level + entry.depth).min(6);
+                let text = flattened(&lines[index]).unwrap_or_default().to_string();
+                lines[index] = format!("{} {text}", "#".repeat(level));
+                applied.restored += 1;
+                cursor = index + 1;
+            }
+            None if !searchable.contains(&wanted) => applied.missing.push(entry.text.clone()),
+            None => {}
+        }
+    }
+
+    applied.markdown = if applied.restored == 0 {
+        markdown.to_string()
+    } else {
+        let mut joined = lines.join("\n");
+        if markdown.ends_with('\n') {
+            joined.push('\n');
+        }
+        joined
+    };
+    applied
+}
+
+/// Warning for entries of the page's table of contents missing from `url`'s
+/// converted content.
+pub fn missing_warning(url: &str, missing: &[String]) -> Option<String> {
+    use std::fmt::Write;
+
+    if missing.is_empty() {
+        return None;
+    }
+    let mut named = missing
+        .iter()
+        .take(MAX_NAMED)
+        .map(|text| format!("\"{text}\""))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if missing.len() > MAX_NAMED {
+        write!(named, " and {} more", missing.len() - MAX_NAMED).unwrap();
+    }
+    Some(format!(
+        "The table of contents of {url} lists sections missing from the converted content ({named}); extraction may have dropped part of the page"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract() {
+        let html = r##"<nav class="breadcrumbs"><a href="#top">Top</a></nav>
+            <div class="toc"><ul>
+              <li><a href="#install">Install</a>
+                <ul><li><a href="#linux"> Linux </a></li></ul></li>
+              <li><a href="#usage">Usage</a></li>
+              <li><a href="/other">Elsewhere</a></li>
+            </ul></div>"##;
+        assert_eq!(
+            extract(html),
+            [
+                Entry {
+                    depth: 0,
+                    text: "Install".to_string()
+                },
+                Entry {
+                    depth: 1,
+                    text: "Linux".to_string()
+                },
+                Entry {
+                    depth: 0,
+                    text: "Usage".to_string()
+                },
+            ]
+        );
+        assert!(extract(r##"<nav class="toc"><a href="#a">A</a></nav>"##).is_empty());
+        assert!(extract("<p>No table of contents</p>").is_empty());
+    }
+
+    #[test]
+    fn test_apply() {
+        let entries = [
+            Entry {
+                depth: 0,
+                text: "Install".to_string(),
+            },
+            Entry {
+                depth: 1,
+                text: "Linux".to_string(),
+            },
+            Entry {
+                depth: 0,
+                text: "Usage".to_string(),
+            },
+            Entry {
+                depth: 0,
+                text: "Changelog".to_string(),
+            },
+        ];
+        let markdown = "# Guide\n\n## Install\n\nSee Linux below.\n\n**Linux:**\n\napt install\n\n```\nUsage\n```\n\nUsage\n\nRun it.\n";
+        let applied = apply(markdown, &entries);
+        assert_eq!(applied.restored, 2);
+        assert_eq!(applied.missing, ["Changelog"]);
+        assert_eq!(
+            applied.markdown,
+            "# Guide\n\n## Install\n\nSee Linux below.\n\n### Linux:\n\napt install\n\n```\nUsage\n```\n\n## Usage\n\nRun it.\n"
+        );
+
+        let untouched = apply("## Install\n\n- Usage\n", &entries);
+        assert_eq!(untouched.markdown, "## Install\n\n- Usage\n");
+        assert_eq!(untouched.missing, ["Linux", "Changelog"]);
+    }
+
+    #[test]
+    fn test_missing_warning() {
+        assert_eq!(missing_warning("https://e.com", &[]), None);
+        let missing: Vec<String> = (1..=7).map(|i| format!("S{i}")).collect();
+        let warning = missing_warning("https://e.com", &missing).unwrap();
+        assert!(warning.contains("\"S5\" and 2 more"));
+    }
+}
@@ -0,0 +1,115 @@
+//! Paywall and login-wall detection.
+//!
+//! Restricted pages usually answer 200 with a teaser and a subscribe or sign-in
+//! prompt. Cached as if it were the page, the teaser would silently stand in
+//! for content the agent never saw, so such pages are reported instead.
+
+/// Converted pages at least this long are treated as the real content even if
+/// they mention subscribing: a teaser is a few paragraphs.
+const MAX_TEASER_WORDS: usize = 500;
+
+/// Below this, a page that also asks not to be archived is taken to be a
+/// stub shown to anonymous visitors.
+const MAX_STUB_WORDS: usize = 150;
+
+/// Prompts that replace the rest of an article, lowercase.
+const PROMPTS: [&str; 10] = [
+    "subscribe to continue",
+    "subscribe to read",
+    "subscribe to unlock",
+    "for subscribers only",
+    "sign in to continue",
+    "sign in to read",
+    "log in to continue",
+    "login to continue",
+    "create a free account to continue",
+    "members-only content",
+];
+
+/// Returns why a fetched page looks restricted, given its HTML and the
+/// Markdown it converted to, or `None` if it looks like the full page.
+pub fn detect(html: &str, markdown: &str) -> Option<String> {
+    let words = markdown.split_whitespace().count();
+    if words >= MAX_TEASER_WORDS {
+        return None;
+    }
+
+    let text = markdown.to_lowercase();
+    if let Some(prompt) = PROMPTS.iter().find(|prompt| text.contains(*prompt)) {
+        return Some(format!("the page asks to \"{prompt}\""));
+    }
+
+    let document = dom_query::Document::from(html);
+    // Structured data publishers add for search engines' paywall handling
+    let not_free = document
+        .select("script[type='application/ld+json']")
+        .iter()
+        .any(|script| {
+            let json: String = script
+                .text()
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect();
+            let json = json.to_lowercase();
+            json.contains(r#""isaccessibleforfree":false"#)
+                || json.contains(r#""isaccessibleforfree":"false""#)
+        })
+        || document
+            .select("meta[itemprop='isAccessibleForFree']")
+            .attr("content")
+            .is_some_and(|content| content.trim().eq_ignore_ascii_case("false"));
+    if not_free {
+        return Some("the page is marked isAccessibleForFree: false".to_string());
+    }
+
+    let no_archive = document
+        .select("meta[name='robots' i]")
+        .iter()
+        .filter_map(|meta| meta.attr("content"))
+        .any(|content| content.to_lowercase().contains("noarchive"));
+    if no_archive && words < MAX_STUB_WORDS {
+        return Some(format!(
+            "the page is marked noarchive and has only {words} words of content"
+        ));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_prompts() {
+        let teaser = "# Big Story\n\nThe first paragraph.\n\nSubscribe to continue reading.";
+        assert_eq!(
+            detect("", teaser).as_deref(),
+            Some("the page asks to \"subscribe to continue\"")
+        );
+        // A newsletter footer on a full page is fine
+        let full = format!(
+            "{}\n\nSubscribe to continue",
+            "word ".repeat(MAX_TEASER_WORDS)
+        );
+        assert_eq!(detect("", &full), None);
+    }
+
+    #[test]
+    fn test_detect_markup() {
+        let structured = r#"<html><head><script type="application/ld+json">
+            {"@type": "NewsArticle", "isAccessibleForFree": "False"}
+            </script></head><body><p>Teaser.</p></body></html>"#;
+        assert!(detect(structured, "Teaser.").is_some());
+
+        let no_archive =
+            r#"<html><head><meta name="robots" content="noindex, noarchive"></head></html>"#;
+        assert!(detect(no_archive, "Teaser.").is_some());
+        let long = "word ".repeat(MAX_STUB_WORDS);
+        assert_eq!(detect(no_archive, &long), None);
+
+        assert_eq!(
+            detect("<html><body>Short page.</body></html>", "Short page."),
+            None
+        );
+    }
+}
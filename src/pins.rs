@@ -0,0 +1,201 @@
+//! URLs kept warm in the cache.
+//!
+//! Pinned URLs are refetched every `interval` seconds while the server runs,
+//! so frequently consulted pages (release notes, status pages) are fresh
+//! whenever they are read from the cache. Pins come from the config file or
+//! the `pin` tool; the tool's pins last until the server stops. Each
+//! namespace pins its URLs separately, like it caches them.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Shortest refresh interval, so a pin can't turn into a request loop.
+pub const MIN_INTERVAL: u64 = 60;
+
+/// How often the schedule is checked for due pins.
+pub const TICK: Duration = Duration::from_secs(5);
+
+/// A pinned URL as reported by the `pin` tool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinInfo {
+    pub url: String,
+    pub interval_secs: u64,
+    pub namespace: Option<String>,
+    /// Whether the pin is from the config file rather than the `pin` tool
+    pub configured: bool,
+}
+
+#[derive(Debug)]
+struct Pin {
+    interval: Duration,
+    configured: bool,
+    next_due: Instant,
+}
+
+/// Namespace and URL of a pin.
+pub type Key = (Option<String>, String);
+
+fn key(namespace: Option<&str>, url: &str) -> Key {
+    (namespace.map(str::to_string), url.to_string())
+}
+
+/// Checks that `url` can be pinned for `interval` seconds.
+pub fn validate(url: &str, interval: u64) -> Result<(), String> {
+    let scheme = url::Url::parse(url).map(|u| u.scheme().to_string());
+    if !matches!(scheme.as_deref(), Ok("http" | "https")) {
+        return Err(format!("only http(s) URLs can be pinned, not \"{url}\""));
+    }
+    if interval < MIN_INTERVAL {
+        return Err(format!(
+            "refresh interval for {url} must be at least {MIN_INTERVAL} seconds"
+        ));
+    }
+    Ok(())
+}
+
+/// The refresh schedule, keyed by namespace and URL.
+#[derive(Debug, Default)]
+pub struct Pins(Mutex<BTreeMap<Key, Pin>>);
+
+impl Pins {
+    /// Replaces the config file's pins with `configured` (URL → interval in
+    /// seconds), leaving the `pin` tool's. New pins are due at once; kept ones
+    /// keep their schedule unless the interval changed.
+    pub fn configure(&self, configured: &BTreeMap<String, u64>, namespace: Option<&str>) {
+        let mut pins = self.0.lock().unwrap();
+        pins.retain(|(pin_namespace, url), pin| {
+            !pin.configured
+                || (pin_namespace.as_deref() == namespace && configured.contains_key(url))
+        });
+        for (url, &interval) in configured {
+            let interval = Duration::from_secs(interval);
+            match pins.get_mut(&key(namespace, url)) {
+                Some(pin) if pin.interval == interval => pin.configured = true,
+                _ => {
+                    pins.insert(
+                        key(namespace, url),
+                        Pin {
+                            interval,
+                            configured: true,
+                            next_due: Instant::now(),
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    /// Pins `url` in `namespace` every `interval` seconds, due at once, or
+    /// unpins it when `interval` is 0. Returns whether it was pinned before.
+    pub fn set(&self, url: &str, interval: u64, namespace: Option<&str>) -> bool {
+        let mut pins = self.0.lock().unwrap();
+        if interval == 0 {
+            return pins.remove(&key(namespace, url)).is_some();
+        }
+        pins.insert(
+            key(namespace, url),
+            Pin {
+                interval: Duration::from_secs(interval),
+                configured: false,
+                next_due: Instant::now(),
+            },
+        )
+        .is_some()
+    }
+
+    /// URLs (with their namespace) due for a refresh at `now`, scheduling
+    /// each for its next one.
+    pub fn take_due(&self, now: Instant) -> Vec<(String, Option<String>)> {
+        let mut pins = self.0.lock().unwrap();
+        pins.iter_mut()
+            .filter(|(_, pin)| pin.next_due <= now)
+            .map(|((namespace, url), pin)| {
+                pin.next_due = now + pin.interval;
+                (url.clone(), namespace.clone())
+            })
+            .collect()
+    }
+
+    /// The pins of `namespace`.
+    pub fn list(&self, namespace: Option<&str>) -> Vec<PinInfo> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((pin_namespace, _), _)| pin_namespace.as_deref() == namespace)
+            .map(|((namespace, url), pin)| PinInfo {
+                url: url.clone(),
+                interval_secs: pin.interval.as_secs(),
+                namespace: namespace.clone(),
+                configured: pin.configured,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTES: &str = "https://example.com/releases";
+    const STATUS: &str = "https://status.example.com/";
+
+    #[test]
+    fn test_schedule() {
+        let pins = Pins::default();
+        pins.configure(&BTreeMap::from([(NOTES.to_string(), 3600)]), None);
+        assert!(!pins.set(STATUS, 60, Some("ops")));
+
+        let start = Instant::now();
+        let due: Vec<String> = pins.take_due(start).into_iter().map(|(u, _)| u).collect();
+        assert_eq!(due, [NOTES, STATUS]);
+        assert!(pins.take_due(start).is_empty());
+        assert_eq!(
+            pins.take_due(start + Duration::from_mins(1)),
+            [(STATUS.to_string(), Some("ops".to_string()))]
+        );
+
+        assert!(pins.set(STATUS, 0, Some("ops")));
+        assert_eq!(pins.take_due(start + Duration::from_hours(2)).len(), 1);
+    }
+
+    #[test]
+    fn test_configure_keeps_tool_pins() {
+        let pins = Pins::default();
+        pins.configure(&BTreeMap::from([(NOTES.to_string(), 3600)]), None);
+        pins.set(STATUS, 600, None);
+        pins.take_due(Instant::now());
+
+        // Reloading with the same pin keeps its schedule
+        pins.configure(&BTreeMap::from([(NOTES.to_string(), 3600)]), None);
+        assert!(pins.take_due(Instant::now()).is_empty());
+
+        pins.configure(&BTreeMap::new(), None);
+        let urls: Vec<String> = pins.list(None).into_iter().map(|pin| pin.url).collect();
+        assert_eq!(urls, [STATUS]);
+    }
+
+    #[test]
+    fn test_namespaces_pin_separately() {
+        let pins = Pins::default();
+        pins.set(NOTES, 600, Some("a"));
+        assert!(!pins.set(NOTES, 3600, Some("b")));
+        assert!(!pins.set(NOTES, 0, None));
+        assert_eq!(pins.take_due(Instant::now()).len(), 2);
+
+        assert!(pins.set(NOTES, 0, Some("b")));
+        let listed = pins.list(Some("a"));
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].interval_secs, 600);
+        assert!(pins.list(Some("b")).is_empty());
+        assert!(pins.list(None).is_empty());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(NOTES, 3600).is_ok());
+        assert!(validate(NOTES, 10).is_err());
+        assert!(validate("file:///etc/hosts", 3600).is_err());
+    }
+}
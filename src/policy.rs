@@ -0,0 +1,498 @@
+//! Per-domain content-handling policies from the config file.
+//!
+//! A policy applies to its domain and every subdomain; when several match, the
+//! longest (most specific) domain wins, so `docs.example.com` can refine a
+//! policy set for `example.com`.
+
+use crate::normalize::Normalize;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Marks a `selector` as an XPath expression rather than CSS.
+pub const XPATH_PREFIX: &str = "xpath:";
+
+/// Variation names accepted in `variations`, in default try order.
+pub const VARIATION_NAMES: [&str; 6] =
+    ["original", "md", "html-md", "index-md", "llms", "llms-full"];
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct DomainPolicy {
+    /// Variations to try, in order; others are not requested
+    pub variations: Option<Vec<String>>,
+    /// Extra llms.txt locations, as absolute paths, tried after the variations
+    pub llms_paths: Option<Vec<String>>,
+    /// CSS selector for the main content, tried before Readability, or an
+    /// XPath expression after [`XPATH_PREFIX`]. Several, as a list or
+    /// separated by commas, merge their matches in document order
+    #[serde(default, deserialize_with = "selector_list")]
+    pub selector: Option<String>,
+    /// Set to `false` to convert the whole `<body>` instead of running Readability
+    pub readability: Option<bool>,
+    /// Overrides the global `bold-headings` for this domain
+    pub bold_headings: Option<bool>,
+    /// Overrides the global `normalize` for this domain
+    pub normalize: Option<Normalize>,
+    /// Maximum requests per second to this domain
+    pub requests_per_second: Option<f64>,
+    /// Overrides the global `negative-cache-ttl` for this domain
+    pub negative_cache_ttl: Option<u64>,
+    /// Query parameters that select different content; all others are dropped
+    pub keep_query: Option<Vec<String>>,
+    /// Query parameters that don't change the content, dropped before fetching
+    pub strip_query: Option<Vec<String>>,
+    /// Printer-friendly version of HTML pages to prefer when it converts to
+    /// cleaner Markdown
+    pub print_view: Option<PrintView>,
+}
+
+/// Where to look for a page's print view: `true` to find it on the page or
+/// guess it, or a URL template such as `"{path}?print=1"`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum PrintView {
+    Probe(bool),
+    Template(String),
+}
+
+/// Stands for the page's path, without a trailing slash, in a
+/// [`PrintView::Template`].
+pub const PATH_PLACEHOLDER: &str = "{path}";
+
+/// Slowest `requests-per-second` allowed: one request an hour. Slower rates
+/// would space requests out past what a `Duration` holds.
+const MIN_REQUESTS_PER_SECOND: f64 = 1.0 / 3600.0;
+
+impl DomainPolicy {
+    /// Rejects unknown variation names and rates below
+    /// [`MIN_REQUESTS_PER_SECOND`], naming the domain.
+    pub fn validate(&self, domain: &str) -> Result<(), String> {
+        for name in self.variations.iter().flatten() {
+            if !VARIATION_NAMES.contains(&name.as_str()) {
+                return Err(format!(
+                    "domains.\"{domain}\": unknown variation \"{name}\" (expected one of {})",
+                    VARIATION_NAMES.join(", ")
+                ));
+            }
+        }
+        for path in self.llms_paths.iter().flatten() {
+            if !path.starts_with('/') {
+                return Err(format!(
+                    "domains.\"{domain}\": llms-paths entry \"{path}\" must be an absolute path"
+                ));
+            }
+        }
+        if let Some(rate) = self.requests_per_second
+            && !(rate.is_finite() && rate >= MIN_REQUESTS_PER_SECOND)
+        {
+            return Err(format!(
+                "domains.\"{domain}\": requests-per-second must be at least 1/3600 \
+                 (one request an hour)"
+            ));
+        }
+        if let Some(selector) = &self.selector {
+            check_selector(selector).map_err(|e| {
+                format!("domains.\"{domain}\": invalid selector \"{selector}\": {e}")
+            })?;
+        }
+        if let Some(PrintView::Template(template)) = &self.print_view
+            && !template.contains(PATH_PLACEHOLDER)
+        {
+            return Err(format!(
+                "domains.\"{domain}\": print-view \"{template}\" must contain {PATH_PLACEHOLDER}"
+            ));
+        }
+        if self.keep_query.is_some() && self.strip_query.is_some() {
+            return Err(format!(
+                "domains.\"{domain}\": set either keep-query or strip-query, not both"
+            ));
+        }
+        Ok(())
+    }
+
+    /// The URL `url` is fetched and cached as: with `keep-query` or
+    /// `strip-query`, the insignificant query parameters are dropped and the
+    /// rest sorted by name, so equivalent URLs share a cache entry. URLs are
+    /// unchanged when neither is set.
+    pub fn canonical_url(&self, url: &str) -> String {
+        if self.keep_query.is_none() && self.strip_query.is_none() {
+            return url.to_string();
+        }
+        let significant = |name: &str| match (&self.keep_query, &self.strip_query) {
+            (Some(keep), _) => keep.iter().any(|pattern| param_matches(pattern, name)),
+            (None, Some(strip)) => !strip.iter().any(|pattern| param_matches(pattern, name)),
+            (None, None) => true,
+        };
+        let Ok(mut parsed) = url::Url::parse(url) else {
+            return url.to_string();
+        };
+        if parsed.query().is_none() {
+            return url.to_string();
+        }
+        let mut params: Vec<(String, String)> = parsed
+            .query_pairs()
+            .filter(|(name, _)| significant(name))
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        params.sort();
+        if params.is_empty() {
+            parsed.set_query(None);
+        } else {
+            parsed.query_pairs_mut().clear().extend_pairs(params);
+        }
+        parsed.to_string()
+    }
+
+    pub fn use_readability(&self) -> bool {
+        self.readability.unwrap_or(true)
+    }
+
+    /// Filters and reorders `variations` of `url` per the policy.
+    pub fn apply_variations(&self, url: &str, variations: Vec<String>) -> Vec<String> {
+        let Some(preferred) = &self.variations else {
+            return variations;
+        };
+        preferred
+            .iter()
+            .filter_map(|name| {
+                variations
+                    .iter()
+                    .find(|v| variation_name(url, v) == name)
+                    .cloned()
+            })
+            .collect()
+    }
+}
+
+/// Whether the query parameter `name` matches `pattern`, a name or a prefix
+/// ending in `*` (`utm_*`).
+fn param_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    }
+}
+
+/// Names a variation of `url` as used in [`DomainPolicy::variations`].
+pub fn variation_name(url: &str, variation: &str) -> &'static str {
+    if variation == url {
+        "original"
+    } else if variation.ends_with("/llms-full.txt") {
+        "llms-full"
+    } else if variation.ends_with("/llms.txt") {
+        "llms"
+    } else if variation.ends_with("/index.md") {
+        "index-md"
+    } else if variation.ends_with(".html.md") {
+        "html-md"
+    } else {
+        "md"
+    }
+}
+
+/// All configured policies, keyed by lowercase domain.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Policies(BTreeMap<String, DomainPolicy>);
+
+impl Policies {
+    pub fn new(domains: BTreeMap<String, DomainPolicy>) -> Result<Self, String> {
+        for (domain, policy) in &domains {
+            policy.validate(domain)?;
+        }
+        Ok(Self(
+            domains
+                .into_iter()
+                .map(|(domain, policy)| (domain.to_lowercase(), policy))
+                .collect(),
+        ))
+    }
+
+    /// Returns the most specific policy for the host of `url`, or the default.
+    pub fn for_url(&self, url: &str) -> DomainPolicy {
+        url::Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_lowercase))
+            .and_then(|host| self.for_host(&host))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The domains with a policy, sorted.
+    pub fn domains(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+
+    /// Longest per-domain `negative-cache-ttl`, so pruning keeps every domain's misses.
+    pub fn longest_negative_cache_ttl(&self) -> Option<u64> {
+        self.0.values().filter_map(|p| p.negative_cache_ttl).max()
+    }
+
+    fn for_host(&self, host: &str) -> Option<&DomainPolicy> {
+        self.0
+            .iter()
+            .filter(|(domain, _)| {
+                host == domain.as_str()
+                    || host
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|prefix| prefix.ends_with('.'))
+            })
+            .max_by_key(|(domain, _)| domain.len())
+            .map(|(_, policy)| policy)
+    }
+}
+
+/// Spaces out requests to hosts with a `requests-per-second` policy.
+///
+/// Kept outside the reloadable settings so pacing survives config reloads.
+#[derive(Debug, Default)]
+pub struct Throttle {
+    next_slot: Mutex<HashMap<String, Instant>>,
+}
+
+impl Throttle {
+    /// Waits until a request to `url`'s host is allowed at `requests_per_second`.
+    pub async fn wait(&self, url: &str, requests_per_second: f64) {
+        let Some(host) = url::Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_lowercase))
+        else {
+            return;
+        };
+        let interval =
+            Duration::from_secs_f64(1.0 / requests_per_second.max(MIN_REQUESTS_PER_SECOND));
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let now = Instant::now();
+            let slot = next_slot.get(&host).map_or(now, |&next| next.max(now));
+            next_slot.insert(host, slot + interval);
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+/// Checks that `selector` parses, as XPath after [`XPATH_PREFIX`] and as CSS
+/// otherwise.
+fn check_selector(selector: &str) -> Result<(), String> {
+    match selector.strip_prefix(XPATH_PREFIX) {
+        #[cfg(feature = "xpath")]
+        Some(expression) => crate::xpath::validate(expression),
+        #[cfg(not(feature = "xpath"))]
+        Some(_) => Err("XPath selectors need the xpath feature".to_string()),
+        None => dom_query::Matcher::new(selector)
+            .map(drop)
+            .map_err(|_| "not a valid CSS selector".to_string()),
+    }
+}
+
+/// Reads `selector` as one selector or a list of them, joined into a single
+/// comma-separated selector list, or an XPath union when they're all XPath.
+fn selector_list<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Selectors {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(Some(match Selectors::deserialize(deserializer)? {
+        Selectors::One(selector) => selector,
+        Selectors::Many(selectors) => {
+            let expressions: Option<Vec<&str>> = selectors
+                .iter()
+                .map(|selector| selector.strip_prefix(XPATH_PREFIX))
+                .collect();
+            match expressions {
+                Some(expressions) if !expressions.is_empty() => {
+                    format!("{XPATH_PREFIX}{}", expressions.join(" | "))
+                }
+                _ => selectors.join(", "),
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policies(toml: &str) -> Policies {
+        let domains: BTreeMap<String, DomainPolicy> = toml::from_str(toml).unwrap();
+        Policies::new(domains).unwrap()
+    }
+
+    #[test]
+    fn test_most_specific_domain_wins() {
+        let policies = policies(
+            r#"
+            "example.com" = { readability = false }
+            "docs.example.com" = { selector = "main" }
+            "#,
+        );
+        assert_eq!(
+            policies
+                .for_url("https://docs.example.com/a")
+                .selector
+                .as_deref(),
+            Some("main")
+        );
+        assert_eq!(
+            policies.for_url("https://api.example.com/a").readability,
+            Some(false)
+        );
+        assert_eq!(
+            policies.for_url("https://notexample.com/a"),
+            DomainPolicy::default()
+        );
+    }
+
+    #[test]
+    fn test_selector_list() {
+        let policies = policies(
+            r#"
+            "docs.example.com" = { selector = ["main", ".api-sidebar"] }
+            "blog.example.com" = { selector = "article, .comments" }
+            "#,
+        );
+        let selector = |url: &str| policies.for_url(url).selector;
+        assert_eq!(
+            selector("https://docs.example.com/a").as_deref(),
+            Some("main, .api-sidebar")
+        );
+        assert_eq!(
+            selector("https://blog.example.com/a").as_deref(),
+            Some("article, .comments")
+        );
+    }
+
+    #[test]
+    fn test_xpath_selector() {
+        let domains: BTreeMap<String, DomainPolicy> = toml::from_str(
+            r#""example.com" = { selector = ["xpath://main", "xpath://aside[@id='api']"] }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            domains["example.com"].selector.as_deref(),
+            Some("xpath://main | //aside[@id='api']")
+        );
+        // Without the feature, XPath selectors are rejected rather than ignored
+        assert_eq!(Policies::new(domains).is_ok(), cfg!(feature = "xpath"));
+        let invalid: BTreeMap<String, DomainPolicy> =
+            toml::from_str(r#""example.com" = { selector = "xpath://main[" }"#).unwrap();
+        assert!(Policies::new(invalid).is_err());
+    }
+
+    #[test]
+    fn test_preferred_variations_filter_and_order() {
+        let policy = DomainPolicy {
+            variations: Some(vec!["llms-full".to_string(), "original".to_string()]),
+            ..DomainPolicy::default()
+        };
+        let url = "https://docs.example.com";
+        let variations = vec![
+            url.to_string(),
+            format!("{url}.md"),
+            format!("{url}/llms.txt"),
+            format!("{url}/llms-full.txt"),
+        ];
+        assert_eq!(
+            policy.apply_variations(url, variations),
+            vec![format!("{url}/llms-full.txt"), url.to_string()]
+        );
+    }
+
+    #[test]
+    fn test_canonical_url() {
+        let policies = policies(
+            r#"
+            "docs.example.com" = { keep-query = ["version", "lang"] }
+            "blog.example.com" = { strip-query = ["utm_*", "ref"] }
+            "#,
+        );
+        let canonical = |url: &str| policies.for_url(url).canonical_url(url);
+        assert_eq!(
+            canonical("https://docs.example.com/a?version=2.x&utm_source=x&lang=en#top"),
+            "https://docs.example.com/a?lang=en&version=2.x#top"
+        );
+        assert_eq!(
+            canonical("https://docs.example.com/a?utm_source=x"),
+            "https://docs.example.com/a"
+        );
+        assert_eq!(
+            canonical("https://blog.example.com/post?utm_medium=mail&page=2&ref=hn"),
+            "https://blog.example.com/post?page=2"
+        );
+        assert_eq!(
+            canonical("https://other.example.org/?b=1&a=2"),
+            "https://other.example.org/?b=1&a=2"
+        );
+    }
+
+    #[test]
+    fn test_invalid_policies_rejected() {
+        let unknown: BTreeMap<String, DomainPolicy> =
+            toml::from_str(r#""example.com" = { variations = ["pdf"] }"#).unwrap();
+        assert!(Policies::new(unknown).is_err());
+        let zero_rate: BTreeMap<String, DomainPolicy> =
+            toml::from_str(r#""example.com" = { requests-per-second = 0 }"#).unwrap();
+        assert!(Policies::new(zero_rate).is_err());
+        let tiny_rate: BTreeMap<String, DomainPolicy> =
+            toml::from_str(r#""example.com" = { requests-per-second = 1e-300 }"#).unwrap();
+        assert!(Policies::new(tiny_rate).is_err());
+        let both: BTreeMap<String, DomainPolicy> =
+            toml::from_str(r#""example.com" = { keep-query = ["v"], strip-query = ["utm_*"] }"#)
+                .unwrap();
+        assert!(Policies::new(both).is_err());
+        let relative: BTreeMap<String, DomainPolicy> =
+            toml::from_str(r#""example.com" = { llms-paths = ["docs/llms.txt"] }"#).unwrap();
+        assert!(Policies::new(relative).is_err());
+        let invalid_selector: BTreeMap<String, DomainPolicy> =
+            toml::from_str(r#""example.com" = { selector = ["main", "div["] }"#).unwrap();
+        assert!(Policies::new(invalid_selector).is_err());
+        let print_view: BTreeMap<String, DomainPolicy> =
+            toml::from_str(r#""example.com" = { print-view = "?print=1" }"#).unwrap();
+        assert!(Policies::new(print_view).is_err());
+    }
+
+    #[test]
+    fn test_print_view() {
+        let policies = policies(
+            r#"
+            "docs.example.com" = { print-view = true }
+            "blog.example.com" = { print-view = "/print{path}?format=plain" }
+            "#,
+        );
+        assert_eq!(
+            policies.for_url("https://docs.example.com/a").print_view,
+            Some(PrintView::Probe(true))
+        );
+        assert_eq!(
+            policies.for_url("https://blog.example.com/a").print_view,
+            Some(PrintView::Template("/print{path}?format=plain".to_string()))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttle_spaces_requests_per_host() {
+        let throttle = Throttle::default();
+        let start = Instant::now();
+        throttle.wait("https://a.example.com/1", 2.0).await;
+        throttle.wait("https://a.example.com/2", 2.0).await;
+        throttle.wait("https://b.example.com/1", 2.0).await;
+        assert_eq!(start.elapsed(), Duration::from_millis(500));
+        throttle.wait("https://a.example.com/3", 2.0).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+        // Rates too slow for a `Duration` wait an hour
+        throttle.wait("https://c.example.com/1", 1e-300).await;
+        throttle.wait("https://c.example.com/2", 1e-300).await;
+        assert_eq!(
+            start.elapsed(),
+            Duration::from_secs(1) + Duration::from_hours(1)
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//! Printer-friendly versions of pages, for the `print-view` domain policy.
+//!
+//! Some sites serve a print view of each page (`?print=1`, `/print/`, `.txt`)
+//! without the navigation, sidebars and widgets around the content. When
+//! Readability can't separate those from the content, the print view converts
+//! to much cleaner Markdown, so it's fetched too and kept instead when it is.
+//! Cleanliness is the share of words outside links: the print view must
+//! raise it by [`MIN_GAIN`] while keeping most of the page's other words.
+
+use crate::policy::{PATH_PLACEHOLDER, PrintView};
+
+/// Print views guessed when the page doesn't link to one, in the order tried.
+const GUESSES: [&str; 3] = ["{path}?print=1", "{path}/print/", "{path}.txt"];
+
+/// Link texts of print links, lowercase.
+const LINK_TEXTS: [&str; 6] = [
+    "print",
+    "print this page",
+    "print version",
+    "print view",
+    "printer-friendly version",
+    "printer friendly version",
+];
+
+/// How much larger the share of words outside links must be in the print view.
+const MIN_GAIN: f64 = 0.1;
+
+/// Share of the page's words outside links the print view must keep.
+const MIN_KEPT: f64 = 0.8;
+
+/// The print views of the page at `page_url` to try, in order, for the
+/// `print-view` setting.
+pub fn candidates(setting: &PrintView, html: &str, page_url: &str) -> Vec<String> {
+    let Ok(page) = url::Url::parse(page_url) else {
+        return Vec::new();
+    };
+    let mut candidates: Vec<String> = match setting {
+        PrintView::Probe(false) => Vec::new(),
+        PrintView::Template(template) => vec![apply(template, &page)],
+        PrintView::Probe(true) => {
+            let mut candidates = linked(html, &page);
+            // A site's home page rarely has a print view
+            if page.path() != "/" {
+                candidates.extend(GUESSES.iter().map(|guess| apply(guess, &page)));
+            }
+            candidates
+        }
+    };
+    let mut seen = vec![page_url.to_string()];
+    candidates.retain(|candidate| {
+        let new = !seen.contains(candidate);
+        seen.push(candidate.clone());
+        new
+    });
+    candidates
+}
+
+/// Whether the print view's Markdown is substantially cleaner than the
+/// page's, or converts at all when the page didn't (`None`).
+pub fn is_cleaner(page: Option<&str>, print: &str) -> bool {
+    let print = Words::count(print);
+    let Some(page) = page.map(Words::count) else {
+        return print.prose > 0;
+    };
+    #[allow(clippy::cast_precision_loss)]
+    let kept = print.prose as f64 >= page.prose as f64 * MIN_KEPT;
+    kept && print.prose_share() >= page.prose_share() + MIN_GAIN
+}
+
+/// The URL `template` gives for `page`: `{path}` becomes its path without a
+/// trailing slash, and the template's query is added to the page's.
+fn apply(template: &str, page: &url::Url) -> String {
+    let (path, query) = template.split_once('?').unwrap_or((template, ""));
+    let path = path.replace(PATH_PLACEHOLDER, page.path().trim_end_matches('/'));
+    let mut print = page.clone();
+    print.set_path(if path.is_empty() { "/" } else { &path });
+    let query: Vec<&str> = page
+        .query()
+        .into_iter()
+        .chain(Some(query))
+        .filter(|query| !query.is_empty())
+        .collect();
+    print.set_query((!query.is_empty()).then(|| query.join("&")).as_deref());
+    print.set_fragment(None);
+    print.to_string()
+}
+
+/// Print views the page links to on its own host: a
+/// `<link rel="alternate" media="print">` or a link labelled as a print
+/// version.
+fn linked(html: &str, page: &url::Url) -> Vec<String> {
+    let document = dom_query::Document::from(html);
+    let alternates = document
+        .select("link[rel~='alternate'][media~='print'][href]")
+        .iter()
+        .filter_map(|link| link.attr("href"));
+    let anchors = document.select("a[href]").iter().filter_map(|anchor| {
+        let text = anchor
+            .text()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        LINK_TEXTS
+            .contains(&text.to_lowercase().as_str())
+            .then(|| anchor.attr("href"))
+            .flatten()
+    });
+    alternates
+        .chain(anchors)
+        .filter_map(|href| page.join(href.trim()).ok())
+        .filter(|url| matches!(url.scheme(), "http" | "https") && url.host_str() == page.host_str())
+        .map(|mut url| {
+            url.set_fragment(None);
+            url.to_string()
+        })
+        .collect()
+}
+
+/// Words of `text`, leaving out Markdown syntax such as list bullets.
+fn count_words(text: &str) -> usize {
+    text.split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+/// Words of Markdown, split by whether they're part of a link or image.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Words {
+    prose: usize,
+    linked: usize,
+}
+
+impl Words {
+    fn count(markdown: &str) -> Self {
+        let mut words = Self::default();
+        let mut rest = markdown;
+        // `[text](url)`, with `![alt](src)` images counted the same way
+        while let Some(open) = rest.find('[') {
+            let Some((text, after)) = rest[open + 1..].split_once("](") else {
+                break;
+            };
+            let Some(close) = after.find(')') else {
+                break;
+            };
+            words.prose += count_words(&rest[..open]);
+            words.linked += count_words(text).max(1);
+            rest = &after[close + 1..];
+        }
+        words.prose += count_words(rest);
+        words
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn prose_share(&self) -> f64 {
+        let total = self.prose + self.linked;
+        if total == 0 {
+            0.0
+        } else {
+            self.prose as f64 / total as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates() {
+        let page = "https://docs.example.com/guide/intro/?lang=en#setup";
+        let html = r#"<html><head>
+            <link rel="alternate" media="print" href="/print/guide/intro">
+            </head><body>
+            <a href="javascript:window.print()">Print</a>
+            <a href="https://printfriendly.example/?url=x">Print</a>
+            <a href="intro.pdf"> Printer-friendly
+              version </a>
+            </body></html>"#;
+        assert_eq!(
+            candidates(&PrintView::Probe(true), html, page),
+            [
+                "https://docs.example.com/print/guide/intro",
+                "https://docs.example.com/guide/intro/intro.pdf",
+                "https://docs.example.com/guide/intro?lang=en&print=1",
+                "https://docs.example.com/guide/intro/print/?lang=en",
+                "https://docs.example.com/guide/intro.txt?lang=en",
+            ]
+        );
+        assert_eq!(
+            candidates(
+                &PrintView::Template("/print{path}".to_string()),
+                "",
+                "https://docs.example.com/guide"
+            ),
+            ["https://docs.example.com/print/guide"]
+        );
+        assert!(candidates(&PrintView::Probe(true), "", "https://docs.example.com/").is_empty());
+        assert!(candidates(&PrintView::Probe(false), html, page).is_empty());
+    }
+
+    #[test]
+    fn test_is_cleaner() {
+        let nav = "- [Home](/)\n- [Guides](/guides)\n- [API reference](/api)\n- [Blog](/blog)\n";
+        let content = "# Intro\n\nInstall the tool, then run it on your project to see a report \
+            of what it found. See [the guide](/guide) for more.\n";
+        let noisy = format!("{nav}\n{content}\n{nav}");
+        assert_eq!(
+            Words::count(content),
+            Words {
+                prose: 21,
+                linked: 2
+            }
+        );
+
+        assert!(is_cleaner(Some(&noisy), content));
+        assert!(!is_cleaner(Some(content), content));
+        // A print view that lost the content isn't cleaner
+        assert!(!is_cleaner(Some(&noisy), "# Intro\n"));
+        assert!(is_cleaner(None, content));
+        assert!(!is_cleaner(None, ""));
+    }
+}
@@ -0,0 +1,174 @@
+//! Per-session fetch quotas.
+//!
+//! Usage accumulates for the lifetime of the server process (one MCP session
+//! over stdio) until the `reset_quota` tool clears it. Limits come from the
+//! reloadable settings, so raising them takes effect without losing the count.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Configured limits; `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub max_requests: Option<u64>,
+    pub max_bytes: Option<u64>,
+}
+
+impl Limits {
+    pub fn is_unlimited(self) -> bool {
+        self.max_requests.is_none() && self.max_bytes.is_none()
+    }
+}
+
+/// Returned once a limit has been reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exceeded {
+    pub resource: &'static str,
+    pub used: u64,
+    pub limit: u64,
+}
+
+impl fmt::Display for Exceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Session quota exceeded: {} of {} {} used; call reset_quota or restart the server",
+            self.used, self.limit, self.resource
+        )
+    }
+}
+
+impl std::error::Error for Exceeded {}
+
+/// Requests sent and bytes downloaded so far in this session.
+#[derive(Debug, Default)]
+pub struct Usage {
+    requests: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl Usage {
+    /// Fails if either limit has already been reached.
+    pub fn check(&self, limits: Limits) -> Result<(), Exceeded> {
+        let (requests, bytes) = self.get();
+        if let Some(limit) = limits.max_requests
+            && requests >= limit
+        {
+            return Err(Exceeded {
+                resource: "requests",
+                used: requests,
+                limit,
+            });
+        }
+        if let Some(limit) = limits.max_bytes
+            && bytes >= limit
+        {
+            return Err(Exceeded {
+                resource: "bytes",
+                used: bytes,
+                limit,
+            });
+        }
+        Ok(())
+    }
+
+    /// Counts one request against the quota, or fails without counting it.
+    pub fn reserve_request(&self, limits: Limits) -> Result<(), Exceeded> {
+        self.check(limits)?;
+        let Some(limit) = limits.max_requests else {
+            self.requests.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        };
+        // Parallel variations race for the last slots
+        self.requests
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                (used < limit).then_some(used + 1)
+            })
+            .map(|_| ())
+            .map_err(|used| Exceeded {
+                resource: "requests",
+                used,
+                limit,
+            })
+    }
+
+    /// Counts requests made outside [`Usage::reserve_request`], such as GitHub
+    /// API pagination.
+    pub fn add_requests(&self, requests: u64) {
+        self.requests.fetch_add(requests, Ordering::Relaxed);
+    }
+
+    pub fn add_bytes(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Returns `(requests, bytes)`.
+    pub fn get(&self) -> (u64, u64) {
+        (
+            self.requests.load(Ordering::Relaxed),
+            self.bytes.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Clears the counters, returning the usage before the reset.
+    pub fn reset(&self) -> (u64, u64) {
+        (
+            self.requests.swap(0, Ordering::Relaxed),
+            self.bytes.swap(0, Ordering::Relaxed),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_limit() {
+        let usage = Usage::default();
+        let limits = Limits {
+            max_requests: Some(2),
+            max_bytes: None,
+        };
+        assert!(usage.reserve_request(limits).is_ok());
+        assert!(usage.reserve_request(limits).is_ok());
+        let exceeded = usage.reserve_request(limits).unwrap_err();
+        assert_eq!(exceeded.resource, "requests");
+        assert_eq!(usage.get(), (2, 0));
+        assert!(exceeded.to_string().contains("reset_quota"));
+
+        assert_eq!(usage.reset(), (2, 0));
+        assert!(usage.check(limits).is_ok());
+    }
+
+    #[test]
+    fn test_byte_limit_blocks_further_requests() {
+        let usage = Usage::default();
+        let limits = Limits {
+            max_requests: None,
+            max_bytes: Some(1000),
+        };
+        usage.reserve_request(limits).unwrap();
+        usage.add_bytes(999);
+        assert!(usage.check(limits).is_ok());
+        usage.add_bytes(5);
+        assert_eq!(
+            usage.reserve_request(limits),
+            Err(Exceeded {
+                resource: "bytes",
+                used: 1004,
+                limit: 1000
+            })
+        );
+        assert_eq!(usage.get(), (1, 1004));
+    }
+
+    #[test]
+    fn test_unlimited_still_counts() {
+        let usage = Usage::default();
+        assert!(Limits::default().is_unlimited());
+        usage.reserve_request(Limits::default()).unwrap();
+        usage.add_requests(3);
+        assert_eq!(usage.get(), (4, 0));
+    }
+}
@@ -0,0 +1,312 @@
+//! Full-text search over the cache.
+//!
+//! Without the `search-index` feature, or when the index can't be opened, the
+//! `search` tool scans the cached files directly: a file matches when it
+//! contains every term of the query, and files are ranked by how often the
+//! terms occur. Either way, each hit points at the line that matches best,
+//! and the section of the document containing it.
+
+use crate::memory::Store;
+use crate::toc;
+use std::path::{Path, PathBuf};
+
+/// Hits returned when the caller doesn't give a limit.
+pub const DEFAULT_LIMIT: usize = 10;
+
+/// Upper bound on `limit`.
+pub const MAX_LIMIT: usize = 50;
+
+/// Snippets longer than this are cut around the first match.
+const MAX_SNIPPET_CHARS: usize = 200;
+
+/// A search query: words, and phrases in double quotes, all lowercase.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    pub terms: Vec<String>,
+}
+
+impl Query {
+    /// Parses `query`, or returns `None` if it has no terms.
+    pub fn parse(query: &str) -> Option<Self> {
+        let mut terms = Vec::new();
+        for (i, part) in query.split('"').enumerate() {
+            let part = part.to_lowercase();
+            if i % 2 == 1 {
+                let phrase = part.split_whitespace().collect::<Vec<_>>().join(" ");
+                if !phrase.is_empty() {
+                    terms.push(phrase);
+                }
+            } else {
+                terms.extend(part.split_whitespace().map(str::to_string));
+            }
+        }
+        (!terms.is_empty()).then_some(Self { terms })
+    }
+
+    /// How often the terms occur in `text` (lowercase), or `None` if one of
+    /// them doesn't.
+    fn occurrences(&self, text: &str) -> Option<usize> {
+        self.terms.iter().try_fold(0, |total, term| {
+            let count = text.matches(term.as_str()).count();
+            (count > 0).then_some(total + count)
+        })
+    }
+}
+
+/// A matching cached file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    pub path: PathBuf,
+    pub source_url: Option<String>,
+    /// Higher is better; only comparable within one search
+    pub score: f32,
+    /// 1-based line that matches the most terms
+    pub line: usize,
+    pub snippet: String,
+    /// `None` when the line comes before the first heading
+    pub section: Option<Section>,
+}
+
+/// The part of a document under one heading.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// Text of the enclosing headings, outermost first
+    pub breadcrumb: Vec<String>,
+    /// 1-based line of the heading
+    pub start_line: usize,
+    /// 1-based last line, before the next heading of the same or a higher level
+    pub end_line: usize,
+}
+
+/// The innermost section of `content` containing `line`.
+pub fn section_at(content: &str, line: usize) -> Option<Section> {
+    let headings = toc::extract_headings(content);
+    let position = headings
+        .iter()
+        .rposition(|heading| heading.line_number <= line)?;
+    let heading = &headings[position];
+
+    let mut breadcrumb = Vec::new();
+    let mut level = u8::MAX;
+    for outer in headings[..=position].iter().rev() {
+        if outer.level < level {
+            level = outer.level;
+            breadcrumb.push(outer.text.trim_matches('#').trim().to_string());
+        }
+    }
+    breadcrumb.reverse();
+
+    let end_line = headings[position + 1..]
+        .iter()
+        .find(|next| next.level <= heading.level)
+        .map_or_else(|| content.lines().count(), |next| next.line_number - 1);
+    Some(Section {
+        breadcrumb,
+        start_line: heading.line_number,
+        end_line,
+    })
+}
+
+/// The line of `content` matching the most terms (the first, on ties), with
+/// its 1-based number, or `None` if no line matches any.
+pub fn best_line(content: &str, query: &Query) -> Option<(usize, String)> {
+    let mut best: Option<(usize, usize, &str)> = None;
+    for (number, line) in content.lines().enumerate() {
+        let lower = line.to_lowercase();
+        let matched = query
+            .terms
+            .iter()
+            .filter(|term| lower.contains(term.as_str()))
+            .count();
+        if matched > best.map_or(0, |(count, _, _)| count) {
+            best = Some((matched, number + 1, line));
+        }
+    }
+    best.map(|(_, number, line)| (number, snippet(line, query)))
+}
+
+/// `line` trimmed, cut to about [`MAX_SNIPPET_CHARS`] around the first term
+/// it contains.
+fn snippet(line: &str, query: &Query) -> String {
+    let line = line.trim();
+    let chars: Vec<char> = line.chars().collect();
+    if chars.len() <= MAX_SNIPPET_CHARS {
+        return line.to_string();
+    }
+    let lower = line.to_lowercase();
+    let first = query
+        .terms
+        .iter()
+        .filter_map(|term| lower.find(term.as_str()))
+        .min()
+        .map_or(0, |byte| lower[..byte].chars().count());
+    let start = first
+        .saturating_sub(MAX_SNIPPET_CHARS / 4)
+        .min(chars.len() - MAX_SNIPPET_CHARS);
+    let end = start + MAX_SNIPPET_CHARS;
+    let mut snippet: String = chars[start..end].iter().collect();
+    if start > 0 {
+        snippet.insert(0, '…');
+    }
+    if end < chars.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+/// Whether `url` is on `domain` or one of its subdomains.
+pub fn on_domain(url: &str, domain: &str) -> bool {
+    let domain = domain.trim().trim_start_matches('.').to_lowercase();
+    url::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_lowercase))
+        .is_some_and(|host| host == domain || host.ends_with(&format!(".{domain}")))
+}
+
+/// Searches the files cached in `store` under `root` by reading each one.
+pub fn scan(
+    store: &Store,
+    root: &Path,
+    query: &Query,
+    domain: Option<&str>,
+    limit: usize,
+) -> std::io::Result<Vec<Hit>> {
+    let mut hits = Vec::new();
+    for entry in store.list(root)? {
+        let source_url = entry.sidecar.map(|meta| meta.url);
+        if let Some(domain) = domain
+            && !source_url
+                .as_deref()
+                .is_some_and(|url| on_domain(url, domain))
+        {
+            continue;
+        }
+        let Ok(content) = store.read(&entry.path) else {
+            continue;
+        };
+        let Some(occurrences) = query.occurrences(&content.to_lowercase()) else {
+            continue;
+        };
+        let (line, snippet) = best_line(&content, query).unwrap_or_default();
+        hits.push(Hit {
+            path: entry.path,
+            source_url,
+            #[allow(clippy::cast_precision_loss)]
+            score: occurrences as f32,
+            line,
+            snippet,
+            section: section_at(&content, line),
+        });
+    }
+    hits.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    hits.truncate(limit);
+    Ok(hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sidecar;
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(
+            Query::parse(r#"Async  "Error   Handling" tokio"#)
+                .unwrap()
+                .terms,
+            ["async", "error handling", "tokio"]
+        );
+        assert!(Query::parse(r#"  "" "#).is_none());
+    }
+
+    #[test]
+    fn test_best_line() {
+        let query = Query::parse("tokio runtime").unwrap();
+        let content = "# Guide\nThe runtime.\nStart a Tokio runtime here.\nTokio";
+        assert_eq!(
+            best_line(content, &query),
+            Some((3, "Start a Tokio runtime here.".to_string()))
+        );
+        assert_eq!(best_line("nothing", &query), None);
+
+        let long = format!("{} tokio {}", "a".repeat(300), "b".repeat(300));
+        let (_, snippet) = best_line(&long, &query).unwrap();
+        assert!(snippet.starts_with('…') && snippet.ends_with('…'));
+        assert!(snippet.contains("tokio"));
+    }
+
+    #[test]
+    fn test_section_at() {
+        let content = "intro\n# Guide\ntext\n## Install\n### Linux\napt\n## Usage\nrun\n# API\n";
+        assert_eq!(section_at(content, 1), None);
+        assert_eq!(
+            section_at(content, 6),
+            Some(Section {
+                breadcrumb: vec![
+                    "Guide".to_string(),
+                    "Install".to_string(),
+                    "Linux".to_string()
+                ],
+                start_line: 5,
+                end_line: 6,
+            })
+        );
+        let usage = section_at(content, 8).unwrap();
+        assert_eq!(usage.breadcrumb, ["Guide", "Usage"]);
+        assert_eq!((usage.start_line, usage.end_line), (7, 8));
+        let guide = section_at(content, 3).unwrap();
+        assert_eq!((guide.start_line, guide.end_line), (2, 8));
+        assert_eq!(section_at(content, 9).unwrap().end_line, 9);
+    }
+
+    #[test]
+    fn test_on_domain() {
+        assert!(on_domain("https://docs.rs/tokio", "docs.rs"));
+        assert!(on_domain("https://api.Example.com/x", "example.com"));
+        assert!(!on_domain("https://notexample.com/x", "example.com"));
+    }
+
+    #[test]
+    fn test_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, url: &str, content: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            let meta = sidecar::Sidecar {
+                url: url.to_string(),
+                fetched_at: 0,
+                http: None,
+                version: None,
+                sha256: None,
+                sections: Vec::new(),
+            };
+            std::fs::write(
+                sidecar::sidecar_path(&path),
+                serde_json::to_string(&meta).unwrap(),
+            )
+            .unwrap();
+        };
+        write("a.md", "https://docs.rs/a", "tokio spawn\ntokio tokio");
+        write("b.md", "https://example.com/b", "tokio spawn");
+        write("c.md", "https://docs.rs/c", "spawn only");
+
+        let query = Query::parse("tokio spawn").unwrap();
+        let hits = scan(&Store::Disk, dir.path(), &query, None, 10).unwrap();
+        let names: Vec<_> = hits.iter().map(|h| h.path.file_name().unwrap()).collect();
+        assert_eq!(names, ["a.md", "b.md"]);
+        assert_eq!((hits[0].line, hits[0].snippet.as_str()), (1, "tokio spawn"));
+
+        let hits = scan(&Store::Disk, dir.path(), &query, Some("example.com"), 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(
+            scan(&Store::Disk, dir.path(), &query, None, 1)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
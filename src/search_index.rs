@@ -0,0 +1,372 @@
+//! Tantivy index of the cache, for ranked search.
+//!
+//! With the `search-index` feature, each cache root gets an index in
+//! [`CACHE_DIR`], built from the cached files the first time it's needed and
+//! updated whenever a file is written to the cache. Results are ranked with
+//! BM25, phrases in double quotes are matched as phrases, and a search can be
+//! limited to a domain. `llms-fetch-mcp reindex` rebuilds the index, for
+//! caches changed by other tools.
+
+use crate::search::{self, Hit, Query};
+use crate::sidecar;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tantivy::collector::TopDocs;
+use tantivy::query::{BooleanQuery, Occur, QueryParser, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption, STORED, STRING, Schema, TEXT, Value};
+use tantivy::{Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+
+/// Directory of the index inside a cache root.
+pub const CACHE_DIR: &str = ".search";
+
+/// Where a new index is built before it's moved to [`CACHE_DIR`], so an
+/// interrupted build doesn't leave an incomplete index behind.
+const BUILD_DIR: &str = ".search.tmp";
+
+/// Memory the index writer buffers documents in before flushing a segment.
+const WRITER_MEMORY: usize = 15_000_000;
+
+#[derive(Debug, Clone, Copy)]
+struct Fields {
+    /// Path of the cached file, identifying its document
+    path: Field,
+    url: Field,
+    /// The URL's host and its parent domains, for domain filters
+    domain: Field,
+    body: Field,
+}
+
+fn schema() -> (Schema, Fields) {
+    let mut builder = Schema::builder();
+    let fields = Fields {
+        path: builder.add_text_field("path", STRING | STORED),
+        url: builder.add_text_field("url", STRING | STORED),
+        domain: builder.add_text_field("domain", STRING),
+        body: builder.add_text_field("body", TEXT),
+    };
+    (builder.build(), fields)
+}
+
+/// `docs.example.com` → `docs.example.com`, `example.com`.
+fn domains(url: &str) -> Vec<String> {
+    let Some(host) = url::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_lowercase))
+    else {
+        return Vec::new();
+    };
+    let mut domains = vec![host.clone()];
+    let mut rest = host.as_str();
+    while let Some((_, parent)) = rest.split_once('.')
+        && parent.contains('.')
+    {
+        domains.push(parent.to_string());
+        rest = parent;
+    }
+    domains
+}
+
+fn document(fields: Fields, path: &Path, url: Option<&str>, content: &str) -> TantivyDocument {
+    let mut document = TantivyDocument::new();
+    document.add_text(fields.path, path.to_string_lossy());
+    if let Some(url) = url {
+        document.add_text(fields.url, url);
+        for domain in domains(url) {
+            document.add_text(fields.domain, domain);
+        }
+    }
+    document.add_text(fields.body, content);
+    document
+}
+
+/// Adds the cached files under `root` to the index, returning how many.
+fn add_files(writer: &mut IndexWriter, fields: Fields, root: &Path) -> tantivy::Result<usize> {
+    let mut count = 0;
+    for entry in sidecar::list(root)? {
+        let Ok(content) = std::fs::read_to_string(&entry.path) else {
+            continue;
+        };
+        let url = entry.sidecar.map(|meta| meta.url);
+        writer.add_document(document(fields, &entry.path, url.as_deref(), &content))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// The index of one cache root.
+pub struct SearchIndex {
+    index: Index,
+    reader: IndexReader,
+    /// `None` when opened read-only
+    writer: Option<Mutex<IndexWriter>>,
+    fields: Fields,
+}
+
+impl SearchIndex {
+    /// Whether `root` has an index yet.
+    pub fn exists(root: &Path) -> bool {
+        root.join(CACHE_DIR).join("meta.json").is_file()
+    }
+
+    /// Opens the index of `root`. If it's writable and there's no index yet,
+    /// one is built from the cached files first. Only one writable index can
+    /// be open per cache root at a time.
+    pub fn open(root: &Path, writable: bool) -> tantivy::Result<Self> {
+        let dir = root.join(CACHE_DIR);
+        if writable && !Self::exists(root) {
+            Self::build(root)?;
+        }
+        let index = Index::open_in_dir(&dir)?;
+        let fields = schema().1;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        let writer = if writable {
+            Some(Mutex::new(index.writer_with_num_threads(1, WRITER_MEMORY)?))
+        } else {
+            None
+        };
+        Ok(Self {
+            index,
+            reader,
+            writer,
+            fields,
+        })
+    }
+
+    fn build(root: &Path) -> tantivy::Result<()> {
+        let building = root.join(BUILD_DIR);
+        let _ = std::fs::remove_dir_all(&building);
+        std::fs::create_dir_all(&building)?;
+        let (schema, fields) = schema();
+        let index = Index::create_in_dir(&building, schema)?;
+        let mut writer: IndexWriter = index.writer_with_num_threads(1, WRITER_MEMORY)?;
+        add_files(&mut writer, fields, root)?;
+        writer.commit()?;
+        writer.wait_merging_threads()?;
+        drop(index);
+        let dir = root.join(CACHE_DIR);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::rename(&building, &dir)?;
+        Ok(())
+    }
+
+    /// Indexes the cached file at `path`, replacing its earlier version.
+    pub fn update(&self, path: &Path, url: &str, content: &str) -> tantivy::Result<()> {
+        let Some(writer) = &self.writer else {
+            return Ok(());
+        };
+        let mut writer = writer.lock().unwrap();
+        writer.delete_term(Term::from_field_text(
+            self.fields.path,
+            &path.to_string_lossy(),
+        ));
+        writer.add_document(document(self.fields, path, Some(url), content))?;
+        writer.commit()?;
+        self.reader.reload()
+    }
+
+    /// Replaces the index's contents with the cached files under `root`.
+    /// Returns how many were indexed.
+    pub fn rebuild(&self, root: &Path) -> tantivy::Result<usize> {
+        let Some(writer) = &self.writer else {
+            return Ok(0);
+        };
+        let mut writer = writer.lock().unwrap();
+        writer.delete_all_documents()?;
+        let count = add_files(&mut writer, self.fields, root)?;
+        writer.commit()?;
+        self.reader.reload()?;
+        Ok(count)
+    }
+
+    /// Ranks the indexed files for `text` (parsed as `query` for snippets),
+    /// optionally only those on `domain` and its subdomains.
+    pub fn search(
+        &self,
+        text: &str,
+        query: &Query,
+        domain: Option<&str>,
+        limit: usize,
+    ) -> tantivy::Result<Vec<Hit>> {
+        let mut parser = QueryParser::for_index(&self.index, vec![self.fields.body]);
+        // Like the file scan, every term has to match
+        parser.set_conjunction_by_default();
+        let (parsed, _) = parser.parse_query_lenient(text);
+        let parsed = match domain {
+            Some(domain) => {
+                let domain = domain.trim().trim_start_matches('.').to_lowercase();
+                let term = Term::from_field_text(self.fields.domain, &domain);
+                Box::new(BooleanQuery::new(vec![
+                    (Occur::Must, parsed),
+                    (
+                        Occur::Must,
+                        Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+                    ),
+                ]))
+            }
+            None => parsed,
+        };
+
+        let searcher = self.reader.searcher();
+        let mut hits = Vec::new();
+        for (score, address) in searcher.search(&parsed, &TopDocs::with_limit(limit))? {
+            let document: TantivyDocument = searcher.doc(address)?;
+            let text_of = |field| {
+                document
+                    .get_first(field)
+                    .and_then(|value| value.as_str().map(str::to_string))
+            };
+            let Some(path) = text_of(self.fields.path).map(PathBuf::from) else {
+                continue;
+            };
+            // The file may have changed or gone since it was indexed
+            let Ok(content) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            let (line, snippet) = search::best_line(&content, query).unwrap_or_default();
+            hits.push(Hit {
+                path,
+                source_url: text_of(self.fields.url),
+                score,
+                line,
+                snippet,
+                section: search::section_at(&content, line),
+            });
+        }
+        Ok(hits)
+    }
+}
+
+/// A cache root and whether its index is opened for writing.
+type IndexKey = (PathBuf, bool);
+
+/// The indexes of the cache roots used so far, opened on first use.
+#[derive(Default)]
+pub struct Indexes {
+    /// `None` for indexes that couldn't be opened
+    opened: Mutex<HashMap<IndexKey, Option<Arc<SearchIndex>>>>,
+}
+
+impl Indexes {
+    /// The index of `root`, built from the cached files if it's new, or
+    /// `None` if it can't be opened (reported once). Blocks, so call it from
+    /// the blocking pool.
+    pub fn get(&self, root: &Path, writable: bool) -> Option<Arc<SearchIndex>> {
+        let mut opened = self.opened.lock().unwrap();
+        opened
+            .entry((root.to_path_buf(), writable))
+            .or_insert_with(|| {
+                if !writable && !SearchIndex::exists(root) {
+                    return None;
+                }
+                match SearchIndex::open(root, writable) {
+                    Ok(index) => Some(Arc::new(index)),
+                    Err(e) => {
+                        eprintln!(
+                            "warning: search index in {} unavailable ({e}); searching the files instead",
+                            root.display()
+                        );
+                        None
+                    }
+                }
+            })
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_file(root: &Path, name: &str, url: &str, content: &str) -> PathBuf {
+        let path = root.join(name);
+        std::fs::write(&path, content).unwrap();
+        let meta = sidecar::Sidecar {
+            url: url.to_string(),
+            fetched_at: 0,
+            http: None,
+            version: None,
+            sha256: None,
+            sections: Vec::new(),
+        };
+        std::fs::write(
+            sidecar::sidecar_path(&path),
+            serde_json::to_string(&meta).unwrap(),
+        )
+        .unwrap();
+        path
+    }
+
+    fn names(hits: &[Hit]) -> Vec<String> {
+        hits.iter()
+            .map(|hit| hit.path.file_name().unwrap().to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_domains() {
+        assert_eq!(
+            domains("https://api.docs.example.com/x"),
+            ["api.docs.example.com", "docs.example.com", "example.com"]
+        );
+        assert_eq!(domains("http://localhost:8080/"), ["localhost"]);
+    }
+
+    #[test]
+    fn test_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        cache_file(
+            root,
+            "a.md",
+            "https://docs.rs/a",
+            "# Tokio\nspawn a task on the runtime",
+        );
+        cache_file(
+            root,
+            "b.md",
+            "https://example.com/b",
+            "the runtime can spawn tasks",
+        );
+        let indexes = Indexes::default();
+        let index = indexes.get(root, true).unwrap();
+        let search = |text: &str, domain| {
+            let query = Query::parse(text).unwrap();
+            index.search(text, &query, domain, 10).unwrap()
+        };
+
+        assert_eq!(names(&search("spawn runtime", None)).len(), 2);
+        assert_eq!(names(&search(r#""spawn a task""#, None)), ["a.md"]);
+        assert_eq!(names(&search("runtime", Some("example.com"))), ["b.md"]);
+        let hit = &search("tokio", None)[0];
+        assert_eq!(
+            (hit.line, hit.source_url.as_deref()),
+            (1, Some("https://docs.rs/a"))
+        );
+
+        // Writes replace the file's earlier version
+        let path = cache_file(root, "c.md", "https://docs.rs/c", "reqwest client");
+        index
+            .update(&path, "https://docs.rs/c", "reqwest client")
+            .unwrap();
+        index
+            .update(&path, "https://docs.rs/c", "hyper client")
+            .unwrap();
+        assert!(search("reqwest", None).is_empty());
+        assert_eq!(names(&search("hyper", None)), ["c.md"]);
+
+        // A read-only view sees the same index
+        assert_eq!(
+            Indexes::default()
+                .get(root, false)
+                .unwrap()
+                .search("hyper", &Query::parse("hyper").unwrap(), None, 10)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
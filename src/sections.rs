@@ -0,0 +1,215 @@
+//! Per-section hashes of large cached files, to tell what a refresh changed.
+//!
+//! Aggregate documents such as `llms-full.txt` run to megabytes and are
+//! refetched regularly, while a release usually touches a few of their pages.
+//! Their sidecars record a hash of each top-level (`#`) section, so a refresh
+//! can report which sections changed instead of only that the file did, and
+//! a refresh that changed nothing leaves the file alone.
+
+use crate::toc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Write};
+
+/// Files smaller than this aren't worth hashing by section: the agent can
+/// read them again.
+pub const MIN_BYTES: usize = 100 * 1024;
+
+/// Heading recorded for the text before the first section.
+const PREAMBLE: &str = "(preamble)";
+
+/// Section names listed in a summary before the rest are counted.
+const MAX_LISTED: usize = 10;
+
+/// A top-level section of a cached file and the hash of its text.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectionHash {
+    pub heading: String,
+    pub sha256: String,
+}
+
+/// What changed in a file's sections since it was last cached.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Changes {
+    /// Headings of sections whose text changed
+    pub changed: Vec<String>,
+    /// Headings of sections that are new
+    pub added: Vec<String>,
+    /// Headings of sections that are gone
+    pub removed: Vec<String>,
+    /// Number of sections left as they were
+    pub unchanged: usize,
+}
+
+impl Changes {
+    fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl fmt::Display for Changes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "all {} unchanged since the last fetch", self.unchanged);
+        }
+        let total = self.changed.len() + self.unchanged;
+        let mut parts = vec![format!(
+            "{} of {total} changed since the last fetch{}",
+            self.changed.len(),
+            list(&self.changed)
+        )];
+        if !self.added.is_empty() {
+            parts.push(format!("{} added{}", self.added.len(), list(&self.added)));
+        }
+        if !self.removed.is_empty() {
+            parts.push(format!(
+                "{} removed{}",
+                self.removed.len(),
+                list(&self.removed)
+            ));
+        }
+        write!(f, "{}", parts.join("; "))
+    }
+}
+
+/// ` (A, B, …)` naming up to [`MAX_LISTED`] headings, or nothing.
+fn list(headings: &[String]) -> String {
+    if headings.is_empty() {
+        return String::new();
+    }
+    let mut listed = headings[..headings.len().min(MAX_LISTED)].join(", ");
+    if headings.len() > MAX_LISTED {
+        write!(listed, " and {} more", headings.len() - MAX_LISTED).unwrap();
+    }
+    format!(" ({listed})")
+}
+
+/// Hashes of the top-level sections of `markdown`, in order, or none for
+/// files under [`MIN_BYTES`] or with fewer than two sections.
+pub fn hash(markdown: &str) -> Vec<SectionHash> {
+    if markdown.len() < MIN_BYTES {
+        return Vec::new();
+    }
+    let mut starts = toc::extract_headings(markdown)
+        .into_iter()
+        .filter(|heading| heading.level == 1)
+        .map(|heading| {
+            let text = heading.text.trim_start_matches('#').trim().to_string();
+            (heading.line_number, text)
+        })
+        .peekable();
+    let mut sections = Vec::new();
+    let mut heading = PREAMBLE.to_string();
+    let mut text = String::new();
+    for (index, line) in markdown.split_inclusive('\n').enumerate() {
+        if let Some((_, next)) = starts.next_if(|(number, _)| *number == index + 1) {
+            if !text.is_empty() {
+                sections.push(section(heading, &text));
+            }
+            heading = next;
+            text.clear();
+        }
+        text.push_str(line);
+    }
+    sections.push(section(heading, &text));
+    if sections.len() < 2 {
+        return Vec::new();
+    }
+    sections
+}
+
+fn section(heading: String, text: &str) -> SectionHash {
+    SectionHash {
+        heading,
+        sha256: crate::sidecar::sha256(text.as_bytes()),
+    }
+}
+
+/// Compares the sections of a file as cached before (`old`) and now (`new`).
+/// Sections are matched by heading, repeated headings by their order.
+pub fn compare(old: &[SectionHash], new: &[SectionHash]) -> Changes {
+    let key = |sections: &[SectionHash], index: usize| {
+        let heading = &sections[index].heading;
+        let repeat = sections[..index]
+            .iter()
+            .filter(|section| section.heading == *heading)
+            .count();
+        (heading.clone(), repeat)
+    };
+    let old_keys: Vec<_> = (0..old.len()).map(|index| key(old, index)).collect();
+    let new_keys: Vec<_> = (0..new.len()).map(|index| key(new, index)).collect();
+    let mut changes = Changes::default();
+    for (section, new_key) in new.iter().zip(&new_keys) {
+        match old_keys.iter().position(|old_key| old_key == new_key) {
+            Some(index) if old[index].sha256 == section.sha256 => changes.unchanged += 1,
+            Some(_) => changes.changed.push(section.heading.clone()),
+            None => changes.added.push(section.heading.clone()),
+        }
+    }
+    for (section, old_key) in old.iter().zip(&old_keys) {
+        if !new_keys.contains(old_key) {
+            changes.removed.push(section.heading.clone());
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(sections: &[(&str, &str)]) -> String {
+        let filler = "Documentation text. ".repeat(MIN_BYTES / 20 / sections.len() + 1);
+        let mut markdown = "Aggregated docs.\n\n".to_string();
+        for (heading, body) in sections {
+            write!(markdown, "# {heading}\n\n{body}\n\n{filler}\n\n").unwrap();
+        }
+        markdown
+    }
+
+    #[test]
+    fn test_hash() {
+        let markdown = document(&[("Intro", "Welcome."), ("API", "```\n# not a heading\n```")]);
+        let headings: Vec<String> = hash(&markdown).into_iter().map(|s| s.heading).collect();
+        assert_eq!(headings, [PREAMBLE, "Intro", "API"]);
+        assert!(hash("# Small\n\ntext\n\n# File\n").is_empty());
+        let one_section = format!("# Only\n\n{}", "text ".repeat(MIN_BYTES));
+        assert!(hash(&one_section).is_empty());
+    }
+
+    #[test]
+    fn test_compare() {
+        let old = hash(&document(&[
+            ("Intro", "Welcome."),
+            ("API", "v1"),
+            ("FAQ", "None yet."),
+            ("Notes", "a"),
+            ("Notes", "b"),
+        ]));
+        let new = hash(&document(&[
+            ("Intro", "Welcome."),
+            ("API", "v2"),
+            ("Notes", "a"),
+            ("Notes", "c"),
+            ("Changelog", "1.0"),
+        ]));
+        let changes = compare(&old, &new);
+        assert_eq!(
+            changes,
+            Changes {
+                changed: vec!["API".to_string(), "Notes".to_string()],
+                added: vec!["Changelog".to_string()],
+                removed: vec!["FAQ".to_string()],
+                unchanged: 3,
+            }
+        );
+        assert_eq!(
+            changes.to_string(),
+            "2 of 5 changed since the last fetch (API, Notes); 1 added (Changelog); 1 removed (FAQ)"
+        );
+        assert_eq!(
+            compare(&new, &new).to_string(),
+            "all 6 unchanged since the last fetch"
+        );
+    }
+}
@@ -0,0 +1,327 @@
+//! Metadata sidecars stored next to cached files.
+//!
+//! `page.md` gets a `page.md.meta.json` recording where the content came from
+//! and the response headers that later requests may need (validators for
+//! revalidation, language, alternate links). The cached file itself stays
+//! plain Markdown so it can be read with any tool.
+
+use crate::sections;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// Appended to a cached file's name to get its sidecar.
+pub const SIDECAR_SUFFIX: &str = ".meta.json";
+
+/// Name of a cached file inside the directory that took its place, as when
+/// `/a.md` is cached alongside `/a.md/b.md`. URLs never map to it, since every
+/// file name they do is `index` or has an extension.
+pub const DISPLACED_NAME: &str = "~index";
+
+/// Prefix of the temp files atomic writes rename into place.
+const TEMP_PREFIX: &str = "~";
+
+/// Suffix of the temp files atomic writes rename into place. A URL ending in
+/// `.tmp` is cached under that name, so temp files need one pages don't have.
+const TEMP_SUFFIX: &str = ".llms-fetch-tmp";
+
+/// Response headers kept for a cached file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct HttpHeaders {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_language: Option<String>,
+    /// All `Link` headers, joined with `, ` as HTTP allows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    /// URLs that redirected to the response, starting with the one requested
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redirects: Vec<String>,
+}
+
+impl HttpHeaders {
+    pub fn from_response(headers: &reqwest::header::HeaderMap) -> Self {
+        let get = |name: reqwest::header::HeaderName| {
+            let values: Vec<&str> = headers
+                .get_all(name)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .collect();
+            (!values.is_empty()).then(|| values.join(", "))
+        };
+        Self {
+            content_type: get(reqwest::header::CONTENT_TYPE),
+            etag: get(reqwest::header::ETAG),
+            last_modified: get(reqwest::header::LAST_MODIFIED),
+            content_language: get(reqwest::header::CONTENT_LANGUAGE),
+            link: get(reqwest::header::LINK),
+            redirects: Vec::new(),
+        }
+    }
+}
+
+/// Contents of a sidecar file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sidecar {
+    /// URL the cached content was fetched from
+    pub url: String,
+    /// Unix timestamp (seconds) of the fetch
+    pub fetched_at: u64,
+    /// Absent for content that didn't come from a single HTTP response, such
+    /// as pages assembled from the GitHub API
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<HttpHeaders>,
+    /// Docs version named in the URL (`/v2/`, `/en/stable/`), if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Hex SHA-256 of the cached file as written, so later changes to it can
+    /// be detected; absent for files cached before hashes were recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Hashes of the file's top-level sections, for large files
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sections: Vec<sections::SectionHash>,
+}
+
+/// Hex SHA-256 of `content`, as recorded in sidecars.
+pub fn sha256(content: &[u8]) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, content);
+    digest.as_ref().iter().fold(String::new(), |mut hex, byte| {
+        write!(hex, "{byte:02x}").unwrap();
+        hex
+    })
+}
+
+pub fn sidecar_path(file_path: &Path) -> PathBuf {
+    let mut name = file_path.as_os_str().to_os_string();
+    name.push(SIDECAR_SUFFIX);
+    PathBuf::from(name)
+}
+
+pub fn is_sidecar(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.ends_with(SIDECAR_SUFFIX))
+}
+
+/// Where an atomic write of `path` puts its content before renaming it into
+/// place: `page.md` is written as `~page.md.llms-fetch-tmp` next to it.
+pub fn temp_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(TEMP_PREFIX);
+    name.push(path.file_name().unwrap_or_default());
+    name.push(TEMP_SUFFIX);
+    path.with_file_name(name)
+}
+
+/// Whether `path` is a [`temp_path`].
+pub fn is_temp(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with(TEMP_PREFIX) && name.ends_with(TEMP_SUFFIX))
+}
+
+/// Writes the sidecar for `file_path`, atomically like the file itself.
+pub async fn write(file_path: &Path, sidecar: &Sidecar) -> std::io::Result<()> {
+    let path = sidecar_path(file_path);
+    let temp_path = temp_path(&path);
+    tokio::fs::write(&temp_path, serde_json::to_vec(sidecar)?).await?;
+    tokio::fs::rename(&temp_path, &path).await
+}
+
+/// Reads the sidecar for `file_path`, if it has a readable one. Files cached
+/// before sidecars existed have none.
+pub async fn read(file_path: &Path) -> Option<Sidecar> {
+    let json = tokio::fs::read(sidecar_path(file_path)).await.ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+/// A cached file found by [`list`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub sidecar: Option<Sidecar>,
+}
+
+/// Lists the cached files under `root`, sorted by path.
+///
+/// Skips the cache's own files and namespace directories, which are listed
+/// separately; see [`is_document`].
+pub fn list(root: &Path) -> std::io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    if root.is_dir() {
+        walk(root, root, &mut entries)?;
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// Whether `path` is a cached document in the cache rooted at `root`, rather
+/// than one of the cache's own files (dotfiles and dot-directories, the audit
+/// log, temp files and sidecars) or a file in a namespace directory.
+pub fn is_document(root: &Path, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return false;
+    };
+    let mut names = Vec::new();
+    for component in relative.components() {
+        let std::path::Component::Normal(name) = component else {
+            return false;
+        };
+        names.push(name.to_string_lossy());
+    }
+    let Some(first) = names.first() else {
+        return false;
+    };
+    let internal = first.starts_with('@')
+        || names.iter().any(|name| name.starts_with('.'))
+        || (names.len() == 1 && first == crate::audit::AUDIT_FILE)
+        || is_sidecar(path)
+        || is_temp(path);
+    !internal
+}
+
+fn walk(root: &Path, dir: &Path, entries: &mut Vec<Entry>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if !(name.starts_with('.') || (dir == root && name.starts_with('@'))) {
+                walk(root, &path, entries)?;
+            }
+        } else if file_type.is_file() && is_document(root, &path) {
+            let sidecar = std::fs::read(sidecar_path(&path))
+                .ok()
+                .and_then(|json| serde_json::from_slice(&json).ok());
+            entries.push(Entry {
+                bytes: entry.metadata()?.len(),
+                path,
+                sidecar,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headers_from_response() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            "content-type",
+            "text/markdown; charset=utf-8".parse().unwrap(),
+        );
+        headers.insert("etag", "\"abc123\"".parse().unwrap());
+        headers.append(
+            "link",
+            "<https://example.com/de>; rel=alternate".parse().unwrap(),
+        );
+        headers.append(
+            "link",
+            "<https://example.com/fr>; rel=alternate".parse().unwrap(),
+        );
+
+        let http = HttpHeaders::from_response(&headers);
+        assert_eq!(
+            http.content_type.as_deref(),
+            Some("text/markdown; charset=utf-8")
+        );
+        assert_eq!(http.etag.as_deref(), Some("\"abc123\""));
+        assert_eq!(http.last_modified, None);
+        assert_eq!(
+            http.link.as_deref(),
+            Some(
+                "<https://example.com/de>; rel=alternate, <https://example.com/fr>; rel=alternate"
+            )
+        );
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            sha256(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_is_document() {
+        let root = Path::new("/cache");
+        assert!(is_document(root, Path::new("/cache/example.com/page.md")));
+        assert!(is_document(root, Path::new("/cache/example.com/notes.tmp")));
+        assert!(is_document(
+            Path::new("/cache/@ns"),
+            Path::new("/cache/@ns/example.com/page.md")
+        ));
+        for internal in [
+            "/cache/fetch-audit.jsonl",
+            "/cache/.index.json",
+            "/cache/.crawls/example.com/frontier.json",
+            "/cache/example.com/page.md.meta.json",
+            "/cache/example.com/~page.md.llms-fetch-tmp",
+            "/cache/@other/example.com/page.md",
+            "/cache",
+            "/elsewhere/page.md",
+        ] {
+            assert!(!is_document(root, Path::new(internal)), "{internal}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sidecar_round_trip_and_listing() {
+        let dir = tempfile::tempdir().unwrap();
+        let site = dir.path().join("example.com");
+        std::fs::create_dir_all(&site).unwrap();
+        std::fs::create_dir_all(dir.path().join("@other/example.com")).unwrap();
+        std::fs::write(site.join("page.md"), "# Page").unwrap();
+        std::fs::write(site.join("legacy.md"), "# Old").unwrap();
+        std::fs::write(dir.path().join("@other/example.com/page.md"), "# Page").unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "*\n").unwrap();
+        std::fs::write(dir.path().join(crate::audit::AUDIT_FILE), "").unwrap();
+
+        let sidecar = Sidecar {
+            url: "https://example.com/page.md".to_string(),
+            fetched_at: 1_760_572_800,
+            http: Some(HttpHeaders {
+                etag: Some("\"v1\"".to_string()),
+                ..HttpHeaders::default()
+            }),
+            version: None,
+            sha256: Some(sha256(b"# Page")),
+            sections: Vec::new(),
+        };
+        write(&site.join("page.md"), &sidecar).await.unwrap();
+        assert!(site.join("page.md.meta.json").exists());
+        assert_eq!(read(&site.join("page.md")).await, Some(sidecar.clone()));
+        assert_eq!(read(&site.join("legacy.md")).await, None);
+
+        let entries = list(dir.path()).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                Entry {
+                    path: site.join("legacy.md"),
+                    bytes: 5,
+                    sidecar: None,
+                },
+                Entry {
+                    path: site.join("page.md"),
+                    bytes: 6,
+                    sidecar: Some(sidecar),
+                },
+            ]
+        );
+        assert_eq!(list(&dir.path().join("@other")).unwrap().len(), 1);
+    }
+}
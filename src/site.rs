@@ -0,0 +1,113 @@
+//! Keeping automatic follow-ups on the site that was asked for.
+//!
+//! Fetched content can point anywhere: a redirect, an AMP page's canonical
+//! link or a page's `hreflang` alternates may lead to an unrelated host, and a
+//! link injected into a page shouldn't be able to pull the agent there. Under
+//! `--same-site`, URLs the server follows on its own must share the requested
+//! URL's registrable domain (`docs.example.com` and `example.com` do,
+//! `example.org` doesn't) or lie on a domain allowed with `--allow-site`.
+//!
+//! The registrable domain comes from the public suffix list, private domains
+//! included, so `a.github.io` and `b.github.io` are different sites, as are
+//! two projects on `readthedocs.io` or `netlify.app`.
+
+use publicsuffix::Psl;
+use std::sync::LazyLock;
+use url::{Host, Url};
+
+/// The public suffix list, from <https://publicsuffix.org/list/>.
+pub static PUBLIC_SUFFIXES: LazyLock<publicsuffix::List> = LazyLock::new(|| {
+    include_str!("../data/public_suffix_list.dat")
+        .parse()
+        .expect("the bundled public suffix list parses")
+});
+
+/// The registrable domain of the domain name `host`, lowercase:
+/// `docs.example.com` → `example.com`, `www.example.co.uk` →
+/// `example.co.uk`. A host that is itself a public suffix, or a single
+/// label, is its own.
+pub fn registrable_domain(host: &str) -> &str {
+    let host = host.trim_end_matches('.');
+    PUBLIC_SUFFIXES
+        .domain(host.as_bytes())
+        .map_or(host, |domain| &host[host.len() - domain.as_bytes().len()..])
+}
+
+/// The site `url` belongs to: its registrable domain, or its address for IP
+/// hosts.
+fn site(url: &Url) -> Option<String> {
+    match url.host()? {
+        Host::Domain(domain) => Some(registrable_domain(&domain.to_lowercase()).to_string()),
+        address => Some(address.to_string()),
+    }
+}
+
+/// Restriction of automatic follow-ups to the requested site.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SameSite {
+    /// Lowercase domains that may be followed to from anywhere, with their
+    /// subdomains
+    pub allowed: Vec<String>,
+}
+
+impl SameSite {
+    /// Whether `to` may be followed from `from` without the caller asking:
+    /// it's on the same site, or on an allowed domain.
+    pub fn permits(&self, from: &Url, to: &Url) -> bool {
+        if site(from).is_some_and(|from| site(to).as_ref() == Some(&from)) {
+            return true;
+        }
+        let Some(host) = to.host_str().map(str::to_lowercase) else {
+            return false;
+        };
+        self.allowed.iter().any(|domain| {
+            host == *domain
+                || host
+                    .strip_suffix(domain.as_str())
+                    .is_some_and(|rest| rest.ends_with('.'))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registrable_domain() {
+        assert_eq!(registrable_domain("docs.example.com"), "example.com");
+        assert_eq!(registrable_domain("example.com"), "example.com");
+        assert_eq!(registrable_domain("a.b.example.co.uk"), "example.co.uk");
+        assert_eq!(registrable_domain("example.co.uk"), "example.co.uk");
+        assert_eq!(registrable_domain("www.example.de."), "example.de");
+        assert_eq!(registrable_domain("localhost"), "localhost");
+        assert_eq!(registrable_domain("x.co.uk"), "x.co.uk");
+        assert_eq!(registrable_domain("co.uk"), "co.uk");
+        assert_eq!(registrable_domain("docs.a.github.io"), "a.github.io");
+    }
+
+    #[test]
+    fn test_permits() {
+        let same_site = SameSite {
+            allowed: vec!["cdn.example.net".to_string()],
+        };
+        let url = |s: &str| Url::parse(s).unwrap();
+        let from = url("https://docs.example.com/guide");
+        assert!(same_site.permits(&from, &url("https://example.com/")));
+        assert!(same_site.permits(&from, &url("http://api.example.com/v1")));
+        assert!(!same_site.permits(&from, &url("https://example.org/")));
+        assert!(!same_site.permits(&from, &url("https://notexample.com/")));
+        assert!(same_site.permits(&from, &url("https://cdn.example.net/a")));
+        assert!(same_site.permits(&from, &url("https://eu.cdn.example.net/a")));
+        assert!(!same_site.permits(&from, &url("https://example.net/")));
+        let pages = url("https://a.github.io/docs/");
+        assert!(same_site.permits(&pages, &url("https://a.github.io/other")));
+        assert!(!same_site.permits(&pages, &url("https://b.github.io/")));
+        let uk = url("https://x.co.uk/");
+        assert!(same_site.permits(&uk, &url("https://www.x.co.uk/")));
+        assert!(!same_site.permits(&uk, &url("https://y.co.uk/")));
+        let ip = url("http://127.0.0.1:8000/");
+        assert!(same_site.permits(&ip, &url("http://127.0.0.1:9000/")));
+        assert!(!same_site.permits(&ip, &url("http://127.0.0.2/")));
+    }
+}
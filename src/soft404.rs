@@ -0,0 +1,182 @@
+//! Soft-404 detection.
+//!
+//! Some servers and CDNs answer missing pages, or every page during
+//! maintenance, with a "Not found" or "Down for maintenance" page and status
+//! 200. Cached as if it were the docs, that page would hide the fact that
+//! there's nothing there, so such variations count as failures instead.
+
+/// Pages at least this long are taken for real content even if they mention
+/// a missing page: error pages are a sentence or two plus navigation.
+const MAX_ERROR_PAGE_WORDS: usize = 150;
+
+/// Phrases of error pages, lowercase. Matched in the title and first heading
+/// when they make it up, and in the text of very short pages. A bare `404` is matched
+/// separately, by [`says_404`].
+const PHRASES: [&str; 13] = [
+    "not found",
+    "page not found",
+    "page cannot be found",
+    "page could not be found",
+    "page doesn't exist",
+    "page does not exist",
+    "no longer exists",
+    "nothing here",
+    "under maintenance",
+    "down for maintenance",
+    "temporarily unavailable",
+    "service unavailable",
+    "scheduled maintenance",
+];
+
+/// Below this many words, the phrases anywhere in the text are enough.
+const MAX_STUB_WORDS: usize = 40;
+
+/// Whether `text`, lowercase, is `404` alone or has it as a word next to
+/// "error"; "404 not found" is already a phrase. Elsewhere the number is more
+/// likely a topic, as in "HTTP 404 handling", or part of a version such as
+/// `v2.404.0`.
+fn says_404(text: &str) -> bool {
+    let words: Vec<&str> = text
+        .split(|c: char| !(c.is_alphanumeric() || c == '.'))
+        .map(|word| word.trim_matches('.'))
+        .filter(|word| !word.is_empty())
+        .collect();
+    words.iter().enumerate().any(|(i, word)| {
+        *word == "404"
+            && (words.len() == 1
+                || (i > 0 && words[i - 1] == "error")
+                || words.get(i + 1) == Some(&"error"))
+    })
+}
+
+/// The error page phrase `text`, lowercase, contains, if any.
+fn find_phrase(text: &str) -> Option<&'static str> {
+    PHRASES
+        .iter()
+        .find(|phrase| text.contains(*phrase))
+        .copied()
+        .or_else(|| says_404(text).then_some("404"))
+}
+
+/// The error page phrase a title or heading, lowercase, is made of, if any:
+/// one of its parts between separators such as ` | ` starts with the phrase,
+/// or the phrase is most of its words. Titles that only mention one, such as
+/// "Error: module not found" or "503 Service Unavailable", are topics.
+fn title_phrase(text: &str) -> Option<&'static str> {
+    text.split(['|', ':', '·', '-', '–', '—'])
+        .find_map(|part| {
+            let words: Vec<&str> = part
+                .split_whitespace()
+                .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric() && c != '\''))
+                .filter(|word| !word.is_empty())
+                .collect();
+            let padded = format!(" {} ", words.join(" "));
+            PHRASES.iter().copied().find(|phrase| {
+                let phrase_words = phrase.split(' ').count();
+                let padded_phrase = format!(" {phrase} ");
+                padded.starts_with(&padded_phrase)
+                    || (padded.contains(&padded_phrase) && 4 * phrase_words >= 3 * words.len())
+            })
+        })
+        .or_else(|| says_404(text).then_some("404"))
+}
+
+/// The first Markdown heading of `markdown`, lowercase, or `None`.
+fn first_heading(markdown: &str) -> Option<String> {
+    crate::toc::extract_headings(markdown)
+        .first()
+        .map(|heading| heading.text.trim_matches('#').trim().to_lowercase())
+}
+
+/// Returns why a fetched variation looks like an error page served with
+/// status 200, given its HTML (if it was HTML) and the Markdown it converted
+/// to, or `None` if it looks like real content.
+pub fn detect(html: Option<&str>, markdown: &str) -> Option<String> {
+    let words = markdown.split_whitespace().count();
+    if words >= MAX_ERROR_PAGE_WORDS {
+        return None;
+    }
+    if words == 0 {
+        return Some("the page is empty".to_string());
+    }
+
+    let title = html.and_then(|html| {
+        let document = dom_query::Document::from(html);
+        let title = document.select("title").text().trim().to_lowercase();
+        (!title.is_empty()).then_some(title)
+    });
+    let labels = [("title", title), ("first heading", first_heading(markdown))];
+    for (label, text) in labels {
+        if let Some(text) = text
+            && let Some(phrase) = title_phrase(&text)
+        {
+            return Some(format!("its {label} says \"{phrase}\""));
+        }
+    }
+
+    let text = markdown.to_lowercase();
+    if words < MAX_STUB_WORDS
+        && let Some(phrase) = find_phrase(&text)
+    {
+        return Some(format!("it has only {words} words and says \"{phrase}\""));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_pages() {
+        let html =
+            "<html><head><title>Page Not Found | Example Docs</title></head><body></body></html>";
+        assert_eq!(
+            detect(Some(html), "Sorry, we looked everywhere.\n\n[Home](/)").as_deref(),
+            Some("its title says \"page not found\"")
+        );
+        assert_eq!(
+            detect(None, "# 404\n\nThis page could not be found.").as_deref(),
+            Some("its first heading says \"404\"")
+        );
+        let html = "<html><head><title>Error 404 | Example</title></head></html>";
+        assert_eq!(
+            detect(Some(html), "Try the search.").as_deref(),
+            Some("its title says \"404\"")
+        );
+        assert_eq!(
+            detect(None, "We are down for maintenance. Back soon!").as_deref(),
+            Some("it has only 7 words and says \"down for maintenance\"")
+        );
+        assert_eq!(detect(None, " \n").as_deref(), Some("the page is empty"));
+    }
+
+    #[test]
+    fn test_real_pages() {
+        let html = "<html><head><title>Handling 404 errors</title></head></html>";
+        let long = "When a route is not found the router responds with a 404 page. ".repeat(20);
+        assert_eq!(detect(Some(html), &long), None);
+        assert_eq!(
+            detect(None, "# Install\n\nRun `cargo add tokio` and you're done."),
+            None
+        );
+        // 404 as a topic or in a version number
+        let html = "<html><head><title>HTTP 404 handling</title></head></html>";
+        assert_eq!(detect(Some(html), "Return a custom page."), None);
+        assert_eq!(detect(None, "# Release v2.404.0\n\nBug fixes."), None);
+        // Short pages mention errors without being one
+        let mentions = "# Errors\n\nLookups return `None` when the key is not found, and the caller decides what to do next. The map never panics on a missing key, so there's nothing to catch and no error type to match on here.";
+        assert_eq!(detect(None, mentions), None);
+        // Titles naming an error as their topic
+        let advice = "Retry the request after the delay the Retry-After header gives. ".repeat(5);
+        let html = "<html><head><title>Error: module not found</title></head></html>";
+        assert_eq!(detect(Some(html), &advice), None);
+        let heading = format!("# 503 Service Unavailable\n\n{advice}");
+        assert_eq!(detect(None, &heading), None);
+        let html = "<html><head><title>Docs | Oops! Page not found</title></head></html>";
+        assert_eq!(
+            detect(Some(html), &advice).as_deref(),
+            Some("its title says \"page not found\"")
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! Per-session usage statistics, reported by the `session_stats` tool.
+//!
+//! Counts live in memory for the lifetime of the server process and are never
+//! written to disk or sent anywhere. Unlike the quota's usage they aren't
+//! cleared by `reset_quota`.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// What happened so far in this session.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Counts {
+    /// Tool name → calls
+    pub calls: BTreeMap<String, u64>,
+    /// Host → URLs of it passed to `fetch`
+    pub domains: BTreeMap<String, u64>,
+    /// Fetches served from the cache without a request
+    pub cache_hits: u64,
+    /// Fetches that went to the network
+    pub cache_misses: u64,
+}
+
+impl Counts {
+    /// Share of fetches served from the cache; `None` before the first fetch.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        let fetches = self.cache_hits + self.cache_misses;
+        (fetches > 0).then(|| self.cache_hits as f64 / fetches as f64)
+    }
+}
+
+/// Session counters, updated as tools are called.
+#[derive(Debug)]
+pub struct Stats {
+    started: Instant,
+    counts: Mutex<Counts>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            counts: Mutex::default(),
+        }
+    }
+}
+
+impl Stats {
+    pub fn record_call(&self, tool: &str) {
+        let mut counts = self.counts.lock().unwrap();
+        *counts.calls.entry(tool.to_string()).or_default() += 1;
+    }
+
+    /// Counts a fetch of `url`, an `http(s)`, `ftp`, `gemini` or `gopher` URL,
+    /// as a cache hit or miss. Local files and `data:` URLs aren't counted.
+    pub fn record_fetch(&self, url: &str, from_cache: bool) {
+        let mut counts = self.counts.lock().unwrap();
+        if let Some(host) = url::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+        {
+            *counts.domains.entry(host).or_default() += 1;
+        }
+        if from_cache {
+            counts.cache_hits += 1;
+        } else {
+            counts.cache_misses += 1;
+        }
+    }
+
+    pub fn uptime_secs(&self) -> u64 {
+        self.started.elapsed().as_secs()
+    }
+
+    pub fn get(&self) -> Counts {
+        self.counts.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let stats = Stats::default();
+        assert_eq!(stats.get().cache_hit_rate(), None);
+
+        stats.record_call("fetch");
+        stats.record_call("fetch");
+        stats.record_call("grep");
+        stats.record_fetch("https://docs.example.com/guide", false);
+        stats.record_fetch("https://docs.example.com/api", true);
+        stats.record_fetch("https://DOCS.example.com/api", true);
+        stats.record_fetch("gemini://capsule.example/", false);
+
+        let counts = stats.get();
+        assert_eq!(
+            counts.calls,
+            BTreeMap::from([("fetch".to_string(), 2), ("grep".to_string(), 1)])
+        );
+        assert_eq!(
+            counts.domains,
+            BTreeMap::from([
+                ("capsule.example".to_string(), 1),
+                ("docs.example.com".to_string(), 3)
+            ])
+        );
+        assert_eq!(counts.cache_hits, 2);
+        assert_eq!(counts.cache_misses, 2);
+        assert_eq!(counts.cache_hit_rate(), Some(0.5));
+    }
+}
@@ -0,0 +1,151 @@
+//! Suggestions for URLs that don't exist.
+//!
+//! Docs move, and agents often guess URLs from memory or from an outdated
+//! link. When a page is gone but the site has a sitemap, the sitemap's pages
+//! with the most similar paths are usually what was meant, so the failure
+//! names them and the next call can fetch the right one.
+
+use url::Url;
+
+/// Suggestions returned at most.
+pub const MAX_SUGGESTIONS: usize = 5;
+
+/// Candidates scoring lower aren't suggested: about one shared word, or a
+/// last segment that is mostly the same.
+const MIN_SCORE: f64 = 1.0;
+
+/// Lowercase words of a path: `/docs/Getting_Started.html` → `docs`,
+/// `getting`, `started`, `html`.
+fn words(path: &str) -> Vec<String> {
+    path.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// The last non-empty segment of `path`, lowercase, without an extension.
+fn last_segment(path: &str) -> String {
+    let last = path.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+    let stem = last.rsplit_once('.').map_or(last, |(stem, _)| stem);
+    stem.to_lowercase()
+}
+
+/// Whether two words are the same word, give or take a suffix (`guide` and
+/// `guides`, `install` and `installation`).
+fn same_word(a: &str, b: &str) -> bool {
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    short == long || (short.len() >= 3 && long.starts_with(short))
+}
+
+/// Levenshtein distance between `a` and `b`, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// How similar the path of `candidate` is to `wanted`: shared leading
+/// segments, shared words, and how alike the last segments are.
+#[allow(clippy::cast_precision_loss)]
+fn score(wanted: &str, candidate: &str) -> f64 {
+    let leading = wanted
+        .split('/')
+        .zip(candidate.split('/'))
+        .skip(1)
+        .take_while(|(a, b)| !a.is_empty() && a.eq_ignore_ascii_case(b))
+        .count();
+    let candidate_words = words(candidate);
+    let mut wanted_words = words(wanted);
+    wanted_words.sort();
+    wanted_words.dedup();
+    let shared = wanted_words
+        .iter()
+        .filter(|word| candidate_words.iter().any(|other| same_word(word, other)))
+        .count();
+    let (a, b) = (last_segment(wanted), last_segment(candidate));
+    let longest = a.chars().count().max(b.chars().count());
+    let alike = if longest == 0 {
+        0.0
+    } else {
+        1.0 - edit_distance(&a, &b) as f64 / longest as f64
+    };
+    0.5 * leading as f64 + shared as f64 + 2.0 * alike
+}
+
+/// The URLs among `locs` on `url`'s host whose paths are closest to `url`'s,
+/// best first, at most [`MAX_SUGGESTIONS`].
+pub fn closest(url: &Url, locs: &[String]) -> Vec<String> {
+    let wanted = url.path();
+    let mut scored: Vec<(f64, &String)> = locs
+        .iter()
+        .filter_map(|loc| {
+            let parsed = Url::parse(loc).ok()?;
+            if parsed.host_str() != url.host_str() || parsed.path() == wanted {
+                return None;
+            }
+            let score = score(wanted, parsed.path());
+            (score >= MIN_SCORE).then_some((score, loc))
+        })
+        .collect();
+    scored.sort_by(|(a, a_loc), (b, b_loc)| {
+        b.total_cmp(a)
+            .then_with(|| a_loc.len().cmp(&b_loc.len()))
+            .then_with(|| a_loc.cmp(b_loc))
+    });
+    let mut suggestions: Vec<String> = Vec::new();
+    for (_, loc) in scored {
+        if !suggestions.contains(loc) {
+            suggestions.push(loc.clone());
+            if suggestions.len() == MAX_SUGGESTIONS {
+                break;
+            }
+        }
+    }
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("install", "instal"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_closest() {
+        let locs: Vec<String> = [
+            "https://docs.example.com/",
+            "https://docs.example.com/guide/installation",
+            "https://docs.example.com/guide/configuration",
+            "https://docs.example.com/reference/install-cli",
+            "https://docs.example.com/blog/2024/release",
+            "https://other.com/guide/install",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+        let url = Url::parse("https://docs.example.com/guides/install").unwrap();
+        assert_eq!(
+            closest(&url, &locs),
+            [
+                "https://docs.example.com/guide/installation",
+                "https://docs.example.com/reference/install-cli",
+                "https://docs.example.com/guide/configuration",
+            ]
+        );
+        let unrelated = Url::parse("https://docs.example.com/zzz").unwrap();
+        assert!(closest(&unrelated, &locs).is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+//! Tabbed widgets (code groups), flattened so every tab survives conversion.
+//!
+//! Docs show one snippet per package manager or language behind tabs:
+//! Docusaurus and Starlight with ARIA `tab`s and `tabpanel`s, `MkDocs`
+//! Material with a `.tabbed-set` of radio `label`s and blocks, Sphinx with
+//! `sphinx-tabs` or `sphinx-design`'s `.sd-tab-set`, and `VitePress` with a
+//! `.vp-code-group`. Converted as they are, readability keeps only the
+//! visible tab or the labels run together above all the panels, so no
+//! snippet can be told apart. Each set is rewritten before conversion as its
+//! panels in order, each after its tab's label as a bold line. Bold rather
+//! than a heading, so the tabs don't show up in the table of contents.
+
+use std::borrow::Cow;
+use std::fmt::Write;
+
+/// Elements taken for tab sets.
+const SELECTOR: &str =
+    ".tabs-container, starlight-tabs, .tabbed-set, .sphinx-tabs, .sd-tab-set, .vp-code-group";
+
+/// For each kind of tab set, its container, and its labels and panels
+/// within it. A container is flattened by the first entry it matches with
+/// as many labels as panels.
+const WIDGETS: [(&str, &str, &str); 7] = [
+    // Docusaurus
+    (".tabs-container", "[role='tab']", "[role='tabpanel']"),
+    // Starlight
+    ("starlight-tabs", "[role='tab']", "[role='tabpanel']"),
+    // MkDocs Material
+    (".tabbed-set", ".tabbed-labels > label", ".tabbed-block"),
+    // MkDocs Material without `alternate_style`
+    (".tabbed-set", "label", ".tabbed-content"),
+    (".sphinx-tabs", "[role='tab']", "[role='tabpanel']"),
+    (".sd-tab-set", "label.sd-tab-label", ".sd-tab-content"),
+    // VitePress
+    (".vp-code-group", ".tabs label", ".blocks > div"),
+];
+
+/// `html` with its tab sets rewritten as each tab's label and panel in turn.
+pub fn prepare(html: &str) -> Cow<'_, str> {
+    let document = dom_query::Document::from(html);
+    let sets: Vec<_> = document.select(SELECTOR).iter().collect();
+    if sets.is_empty() {
+        return Cow::Borrowed(html);
+    }
+    let mut changed = false;
+    // Innermost first, so an outer set takes in the inner one's flattened tabs
+    for set in sets.into_iter().rev() {
+        let Some((labels, panels)) = WIDGETS.iter().find_map(|(container, label, panel)| {
+            if !set.is(container) {
+                return None;
+            }
+            let labels: Vec<_> = set.select(label).iter().collect();
+            let panels: Vec<_> = set.select(panel).iter().collect();
+            (!labels.is_empty() && labels.len() == panels.len()).then_some((labels, panels))
+        }) else {
+            continue;
+        };
+        let mut flat = String::new();
+        for (label, panel) in labels.iter().zip(&panels) {
+            let text = label
+                .text()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            if !text.is_empty() {
+                let text = html_escape::encode_text(&text);
+                write!(flat, "<p><strong>{text}</strong></p>").unwrap();
+            }
+            flat.push_str(&panel.inner_html());
+        }
+        set.replace_with_html(flat);
+        changed = true;
+    }
+    if changed {
+        Cow::Owned(document.html().to_string())
+    } else {
+        Cow::Borrowed(html)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(html: &str) -> String {
+        html2md::parse_html(&prepare(html))
+    }
+
+    #[test]
+    fn test_prepare() {
+        // Docusaurus, with hidden panels
+        let markdown = convert(
+            r#"<div class="tabs-container"><ul role="tablist" class="tabs">
+               <li role="tab" class="tabs__item">npm</li><li role="tab" class="tabs__item">Yarn</li></ul>
+               <div class="margin-top--md">
+               <div role="tabpanel"><pre><code>npm install foo</code></pre></div>
+               <div role="tabpanel" hidden><pre><code>yarn add foo</code></pre></div></div></div>"#,
+        );
+        let npm = markdown.find("**npm**").expect(&markdown);
+        let npm_code = markdown.find("npm install foo").expect(&markdown);
+        let yarn = markdown.find("**Yarn**").expect(&markdown);
+        let yarn_code = markdown.find("yarn add foo").expect(&markdown);
+        assert!(
+            npm < npm_code && npm_code < yarn && yarn < yarn_code,
+            "{markdown:?}"
+        );
+
+        // MkDocs Material
+        let markdown = convert(
+            r#"<div class="tabbed-set tabbed-alternate" data-tabs="1:2">
+               <input checked id="__tabbed_1_1" name="__tabbed_1" type="radio">
+               <input id="__tabbed_1_2" name="__tabbed_1" type="radio">
+               <div class="tabbed-labels"><label for="__tabbed_1_1">Python</label>
+               <label for="__tabbed_1_2">JavaScript</label></div>
+               <div class="tabbed-content"><div class="tabbed-block"><p>import foo</p></div>
+               <div class="tabbed-block"><p>require("foo")</p></div></div></div>"#,
+        );
+        assert!(markdown.contains("**Python**"), "{markdown:?}");
+        assert!(
+            markdown.find("**JavaScript**") < markdown.find("require(\"foo\")"),
+            "{markdown:?}"
+        );
+
+        // Nested, in the old MkDocs Material markup
+        let markdown = convert(
+            r#"<div class="tabbed-set"><input type="radio"><label>Linux</label>
+               <div class="tabbed-content"><div class="sd-tab-set">
+               <input type="radio"><label class="sd-tab-label">x86</label>
+               <div class="sd-tab-content"><p>amd64</p></div></div></div>
+               <input type="radio"><label>macOS</label>
+               <div class="tabbed-content"><p>brew</p></div></div>"#,
+        );
+        for text in ["**Linux**", "**x86**", "amd64", "**macOS**", "brew"] {
+            assert!(markdown.contains(text), "{text} in {markdown:?}");
+        }
+
+        // Labels and panels that don't pair up are left alone
+        let unpaired = r#"<div class="sphinx-tabs"><button role="tab">A</button></div>"#;
+        assert!(matches!(prepare(unpaired), Cow::Borrowed(_)));
+        let plain = "<p>No tabs.</p>";
+        assert!(matches!(prepare(plain), Cow::Borrowed(_)));
+    }
+}
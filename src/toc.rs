@@ -1,19 +1,77 @@
 //! Table of Contents generation for markdown documents.
 //!
-//! Extracts headings with line numbers, preserving original markdown syntax except
-//! empty anchor links. Adaptively selects heading depth to fit within budget.
+//! Extracts headings with line numbers and character offsets, preserving original
+//! markdown syntax except empty anchor links (or, in the clean text mode, only
+//! the text and code spans). Adaptively selects heading depth to
+//! fit within budget. The annotated style also follows each heading with the
+//! first sentence of its section.
 
 use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 pub const DEFAULT_TOC_BUDGET: usize = 4000;
 pub const DEFAULT_TOC_THRESHOLD: usize = 8000;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// Widest snippet under an annotated `ToC` entry, in terminal columns, so
+/// wide (CJK) characters count double.
+pub const MAX_SNIPPET_WIDTH: usize = 100;
+
+/// Separator between an entry's line number and its heading.
+pub const DEFAULT_TOC_SEPARATOR: &str = "→";
+
+/// Longest bold line [`promote_bold_headings`] takes for a heading.
+pub const MAX_BOLD_HEADING_CHARS: usize = 80;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct TocConfig {
     /// Maximum `ToC` size in bytes. Algorithm selects deepest heading level that fits.
     pub toc_budget: usize,
     /// Minimum document size to generate `ToC`. Smaller docs return `None`.
     pub full_content_threshold: usize,
+    /// Append each heading's character offset to its entry, as `@{offset}`.
+    pub offsets: bool,
+    /// Whether entries are followed by a snippet of their section.
+    pub style: TocStyle,
+    /// Put between each line number and heading, like `→` or `\t`.
+    pub separator: String,
+    /// Whether line numbers are padded to a common width.
+    pub padding: TocPadding,
+    /// Whether headings keep their markdown syntax.
+    pub text: TocText,
+}
+
+/// Heading text of `ToC` entries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TocText {
+    /// As written, hashes and inline markdown included
+    #[default]
+    Raw,
+    /// Without hashes and inline markdown, keeping code spans
+    Clean,
+}
+
+/// Padding of `ToC` line numbers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TocPadding {
+    /// Right-aligned with spaces, so headings start in one column
+    #[default]
+    Aligned,
+    /// Not padded, saving budget
+    None,
+}
+
+/// How much each `ToC` entry shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TocStyle {
+    /// Headings only
+    #[default]
+    Plain,
+    /// Headings, each followed by the first sentence of its section when the
+    /// budget allows
+    Annotated,
 }
 
 impl Default for TocConfig {
@@ -21,6 +79,11 @@ impl Default for TocConfig {
         Self {
             toc_budget: DEFAULT_TOC_BUDGET,
             full_content_threshold: DEFAULT_TOC_THRESHOLD,
+            offsets: false,
+            style: TocStyle::Plain,
+            separator: DEFAULT_TOC_SEPARATOR.to_string(),
+            padding: TocPadding::Aligned,
+            text: TocText::Raw,
         }
     }
 }
@@ -34,8 +97,14 @@ pub struct Heading {
     pub level: u8,
     /// Line number where heading appears (1-indexed)
     pub line_number: usize,
+    /// Characters before the heading in the document (0-indexed). Unlike line
+    /// numbers, it doesn't depend on how line endings are counted.
+    pub offset: usize,
     /// Heading text with formatting preserved
     pub text: String,
+    /// First sentence of the section's first paragraph as plain text, cut to
+    /// [`MAX_SNIPPET_WIDTH`]
+    pub snippet: Option<String>,
 }
 
 /// Check if text is empty or contains only whitespace/invisible/permalink characters.
@@ -54,15 +123,53 @@ fn is_empty_or_invisible(text: &str) -> bool {
     })
 }
 
-/// Extracts headings with line numbers, filtering out empty anchor links.
+/// `text` without a trailing `{#id}`, the page's own id kept for the heading.
+fn strip_id(text: &str) -> &str {
+    text.strip_suffix('}')
+        .and_then(|rest| rest.rfind("{#").map(|start| (start, &rest[start + 2..])))
+        .filter(|(_, id)| !id.is_empty() && !id.contains(char::is_whitespace))
+        .map_or(text, |(start, _)| text[..start].trim_end())
+}
+
+/// First sentence of `paragraph` with whitespace collapsed, cut to
+/// [`MAX_SNIPPET_WIDTH`].
+fn first_sentence(paragraph: &str) -> Option<String> {
+    let text = paragraph.split_whitespace().collect::<Vec<_>>().join(" ");
+    let end = text
+        .match_indices(['.', '!', '?'])
+        .map(|(i, _)| i + 1)
+        .find(|&i| text[i..].starts_with(' '))
+        .unwrap_or(text.len());
+    let sentence = &text[..end];
+    if sentence.is_empty() {
+        return None;
+    }
+    if sentence.width() <= MAX_SNIPPET_WIDTH {
+        return Some(sentence.to_string());
+    }
+    let mut cut = String::new();
+    let mut width = 0;
+    for c in sentence.chars() {
+        width += c.width().unwrap_or(0);
+        if width >= MAX_SNIPPET_WIDTH {
+            break;
+        }
+        cut.push(c);
+    }
+    Some(format!("{}…", cut.trim_end()))
+}
+
+/// Extracts headings with line numbers, offsets and snippets, filtering out
+/// empty anchor links.
 #[allow(clippy::too_many_lines)]
-fn extract_headings(markdown: &str) -> Vec<Heading> {
+pub fn extract_headings(markdown: &str) -> Vec<Heading> {
     use std::ops::Range;
 
     struct HeadingState {
         level: HeadingLevel,
         start: usize,
         line_number: usize,
+        offset: usize,
         empty_link_ranges: Vec<Range<usize>>,
         current_link: Option<LinkState>,
     }
@@ -72,29 +179,39 @@ fn extract_headings(markdown: &str) -> Vec<Heading> {
         text_content: String,
     }
 
-    let mut headings = Vec::new();
+    let mut headings: Vec<Heading> = Vec::new();
     let mut current_heading: Option<HeadingState> = None;
+    // The last heading while its section hasn't had a paragraph yet, and the
+    // text of the paragraph being read for its snippet
+    let mut awaiting_snippet: Option<usize> = None;
+    let mut paragraph: Option<String> = None;
 
-    // Track line number incrementally to avoid O(n*h) rescanning
+    // Track line number and character offset incrementally to avoid O(n*h)
+    // rescanning
     let mut current_line = 1;
+    let mut current_offset = 0;
     let mut last_pos = 0;
 
     for (event, range) in Parser::new_ext(markdown, Options::all()).into_offset_iter() {
-        // Update line number, handling overlapping/backward ranges
+        // Update position, handling overlapping/backward ranges
         if range.start > last_pos {
-            current_line += markdown[last_pos..range.start]
-                .chars()
-                .filter(|&c| c == '\n')
-                .count();
+            for c in markdown[last_pos..range.start].chars() {
+                current_offset += 1;
+                if c == '\n' {
+                    current_line += 1;
+                }
+            }
         }
         last_pos = last_pos.max(range.start);
 
         match event {
             Event::Start(Tag::Heading { level, .. }) => {
+                awaiting_snippet = None;
                 current_heading = Some(HeadingState {
                     level,
                     start: range.start,
                     line_number: current_line,
+                    offset: current_offset,
                     empty_link_ranges: Vec::new(),
                     current_link: None,
                 });
@@ -107,6 +224,9 @@ fn extract_headings(markdown: &str) -> Vec<Heading> {
                     });
                 }
             }
+            Event::Start(Tag::Paragraph) if awaiting_snippet.is_some() => {
+                paragraph = Some(String::new());
+            }
             Event::Text(text) | Event::Code(text) => {
                 // Collect text content from current link
                 if let Some(heading) = &mut current_heading
@@ -114,6 +234,23 @@ fn extract_headings(markdown: &str) -> Vec<Heading> {
                 {
                     link.text_content.push_str(&text);
                 }
+                if let Some(paragraph) = &mut paragraph {
+                    paragraph.push_str(&text);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some(paragraph) = &mut paragraph {
+                    paragraph.push(' ');
+                }
+            }
+            Event::End(TagEnd::Paragraph) => {
+                if let Some(text) = paragraph.take()
+                    && let Some(index) = awaiting_snippet
+                    && let Some(snippet) = first_sentence(&text)
+                {
+                    headings[index].snippet = Some(snippet);
+                    awaiting_snippet = None;
+                }
             }
             Event::End(TagEnd::Link) => {
                 if let Some(heading) = &mut current_heading
@@ -174,6 +311,7 @@ fn extract_headings(markdown: &str) -> Vec<Heading> {
                     } else {
                         text
                     };
+                    let text = strip_id(text);
 
                     // Collapse consecutive spaces
                     let mut result = String::with_capacity(text.len());
@@ -207,8 +345,11 @@ fn extract_headings(markdown: &str) -> Vec<Heading> {
                         headings.push(Heading {
                             level: level_num,
                             line_number: heading.line_number,
+                            offset: heading.offset,
                             text: text.clone(),
+                            snippet: None,
                         });
+                        awaiting_snippet = Some(headings.len() - 1);
                     }
                 }
             }
@@ -220,7 +361,11 @@ fn extract_headings(markdown: &str) -> Vec<Heading> {
 }
 
 /// Returns deepest heading level that fits within budget, with rendered `ToC`.
-fn find_optimal_level(headings: &[Heading], budget: usize) -> Option<(u8, String)> {
+fn find_optimal_level(
+    headings: &[Heading],
+    config: &TocConfig,
+    annotated: bool,
+) -> Option<(u8, String)> {
     if headings.is_empty() {
         return None;
     }
@@ -229,13 +374,13 @@ fn find_optimal_level(headings: &[Heading], budget: usize) -> Option<(u8, String
 
     let mut best: Option<(u8, String)> = None;
     for level in 1..=max_level {
-        let rendered = render_toc(headings, level);
+        let rendered = render_toc(headings, level, config, annotated);
         if rendered.is_empty() {
             continue; // Skip levels with no headings
         }
 
         let byte_size = rendered.len();
-        if byte_size <= budget {
+        if byte_size <= config.toc_budget {
             best = Some((level, rendered));
         }
         // Don't break early - size may not increase monotonically
@@ -244,7 +389,21 @@ fn find_optimal_level(headings: &[Heading], budget: usize) -> Option<(u8, String
     best
 }
 
-fn render_toc(headings: &[Heading], max_level: u8) -> String {
+/// Whitespace as wide as `text` on screen, keeping its tabs.
+fn blank(text: &str) -> String {
+    text.chars()
+        .flat_map(|c| {
+            let (fill, count) = if c == '\t' {
+                ('\t', 1)
+            } else {
+                (' ', c.width().unwrap_or(0))
+            };
+            std::iter::repeat_n(fill, count)
+        })
+        .collect()
+}
+
+fn render_toc(headings: &[Heading], max_level: u8, config: &TocConfig, annotated: bool) -> String {
     use std::fmt::Write;
 
     let filtered: Vec<_> = headings.iter().filter(|h| h.level <= max_level).collect();
@@ -261,7 +420,9 @@ fn render_toc(headings: &[Heading], max_level: u8) -> String {
         clippy::cast_possible_truncation,
         clippy::cast_sign_loss
     )]
-    let width = if max_line_num < 100 {
+    let width = if config.padding == TocPadding::None {
+        0
+    } else if max_line_num < 100 {
         3
     } else if max_line_num < 1000 {
         4
@@ -279,29 +440,162 @@ fn render_toc(headings: &[Heading], max_level: u8) -> String {
         if i > 0 {
             result.push('\n');
         }
-        write!(result, "{:>width$}→{}", h.line_number, h.text).unwrap();
+        let start = result.len();
+        write!(result, "{:>width$}{}", h.line_number, config.separator).unwrap();
+        let prefix_end = result.len();
+        result.push_str(&h.text);
+        if config.offsets {
+            write!(result, " @{}", h.offset).unwrap();
+        }
+        if annotated && let Some(snippet) = &h.snippet {
+            // Line the snippet up under the heading text
+            let indent = blank(&result[start..prefix_end]);
+            write!(result, "\n{indent}{snippet}").unwrap();
+        }
     }
 
     result
 }
 
-/// Generates `ToC` with format `{line_number}→{heading_text}` per line.
+/// `## Install [`tokio`](url) *now* ##` → ``Install `tokio` now``: the heading
+/// text without hashes and inline markdown, except code spans. Falls back to
+/// `heading` when nothing is left.
+pub fn clean_text(heading: &str) -> String {
+    let text = heading.trim().trim_start_matches('#');
+    // Closing hashes of ATX headings need a space before them
+    let trimmed = text.trim_end_matches('#');
+    let text = if trimmed.len() < text.len() && trimmed.ends_with([' ', '\t']) {
+        trimmed
+    } else {
+        text
+    };
+
+    let mut clean = String::new();
+    for event in Parser::new_ext(text.trim(), Options::all()) {
+        match event {
+            Event::Text(text) => clean.push_str(&text),
+            Event::Code(code) => {
+                let fence = if code.contains('`') { "``" } else { "`" };
+                clean.push_str(fence);
+                clean.push_str(&code);
+                clean.push_str(fence);
+            }
+            Event::SoftBreak | Event::HardBreak => clean.push(' '),
+            _ => {}
+        }
+    }
+    let clean = clean.split_whitespace().collect::<Vec<_>>().join(" ");
+    if clean.is_empty() {
+        heading.to_string()
+    } else {
+        clean
+    }
+}
+
+/// Generates `ToC` with format `{line_number}{separator}{heading_text}` per line,
+/// line numbers padded per [`TocConfig::padding`], followed
+/// by ` @{offset}` when [`TocConfig::offsets`] is set. The annotated style puts
+/// each heading's snippet on an indented line below it, falling back to plain
+/// entries when no level fits the budget with snippets.
 /// Returns `None` if document too small or no headings fit within budget.
 pub fn generate_toc(markdown: &str, total_bytes: usize, config: &TocConfig) -> Option<String> {
     if total_bytes < config.full_content_threshold {
         return None;
     }
 
-    let headings = extract_headings(markdown);
+    let mut headings = extract_headings(markdown);
     if headings.is_empty() {
         return None;
     }
+    if config.text == TocText::Clean {
+        for heading in &mut headings {
+            heading.text = clean_text(&heading.text);
+        }
+    }
 
-    let (_level, toc) = find_optimal_level(&headings, config.toc_budget)?;
+    let annotated = config.style == TocStyle::Annotated;
+    let (_level, toc) = annotated
+        .then(|| find_optimal_level(&headings, config, true))
+        .flatten()
+        .or_else(|| find_optimal_level(&headings, config, false))?;
 
     if toc.is_empty() { None } else { Some(toc) }
 }
 
+/// Whether each line of `markdown` is part of a fenced code block, fences
+/// included.
+pub fn fenced_lines(markdown: &str) -> Vec<bool> {
+    let mut fenced = false;
+    markdown
+        .lines()
+        .map(|line| {
+            let line = line.trim_start();
+            let fence = line.starts_with("```") || line.starts_with("~~~");
+            let in_code = fenced || fence;
+            fenced ^= fence;
+            in_code
+        })
+        .collect()
+}
+
+/// Text of a standalone bold line that reads like a heading: `**Install**`
+/// or `__Install:__`, short, and not a sentence.
+fn bold_heading(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let text = ["**", "__"]
+        .iter()
+        .find_map(|mark| line.strip_prefix(mark)?.strip_suffix(mark))?
+        .trim();
+    let heading_like = !text.is_empty()
+        && text.chars().count() <= MAX_BOLD_HEADING_CHARS
+        && !text.contains("**")
+        && !text.contains("__")
+        && !text.ends_with(['.', ',', ';', '!', '?']);
+    heading_like.then_some(text)
+}
+
+/// Turns bold lines standing alone between blank lines (`**Section Title**`)
+/// into headings, for pages that mark their structure that way and would
+/// otherwise get no `ToC`. They're placed one level below the document's
+/// top-level headings (at level 2 if it has none). Returns `None` if there's
+/// nothing to promote.
+pub fn promote_bold_headings(markdown: &str) -> Option<String> {
+    let lines: Vec<&str> = markdown.lines().collect();
+    let in_code = fenced_lines(markdown);
+    let blank = |index: Option<usize>| {
+        index
+            .and_then(|index| lines.get(index))
+            .is_none_or(|line| line.trim().is_empty())
+    };
+    let promoted: Vec<(usize, &str)> = lines
+        .iter()
+        .enumerate()
+        .filter(|&(index, _)| {
+            !in_code[index] && blank(index.checked_sub(1)) && blank(Some(index + 1))
+        })
+        .filter_map(|(index, line)| bold_heading(line).map(|text| (index, text)))
+        .collect();
+    if promoted.is_empty() {
+        return None;
+    }
+
+    let level = extract_headings(markdown)
+        .iter()
+        .map(|heading| heading.level + 1)
+        .min()
+        .unwrap_or(2)
+        .min(6);
+    let mut result: Vec<String> = lines.iter().map(ToString::to_string).collect();
+    for (index, text) in promoted {
+        result[index] = format!("{} {text}", "#".repeat(usize::from(level)));
+    }
+    let mut result = result.join("\n");
+    if markdown.ends_with('\n') {
+        result.push('\n');
+    }
+    Some(result)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -349,6 +643,13 @@ mod tests {
         assert_eq!(headings[1].level, 2);
     }
 
+    #[test]
+    fn test_heading_ids_excluded() {
+        let md = "Guide {#top}\n==\n\n## Setup `tool` {#setup-1}\n\n## Sets {a, b}";
+        let texts: Vec<String> = extract_headings(md).into_iter().map(|h| h.text).collect();
+        assert_eq!(texts, ["Guide", "## Setup `tool`", "## Sets {a, b}"]);
+    }
+
     #[test]
     fn test_empty_links_excluded() {
         // Empty anchor links should be excluded
@@ -478,21 +779,34 @@ mod tests {
             Heading {
                 level: 1,
                 line_number: 1,
+                offset: 0,
                 text: "# ".repeat(50),
+                snippet: None,
             },
             Heading {
                 level: 2,
                 line_number: 2,
+                offset: 0,
                 text: "## ".repeat(50),
+                snippet: None,
             },
             Heading {
                 level: 3,
                 line_number: 3,
+                offset: 0,
                 text: "### ".repeat(50),
+                snippet: None,
             },
         ];
 
-        let result = find_optimal_level(&headings, 400);
+        let result = find_optimal_level(
+            &headings,
+            &TocConfig {
+                toc_budget: 400,
+                ..default_config()
+            },
+            false,
+        );
         assert!(result.is_some());
         let (level, _toc) = result.unwrap();
         assert!(level >= 1);
@@ -501,7 +815,7 @@ mod tests {
     #[test]
     fn test_empty_headings() {
         let headings: Vec<Heading> = vec![];
-        let toc = render_toc(&headings, 3);
+        let toc = render_toc(&headings, 3, &default_config(), false);
         assert_eq!(toc, "");
     }
 
@@ -511,16 +825,27 @@ mod tests {
             Heading {
                 level: 1,
                 line_number: 1,
+                offset: 0,
                 text: "# ".to_string() + &"x".repeat(10000),
+                snippet: None,
             },
             Heading {
                 level: 1,
                 line_number: 2,
+                offset: 0,
                 text: "# ".to_string() + &"x".repeat(10000),
+                snippet: None,
             },
         ];
 
-        let level = find_optimal_level(&headings, 10);
+        let level = find_optimal_level(
+            &headings,
+            &TocConfig {
+                toc_budget: 10,
+                ..default_config()
+            },
+            false,
+        );
         assert!(level.is_none());
     }
 
@@ -562,6 +887,22 @@ mod tests {
     }
 
     // Snapshot tests with real-world documentation
+    #[test]
+    fn test_promote_bold_headings() {
+        let md = "# Guide\n\n**Install**\n\nRun it.\n**Inline** bold.\n\n__Usage:__\n\n**A whole sentence.**\n\n```\n\n**Code**\n\n```\n";
+        assert_eq!(
+            promote_bold_headings(md).unwrap(),
+            "# Guide\n\n## Install\n\nRun it.\n**Inline** bold.\n\n## Usage:\n\n**A whole sentence.**\n\n```\n\n**Code**\n\n```\n"
+        );
+        assert_eq!(
+            promote_bold_headings("**Intro**\n\ntext").unwrap(),
+            "## Intro\n\ntext"
+        );
+        assert_eq!(promote_bold_headings("## Title\n\nplain text"), None);
+        let long = format!("**{}**", "x".repeat(MAX_BOLD_HEADING_CHARS + 1));
+        assert_eq!(promote_bold_headings(&long), None);
+    }
+
     mod snapshots {
         use super::*;
 
@@ -628,6 +969,7 @@ mod tests {
             let config = TocConfig {
                 toc_budget: 4000,
                 full_content_threshold: 2000,
+                ..default_config()
             };
             let toc = generate_toc(md, md.len(), &config);
             insta::assert_snapshot!(toc.unwrap_or_default());
@@ -640,6 +982,7 @@ mod tests {
             let config = TocConfig {
                 toc_budget: 4000,
                 full_content_threshold: 1000,
+                ..default_config()
             };
             let toc = generate_toc(md, md.len(), &config);
             insta::assert_snapshot!(toc.unwrap_or_default());
@@ -652,6 +995,7 @@ mod tests {
             let config = TocConfig {
                 toc_budget: 4000,
                 full_content_threshold: 500,
+                ..default_config()
             };
             let toc = generate_toc(md, md.len(), &config);
             insta::assert_snapshot!(toc.unwrap_or_default());
@@ -685,6 +1029,7 @@ mod tests {
             let config = TocConfig {
                 toc_budget: 4000,
                 full_content_threshold: 2000,
+                ..default_config()
             };
             let toc = generate_toc(md, md.len(), &config);
             insta::assert_snapshot!(toc.unwrap_or_default());
@@ -697,6 +1042,7 @@ mod tests {
             let config = TocConfig {
                 toc_budget: 4000,
                 full_content_threshold: 2000,
+                ..default_config()
             };
             let toc = generate_toc(md, md.len(), &config);
             insta::assert_snapshot!(toc.unwrap_or_default());
@@ -709,6 +1055,7 @@ mod tests {
             let config = TocConfig {
                 toc_budget: 4000,
                 full_content_threshold: 2000,
+                ..default_config()
             };
             let toc = generate_toc(md, md.len(), &config);
             insta::assert_snapshot!(toc.unwrap_or_default());
@@ -725,6 +1072,7 @@ mod tests {
             let config = TocConfig {
                 toc_budget: 1500,
                 full_content_threshold: 8000,
+                ..default_config()
             };
             let toc = generate_toc(md, md.len(), &config);
             insta::assert_snapshot!(toc.unwrap_or_default());
@@ -737,6 +1085,7 @@ mod tests {
             let config = TocConfig {
                 toc_budget: 10000,
                 full_content_threshold: 8000,
+                ..default_config()
             };
             let toc = generate_toc(md, md.len(), &config);
             insta::assert_snapshot!(toc.unwrap_or_default());
@@ -749,6 +1098,7 @@ mod tests {
             let config = TocConfig {
                 toc_budget: 4000,
                 full_content_threshold: 2000,
+                ..default_config()
             };
             let toc = generate_toc(md, md.len(), &config);
             insta::assert_snapshot!(toc.unwrap_or_default());
@@ -761,6 +1111,7 @@ mod tests {
             let config = TocConfig {
                 toc_budget: 50000,
                 full_content_threshold: 8000,
+                ..default_config()
             };
             let toc = generate_toc(md, md.len(), &config);
             insta::assert_snapshot!(toc.unwrap_or_default());
@@ -773,6 +1124,7 @@ mod tests {
             let config = TocConfig {
                 toc_budget: 50000,
                 full_content_threshold: 8000,
+                ..default_config()
             };
             let toc = generate_toc(md, md.len(), &config);
             insta::assert_snapshot!(toc.unwrap_or_default());
@@ -785,6 +1137,7 @@ mod tests {
             let config = TocConfig {
                 toc_budget: 300,
                 full_content_threshold: 2000,
+                ..default_config()
             };
             let toc = generate_toc(md, md.len(), &config);
             insta::assert_snapshot!(toc.unwrap_or_default());
@@ -797,6 +1150,7 @@ mod tests {
             let config = TocConfig {
                 toc_budget: 4000,
                 full_content_threshold: 1000,
+                ..default_config()
             };
             let toc = generate_toc(md, md.len(), &config);
             insta::assert_snapshot!(toc.unwrap_or_default());
@@ -809,6 +1163,7 @@ mod tests {
             let config = TocConfig {
                 toc_budget: 100_000,
                 full_content_threshold: 8000,
+                ..default_config()
             };
             let toc = generate_toc(md, md.len(), &config);
             insta::assert_snapshot!(toc.unwrap_or_default());
@@ -853,10 +1208,12 @@ mod tests {
             let small_budget = TocConfig {
                 toc_budget: 500,
                 full_content_threshold: 2000,
+                ..default_config()
             };
             let large_budget = TocConfig {
                 toc_budget: 10000,
                 full_content_threshold: 2000,
+                ..default_config()
             };
 
             let toc_small = generate_toc(md, md.len(), &small_budget);
@@ -880,10 +1237,12 @@ mod tests {
             let low_threshold = TocConfig {
                 toc_budget: 1000,
                 full_content_threshold: 1000,
+                ..default_config()
             };
             let high_threshold = TocConfig {
                 toc_budget: 1000,
                 full_content_threshold: 100_000,
+                ..default_config()
             };
 
             let toc_low = generate_toc(md, md.len(), &low_threshold);
@@ -900,6 +1259,7 @@ mod tests {
             let config = TocConfig {
                 toc_budget: 1000,
                 full_content_threshold: 0,
+                ..default_config()
             };
 
             let toc = generate_toc(small_md, small_md.len(), &config);
@@ -913,6 +1273,7 @@ mod tests {
             let tiny_budget = TocConfig {
                 toc_budget: 10,
                 full_content_threshold: 2000,
+                ..default_config()
             };
 
             let toc = generate_toc(md, md.len(), &tiny_budget);
@@ -929,4 +1290,170 @@ mod tests {
             assert_eq!(config.full_content_threshold, DEFAULT_TOC_THRESHOLD);
         }
     }
+
+    #[test]
+    fn test_offsets() {
+        let md = "Intro\r\n\r\n# Café\r\n\r\nText é\r\n\r\n## Next\r\n";
+        let headings = extract_headings(md);
+        assert_eq!(headings.len(), 2);
+        assert_eq!(headings[0].offset, 9);
+        assert_eq!(
+            headings[1].offset,
+            md[..md.find("## Next").unwrap()].chars().count()
+        );
+        assert_eq!(headings[1].line_number, 7);
+
+        let config = TocConfig {
+            toc_budget: 1000,
+            full_content_threshold: 0,
+            offsets: true,
+            style: TocStyle::Plain,
+            ..default_config()
+        };
+        let toc = generate_toc(md, md.len(), &config).unwrap();
+        assert_eq!(toc, "  3→# Café @9\n  7→## Next @29");
+    }
+
+    #[test]
+    fn test_annotated() {
+        let md = "# Guide\n\nStart here. More text.\n\n## Install\n\n```sh\ncargo add x\n```\n\nAdd it with `cargo add`, or by\nhand!\n\n## Empty\n\n## Long\n\n";
+        let md = format!("{md}{}\n", "word ".repeat(40));
+        let headings = extract_headings(&md);
+        let snippets: Vec<_> = headings.iter().map(|h| h.snippet.as_deref()).collect();
+        assert_eq!(
+            snippets[..3],
+            [
+                Some("Start here."),
+                Some("Add it with cargo add, or by hand!"),
+                None,
+            ]
+        );
+        let long = snippets[3].unwrap();
+        assert_eq!(long.width(), MAX_SNIPPET_WIDTH);
+        assert!(long.ends_with("word…"));
+
+        let mut config = TocConfig {
+            toc_budget: 1000,
+            full_content_threshold: 0,
+            offsets: false,
+            style: TocStyle::Annotated,
+            ..default_config()
+        };
+        let toc = generate_toc(&md, md.len(), &config).unwrap();
+        assert!(toc.starts_with("  1→# Guide\n    Start here.\n  5→## Install\n"));
+
+        // Falls back to plain entries when snippets don't fit
+        config.toc_budget = 20;
+        let toc = generate_toc(&md, md.len(), &config).unwrap();
+        assert_eq!(toc, "  1→# Guide");
+    }
+
+    #[test]
+    fn test_separator_and_padding() {
+        let md = "# 安装指南\n\n首先安装工具链。然后继续。\n\n## Next\n";
+        let mut config = TocConfig {
+            full_content_threshold: 0,
+            style: TocStyle::Annotated,
+            separator: "\t".to_string(),
+            ..default_config()
+        };
+        let toc = generate_toc(md, md.len(), &config).unwrap();
+        assert_eq!(
+            toc,
+            "  1\t# 安装指南\n   \t首先安装工具链。然后继续。\n  5\t## Next"
+        );
+
+        config.padding = TocPadding::None;
+        config.separator = "→".to_string();
+        let toc = generate_toc(md, md.len(), &config).unwrap();
+        assert_eq!(toc, "1→# 安装指南\n  首先安装工具链。然后继续。\n5→## Next");
+
+        // Wide characters count double against the snippet width
+        let long = format!("# 标题\n\n{}\n", "字".repeat(80));
+        let snippet = extract_headings(&long)[0].snippet.clone().unwrap();
+        assert_eq!(snippet.chars().count(), 50);
+        assert!(snippet.width() <= MAX_SNIPPET_WIDTH);
+    }
+
+    #[test]
+    fn test_clean_text() {
+        assert_eq!(
+            clean_text("## Install [`tokio`](url) *now* ##"),
+            "Install `tokio` now"
+        );
+        assert_eq!(clean_text("# C#"), "C#");
+        assert_eq!(
+            clean_text("### **Bold** and _em_ ![logo](a.png)"),
+            "Bold and em logo"
+        );
+        assert_eq!(clean_text("Setext <span>Title</span>"), "Setext Title");
+        assert_eq!(clean_text("## ``a`b``"), "``a`b``");
+        assert_eq!(clean_text("# <br>"), "# <br>");
+
+        let md = "# **Guide**\n\n## Using [x](y)\n";
+        let config = TocConfig {
+            full_content_threshold: 0,
+            text: TocText::Clean,
+            ..default_config()
+        };
+        let toc = generate_toc(md, md.len(), &config).unwrap();
+        assert_eq!(toc, "  1→Guide\n  3→Using x");
+    }
+
+    /// Markdown lines biased towards headings, setext underlines, fences and
+    /// empty anchor links, joined with `\n` or `\r\n`.
+    fn arbitrary_markdown() -> impl proptest::strategy::Strategy<Value = String> {
+        use proptest::prelude::*;
+
+        let line = prop_oneof![
+            "#{1,7}[ \t]?\\PC{0,40}",
+            "\\PC{0,80}",
+            "[=-]{1,5}",
+            "(```|~~~)[a-z]{0,4}",
+            "#{1,3} \\[[\u{200B}¶ ]{0,2}\\]\\(#[a-z]{0,5}\\)\\PC{0,10}",
+            "\\*\\*\\PC{0,20}\\*\\*",
+            "[ \t>*-]{0,4}\\PC{0,20}",
+        ];
+        (
+            prop::collection::vec(line, 0..60),
+            prop_oneof!["\n", "\r\n"],
+        )
+            .prop_map(|(lines, newline)| lines.join(&newline))
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn prop_extract_headings(md in arbitrary_markdown()) {
+            let lines = md.split('\n').count();
+            let chars = md.chars().count();
+            for heading in extract_headings(&md) {
+                proptest::prop_assert!((1..=6).contains(&heading.level));
+                proptest::prop_assert!(heading.line_number >= 1 && heading.line_number <= lines);
+                proptest::prop_assert!(heading.offset < chars);
+                proptest::prop_assert!(!heading.text.is_empty());
+                proptest::prop_assert!(!clean_text(&heading.text).is_empty());
+            }
+        }
+
+        #[test]
+        fn prop_toc_within_budget(
+            md in arbitrary_markdown(),
+            budget in 0usize..2000,
+            offsets: bool,
+            annotated: bool,
+            clean: bool,
+        ) {
+            let config = TocConfig {
+                toc_budget: budget,
+                full_content_threshold: 0,
+                offsets,
+                style: if annotated { TocStyle::Annotated } else { TocStyle::Plain },
+                text: if clean { TocText::Clean } else { TocText::Raw },
+                ..default_config()
+            };
+            if let Some(toc) = generate_toc(&md, md.len(), &config) {
+                proptest::prop_assert!(toc.len() <= budget);
+            }
+        }
+    }
 }
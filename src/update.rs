@@ -0,0 +1,86 @@
+//! Checking for a newer release, only when asked to with `--update-check` or
+//! the `update-check` subcommand.
+//!
+//! The check asks the GitHub releases API for the latest release and compares
+//! its tag with this version. Nothing is downloaded or installed: the server
+//! logs the result to stderr and the `info` tool reports it, and upgrading is
+//! left to whoever installed the binary.
+
+use crate::changelog::Version;
+use crate::github;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::fmt;
+
+const OWNER: &str = "Crazytieguy";
+const REPO: &str = "llms-fetch-mcp";
+
+/// Where newer releases are listed.
+pub const RELEASES_URL: &str = "https://github.com/Crazytieguy/llms-fetch-mcp/releases";
+
+/// Outcome of an update check.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Status {
+    /// Tag of the latest release
+    pub latest: String,
+    /// The latest release is newer than this version
+    pub available: bool,
+}
+
+impl Status {
+    /// Compares `latest`, a release tag, with `current`. Tags that don't look
+    /// like a version are never reported as newer.
+    pub fn new(current: &str, latest: String) -> Self {
+        let available = match (Version::find_in(current), Version::find_in(&latest)) {
+            (Some(current), Some(latest)) => latest > current,
+            _ => false,
+        };
+        Self { latest, available }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let current = env!("CARGO_PKG_VERSION");
+        if self.available {
+            write!(
+                f,
+                "llms-fetch-mcp {} is available (running {current}): {RELEASES_URL}",
+                self.latest
+            )
+        } else {
+            write!(
+                f,
+                "llms-fetch-mcp {current} is up to date (latest release: {})",
+                self.latest
+            )
+        }
+    }
+}
+
+/// Asks GitHub for the latest release and compares it with this version.
+pub async fn check(api: &github::Api<'_>) -> Result<Status, String> {
+    let latest = github::latest_release(api, OWNER, REPO)
+        .await
+        .map_err(|e| format!("update check failed: {e}"))?;
+    Ok(Status::new(env!("CARGO_PKG_VERSION"), latest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status() {
+        assert!(Status::new("0.3.1", "v0.4.0".to_string()).available);
+        assert!(Status::new("0.3.1", "0.3.2".to_string()).available);
+        assert!(!Status::new("0.3.1", "v0.3.1".to_string()).available);
+        assert!(!Status::new("0.3.1", "v0.3.0".to_string()).available);
+        assert!(!Status::new("0.4.0", "v0.4.0-beta.1".to_string()).available);
+        assert!(!Status::new("0.3.1", "nightly".to_string()).available);
+
+        let status = Status::new("0.0.1", "v99.0.0".to_string());
+        assert!(status.to_string().contains("v99.0.0 is available"));
+        assert!(status.to_string().contains(RELEASES_URL));
+    }
+}
@@ -0,0 +1,370 @@
+//! Cache consistency check, run at startup and by the `verify` subcommand.
+//!
+//! Interrupted writes leave temp files behind and can truncate the index or
+//! the audit log, and deleting cached files by hand leaves the index
+//! recording paths that are gone. None of these break fetching outright, but
+//! they accumulate in long-lived caches, so they are repaired before the
+//! server starts.
+//!
+//! The `verify` subcommand also checks cached files against the hashes their
+//! sidecars recorded when they were written, and optionally against a
+//! lockfile. Those problems can't be repaired, only reported.
+
+use crate::lockfile::Lockfile;
+use crate::{audit, index, sidecar};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Temp files younger than this may belong to a write still in progress in
+/// another server sharing the cache.
+const ORPHAN_AGE: Duration = Duration::from_mins(1);
+
+/// Problems found in the cache directory.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Leftovers from interrupted atomic writes
+    pub orphan_temp_files: Vec<PathBuf>,
+    /// Why `.index.json` could not be parsed, if it couldn't
+    pub invalid_index: Option<String>,
+    /// Paths `.index.json` recorded URLs, aliases or hashes for whose file
+    /// is gone, relative to the cache directory
+    pub dangling_index_paths: Vec<String>,
+    /// The audit log's last record was cut off mid-line
+    pub truncated_audit_log: bool,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_clean() {
+            return write!(f, "cache is consistent");
+        }
+        let mut problems = Vec::new();
+        if !self.orphan_temp_files.is_empty() {
+            problems.push(format!(
+                "removed {} orphan temp file(s)",
+                self.orphan_temp_files.len()
+            ));
+        }
+        if let Some(reason) = &self.invalid_index {
+            problems.push(format!(
+                "moved unreadable {} aside ({reason})",
+                index::INDEX_FILE
+            ));
+        }
+        if !self.dangling_index_paths.is_empty() {
+            problems.push(format!(
+                "dropped {} path(s) of missing files from {}",
+                self.dangling_index_paths.len(),
+                index::INDEX_FILE
+            ));
+        }
+        if self.truncated_audit_log {
+            problems.push(format!(
+                "terminated truncated last line of {}",
+                audit::AUDIT_FILE
+            ));
+        }
+        write!(f, "{}", problems.join("; "))
+    }
+}
+
+/// Scans `cache_dir` and repairs what it finds, returning what was repaired.
+///
+/// A missing cache directory is consistent: there is nothing to check yet.
+pub fn check_and_repair(cache_dir: &Path) -> std::io::Result<Report> {
+    let mut report = Report::default();
+    if !cache_dir.is_dir() {
+        return Ok(report);
+    }
+    let now = SystemTime::now();
+
+    find_orphans(cache_dir, now, &mut report.orphan_temp_files)?;
+    for path in &report.orphan_temp_files {
+        std::fs::remove_file(path)?;
+    }
+
+    let index_path = cache_dir.join(index::INDEX_FILE);
+    if let Ok(json) = std::fs::read_to_string(&index_path) {
+        if let Err(e) = serde_json::from_str::<serde_json::Value>(&json) {
+            report.invalid_index = Some(e.to_string());
+            std::fs::rename(&index_path, index_path.with_extension("json.corrupt"))?;
+        } else {
+            let index = index::CacheIndex::load(cache_dir);
+            report.dangling_index_paths = index.prune_paths(|path| cache_dir.join(path).is_file());
+            if !report.dangling_index_paths.is_empty() {
+                index.save_blocking()?;
+            }
+        }
+    }
+
+    let audit_path = cache_dir.join(audit::AUDIT_FILE);
+    if let Ok(log) = std::fs::read(&audit_path)
+        && log.last().is_some_and(|&byte| byte != b'\n')
+    {
+        report.truncated_audit_log = true;
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&audit_path)?
+            .write_all(b"\n")?;
+    }
+
+    Ok(report)
+}
+
+/// Cached files whose content doesn't match what was recorded for them.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Integrity {
+    /// Files whose sidecar records a hash, all checked
+    pub checked: usize,
+    /// Files changed since they were cached: edited, corrupted or truncated
+    pub modified: Vec<PathBuf>,
+    /// Locked URLs whose cached file has different content
+    pub drifted: Vec<String>,
+    /// Locked URLs with no cached file
+    pub missing: Vec<String>,
+}
+
+impl Integrity {
+    pub fn is_clean(&self) -> bool {
+        self.modified.is_empty() && self.drifted.is_empty() && self.missing.is_empty()
+    }
+}
+
+impl fmt::Display for Integrity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} file(s) match their recorded hashes",
+            self.checked - self.modified.len()
+        )?;
+        for path in &self.modified {
+            write!(f, "\nmodified since cached: {}", path.display())?;
+        }
+        for url in &self.drifted {
+            write!(f, "\ndiffers from the lockfile: {url}")?;
+        }
+        for url in &self.missing {
+            write!(f, "\nin the lockfile but not cached: {url}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Hashes every cached file under `cache_dir`, namespaces included, and
+/// compares it with its sidecar's hash. With a `lockfile`, also compares the
+/// files cached under `root` (the cache directory or a namespace in it) with
+/// the locked hashes.
+pub fn check_integrity(
+    cache_dir: &Path,
+    root: &Path,
+    lockfile: Option<&Lockfile>,
+) -> std::io::Result<Integrity> {
+    let mut integrity = Integrity::default();
+    let mut roots = vec![cache_dir.to_path_buf()];
+    if cache_dir.is_dir() {
+        for entry in std::fs::read_dir(cache_dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() && entry.file_name().to_string_lossy().starts_with('@') {
+                roots.push(entry.path());
+            }
+        }
+    }
+    // URL → hash of its file, for the lockfile comparison
+    let mut cached: HashMap<String, String> = HashMap::new();
+    for dir in &roots {
+        for entry in sidecar::list(dir)? {
+            let Some(meta) = entry.sidecar else {
+                continue;
+            };
+            let actual = sidecar::sha256(&std::fs::read(&entry.path)?);
+            if let Some(expected) = &meta.sha256 {
+                integrity.checked += 1;
+                if *expected != actual {
+                    integrity.modified.push(entry.path);
+                }
+            }
+            if dir == root {
+                cached.insert(meta.url, actual);
+            }
+        }
+    }
+    for (url, locked) in lockfile.into_iter().flatten() {
+        match cached.get(url) {
+            None => integrity.missing.push(url.clone()),
+            Some(actual) if *actual != locked.sha256 => integrity.drifted.push(url.clone()),
+            Some(_) => {}
+        }
+    }
+    Ok(integrity)
+}
+
+fn find_orphans(dir: &Path, now: SystemTime, orphans: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        if file_type.is_dir() {
+            find_orphans(&path, now, orphans)?;
+        } else if file_type.is_file() && sidecar::is_temp(&path) {
+            let age = entry
+                .metadata()?
+                .modified()
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok());
+            if age.is_some_and(|age| age >= ORPHAN_AGE) {
+                orphans.push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn age(path: &Path) {
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_mins(5))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_repairs_leftovers() {
+        let dir = tempfile::tempdir().unwrap();
+        let site = dir.path().join("example.com");
+        std::fs::create_dir_all(&site).unwrap();
+        std::fs::write(site.join("page.md"), "# Page").unwrap();
+        let old = sidecar::temp_path(&site.join("old.md"));
+        std::fs::write(&old, "partial").unwrap();
+        age(&old);
+        let fresh = sidecar::temp_path(&site.join("fresh.md"));
+        std::fs::write(&fresh, "in progress").unwrap();
+        // Cached from a URL such as https://example.com/notes.tmp
+        std::fs::write(site.join("notes.tmp"), "# Notes").unwrap();
+        age(&site.join("notes.tmp"));
+        std::fs::write(dir.path().join(index::INDEX_FILE), "{\"missing\": {").unwrap();
+        std::fs::write(
+            dir.path().join(audit::AUDIT_FILE),
+            "{\"url\":\"a\"}\n{\"url\":",
+        )
+        .unwrap();
+
+        let report = check_and_repair(dir.path()).unwrap();
+        assert_eq!(report.orphan_temp_files, vec![old.clone()]);
+        assert!(report.invalid_index.is_some());
+        assert!(report.truncated_audit_log);
+
+        assert!(!old.exists());
+        assert!(fresh.exists());
+        assert!(site.join("page.md").exists());
+        assert!(site.join("notes.tmp").exists());
+        assert!(!dir.path().join(index::INDEX_FILE).exists());
+        assert!(dir.path().join(".index.json.corrupt").exists());
+
+        assert!(check_and_repair(dir.path()).unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_drops_dangling_index_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let site = dir.path().join("example.com");
+        std::fs::create_dir_all(&site).unwrap();
+        std::fs::write(site.join("kept.md"), "# Kept").unwrap();
+        let index = index::CacheIndex::load(dir.path());
+        index.record_url("example.com/kept.md", "https://example.com/kept");
+        index.record_url(
+            "example.com/long~0123456789abcdef.md",
+            "https://example.com/long",
+        );
+        index.record_aliases("example.com/docs/~index", &["https://example.com/docs/"]);
+        index.record_hash("abc", "example.com/deleted.md");
+        index.save_blocking().unwrap();
+
+        let report = check_and_repair(dir.path()).unwrap();
+        assert_eq!(
+            report.dangling_index_paths,
+            [
+                "example.com/deleted.md",
+                "example.com/docs/~index",
+                "example.com/long~0123456789abcdef.md"
+            ]
+        );
+        let index = index::CacheIndex::load(dir.path());
+        assert_eq!(
+            index.url_of("example.com/kept.md").as_deref(),
+            Some("https://example.com/kept")
+        );
+        assert_eq!(index.url_of("example.com/long~0123456789abcdef.md"), None);
+        assert!(index.alias_paths("https://example.com/docs/").is_empty());
+        assert_eq!(index.path_with_hash("abc"), None);
+
+        assert!(check_and_repair(dir.path()).unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_check_integrity() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = |root: &Path, name: &str, url: &str, content: &str| {
+            std::fs::create_dir_all(root).unwrap();
+            let path = root.join(name);
+            std::fs::write(&path, content).unwrap();
+            let meta = sidecar::Sidecar {
+                url: url.to_string(),
+                fetched_at: 0,
+                http: None,
+                version: None,
+                sha256: Some(sidecar::sha256(content.as_bytes())),
+                sections: Vec::new(),
+            };
+            std::fs::write(
+                sidecar::sidecar_path(&path),
+                serde_json::to_string(&meta).unwrap(),
+            )
+            .unwrap();
+            path
+        };
+        let site = dir.path().join("example.com");
+        cache(&site, "a.md", "https://example.com/a", "# A");
+        let edited = cache(&site, "b.md", "https://example.com/b", "# B");
+        std::fs::write(&edited, "# B, edited").unwrap();
+        let other = dir.path().join("@other/example.com");
+        let corrupted = cache(&other, "c.md", "https://example.com/c", "# C");
+        std::fs::write(&corrupted, "").unwrap();
+
+        let integrity = check_integrity(dir.path(), dir.path(), None).unwrap();
+        assert_eq!(integrity.checked, 3);
+        assert_eq!(integrity.modified, vec![edited, corrupted]);
+
+        let lockfile: Lockfile = toml::from_str(&format!(
+            "[\"https://example.com/a\"]\nsha256 = \"{}\"\n\
+             [\"https://example.com/b\"]\nsha256 = \"{}\"\n\
+             [\"https://example.com/gone\"]\nsha256 = \"{}\"\n",
+            sidecar::sha256(b"# A"),
+            sidecar::sha256(b"# B"),
+            sidecar::sha256(b"# Gone"),
+        ))
+        .unwrap();
+        let integrity = check_integrity(dir.path(), dir.path(), Some(&lockfile)).unwrap();
+        assert_eq!(integrity.drifted, ["https://example.com/b"]);
+        assert_eq!(integrity.missing, ["https://example.com/gone"]);
+        assert!(!integrity.is_clean());
+    }
+
+    #[test]
+    fn test_missing_cache_dir_is_clean() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = check_and_repair(&dir.path().join("absent")).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(report.to_string(), "cache is consistent");
+    }
+}
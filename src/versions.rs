@@ -0,0 +1,232 @@
+//! Version segments in documentation URLs.
+//!
+//! Many docs sites publish each release under its own path segment
+//! (`/v2/`, `/en/stable/`, `/3.12/`, `/latest/`). The segment is recorded with
+//! cached pages, and the `versions` tool finds the other versions of a page,
+//! from the site's sitemap or by probing likely neighbours, so an agent can
+//! read the docs matching the version a project depends on.
+
+use regex::Regex;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::sync::LazyLock;
+use url::Url;
+
+/// Channel names used as versions, in the order they're listed.
+const CHANNELS: [&str; 7] = [
+    "stable", "latest", "current", "dev", "nightly", "beta", "next",
+];
+
+/// Child sitemaps of a sitemap index read when looking for versions.
+pub const MAX_CHILD_SITEMAPS: usize = 5;
+
+/// Versions guessed and requested when the sitemap lists none.
+pub const MAX_PROBES: usize = 8;
+
+/// `v2`, `v1.4`, `3.12`, `2.x`; a bare number like `/2/` is too often an ID.
+static NUMBERED: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(v\d+(\.(\d+|x))*|\d+(\.(\d+|x))+)$").unwrap());
+
+static LOC: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<loc>\s*([^<\s]+)\s*</loc>").unwrap());
+
+fn is_version(segment: &str) -> bool {
+    let segment = segment.to_lowercase();
+    NUMBERED.is_match(&segment) || CHANNELS.contains(&segment.as_str())
+}
+
+/// Where a URL names a version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Located {
+    /// Index of the version's segment in the URL path
+    pub segment: usize,
+    pub version: String,
+}
+
+/// The first path segment of `url` that looks like a version.
+pub fn detect(url: &Url) -> Option<Located> {
+    url.path_segments()?
+        .enumerate()
+        .find(|(_, segment)| is_version(segment))
+        .map(|(segment, version)| Located {
+            segment,
+            version: version.to_string(),
+        })
+}
+
+/// The version of `url`, for recording with cached pages.
+pub fn version_of(url: &str) -> Option<String> {
+    detect(&Url::parse(url).ok()?).map(|located| located.version)
+}
+
+/// `url` with the version at `located` replaced by `version`.
+pub fn with_version(url: &Url, located: &Located, version: &str) -> Url {
+    let mut url = url.clone();
+    let segments: Vec<String> = url
+        .path_segments()
+        .map(|segments| {
+            segments
+                .enumerate()
+                .map(|(i, s)| if i == located.segment { version } else { s }.to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    url.set_path(&segments.join("/"));
+    url
+}
+
+/// The `<loc>` URLs of a sitemap or sitemap index.
+pub fn sitemap_locs(xml: &str) -> Vec<String> {
+    LOC.captures_iter(xml)
+        .map(|captures| captures[1].replace("&amp;", "&"))
+        .collect()
+}
+
+/// Versions of the docs `url` belongs to among `locs`: URLs on the same host
+/// with the same path up to a version segment at the same place.
+pub fn sitemap_versions(url: &Url, located: &Located, locs: &[String]) -> BTreeSet<String> {
+    let prefix: Vec<&str> = url
+        .path_segments()
+        .map(|segments| segments.take(located.segment).collect())
+        .unwrap_or_default();
+    let mut versions = BTreeSet::new();
+    for loc in locs {
+        let Ok(loc) = Url::parse(loc) else {
+            continue;
+        };
+        if loc.host_str() != url.host_str() {
+            continue;
+        }
+        let Some(mut segments) = loc.path_segments() else {
+            continue;
+        };
+        if prefix.iter().all(|part| segments.next() == Some(part))
+            && let Some(version) = segments.next()
+            && is_version(version)
+        {
+            versions.insert(version.to_string());
+        }
+    }
+    versions
+}
+
+/// Versions likely to exist next to `version`, at most [`MAX_PROBES`]: the
+/// previous and next release at its last component, the next major release,
+/// then the channels.
+pub fn guesses(version: &str) -> Vec<String> {
+    let mut guesses = Vec::new();
+    let (prefix, number) = match version.strip_prefix('v') {
+        Some(number) => ("v", number),
+        None => ("", version),
+    };
+    let parts: Vec<&str> = number.split('.').collect();
+    let parsed: Vec<Option<u64>> = parts.iter().map(|part| part.parse().ok()).collect();
+    let join = |parts: Vec<String>| format!("{prefix}{}", parts.join("."));
+    if let Some(last) = parsed.iter().rposition(Option::is_some) {
+        let value = parsed[last].unwrap_or_default();
+        for next in [value.checked_sub(1), value.checked_add(1)]
+            .into_iter()
+            .flatten()
+        {
+            let mut changed: Vec<String> = parts.iter().map(ToString::to_string).collect();
+            changed[last] = next.to_string();
+            guesses.push(join(changed));
+        }
+    }
+    if parts.len() > 1
+        && let Some(major) = parsed[0]
+    {
+        let mut next_major = vec![(major + 1).to_string()];
+        next_major.extend(
+            parts[1..]
+                .iter()
+                .map(|part| if *part == "x" { "x" } else { "0" }.to_string()),
+        );
+        guesses.push(join(next_major));
+    }
+    guesses.extend(CHANNELS.iter().map(ToString::to_string));
+    guesses.retain(|guess| guess != version);
+    guesses.dedup();
+    guesses.truncate(MAX_PROBES);
+    guesses
+}
+
+/// Orders versions for listing: channels first, then releases newest first.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    let channel = |v: &str| CHANNELS.iter().position(|c| c.eq_ignore_ascii_case(v));
+    let numbers = |v: &str| -> Vec<u64> {
+        v.trim_start_matches('v')
+            .split('.')
+            .map(|part| part.parse().unwrap_or(u64::MAX))
+            .collect()
+    };
+    match (channel(a), channel(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => numbers(b).cmp(&numbers(a)).then_with(|| a.cmp(b)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn test_detect() {
+        let cases = [
+            ("https://docs.python.org/3.12/library/os.html", Some("3.12")),
+            (
+                "https://docs.djangoproject.com/en/stable/topics/",
+                Some("stable"),
+            ),
+            ("https://example.com/docs/v2/guide", Some("v2")),
+            ("https://docs.rs/tokio/1.x/tokio/", Some("1.x")),
+            ("https://github.com/o/r/issues/2", None),
+            ("https://example.com/docs/vue/", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(version_of(input).as_deref(), expected, "{input}");
+        }
+        let located = detect(&url("https://example.com/docs/v2/guide")).unwrap();
+        assert_eq!(located.segment, 1);
+        assert_eq!(
+            with_version(&url("https://example.com/docs/v2/guide"), &located, "v3").as_str(),
+            "https://example.com/docs/v3/guide"
+        );
+    }
+
+    #[test]
+    fn test_sitemap_versions() {
+        let xml = "<urlset><url><loc>https://docs.example.com/en/3.11/intro</loc></url>\
+            <url><loc> https://docs.example.com/en/stable/ </loc></url>\
+            <url><loc>https://docs.example.com/fr/3.10/intro</loc></url>\
+            <url><loc>https://docs.example.com/en/blog/post</loc></url>\
+            <url><loc>https://other.example.com/en/2.0/</loc></url></urlset>";
+        let page = url("https://docs.example.com/en/3.12/intro");
+        let located = detect(&page).unwrap();
+        let versions = sitemap_versions(&page, &located, &sitemap_locs(xml));
+        assert_eq!(
+            versions,
+            BTreeSet::from(["3.11".to_string(), "stable".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_guesses() {
+        assert_eq!(guesses("3.12")[..4], ["3.11", "3.13", "4.0", "stable"]);
+        assert_eq!(guesses("3.12").len(), MAX_PROBES);
+        assert_eq!(guesses("v1")[..3], ["v0", "v2", "stable"]);
+        assert!(!guesses("stable").contains(&"stable".to_string()));
+    }
+
+    #[test]
+    fn test_compare() {
+        let mut versions = vec!["3.9", "latest", "3.12", "stable", "4.0"];
+        versions.sort_by(|a, b| compare(a, b));
+        assert_eq!(versions, ["stable", "latest", "4.0", "3.12", "3.9"]);
+    }
+}
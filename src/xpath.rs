@@ -0,0 +1,122 @@
+//! XPath selectors for domain policies (`selector = "xpath:..."`), for the
+//! `xpath` feature.
+//!
+//! XPath reaches what CSS can't, such as elements by their text
+//! (`//section[h2[contains(., 'Reference')]]`) or the ancestor of a marker
+//! (`//*[@id='api']/ancestor::div[2]`). The page is parsed as HTML as for CSS
+//! selectors, then mirrored into an XML document for sxd-xpath (XPath 1.0) to
+//! evaluate against, and the elements it selects are mapped back to the page.
+
+use dom_query::{Document, NodeRef};
+use std::collections::HashMap;
+use sxd_document::{Package, dom};
+use sxd_xpath::nodeset::Node;
+use sxd_xpath::{Context, Factory, Value};
+
+/// Checks that `expression` parses as XPath.
+pub fn validate(expression: &str) -> Result<(), String> {
+    match Factory::new().build(expression) {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err("empty expression".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// The elements of `document` that `expression` selects, in document order,
+/// or `None` when it selects none or doesn't evaluate to a node-set.
+// sxd elements hash by identity, so their interior mutability can't move them
+#[allow(clippy::mutable_key_type)]
+pub fn select<'a>(document: &'a Document, expression: &str) -> Option<Vec<NodeRef<'a>>> {
+    let xpath = Factory::new().build(expression).ok()??;
+    let package = Package::new();
+    let mirror = package.as_document();
+    let mut elements = HashMap::new();
+    // Built without recursion, as pages can nest deeper than the stack allows
+    let mut pending: Vec<(NodeRef<'a>, Option<dom::Element>)> = document
+        .root()
+        .children_it(false)
+        .map(|child| (child, None))
+        .collect();
+    pending.reverse();
+    while let Some((node, parent)) = pending.pop() {
+        if node.is_text() {
+            if let Some(parent) = parent {
+                parent.append_child(mirror.create_text(&node.text()));
+            }
+            continue;
+        }
+        let Some(name) = node.node_name().filter(|_| node.is_element()) else {
+            continue;
+        };
+        let element = mirror.create_element(name.as_ref());
+        for attr in node.attrs() {
+            element.set_attribute_value(attr.name.local.as_ref(), &attr.value);
+        }
+        match parent {
+            Some(parent) => parent.append_child(element),
+            None => mirror.root().append_child(element),
+        }
+        let children: Vec<_> = node.children_it(false).collect();
+        pending.extend(
+            children
+                .into_iter()
+                .rev()
+                .map(|child| (child, Some(element))),
+        );
+        elements.insert(element, node);
+    }
+
+    let Ok(Value::Nodeset(matched)) = xpath.evaluate(&Context::new(), mirror.root()) else {
+        return None;
+    };
+    let selected: Vec<NodeRef<'a>> = matched
+        .document_order()
+        .into_iter()
+        .filter_map(|node| match node {
+            Node::Element(element) => elements.get(&element).copied(),
+            _ => None,
+        })
+        .collect();
+    (!selected.is_empty()).then_some(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let document = Document::from(
+            r#"<html><body>
+                <section><h2>Guide</h2><p>Start here.</p></section>
+                <section id="ref"><h2>API Reference</h2><p>Functions.</p></section>
+                <div><div><span id="marker">!</span></div></div>
+            </body></html>"#,
+        );
+        let text = |nodes: Option<Vec<NodeRef>>| -> Vec<String> {
+            nodes
+                .unwrap_or_default()
+                .iter()
+                .map(|node| node.text().trim().to_string())
+                .collect()
+        };
+        assert_eq!(
+            text(select(
+                &document,
+                "//section[h2[contains(., 'Reference')]]/p"
+            )),
+            ["Functions."]
+        );
+        assert_eq!(
+            text(select(&document, "//p | //h2")),
+            ["Guide", "Start here.", "API Reference", "Functions."]
+        );
+        let ancestor = select(&document, "//*[@id='marker']/ancestor::div[2]").unwrap();
+        assert_eq!(ancestor[0].html().matches("<div>").count(), 2);
+        assert!(select(&document, "//table").is_none());
+        assert!(select(&document, "count(//p)").is_none());
+
+        assert!(validate("//section[@id='ref']").is_ok());
+        assert!(validate("//section[").is_err());
+    }
+}
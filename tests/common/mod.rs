@@ -0,0 +1,246 @@
+//! End-to-end harness: a local HTTP server serving fixture pages, and an MCP
+//! client driving the server binary over stdio, so tests exercise the whole
+//! fetch pipeline without touching the network.
+
+use axum::Router;
+use axum::body::Body;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::routing::get;
+use rmcp::ServiceExt;
+use rmcp::model::{
+    CallToolRequestParam, CallToolResult, ReadResourceRequestParam, ReadResourceResult,
+};
+use rmcp::service::{RoleClient, RunningService, ServiceError};
+use rmcp::transport::TokioChildProcess;
+use std::io::Write;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::process::Command;
+use tokio::task::JoinHandle;
+
+const LLMS_TXT: &str = "# Fixture Docs\n\n> Documentation served by the test harness.\n\n- [Guide](/docs/guide.md): Getting started\n";
+
+const GUIDE: &str = "# Guide\n\nInstall the fixture with `cargo add fixture`.\n";
+
+const ARTICLE: &str = "<!doctype html><html><head><title>Article</title></head><body>\
+    <nav><a href=\"/\">Home</a></nav>\
+    <main><article><h1>Fixture Article</h1>\
+    <p>This article is long enough for readability to keep it as the main content of the page, \
+    which is what a documentation page usually looks like once its navigation is stripped.</p>\
+    <h2>Details</h2><p>Some more text about the fixture, with a <a href=\"/docs/guide.md\">link</a>.</p>\
+    </article></main></body></html>";
+
+const AMP_ARTICLE: &str = "<!doctype html><html amp><head><title>Article</title>\
+    <link rel=\"canonical\" href=\"/article\"></head><body><p>Fixture Article</p></body></html>";
+
+/// HTTP server on an ephemeral localhost port, stopped on drop.
+pub struct FixtureServer {
+    base: String,
+    handle: JoinHandle<()>,
+}
+
+impl FixtureServer {
+    /// Serves:
+    /// - `/docs/llms.txt` and `/docs/guide.md`: an llms.txt site
+    /// - `/mirror/guide.md`: the guide again
+    /// - `/amp/article`: the AMP version of `/article`
+    /// - `/article`: an HTML page to convert
+    /// - `/mislabeled`: Markdown served as HTML
+    /// - `/old` → `/article` (301), and `/loop` redirecting to itself
+    /// - `/latin1`: HTML declared and encoded as ISO-8859-1
+    /// - `/gzip`: Markdown, gzip-compressed when the client accepts it
+    /// - `/limited`: 429 with `Retry-After`
+    /// - `/slow`: Markdown after a 5 second delay
+    /// - `/sitemap.xml`: a sitemap index listing `/pages-sitemap.xml` on
+    ///   `localhost`, another site than `127.0.0.1`, which lists the guide
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let index = format!(
+            "<sitemapindex><sitemap><loc>http://localhost:{port}/pages-sitemap.xml</loc>\
+             </sitemap></sitemapindex>"
+        );
+        let pages =
+            format!("<urlset><url><loc>http://127.0.0.1:{port}/docs/guide.md</loc></url></urlset>");
+        let app = Router::new()
+            .route("/docs/llms.txt", get(|| async { markdown(LLMS_TXT) }))
+            .route("/docs/guide.md", get(|| async { markdown(GUIDE) }))
+            .route("/mirror/guide.md", get(|| async { markdown(GUIDE) }))
+            .route("/article", get(|| async { html(ARTICLE) }))
+            .route("/amp/article", get(|| async { html(AMP_ARTICLE) }))
+            .route("/mislabeled", get(|| async { html(GUIDE) }))
+            .route("/old", get(|| async { Redirect::permanent("/article") }))
+            .route("/loop", get(|| async { Redirect::temporary("/loop") }))
+            .route("/latin1", get(latin1))
+            .route("/gzip", get(gzip))
+            .route(
+                "/limited",
+                get(|| async {
+                    (
+                        StatusCode::TOO_MANY_REQUESTS,
+                        [(header::RETRY_AFTER, "120")],
+                        "slow down",
+                    )
+                }),
+            )
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    markdown("# Too Late\n")
+                }),
+            )
+            .route("/sitemap.xml", get(|| async { xml(index) }))
+            .route("/pages-sitemap.xml", get(|| async { xml(pages) }));
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        Self { base, handle }
+    }
+
+    /// Absolute URL of `path` on the server.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base)
+    }
+}
+
+impl Drop for FixtureServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+fn markdown(body: &'static str) -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/markdown; charset=utf-8")],
+        body,
+    )
+        .into_response()
+}
+
+fn xml(body: String) -> Response {
+    ([(header::CONTENT_TYPE, "application/xml")], body).into_response()
+}
+
+fn html(body: &'static str) -> Response {
+    ([(header::CONTENT_TYPE, "text/html; charset=utf-8")], body).into_response()
+}
+
+async fn latin1() -> Response {
+    let mut body = b"<html><body><main><article><h1>Caf".to_vec();
+    body.push(0xE9); // é
+    body.extend_from_slice(b" Menu</h1><p>Cr");
+    body.push(0xE8); // è
+    body.extend_from_slice(
+        b"me br\xFBl\xE9e is served in a page encoded as Latin-1, which must be decoded \
+          before conversion so accented letters survive.</p></article></main></body></html>",
+    );
+    (
+        [(header::CONTENT_TYPE, "text/html; charset=iso-8859-1")],
+        body,
+    )
+        .into_response()
+}
+
+async fn gzip(headers: HeaderMap) -> Response {
+    const BODY: &str = "# Compressed\n\nThis page may travel gzip-compressed.\n";
+    let accepts_gzip = headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("gzip"));
+    if !accepts_gzip {
+        return markdown(BODY);
+    }
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(BODY.as_bytes()).unwrap();
+    (
+        [
+            (header::CONTENT_TYPE, "text/markdown; charset=utf-8"),
+            (header::CONTENT_ENCODING, "gzip"),
+        ],
+        Body::from(encoder.finish().unwrap()),
+    )
+        .into_response()
+}
+
+/// The server binary with a fresh cache, connected over stdio.
+pub struct Server {
+    client: RunningService<RoleClient, ()>,
+    cache: tempfile::TempDir,
+}
+
+impl Server {
+    /// Starts the server with `args` after the cache directory.
+    pub async fn start(args: &[&str]) -> Self {
+        let cache = tempfile::tempdir().unwrap();
+        let mut command = Command::new(env!("CARGO_BIN_EXE_llms-fetch-mcp"));
+        command.arg(cache.path()).args(args);
+        let (transport, _) = TokioChildProcess::builder(command)
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let client = ().serve(transport).await.unwrap();
+        Self { client, cache }
+    }
+
+    pub fn cache_dir(&self) -> &Path {
+        self.cache.path()
+    }
+
+    /// Calls `tool` with `arguments`, a JSON object.
+    pub async fn call(
+        &self,
+        tool: &'static str,
+        arguments: serde_json::Value,
+    ) -> Result<CallToolResult, ServiceError> {
+        let serde_json::Value::Object(arguments) = arguments else {
+            panic!("tool arguments must be an object");
+        };
+        self.client
+            .call_tool(CallToolRequestParam {
+                name: tool.into(),
+                arguments: Some(arguments),
+            })
+            .await
+    }
+
+    /// Reads the resource at `uri`.
+    pub async fn read_resource(&self, uri: &str) -> Result<ReadResourceResult, ServiceError> {
+        self.client
+            .read_resource(ReadResourceRequestParam {
+                uri: uri.to_string(),
+            })
+            .await
+    }
+
+    /// Text content of a successful `fetch` of `url`.
+    pub async fn fetch(&self, url: &str) -> String {
+        let result = self
+            .call("fetch", serde_json::json!({ "url": url }))
+            .await
+            .unwrap();
+        text(&result)
+    }
+
+    /// Message of the error a `fetch` of `url` fails with.
+    pub async fn fetch_error(&self, url: &str) -> String {
+        match self.call("fetch", serde_json::json!({ "url": url })).await {
+            Err(ServiceError::McpError(error)) => error.message.to_string(),
+            other => panic!("expected {url} to fail, got {other:?}"),
+        }
+    }
+}
+
+/// All text content of `result`.
+pub fn text(result: &CallToolResult) -> String {
+    result
+        .content
+        .iter()
+        .filter_map(|content| content.as_text().map(|text| text.text.as_str()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
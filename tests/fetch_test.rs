@@ -0,0 +1,379 @@
+//! End-to-end tests of the `fetch` tool against local fixture pages.
+
+mod common;
+
+use common::{FixtureServer, Server};
+use rmcp::service::ServiceError;
+
+#[tokio::test]
+async fn test_fetch_llms_txt() {
+    let site = FixtureServer::start().await;
+    let server = Server::start(&[]).await;
+
+    let output = server.fetch(&site.url("/docs")).await;
+    assert!(output.contains("# Fixture Docs"), "{output}");
+    assert!(server.cache_dir().join("127.0.0.1/docs/llms.txt").exists());
+}
+
+#[tokio::test]
+async fn test_fetch_converts_html() {
+    let site = FixtureServer::start().await;
+    let server = Server::start(&[]).await;
+
+    let output = server.fetch(&site.url("/article")).await;
+    assert!(output.contains("Fixture Article"), "{output}");
+    assert!(output.contains("Details\n----"), "{output}");
+    assert!(!output.contains("<p>"), "{output}");
+    assert!(!output.contains("Home"), "{output}");
+    // Links are made absolute
+    assert!(output.contains(&format!("[link]({})", site.url("/docs/guide.md"))));
+}
+
+#[tokio::test]
+async fn test_fetch_content_type_hint() {
+    let site = FixtureServer::start().await;
+    let server = Server::start(&[]).await;
+
+    let converted = server.fetch(&site.url("/mislabeled")).await;
+    assert!(!converted.contains("# Guide\n\nInstall"), "{converted}");
+
+    let result = server
+        .call(
+            "fetch",
+            serde_json::json!({ "url": site.url("/mislabeled"), "content_type_hint": "markdown" }),
+        )
+        .await
+        .unwrap();
+    let output = common::text(&result);
+    assert!(output.contains("# Guide\n\nInstall"), "{output}");
+}
+
+#[tokio::test]
+async fn test_fetch_follows_redirects() {
+    let site = FixtureServer::start().await;
+    let server = Server::start(&[]).await;
+
+    let output = server.fetch(&site.url("/old")).await;
+    assert!(output.contains("Fixture Article"), "{output}");
+
+    let error = server.fetch_error(&site.url("/loop")).await;
+    assert!(error.contains("loop"), "{error}");
+}
+
+#[tokio::test]
+async fn test_fetch_decodes_declared_charset() {
+    let site = FixtureServer::start().await;
+    let server = Server::start(&[]).await;
+
+    let output = server.fetch(&site.url("/latin1")).await;
+    assert!(output.contains("Café Menu"), "{output}");
+    assert!(output.contains("Crème brûlée"), "{output}");
+}
+
+#[tokio::test]
+async fn test_fetch_compressed() {
+    let site = FixtureServer::start().await;
+    let server = Server::start(&[]).await;
+
+    let output = server.fetch(&site.url("/gzip")).await;
+    assert!(output.contains("# Compressed"), "{output}");
+}
+
+#[tokio::test]
+async fn test_fetch_rate_limited() {
+    let site = FixtureServer::start().await;
+    let server = Server::start(&[]).await;
+
+    let error = server.fetch_error(&site.url("/limited")).await;
+    assert!(error.contains("429"), "{error}");
+    assert!(error.contains("requests-per-second"), "{error}");
+}
+
+#[tokio::test]
+async fn test_fetch_times_out() {
+    let site = FixtureServer::start().await;
+    let server = Server::start(&["--fetch-timeout", "1"]).await;
+
+    let started = std::time::Instant::now();
+    let error = server.fetch_error(&site.url("/slow")).await;
+    assert!(started.elapsed().as_secs() < 5, "{error}");
+    assert!(error.contains("abandoned at the fetch deadline"), "{error}");
+    assert!(error.contains("raise --fetch-timeout"), "{error}");
+}
+
+#[tokio::test]
+async fn test_fetch_ephemeral() {
+    let site = FixtureServer::start().await;
+    let server = Server::start(&["--ephemeral"]).await;
+
+    let output = server.fetch(&site.url("/docs/guide.md")).await;
+    assert!(
+        output.contains("Kept in memory: :memory:/127.0.0.1/docs/guide.md"),
+        "{output}"
+    );
+    assert!(output.contains("cargo add fixture"), "{output}");
+    let grep = server
+        .call("grep", serde_json::json!({ "pattern": "cargo add" }))
+        .await
+        .unwrap();
+    assert!(common::text(&grep).contains(":memory:/127.0.0.1/docs/guide.md:3:"));
+    assert_eq!(std::fs::read_dir(server.cache_dir()).unwrap().count(), 0);
+    assert!(!std::path::Path::new(":memory:").exists());
+}
+
+#[tokio::test]
+async fn test_record_and_replay() {
+    let cassette = tempfile::tempdir().unwrap();
+    let dir = cassette.path().to_str().unwrap();
+    let site = FixtureServer::start().await;
+    let url = site.url("/article");
+    let recorded = Server::start(&["--record", dir]).await.fetch(&url).await;
+    assert!(recorded.contains("Fixture Article"), "{recorded}");
+    drop(site);
+
+    // The site is gone, and the new server's cache is empty
+    let server = Server::start(&["--replay", dir]).await;
+    let replayed = server.fetch(&url).await;
+    assert!(replayed.contains("Details\n----"), "{replayed}");
+    let cache_dir = server.cache_dir().to_str().unwrap();
+    assert!(replayed.contains(cache_dir), "{replayed}");
+    let error = server.fetch_error(&format!("{url}-missing")).await;
+    assert!(error.contains("network error"), "{error}");
+}
+
+#[tokio::test]
+async fn test_session_stats() {
+    let site = FixtureServer::start().await;
+    let server = Server::start(&["--max-age", "60"]).await;
+
+    server.fetch(&site.url("/docs/guide.md")).await;
+    server.fetch(&site.url("/docs/guide.md")).await;
+    let stats = server
+        .call("session_stats", serde_json::json!({}))
+        .await
+        .unwrap();
+    let text = common::text(&stats);
+    assert!(
+        text.contains("\nTool calls: fetch 2, session_stats 1\n"),
+        "{text}"
+    );
+    assert!(
+        text.contains("\nFetches: 2, 1 from the cache (50%)\nDomains: 127.0.0.1 2"),
+        "{text}"
+    );
+}
+
+#[tokio::test]
+async fn test_fetch_relative_paths() {
+    let site = FixtureServer::start().await;
+    let server = Server::start(&["--relative-paths"]).await;
+
+    let output = server.fetch(&site.url("/docs/guide.md")).await;
+    assert!(
+        output.contains("Saved to: 127.0.0.1/docs/guide.md"),
+        "{output}"
+    );
+    let cite = server
+        .call(
+            "cite",
+            serde_json::json!({ "path": "127.0.0.1/docs/guide.md", "start_line": 3, "end_line": 3 }),
+        )
+        .await
+        .unwrap();
+    assert!(common::text(&cite).contains(&site.url("/docs/guide.md")));
+    let lines = server
+        .call(
+            "read_lines",
+            serde_json::json!({ "path": "127.0.0.1/docs/guide.md", "start_line": 3 }),
+        )
+        .await
+        .unwrap();
+    let text = common::text(&lines);
+    assert!(
+        text.contains("Lines 3-3 of 3 in 127.0.0.1/docs/guide.md\n\n3\tInstall the fixture"),
+        "{text}"
+    );
+
+    std::fs::create_dir_all(server.cache_dir().join("@other")).unwrap();
+    std::fs::write(server.cache_dir().join("@other/secret.md"), "# Secret").unwrap();
+    std::fs::write(server.cache_dir().join("fetch-audit.jsonl"), "{}\n").unwrap();
+    for path in [
+        "fetch-audit.jsonl",
+        "@other/secret.md",
+        "127.0.0.1/docs/guide.md.meta.json",
+    ] {
+        let error = server
+            .call(
+                "read_lines",
+                serde_json::json!({ "path": path, "start_line": 1 }),
+            )
+            .await;
+        assert!(
+            matches!(&error, Err(ServiceError::McpError(e)) if e.message.contains("is not a file in the cache")),
+            "{path}: {error:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_fetch_dedup_scope() {
+    let site = FixtureServer::start().await;
+    let server = Server::start(&["--dedup-scope", "domain", "--max-age", "60"]).await;
+    let guide = server.cache_dir().join("127.0.0.1/docs/guide.md");
+
+    server.fetch(&site.url("/docs/guide.md")).await;
+    let output = server.fetch(&site.url("/mirror/guide.md")).await;
+    assert!(
+        output.contains(&format!("Saved to: {}", guide.display())),
+        "{output}"
+    );
+    let original = site.url("/docs/guide.md");
+    assert!(
+        output.contains(&format!("Same content as {original}; saved once")),
+        "{output}"
+    );
+    assert!(!server.cache_dir().join("127.0.0.1/mirror").exists());
+
+    // The mirror is served from the shared file
+    let cached = server.fetch(&site.url("/mirror/guide.md")).await;
+    assert!(cached.contains(&guide.display().to_string()), "{cached}");
+    let stats = server
+        .call("session_stats", serde_json::json!({}))
+        .await
+        .unwrap();
+    let text = common::text(&stats);
+    assert!(text.contains("\nFetches: 3, 1 from the cache"), "{text}");
+}
+
+#[tokio::test]
+async fn test_fetch_amp_alias() {
+    let site = FixtureServer::start().await;
+    let server = Server::start(&["--max-age", "60"]).await;
+    let amp = site.url("/amp/article");
+
+    let output = server.fetch(&amp).await;
+    assert!(output.contains(&format!("Aliases: {amp}")), "{output}");
+    let article = server.cache_dir().join("127.0.0.1/article");
+    assert!(
+        output.contains(&format!("Saved to: {}", article.display())),
+        "{output}"
+    );
+
+    let listed = server
+        .call("list_cache", serde_json::json!({ "url_prefix": amp }))
+        .await
+        .unwrap();
+    let text = common::text(&listed);
+    assert!(text.contains(&article.display().to_string()), "{text}");
+
+    // The AMP page is served from the canonical page's file
+    server.fetch(&amp).await;
+    let stats = server
+        .call("session_stats", serde_json::json!({}))
+        .await
+        .unwrap();
+    let text = common::text(&stats);
+    assert!(text.contains("\nFetches: 2, 1 from the cache"), "{text}");
+}
+
+#[tokio::test]
+async fn test_fetch_site() {
+    let site = FixtureServer::start().await;
+    let server = Server::start(&[]).await;
+
+    let result = server
+        .call(
+            "fetch_site",
+            serde_json::json!({ "url": site.url("/docs/llms.txt"), "max_depth": 1 }),
+        )
+        .await
+        .unwrap();
+    let output = common::text(&result);
+    assert!(output.contains("Pages cached: 2"), "{output}");
+    let guide = server.cache_dir().join("127.0.0.1/docs/guide.md");
+    assert!(
+        output.contains(&format!(
+            "- Guide ({})\n  Saved to: {}",
+            site.url("/docs/guide.md"),
+            guide.display()
+        )),
+        "{output}"
+    );
+    assert!(guide.exists());
+}
+
+#[tokio::test]
+async fn test_cache_resources_only_documents() {
+    let site = FixtureServer::start().await;
+    let server = Server::start(&[]).await;
+
+    server.fetch(&site.url("/docs/guide.md")).await;
+    let other = server.cache_dir().join("@other/127.0.0.1");
+    std::fs::create_dir_all(&other).unwrap();
+    std::fs::write(other.join("secret.md"), "# Secret").unwrap();
+    std::fs::write(server.cache_dir().join("fetch-audit.jsonl"), "{}\n").unwrap();
+
+    let guide = server
+        .read_resource("llms-fetch://cache/127.0.0.1/docs/guide.md")
+        .await
+        .unwrap();
+    assert_eq!(guide.contents.len(), 1);
+    for uri in [
+        "llms-fetch://cache/fetch-audit.jsonl",
+        "llms-fetch://cache/@other/127.0.0.1/secret.md",
+        "llms-fetch://cache/127.0.0.1/docs/guide.md.meta.json",
+        "llms-fetch://cache/.index.json",
+    ] {
+        let error = server.read_resource(uri).await;
+        assert!(
+            matches!(&error, Err(ServiceError::McpError(e)) if e.message.contains("is not in the cache")),
+            "{uri}: {error:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_fetch_list_failures() {
+    let site = FixtureServer::start().await;
+    let server = Server::start(&[]).await;
+
+    let some = server
+        .call(
+            "fetch",
+            serde_json::json!({ "url": [site.url("/docs/guide.md"), site.url("/loop")] }),
+        )
+        .await
+        .unwrap();
+    assert_ne!(some.is_error, Some(true));
+    assert!(
+        common::text(&some).starts_with("Fetched 1 of 2 URLs"),
+        "{}",
+        common::text(&some)
+    );
+
+    let none = server
+        .call(
+            "fetch",
+            serde_json::json!({ "url": [site.url("/loop"), site.url("/limited")] }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(none.is_error, Some(true));
+    assert!(common::text(&none).starts_with("Fetched 0 of 2 URLs"));
+}
+
+#[tokio::test]
+async fn test_same_site_sitemap() {
+    let site = FixtureServer::start().await;
+    let missing = site.url("/docs/guides.md");
+    let guide = site.url("/docs/guide.md");
+
+    // The sitemap index lists its sitemap on another site
+    let error = Server::start(&[]).await.fetch_error(&missing).await;
+    assert!(error.contains(&guide), "{error}");
+    let error = Server::start(&["--same-site"])
+        .await
+        .fetch_error(&missing)
+        .await;
+    assert!(!error.contains(&guide), "{error}");
+}